@@ -0,0 +1,45 @@
+/*
+Compares GenWriter::write_line (takes the bucket lock for every line) with buffered() (one lock per batch) when many
+threads scatter lines over many buckets, as shuffle and group do.
+
+    cargo run --release --example buffered_writer_bench [zst]
+
+Plain jsonl outputs by default, zstd with `zst`.
+*/
+use datamap_rs::groupfilter::GenWriter;
+use datamap_rs::utils::set_compress_outputs;
+use rayon::prelude::*;
+use std::time::Instant;
+
+fn main() {
+    let compress = std::env::args().nth(1).as_deref() == Some("zst");
+    set_compress_outputs(compress);
+    let line = format!("{{\"id\": 12345, \"text\": \"{}\"}}", "lorem ipsum ".repeat(20));
+    let (files, lines_per_file, buckets) = (64usize, 50_000usize, 256usize);
+    for mode in ["unbuffered", "buffered", "unbuffered", "buffered"] {
+        let dir = std::env::temp_dir().join(format!("bench_buffered_{}", mode));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let writer = GenWriter::new(&dir, buckets, "b", 1 << 30, 1 << 20);
+        let start = Instant::now();
+        (0..files).into_par_iter().for_each(|f| {
+            let mut rng = fastrand::Rng::with_seed(f as u64);
+            if mode == "buffered" {
+                let mut w = writer.buffered();
+                for _ in 0..lines_per_file {
+                    w.write_line(rng.usize(0..buckets), line.as_bytes()).unwrap();
+                }
+                w.flush().unwrap();
+            } else {
+                let mut bytes = line.clone().into_bytes();
+                bytes.push(b'\n');
+                for _ in 0..lines_per_file {
+                    writer.write_line(rng.usize(0..buckets), &bytes).unwrap();
+                }
+            }
+        });
+        writer.finish().unwrap();
+        println!("{} {}: {:.2}s", if compress { "zst" } else { "raw" }, mode, start.elapsed().as_secs_f64());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+/*============================================================
+=                            BLOOM                           =
+============================================================*/
+/*
+A small, persistable bloom filter of document ids, used by `map --seen-bloom` to skip docs that an
+earlier run already emitted (e.g. when re-running over a growing or partially re-crawled input).
+
+Sized from a target capacity and false-positive rate with the usual formulas
+(num_bits = -n ln(p) / ln(2)^2, num_hashes = num_bits / n * ln(2)), and indexed by double hashing
+the two halves of an xxh3-128 of the id. Bits are AtomicU64 words, so rayon workers can test and
+insert concurrently without a lock.
+
+False positives mean an unseen doc is occasionally skipped (at roughly the configured rate once the
+filter is full); there are no false negatives. Inserting well past the capacity raises the effective
+false-positive rate, which the saved filter's stats make visible.
+
+On-disk format (little endian): magic, capacity (u64), fpr (f64), num_bits (u64), num_hashes (u32),
+num_inserted (u64), then the bit words (u64 each).
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use anyhow::{ensure, Error, Result};
+use serde_json::Value;
+use xxhash_rust::xxh3::xxh3_128;
+
+use crate::utils::json_get;
+
+const BLOOM_MAGIC: &[u8; 8] = b"DMBLOOM1";
+const BLOOM_HEADER_LEN: usize = 8 + 8 + 8 + 8 + 4 + 8;
+
+pub struct BloomFilter {
+    pub capacity: u64,   // number of ids the filter was sized for
+    pub fpr: f64,        // target false-positive rate at capacity
+    pub num_bits: u64,   // always a multiple of 64
+    pub num_hashes: u32, // bits set per id
+    num_inserted: AtomicUsize,
+    bits: Vec<AtomicU64>,
+}
+
+impl BloomFilter {
+    pub fn new(capacity: u64, fpr: f64) -> Result<Self, Error> {
+        ensure!(capacity > 0, "Bloom filter capacity must be positive");
+        ensure!(fpr > 0.0 && fpr < 1.0, format!("Bloom filter fpr must be in (0, 1), not {:?}", fpr));
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * fpr.ln() / (ln2 * ln2)).ceil() as u64;
+        let num_words = num_bits.div_ceil(64).max(1);
+        let num_bits = num_words * 64;
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0) as u32;
+        Ok(Self {
+            capacity,
+            fpr,
+            num_bits,
+            num_hashes,
+            num_inserted: AtomicUsize::new(0),
+            bits: (0..num_words).map(|_| AtomicU64::new(0)).collect(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path)?;
+        ensure!(
+            bytes.len() >= BLOOM_HEADER_LEN && &bytes[..8] == BLOOM_MAGIC,
+            format!("{:?} is not a bloom filter file", path)
+        );
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        let capacity = u64_at(8);
+        let fpr = f64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        let num_bits = u64_at(24);
+        let num_hashes = u32::from_le_bytes(bytes[32..36].try_into().unwrap());
+        let num_inserted = u64_at(36) as usize;
+        ensure!(
+            num_bits % 64 == 0 && bytes.len() == BLOOM_HEADER_LEN + (num_bits / 8) as usize,
+            format!("Bloom filter file {:?} is truncated or corrupt", path)
+        );
+        let bits = bytes[BLOOM_HEADER_LEN..]
+            .chunks_exact(8)
+            .map(|w| AtomicU64::new(u64::from_le_bytes(w.try_into().unwrap())))
+            .collect();
+        Ok(Self {
+            capacity,
+            fpr,
+            num_bits,
+            num_hashes,
+            num_inserted: AtomicUsize::new(num_inserted),
+            bits,
+        })
+    }
+
+    pub fn load_or_new(path: &Path, capacity: u64, fpr: f64) -> Result<Self, Error> {
+        // An existing filter keeps the sizing it was created with
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Self::new(capacity, fpr)
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        // Written to a sibling temp file and renamed, so an interrupted save never clobbers the old filter
+        let mut bytes: Vec<u8> = Vec::with_capacity(BLOOM_HEADER_LEN + self.bits.len() * 8);
+        bytes.extend(BLOOM_MAGIC);
+        bytes.extend(self.capacity.to_le_bytes());
+        bytes.extend(self.fpr.to_le_bytes());
+        bytes.extend(self.num_bits.to_le_bytes());
+        bytes.extend(self.num_hashes.to_le_bytes());
+        bytes.extend((self.len() as u64).to_le_bytes());
+        for word in &self.bits {
+            bytes.extend(word.load(Ordering::Relaxed).to_le_bytes());
+        }
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp_path = PathBuf::from(format!("{}.tmp", path.display()));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn bit_positions(&self, key: &[u8]) -> impl Iterator<Item = u64> + '_ {
+        let hash = xxh3_128(key);
+        let h1 = hash as u64;
+        let h2 = ((hash >> 64) as u64) | 1;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits)
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .all(|pos| self.bits[(pos / 64) as usize].load(Ordering::Relaxed) & (1 << (pos % 64)) != 0)
+    }
+
+    pub fn insert(&self, key: &[u8]) -> bool {
+        // Returns true if the key was (probably) not in the filter yet
+        let mut newly_set = false;
+        for pos in self.bit_positions(key) {
+            let mask = 1 << (pos % 64);
+            if self.bits[(pos / 64) as usize].fetch_or(mask, Ordering::Relaxed) & mask == 0 {
+                newly_set = true;
+            }
+        }
+        if newly_set {
+            self.num_inserted.fetch_add(1, Ordering::Relaxed);
+        }
+        newly_set
+    }
+
+    pub fn len(&self) -> usize {
+        // Distinct ids inserted so far (approximate: ids that collided with earlier ones aren't counted)
+        self.num_inserted.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn estimated_fpr(&self) -> f64 {
+        // (1 - e^(-kn/m))^k at the current fill
+        let k = self.num_hashes as f64;
+        (1.0 - (-k * self.len() as f64 / self.num_bits as f64).exp()).powf(k)
+    }
+}
+
+pub fn line_id(line: &str, id_field: &str) -> Option<String> {
+    // The id of a raw jsonl line, as the string the filter is keyed by (non-string ids use their json text)
+    let id_val = gjson::get(line, id_field);
+    match id_val.kind() {
+        gjson::Kind::Null => None,
+        gjson::Kind::String => Some(id_val.str().to_string()),
+        _ => Some(id_val.json().to_string()),
+    }
+}
+
+pub fn value_id(value: &Value, id_field: &str) -> Option<String> {
+    // Same as line_id, for an already parsed doc
+    match json_get(value, id_field) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    }
+}
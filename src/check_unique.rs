@@ -0,0 +1,120 @@
+/*============================================================
+=                            CHECK UNIQUE                    =
+============================================================*/
+/*
+Identifier integrity check: verifies that the values of an id field are globally unique across a dataset.
+
+This is NOT dedup (which is about content): it's a data-quality gate for pre-assigned ids.
+Every id is hashed (xxh3-128) into a sharded concurrent set that counts occurrences; files are scanned in parallel.
+Ids are compared by json type and value, so the string "1" and the number 1 are different ids.
+Writes a small json report with the number of duplicate ids and a sample of the colliding ids.
+*/
+
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anyhow::{Error, Result};
+use dashmap::DashMap;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use xxhash_rust::xxh3::xxh3_128;
+
+use crate::utils::{build_pbar, write_mem_atomic};
+use mj_io::{expand_dirs, read_pathbuf_to_mem};
+
+pub fn check_unique(
+    input_dir: &Path,
+    output_file: &Path,
+    id_field: &String,
+    sample_size: usize,
+) -> Result<(), Error> {
+    let start_main = Instant::now();
+    println!("Starting uniqueness check...");
+    let all_files = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+
+    let id_counts: DashMap<u128, usize> = DashMap::new();
+    let sample: Mutex<Vec<Value>> = Mutex::new(Vec::new());
+    let total_docs = AtomicUsize::new(0);
+    let missing_ids = AtomicUsize::new(0);
+
+    let pbar = build_pbar(all_files.len(), "Paths");
+    all_files.par_iter().for_each(|p| {
+        let (docs, missing) = check_unique_path(p, id_field, &id_counts, &sample, sample_size).unwrap();
+        total_docs.fetch_add(docs, Ordering::SeqCst);
+        missing_ids.fetch_add(missing, Ordering::SeqCst);
+        pbar.inc(1);
+    });
+
+    // Merge step: collapse the per-id counts into the report
+    let unique_ids = id_counts.len();
+    let (duplicate_ids, duplicate_docs) = id_counts
+        .par_iter()
+        .filter(|e| *e.value() > 1)
+        .map(|e| (1, *e.value() - 1))
+        .reduce(|| (0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+    let mut sample = sample.into_inner().unwrap();
+    sample.sort_by_cached_key(|id| id.to_string());
+
+    let total_docs = total_docs.into_inner();
+    let missing_ids = missing_ids.into_inner();
+    let report = json!({
+        "id_field": id_field,
+        "total_docs": total_docs,
+        "missing_ids": missing_ids,
+        "unique_ids": unique_ids,
+        "duplicate_ids": duplicate_ids,
+        "duplicate_docs": duplicate_docs,
+        "sample_duplicates": sample,
+    });
+    write_mem_atomic(&serde_json::to_vec(&report).unwrap(), output_file).unwrap();
+
+    println!("Finished uniqueness check in {:?} secs", start_main.elapsed().as_secs());
+    println!(
+        "Saw {:?} docs | {:?} unique ids | {:?} ids appear more than once ({:?} extra docs) | {:?} docs missing an id",
+        total_docs, unique_ids, duplicate_ids, duplicate_docs, missing_ids
+    );
+    Ok(())
+}
+
+fn check_unique_path(
+    path: &PathBuf,
+    id_field: &str,
+    id_counts: &DashMap<u128, usize>,
+    sample: &Mutex<Vec<Value>>,
+    sample_size: usize,
+) -> Result<(usize, usize), Error> {
+    let contents = read_pathbuf_to_mem(path).unwrap();
+    let mut docs = 0;
+    let mut missing = 0;
+    for line in contents.lines() {
+        let line = line.unwrap();
+        docs += 1;
+        let id_val = gjson::get(&line, id_field);
+        if !id_val.exists() || id_val.kind() == gjson::Kind::Null {
+            missing += 1;
+            continue;
+        }
+        // Strings are keyed with an "s" prefix (no other json value starts with one), so the string "1" never
+        // collides with the number 1
+        let key = if id_val.kind() == gjson::Kind::String {
+            format!("s{}", id_val.str())
+        } else {
+            id_val.json().to_string()
+        };
+
+        let mut count = id_counts.entry(xxh3_128(key.as_bytes())).or_insert(0);
+        *count += 1;
+        let first_collision = *count == 2;
+        drop(count);
+        if first_collision {
+            let mut sample = sample.lock().unwrap();
+            if sample.len() < sample_size {
+                sample.push(serde_json::from_str(id_val.json())?);
+            }
+        }
+    }
+    Ok((docs, missing))
+}
@@ -0,0 +1,115 @@
+/*============================================================
+=                        PIPELINE CONFIGS                    =
+============================================================*/
+/*
+Where a map pipeline config comes from, and how it is parsed into the json the PipelineProcessor is built from.
+A config is a .json/.yaml file (--config path), a http(s):// url (--config url) or an inline string (--config-inline).
+*/
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Error, Result};
+use serde_json::Value;
+
+pub enum ConfigSource {
+    /* A url is fetched once when the source is built, so every later parse uses the same body */
+    File(PathBuf),
+    Url { url: String, body: String },
+    Inline(String),
+}
+
+impl ConfigSource {
+    pub fn new(config: &Option<PathBuf>, config_inline: &Option<String>) -> Result<Self, Error> {
+        match (config, config_inline) {
+            (_, Some(inline)) => Ok(ConfigSource::Inline(inline.clone())),
+            (Some(config), None) => {
+                let config_str = config.to_string_lossy();
+                if config_str.starts_with("http://") || config_str.starts_with("https://") {
+                    let url = config_str.to_string();
+                    let body = fetch_url(&url)?;
+                    Ok(ConfigSource::Url { url, body })
+                } else {
+                    Ok(ConfigSource::File(config.clone()))
+                }
+            }
+            (None, None) => bail!("Need either --config or --config-inline"),
+        }
+    }
+
+    pub fn parse(&self) -> Result<Value, Error> {
+        // Handle either .yaml or .json config and return a Json value
+        let (path, body) = match self {
+            ConfigSource::File(path) => return parse_config_file(path),
+            ConfigSource::Url { url, body } => (url.split(['?', '#']).next().unwrap_or_default(), body),
+            ConfigSource::Inline(body) => ("", body),
+        };
+        // Urls go by their extension like files do; inline configs (and urls without one) can be either format
+        if path.ends_with(".json") {
+            serde_json::from_str(body).map_err(|e| anyhow!("Can't parse config {}: {}", self, e))
+        } else if path.ends_with(".yaml") {
+            parse_yaml(body).map_err(|e| anyhow!("Can't parse config {}: {}", self, e))
+        } else {
+            serde_json::from_str(body).or_else(|json_err| {
+                parse_yaml(body).map_err(|yaml_err| {
+                    anyhow!("Can't parse config {} as json ({}) or as yaml ({})", self, json_err, yaml_err)
+                })
+            })
+        }
+    }
+
+    pub fn parse_with_text_key(&self, text_key: Option<String>) -> Result<Value, Error> {
+        let mut json_config = self.parse()?;
+        if let Some(text_key) = text_key {
+            // CLI --text-key overrides the config's global text_field (steps/routes with their own text_field keep it)
+            json_config["text_field"] = Value::String(text_key);
+        }
+        Ok(json_config)
+    }
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigSource::File(path) => write!(f, "{:?}", path),
+            ConfigSource::Url { url, .. } => write!(f, "{:?}", url),
+            ConfigSource::Inline(_) => write!(f, "--config-inline"),
+        }
+    }
+}
+
+fn fetch_url(url: &str) -> Result<String, Error> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(Duration::from_secs(60)))
+        .build()
+        .into();
+    agent
+        .get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|e| anyhow!("Can't fetch config {:?}: {}", url, e))
+}
+
+fn parse_yaml(body: &str) -> Result<Value, Error> {
+    let yaml_value: serde_yaml::Value = serde_yaml::from_str(body)?;
+    Ok(serde_json::to_value(yaml_value)?)
+}
+
+fn parse_config_file(config: &PathBuf) -> Result<Value, Error> {
+    // Files have to say which format they are in
+    let file = File::open(config).map_err(|e| anyhow!("Can't open config {:?}: {}", config, e))?;
+    let reader = BufReader::new(file);
+
+    let ext = config.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    match ext {
+        "json" => serde_json::from_reader(reader).map_err(|e| anyhow!("Can't parse config {:?}: {}", config, e)),
+        "yaml" => {
+            let yaml_value: serde_yaml::Value =
+                serde_yaml::from_reader(reader).map_err(|e| anyhow!("Can't parse config {:?}: {}", config, e))?;
+            Ok(serde_json::to_value(yaml_value)?)
+        }
+        _ => bail!("Weird config format: {:?}", config),
+    }
+}
@@ -0,0 +1,132 @@
+/*============================================================
+=                            DIFF                            =
+============================================================*/
+/*
+Compares two versions of a dataset (e.g. the outputs of an old and a new pipeline config) by document id.
+
+Each side is loaded into a concurrent id -> content hash map (xxh3-128 of the text field, the same hash
+hash_annotator writes), and the two maps are compared to count and sample the ids that were
+added (only in new), removed (only in old) and changed (in both, but with different text).
+Memory scales with the number of ids on both sides, so this is meant for analysis-sized datasets.
+*/
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use anyhow::{Error, Result};
+use dashmap::DashMap;
+use rayon::prelude::*;
+use serde_json::json;
+
+use crate::map_fxn::HashAnnotator;
+use crate::utils::{build_pbar, expand_input_dirs, read_input_lines, write_mem_atomic};
+
+struct DiffSide {
+    hashes: DashMap<String, u128>,
+    docs: usize,
+    missing_ids: usize,
+    duplicate_ids: usize,
+}
+
+pub fn diff(
+    old_dir: &Path,
+    new_dir: &Path,
+    output_file: &Path,
+    id_field: &String,
+    text_field: &String,
+    sample_size: usize,
+) -> Result<(), Error> {
+    let start_main = Instant::now();
+    println!("Starting diff...");
+    let old = load_side(old_dir, id_field, text_field, "Old paths")?;
+    let new = load_side(new_dir, id_field, text_field, "New paths")?;
+
+    let mut removed: Vec<String> = old
+        .hashes
+        .par_iter()
+        .filter(|e| !new.hashes.contains_key(e.key()))
+        .map(|e| e.key().clone())
+        .collect();
+    let mut changed: Vec<String> = old
+        .hashes
+        .par_iter()
+        .filter(|e| new.hashes.get(e.key()).is_some_and(|h| *h != *e.value()))
+        .map(|e| e.key().clone())
+        .collect();
+    let unchanged = old.hashes.len() - removed.len() - changed.len();
+    let mut added: Vec<String> = new
+        .hashes
+        .par_iter()
+        .filter(|e| !old.hashes.contains_key(e.key()))
+        .map(|e| e.key().clone())
+        .collect();
+
+    let report = json!({
+        "id_field": id_field,
+        "text_field": text_field,
+        "old": {"total_docs": old.docs, "missing_ids": old.missing_ids, "duplicate_ids": old.duplicate_ids},
+        "new": {"total_docs": new.docs, "missing_ids": new.missing_ids, "duplicate_ids": new.duplicate_ids},
+        "added": added.len(),
+        "removed": removed.len(),
+        "changed": changed.len(),
+        "unchanged": unchanged,
+        "sample_added": sample(&mut added, sample_size),
+        "sample_removed": sample(&mut removed, sample_size),
+        "sample_changed": sample(&mut changed, sample_size),
+    });
+    write_mem_atomic(&serde_json::to_vec(&report).unwrap(), output_file).unwrap();
+
+    println!("Finished diff in {:?} secs", start_main.elapsed().as_secs());
+    println!(
+        "{:?} added | {:?} removed | {:?} changed | {:?} unchanged",
+        added.len(),
+        removed.len(),
+        changed.len(),
+        unchanged
+    );
+    Ok(())
+}
+
+fn sample(ids: &mut [String], sample_size: usize) -> Vec<String> {
+    // Sorted so that reports are reproducible
+    ids.par_sort_unstable();
+    ids.iter().take(sample_size).cloned().collect()
+}
+
+fn load_side(input_dir: &Path, id_field: &str, text_field: &str, desc: &str) -> Result<DiffSide, Error> {
+    let paths = expand_input_dirs(input_dir)?;
+    let hashes: DashMap<String, u128> = DashMap::new();
+    let docs = AtomicUsize::new(0);
+    let missing_ids = AtomicUsize::new(0);
+    let duplicate_ids = AtomicUsize::new(0);
+    let pbar = build_pbar(paths.len(), desc);
+    paths.par_iter().for_each(|p| {
+        for line in read_input_lines(p).unwrap() {
+            docs.fetch_add(1, Ordering::SeqCst);
+            let id_val = gjson::get(&line, id_field);
+            if !id_val.exists() || id_val.kind() == gjson::Kind::Null {
+                missing_ids.fetch_add(1, Ordering::SeqCst);
+                continue;
+            }
+            let id = if id_val.kind() == gjson::Kind::String {
+                id_val.str().to_string()
+            } else {
+                id_val.json().to_string()
+            };
+            // Docs without the text field hash as empty text
+            let text_hash = HashAnnotator::hash_text_128(gjson::get(&line, text_field).str());
+            if hashes.insert(id, text_hash).is_some() {
+                duplicate_ids.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        pbar.inc(1);
+    });
+
+    Ok(DiffSide {
+        hashes,
+        docs: docs.into_inner(),
+        missing_ids: missing_ids.into_inner(),
+        duplicate_ids: duplicate_ids.into_inner(),
+    })
+}
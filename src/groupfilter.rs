@@ -1,29 +1,28 @@
 use regex::Regex;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::BuildHasher;
 use ahash::RandomState;
 use std::sync::atomic;
 use serde_json::Value;
 use std::sync::atomic::AtomicUsize;
-use anyhow::{Error, Result};
+use anyhow::{bail, ensure, Error, Result};
 use dashmap::DashMap;
 use std::{
-    fs::{create_dir_all, File, OpenOptions, remove_file},
+    fs::remove_file,
     hash::{Hash, Hasher},
     io::{Write, BufRead},
-    os::unix::fs::OpenOptionsExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Instant,
 };
 use serde_json;
 use rayon::prelude::*;
-use crate::utils::json_get;
-use mj_io::{expand_dirs, read_pathbuf_to_mem, build_pbar, write_mem_to_pathbuf, get_output_filename};
-use zstd::stream::Encoder;
+use crate::utils::{build_pbar, json_get, mirrored_output_filename, output_ext, AtomicFile, FilenameTemplate, JsonlWriter, OutputEncoder, RuntimeBudget};
+use mj_io::{expand_dirs, read_pathbuf_to_mem};
 use serde::{Deserialize, Serialize};
 use ahash::AHasher; 
-use sonic_rs::{JsonValueTrait, Value as SonicValue};
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
+use sonic_rs::{JsonContainerTrait, JsonValueTrait, Value as SonicValue};
 use fastrand;
 
 
@@ -65,6 +64,111 @@ struct GroupFilterConfig {
 	size_key: Option<String>, // if present, add the size of this chunk to the doc we keep in the filter step 
 	#[serde(default="default_delete_after_read")]
 	delete_after_read: bool,
+	#[serde(default)]
+	normalize_keys: HashMap<String, Vec<KeyNormalization>>, // group_key -> normalizations applied to its (string) value before hashing
+	#[serde(default="default_write_buffer_size")]
+	write_buffer_size: usize, // bytes buffered per bucket (per input file) before taking that bucket's writer lock
+	#[serde(default)]
+	hash_algo: HashAlgo, // which hash assigns docs to buckets/groups; keep this fixed across a resumed or sharded run
+	#[serde(default)]
+	expand_lists: bool, // if true, the (single) group key may hold a list and the doc joins the group of every element
+	#[serde(default)]
+	sort_key_type: SortKeyType, // how sort key values are compared (see SortKeyType)
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgo {
+	/* xxh3 over length-prefixed key bytes: stable across rust/crate versions and platforms,
+	   and the group and filter phases hash a given key identically */
+	#[default]
+	Xxh3,
+	/* The pre-versioned behavior (ahash through std::hash::Hash). Only use this to stay compatible
+	   with bucket assignments made by older builds: ahash makes no stability promises */
+	Ahash,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKeyType {
+	/* Every value is compared as its string form (the original behavior). Numbers compare lexicographically,
+	   so 10 sorts before 9: only right for strings and fixed-width values like ISO dates */
+	#[default]
+	String,
+	/* Values are coerced to numbers: json numbers as-is, strings that parse as a (finite) number, e.g. " 42 " or "1e3".
+	   Anything else (other strings, bools, nulls, lists) is uncoercible and never preferred over a coercible value:
+	   it sorts after every number with keep_idx 0 and before every number with keep_idx -1, so a group only keeps
+	   such a doc if none of its docs has a usable value (uncoercible values are then compared as strings) */
+	Number,
+	/* Numbers and numeric strings compare as numbers, other values as strings, and numbers sort before strings.
+	   For fields that are mostly numeric but dirty, when non-numeric values should just be ordered consistently */
+	Auto,
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortKeyPart {
+	// One sort key value, ready to compare. Variants compare in declaration order, then by their contents
+	Low(String),  // an uncoercible value that has to lose to every number (SortKeyType::Number with keep_idx -1)
+	Number(f64),
+	Str(String),  // a string (SortKeyType::String / Auto), or an uncoercible value that has to lose with keep_idx 0
+}
+
+impl Eq for SortKeyPart {}
+
+impl PartialOrd for SortKeyPart {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for SortKeyPart {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		match (self, other) {
+			(SortKeyPart::Low(a), SortKeyPart::Low(b)) | (SortKeyPart::Str(a), SortKeyPart::Str(b)) => a.cmp(b),
+			(SortKeyPart::Number(a), SortKeyPart::Number(b)) => a.total_cmp(b),
+			_ => self.rank().cmp(&other.rank()),
+		}
+	}
+}
+
+impl SortKeyPart {
+	fn rank(&self) -> u8 {
+		match self {
+			SortKeyPart::Low(_) => 0,
+			SortKeyPart::Number(_) => 1,
+			SortKeyPart::Str(_) => 2,
+		}
+	}
+
+	pub fn from_value(value: &Value, sort_key_type: SortKeyType, keep_idx: i32) -> Self {
+		let as_string = match value {
+			Value::String(s) => s.clone(),
+			other => other.to_string(),
+		};
+		let as_number = match value {
+			Value::Number(n) => n.as_f64(),
+			Value::String(s) => s.trim().parse::<f64>().ok().filter(|n| n.is_finite()),
+			_ => None,
+		};
+		match (sort_key_type, as_number) {
+			(SortKeyType::String, _) => SortKeyPart::Str(as_string),
+			(_, Some(n)) => SortKeyPart::Number(n),
+			(SortKeyType::Number, None) if keep_idx == -1 => SortKeyPart::Low(as_string),
+			(_, None) => SortKeyPart::Str(as_string),
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyNormalization {
+	Trim,
+	Lowercase,
+	StripScheme,
 }
 
 
@@ -76,49 +180,220 @@ fn default_delete_after_read() -> bool {
 	false
 }
 
+fn default_write_buffer_size() -> usize {
+	DEFAULT_WRITE_BUFFER_SIZE
+}
+
+
+pub fn normalize_group_str(val: &str, normalizations: &[KeyNormalization]) -> String {
+	/* Normalizes a string group key value before it gets hashed.
+	This is shared by the group and the filter phases: if they disagreed, equal keys could hash differently
+	in the filter step and dedup would silently break.
+	Normalizations are always applied in the order trim -> strip_scheme -> lowercase, regardless of config order
+	*/
+	let mut val = val;
+	if normalizations.contains(&KeyNormalization::Trim) {
+		val = val.trim();
+	}
+	if normalizations.contains(&KeyNormalization::StripScheme) {
+		if let Some(idx) = val.find("://") {
+			let scheme = &val[..idx];
+			if !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+				val = &val[idx + 3..];
+			}
+		}
+	}
+	if normalizations.contains(&KeyNormalization::Lowercase) {
+		val.to_lowercase()
+	} else {
+		val.to_string()
+	}
+}
+
+
+
+fn load_config(config_path: &PathBuf) -> Result<GroupFilterConfig, Error> {
+	let config_contents = read_pathbuf_to_mem(config_path).unwrap();
+	let config: GroupFilterConfig = serde_yaml::from_reader(config_contents).unwrap();
+	ensure!(!config.expand_lists || config.group_keys.len() == 1,
+		"expand_lists needs exactly one group key, got {:?}", config.group_keys);
+	// The filter step re-derives each doc's buckets with the serde hasher, which only matches the group step's under xxh3
+	ensure!(!config.expand_lists || config.hash_algo == HashAlgo::Xxh3, "expand_lists needs hash_algo: xxh3");
+	Ok(config)
+}
+
+
+enum GroupHasher {
+	Xxh3(Box<Xxh3>),
+	Ahash(AHasher),
+}
+
+impl GroupHasher {
+	fn new(algo: HashAlgo, legacy: AHasher) -> Self {
+		match algo {
+			HashAlgo::Xxh3 => GroupHasher::Xxh3(Box::new(Xxh3::new())),
+			HashAlgo::Ahash => GroupHasher::Ahash(legacy),
+		}
+	}
+
+	fn write_str(&mut self, val: &str) {
+		match self {
+			GroupHasher::Xxh3(h) => {
+				// Length prefix so that ["ab", "c"] and ["a", "bc"] don't collide
+				h.update(&(val.len() as u64).to_le_bytes());
+				h.update(val.as_bytes());
+			},
+			GroupHasher::Ahash(h) => val.hash(h),
+		}
+	}
+
+	fn write_bool(&mut self, val: bool) {
+		match self {
+			GroupHasher::Xxh3(_) => self.write_str(if val { "true" } else { "false" }),
+			GroupHasher::Ahash(h) => val.hash(h),
+		}
+	}
+
+	fn finish(&self) -> usize {
+		match self {
+			GroupHasher::Xxh3(h) => h.digest() as usize,
+			GroupHasher::Ahash(h) => h.finish() as usize,
+		}
+	}
+}
+
+fn exact_number_str(as_u64: Option<u64>, as_i64: Option<i64>, as_f64: Option<f64>) -> String {
+	/* The text xxh3 hashes a number as, the same in the sonic and serde phases.
+	Integers are written exactly: going through f64 would merge distinct ids above 2^53 into one group.
+	Everything else goes through f64, whose Display also writes integral floats (1.0) like the integer (1)
+	*/
+	match (as_u64, as_i64) {
+		(Some(n), _) => n.to_string(),
+		(None, Some(n)) => n.to_string(),
+		(None, None) => as_f64.unwrap_or(f64::NAN).to_string(),
+	}
+}
 
 
 /*============================================================
 =                            GROUP STUFF                     =
 ============================================================*/
 
-pub fn group(input_dir: &PathBuf, group_dir: &PathBuf, config_path: &PathBuf, subext: Option<String>) -> Result<(), Error> {
-
+pub fn group(input_dir: &Path, group_dir: &PathBuf, config_path: &PathBuf, subext: Option<String>, max_runtime: Option<u64>, incremental: bool) -> Result<(), Error> {
+	/* With incremental, group_dir keeps a manifest of the input files that have already been grouped into it
+	(see GROUP MANIFEST below): those are skipped, and the new files are appended as a fresh set of chunk files
+	next to the existing ones rather than overwriting them. So re-running against a growing input_dir only groups
+	what arrived since the last run. Bucket assignment must stay the same across runs (same num_buckets and hash_algo).
+	*/
 	let start_main = Instant::now();
 	println!("Starting group operation");	
-	let input_paths = expand_dirs(vec![input_dir.clone()], None).unwrap();
-	let config_contents = read_pathbuf_to_mem(config_path).unwrap();
-	let config: GroupFilterConfig = serde_yaml::from_reader(config_contents).unwrap();
+	let mut input_paths = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+	let config = load_config(config_path)?;
 	let num_buckets = config.num_buckets;
 	let subext = if let Some(subext) = subext {
 		subext
 	} else {
 		"group".to_string()
 	};
-	let writer = GenWriter::new(group_dir, num_buckets, &subext, config.max_file_size);
+	let mut ingested: HashSet<String> = HashSet::new();
+	let mut first_file_idx = 0;
+	if incremental {
+		ingested = read_group_manifest(group_dir, &subext)?;
+		let num_inputs = input_paths.len();
+		input_paths.retain(|p| !ingested.contains(&manifest_entry(p, input_dir)));
+		println!("Skipping {:?} already grouped input files", num_inputs - input_paths.len());
+		first_file_idx = GenWriter::next_file_idx(group_dir, &subext)?;
+	}
+	let writer = GenWriter::new_from_idx(group_dir, num_buckets, &subext, config.max_file_size, config.write_buffer_size, first_file_idx);
+	let budget = RuntimeBudget::new(max_runtime);
+	let grouped: Mutex<Vec<String>> = Mutex::new(Vec::new());
 	let pbar = build_pbar(input_paths.len(), "Paths");
 	input_paths.par_iter().for_each(|p| {
-		group_path(p, &config.group_keys, &writer, &config.delete_after_read).unwrap();
+		if budget.out_of_time(p) {
+			return;
+		}
+		group_path(p, &config, &writer).unwrap();
+		grouped.lock().unwrap().push(manifest_entry(p, input_dir));
 		pbar.inc(1);
 	});
 
 	writer.finish().unwrap();
+	if incremental {
+		// Only after the chunk files are in place: a run that dies before this regroups its inputs next time
+		ingested.extend(grouped.into_inner().unwrap());
+		write_group_manifest(group_dir, &subext, &ingested)?;
+	}
 	println!("Finished group op in {:?} secs", start_main.elapsed().as_secs());
 
+	budget.finish()
+}
+
+
+/*============================================================
+=                            GROUP MANIFEST                  =
+============================================================*/
+/*
+The input files an incremental group run has grouped into group_dir, one path per line (relative to input_dir),
+in group_dir/group_manifest.{subext}.txt. It is rewritten (atomically) at the end of every incremental run.
+The name doesn't start with chunk_, so the filter step never mistakes it for grouped data.
+*/
+
+pub fn group_manifest_path(group_dir: &Path, subext: &str) -> PathBuf {
+	group_dir.join(format!("group_manifest.{}.txt", subext))
+}
+
+fn manifest_entry(path: &Path, input_dir: &Path) -> String {
+	path.strip_prefix(input_dir).unwrap_or(path).to_string_lossy().to_string()
+}
+
+fn read_group_manifest(group_dir: &Path, subext: &str) -> Result<HashSet<String>, Error> {
+	let manifest_path = group_manifest_path(group_dir, subext);
+	if !manifest_path.exists() {
+		return Ok(HashSet::new());
+	}
+	Ok(std::fs::read_to_string(&manifest_path)?
+		.lines()
+		.filter(|l| !l.is_empty())
+		.map(String::from)
+		.collect())
+}
+
+fn write_group_manifest(group_dir: &Path, subext: &str, ingested: &HashSet<String>) -> Result<(), Error> {
+	let mut entries: Vec<&String> = ingested.iter().collect();
+	entries.sort();
+	let mut file = AtomicFile::create(&group_manifest_path(group_dir, subext))?;
+	for entry in entries {
+		file.write_all(entry.as_bytes())?;
+		file.write_all(b"\n")?;
+	}
+	file.commit()?;
 	Ok(())
 }
 
 
-fn group_path(path: &PathBuf, group_keys: &Vec<String>, writer: &GenWriter, delete_after_read: &bool) -> Result<(), Error> {
+fn group_path(path: &PathBuf, config: &GroupFilterConfig, writer: &GenWriter) -> Result<(), Error> {
 	let num_chunks = writer.num_chunks;
 	let contents = read_pathbuf_to_mem(path).unwrap();
-    let mut buckets: Vec<Vec<u8>> = vec![Vec::new(); num_chunks];
+	let mut buckets = writer.buffered();
 
 	for line in contents.lines() {
 		let line = line.unwrap();
         let value: SonicValue = sonic_rs::from_str(&line).unwrap();
 
-		let hash_val = if let Some(hash_val) = get_group_hash_sonic(&value, group_keys).unwrap() {
+		if config.expand_lists {
+			// One copy of the doc per distinct bucket its groups land in
+			if let Some(hashes) = get_group_list_hashes_sonic(&value, &config.group_keys[0], &config.normalize_keys, config.hash_algo)? {
+				let mut bucket_ids: Vec<usize> = hashes.iter().map(|h| h % num_chunks).collect();
+				bucket_ids.sort_unstable();
+				bucket_ids.dedup();
+				for bucket_id in bucket_ids {
+					buckets.write_line(bucket_id, line.as_bytes())?;
+				}
+				continue;
+			}
+		}
+
+		let hash_val = if let Some(hash_val) = get_group_hash_sonic(&value, &config.group_keys, &config.normalize_keys, config.hash_algo).unwrap() {
 			hash_val
 		} else {
 			// missing group info, put in random shard 			
@@ -126,48 +401,89 @@ fn group_path(path: &PathBuf, group_keys: &Vec<String>, writer: &GenWriter, dele
 		};
 
 		let bucket_id = hash_val % num_chunks;
-		buckets[bucket_id].extend_from_slice(line.as_bytes());
-		buckets[bucket_id].push(b'\n');
+		buckets.write_line(bucket_id, line.as_bytes())?;
 
 	}
-	for (bucket_id, contents) in buckets.into_iter().enumerate() {
-		if !contents.is_empty() {
-			writer.write_batch(bucket_id, contents).unwrap();
-		}
-	}
-	if *delete_after_read {
+	buckets.flush()?;
+	if config.delete_after_read {
         remove_file(path).unwrap();
 	}
 	Ok(())
 }
 
-fn get_group_hash_sonic(
+pub fn get_group_hash_sonic(
     value: &sonic_rs::Value, 
     group_keys: &Vec<String>,
+    normalize_keys: &HashMap<String, Vec<KeyNormalization>>,
+    hash_algo: HashAlgo,
 ) -> Result<Option<usize>, Error> {
     let hash_builder = RandomState::with_seeds(1,2,3,4);
-    let mut hasher = hash_builder.build_hasher();
+    let mut hasher = GroupHasher::new(hash_algo, hash_builder.build_hasher());
     for k in group_keys {
         if let Some(group_val) = get_nested_value(value, k)? {
-            // Use the JsonValueTrait methods instead of pattern matching
-            if group_val.is_str() {
-                group_val.as_str().unwrap().hash(&mut hasher);
-            } else if group_val.is_number() {
-                // Hash the string representation for consistency
-                group_val.as_f64().unwrap().to_string().hash(&mut hasher);
-            } else if group_val.is_boolean() {
-                group_val.as_bool().unwrap().hash(&mut hasher);
-            } else if group_val.is_null() {
-                "null".hash(&mut hasher);
-            } else if group_val.is_array() || group_val.is_object() {
-                // For complex types, hash the JSON string representation
-                group_val.to_string().hash(&mut hasher);
-            }
+            write_sonic_val(&mut hasher, group_val, normalize_keys.get(k));
         } else {
             return Ok(None);
         }
     }
-    Ok(Some(hasher.finish() as usize))
+    Ok(Some(hasher.finish()))
+}
+
+pub fn get_group_list_hashes_sonic(
+    value: &sonic_rs::Value,
+    group_key: &str,
+    normalize_keys: &HashMap<String, Vec<KeyNormalization>>,
+    hash_algo: HashAlgo,
+) -> Result<Option<Vec<usize>>, Error> {
+    /* expand_lists counterpart of get_group_hash_sonic: every element of a list-valued group key is its own group,
+    hashed exactly like a scalar key holding that element (so "a" and ["a"] are the same group).
+    A non-list value is a one-element list; a missing key or an empty list means no group.
+    Returns the sorted, deduplicated group hashes
+    */
+    let group_val = if let Some(group_val) = get_nested_value(value, group_key)? {
+        group_val
+    } else {
+        return Ok(None);
+    };
+    let elements: Vec<&sonic_rs::Value> = if let Some(arr) = group_val.as_array() {
+        arr.iter().collect()
+    } else {
+        vec![group_val]
+    };
+    let mut hashes: Vec<usize> = elements.into_iter().map(|el| {
+        let mut hasher = GroupHasher::new(hash_algo, RandomState::with_seeds(1,2,3,4).build_hasher());
+        write_sonic_val(&mut hasher, el, normalize_keys.get(group_key));
+        hasher.finish()
+    }).collect();
+    hashes.sort_unstable();
+    hashes.dedup();
+    Ok(if hashes.is_empty() { None } else { Some(hashes) })
+}
+
+fn write_sonic_val(hasher: &mut GroupHasher, group_val: &sonic_rs::Value, normalizations: Option<&Vec<KeyNormalization>>) {
+    // Use the JsonValueTrait methods instead of pattern matching
+    if group_val.is_str() {
+        if let Some(normalizations) = normalizations {
+            hasher.write_str(&normalize_group_str(group_val.as_str().unwrap(), normalizations));
+        } else {
+            hasher.write_str(group_val.as_str().unwrap());
+        }
+    } else if group_val.is_number() {
+        match hasher {
+            GroupHasher::Xxh3(_) => {
+                hasher.write_str(&exact_number_str(group_val.as_u64(), group_val.as_i64(), group_val.as_f64()))
+            }
+            // The legacy (lossy) encoding, kept so that old ahash runs stay reproducible
+            GroupHasher::Ahash(_) => hasher.write_str(&group_val.as_f64().unwrap().to_string()),
+        }
+    } else if group_val.is_boolean() {
+        hasher.write_bool(group_val.as_bool().unwrap());
+    } else if group_val.is_null() {
+        hasher.write_str("null");
+    } else if group_val.is_array() || group_val.is_object() {
+        // For complex types, hash the JSON string representation
+        hasher.write_str(&group_val.to_string());
+    }
 }
 
 fn get_nested_value<'a>(
@@ -191,43 +507,75 @@ fn get_nested_value<'a>(
     
     Ok(Some(current))
 }
-fn get_group_hash(value: &serde_json::Value, group_keys: &Vec<String>) -> Result<Option<usize>, Error> {
-	let mut hasher = AHasher::default();
+pub fn get_group_hash(value: &serde_json::Value, group_keys: &Vec<String>, normalize_keys: &HashMap<String, Vec<KeyNormalization>>, hash_algo: HashAlgo) -> Result<Option<usize>, Error> {
+	/* The filter-phase twin of get_group_hash_sonic.
+	With xxh3 both produce the same hash for the same key (strings raw, numbers exactly, everything else as json),
+	the legacy ahash path keeps its old (different) encoding so that old runs stay reproducible
+	*/
+	let mut hasher = GroupHasher::new(hash_algo, AHasher::default());
 	for k in group_keys {
-		if let Some(group_val) = json_get(value, &k) {
-			let group_val_string = group_val.to_string();
-			group_val_string.hash(&mut hasher)
+		if let Some(group_val) = json_get(value, k) {
+			write_serde_val(&mut hasher, hash_algo, group_val, normalize_keys.get(k));
 		} else {
 			return Ok(None);
 		}
 	}
-	Ok(Some(hasher.finish() as usize))
+	Ok(Some(hasher.finish()))
 }
 
+pub fn get_group_list_hashes(value: &serde_json::Value, group_key: &str, normalize_keys: &HashMap<String, Vec<KeyNormalization>>, hash_algo: HashAlgo) -> Result<Option<Vec<usize>>, Error> {
+	// The filter-phase twin of get_group_list_hashes_sonic
+	let elements: Vec<&Value> = match json_get(value, group_key) {
+		None => return Ok(None),
+		Some(Value::Array(arr)) => arr.iter().collect(),
+		Some(group_val) => vec![group_val],
+	};
+	let mut hashes: Vec<usize> = elements.into_iter().map(|el| {
+		let mut hasher = GroupHasher::new(hash_algo, AHasher::default());
+		write_serde_val(&mut hasher, hash_algo, el, normalize_keys.get(group_key));
+		hasher.finish()
+	}).collect();
+	hashes.sort_unstable();
+	hashes.dedup();
+	Ok(if hashes.is_empty() { None } else { Some(hashes) })
+}
+
+fn write_serde_val(hasher: &mut GroupHasher, hash_algo: HashAlgo, group_val: &Value, normalizations: Option<&Vec<KeyNormalization>>) {
+	match (hash_algo, group_val, normalizations) {
+		(_, Value::String(s), Some(normalizations)) => hasher.write_str(&normalize_group_str(s, normalizations)),
+		(HashAlgo::Ahash, _, _) => hasher.write_str(&group_val.to_string()),
+		(HashAlgo::Xxh3, Value::String(s), None) => hasher.write_str(s),
+		(HashAlgo::Xxh3, Value::Number(n), _) => hasher.write_str(&exact_number_str(n.as_u64(), n.as_i64(), n.as_f64())),
+		(HashAlgo::Xxh3, Value::Bool(b), _) => hasher.write_bool(*b),
+		(HashAlgo::Xxh3, _, _) => hasher.write_str(&group_val.to_string()),
+	}
+}
 
 
-pub fn group_filter(input_dir: &PathBuf, output_dir: &PathBuf, config_path: &PathBuf, prev_sorted: bool) -> Result<(), Error> {
+
+pub fn group_filter(input_dir: &Path, output_dir: &Path, config_path: &PathBuf, prev_sorted: bool, assert_grouped: bool) -> Result<(), Error> {
 	let start_main = Instant::now();
 	println!("Starting filter operation");	
-	let input_paths = expand_dirs(vec![input_dir.clone()], None).unwrap();
-	let config_contents = read_pathbuf_to_mem(config_path).unwrap();
-	let config: GroupFilterConfig = serde_yaml::from_reader(config_contents).unwrap();	
+	let input_paths = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+	let config = load_config(config_path)?;
+	ensure!(!(prev_sorted && config.expand_lists), "expand_lists can't be combined with a pre-sorted filter: a doc's groups aren't contiguous");
 	let pbar = build_pbar(input_paths.len(), "Paths");
 	let input_chunks = chunk_groups(input_paths).unwrap();
 	let docs_seen = AtomicUsize::new(0);
 	let docs_kept = AtomicUsize::new(0);
 
-	input_chunks.into_par_iter().for_each(|chunk| {
-		let output_path = get_output_filename(&chunk[0], input_dir, output_dir).unwrap();	
+	input_chunks.into_par_iter().try_for_each(|chunk| -> Result<(), Error> {
+		let output_path = mirrored_output_filename(&chunk[0], input_dir, output_dir)?;
 		let (path_seen, path_kept) = if prev_sorted {
-			group_filter_path(&chunk, &output_path, &config).unwrap()	
+			group_filter_path(&chunk, &output_path, &config, assert_grouped)?
 		} else {
 			group_filter_path_unsorted(&chunk, &output_path, &config).unwrap()
 		};
 		docs_seen.fetch_add(path_seen, atomic::Ordering::SeqCst);
 		docs_kept.fetch_add(path_kept, atomic::Ordering::SeqCst);
 		pbar.inc(chunk.len().try_into().unwrap());
-	});
+		Ok(())
+	})?;
 
 	println!("Finished filtering in {:?} secs", start_main.elapsed().as_secs());
 	println!("Saw {:?} docs", docs_seen.into_inner());
@@ -257,7 +605,14 @@ fn chunk_groups(input_paths: Vec<PathBuf>) -> Result<Vec<Vec<PathBuf>>, Error> {
 }
 
 
-fn group_filter_path(input_path_chunk: &Vec<PathBuf>, output_path: &PathBuf, config: &GroupFilterConfig) -> Result<(usize, usize), Error> {
+fn group_filter_path(input_path_chunk: &Vec<PathBuf>, output_path: &Path, config: &GroupFilterConfig, assert_grouped: bool) -> Result<(usize, usize), Error> {
+	/* Filter for PRE-SORTED input: a group is a run of consecutive docs with the same group hash, so this only
+	gives the right answer if every group is contiguous across the chunk's files (in file order).
+	On input that isn't, a group split into several runs keeps one doc per run, i.e. silently keeps
+	(almost) every doc. If unsure, use the unsorted filter (the default), which only needs each group to be
+	within one chunk. With assert_grouped, a group hash that reappears after a different one fails the filter
+	(before anything is written) instead.
+	*/
 	let mut docs_seen = 0;
 	let mut docs_kept = 0;
 	let all_lines: Vec<String> = input_path_chunk.iter().flat_map(|p| {
@@ -272,17 +627,25 @@ fn group_filter_path(input_path_chunk: &Vec<PathBuf>, output_path: &PathBuf, con
 	let keep_idx = config.keep_idx;
 	let mut prev_hash : Option<usize> = None;
 	let mut prev_line : Option<String> = None;
+	let mut seen_groups: HashSet<usize> = HashSet::new();
+	let mut split_groups = 0;
 	
-	let mut output_bytes: Vec<u8> = Vec::new();
+	let mut writer = JsonlWriter::create(output_path)?;
 	for line in all_lines {
 		docs_seen += 1;
 		let line_value = serde_json::from_str(&line).unwrap();		
-		let group_hash = get_group_hash(&line_value, &config.group_keys).unwrap();
+		let group_hash = get_group_hash(&line_value, &config.group_keys, &config.normalize_keys, config.hash_algo).unwrap();
+
+		// A new run starts: its group must not have had a run already (docs without a group end a run too)
+		if let Some(hash) = group_hash {
+			if assert_grouped && group_hash != prev_hash && !seen_groups.insert(hash) {
+				split_groups += 1;
+			}
+		}
 
 		// always keep the things without groups
 		if group_hash.is_none() {
-			output_bytes.extend(line.as_bytes());
-			output_bytes.push(b'\n');
+			writer.write_line(line.as_bytes())?;
 			docs_kept += 1;
 			prev_hash = group_hash;
 			prev_line = Some(line);
@@ -291,41 +654,41 @@ fn group_filter_path(input_path_chunk: &Vec<PathBuf>, output_path: &PathBuf, con
 
 		if group_hash != prev_hash {
 			if keep_idx == 0 {
-				output_bytes.extend(line.as_bytes());
-				output_bytes.push(b'\n');
+				writer.write_line(line.as_bytes())?;
 				docs_kept += 1;
-			} else {
-				if !prev_line.is_none() {
-					output_bytes.extend(prev_line.unwrap().as_bytes());
-					output_bytes.push(b'\n');
-					docs_kept += 1;
-				}
-			}		
+			} else if let Some(prev) = prev_line {
+				writer.write_line(prev.as_bytes())?;
+				docs_kept += 1;
+			}
 			prev_hash = group_hash;
 			prev_line = Some(line);
 		}
 	}
 
+	ensure!(split_groups == 0,
+		"Input isn't grouped: {:?} times a group reappeared after a different one in {:?}. Use the unsorted filter (drop --prev-sorted) or sort the input by group first",
+		split_groups, input_path_chunk);
+
 	if keep_idx == -1 && prev_hash.is_some() {		
 		docs_kept += 1;
-		if prev_line.is_some() {
-			output_bytes.extend(prev_line.unwrap().as_bytes());
-		}
-		output_bytes.push(b'\n');
+		writer.write_line(prev_line.unwrap_or_default().as_bytes())?;
 	}	
 	if config.delete_after_read {
 		for p in input_path_chunk {
-			remove_file(p).unwrap();
+			remove_file(p)?;
 		}
 	}
 
-	write_mem_to_pathbuf(&output_bytes, output_path).unwrap();
+	writer.finish()?;
 	Ok((docs_seen, docs_kept))
 
 }
 
 
 fn group_filter_path_unsorted(input_path_chunk: &Vec<PathBuf>, output_path: &PathBuf, config: &GroupFilterConfig) -> Result<(usize, usize), Error> {
+	if config.expand_lists {
+		return group_filter_path_expanded(input_path_chunk, output_path, config);
+	}
 	let mut docs_seen = 0;
 	let mut docs_kept = 0;	
 	let all_lines: Vec<String> = input_path_chunk.iter().flat_map(|p| {
@@ -337,35 +700,34 @@ fn group_filter_path_unsorted(input_path_chunk: &Vec<PathBuf>, output_path: &Pat
 		values
 	}).collect();	
 	let keep_idx = config.keep_idx;
-	let mut output_bytes: Vec<u8> = Vec::new();
+	let mut writer = JsonlWriter::create(output_path)?;
 
 	// Assume this file contains the entire group, but is unsorted
 	let mut groups: HashMap<usize, Vec<Value>> = HashMap::new();
 	for line in all_lines {
 		docs_seen += 1;
 		let line_value: Value = serde_json::from_str(&line).unwrap();
-		let group_hash_opt = get_group_hash(&line_value, &config.group_keys).unwrap();
+		let group_hash_opt = get_group_hash(&line_value, &config.group_keys, &config.normalize_keys, config.hash_algo).unwrap();
 
 		if let Some(group_hash) = group_hash_opt {
 			groups.entry(group_hash).or_default().push(line_value);			
 		} else {
 			docs_kept += 1;
-			output_bytes.extend(line.as_bytes());
-			output_bytes.push(b'\n');
+			writer.write_line(line.as_bytes()).unwrap();
 		}
 	}
 	docs_kept += groups.len();
 
 	groups.into_iter().for_each(|(_k, mut v)| {
-		v.sort_by_key(|el| extract_sortkey(el, &config.sort_keys).unwrap());
+		// Docs arrive in whatever order the group step's threads wrote them, so break sort key ties on the
+		// serialized doc (keys in their input order, which is fixed per doc) to keep the same doc on every run
+		v.sort_by_cached_key(|el| (extract_sortkey(el, config).unwrap(), el.to_string()));
 		let keep_doc = if keep_idx == 0 {
 			v.first().unwrap()
 		} else {
 			v.last().unwrap()
 		};
-		let keep_bytes = serde_json::to_vec(keep_doc).unwrap();		
-		output_bytes.extend(keep_bytes);
-		output_bytes.push(b'\n');
+		writer.write_value(keep_doc).unwrap();
 	
 	});
 	if config.delete_after_read {
@@ -374,13 +736,80 @@ fn group_filter_path_unsorted(input_path_chunk: &Vec<PathBuf>, output_path: &Pat
 		}
 	}
 
-	write_mem_to_pathbuf(&output_bytes, output_path).unwrap();
+	writer.finish().unwrap();
+
+	Ok((docs_seen, docs_kept))
+}
+
+fn group_filter_path_expanded(input_path_chunk: &Vec<PathBuf>, output_path: &Path, config: &GroupFilterConfig) -> Result<(usize, usize), Error> {
+	/* Filter step for expand_lists: a doc belongs to several groups and was written to the bucket of each of them.
+	Groups are resolved per group exactly like the unsorted filter, but only over the groups that live in this
+	bucket (the doc's other groups are resolved, with their complete membership, in their own buckets).
+	A doc is written once here if it is the kept doc of at least one of this bucket's groups; so a doc that wins
+	groups in different buckets is written once per such bucket.
+	*/
+	let bucket = chunk_bucket(&input_path_chunk[0])?;
+	let mut docs_seen = 0;
+	let mut writer = JsonlWriter::create(output_path)?;
+	let mut docs: Vec<Value> = Vec::new();
+	let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+	for p in input_path_chunk {
+		let path_contents = read_pathbuf_to_mem(p).unwrap();
+		for line in path_contents.lines() {
+			let line = line.unwrap();
+			docs_seen += 1;
+			let line_value: Value = serde_json::from_str(&line).unwrap();
+			if let Some(hashes) = get_group_list_hashes(&line_value, &config.group_keys[0], &config.normalize_keys, config.hash_algo)? {
+				let local: Vec<usize> = hashes.into_iter().filter(|h| h % config.num_buckets == bucket).collect();
+				ensure!(!local.is_empty(), "Doc in {:?} has no group in bucket {:?}; was the group step run with a different config?", p, bucket);
+				for h in local {
+					groups.entry(h).or_default().push(docs.len());
+				}
+				docs.push(line_value);
+			} else {
+				// always keep the things without groups
+				writer.write_line(line.as_bytes()).unwrap();
+			}
+		}
+	}
+	let mut docs_kept = docs_seen - docs.len();
+
+	let mut sort_keys: HashMap<usize, (Vec<SortKeyPart>, String)> = HashMap::new();
+	let mut winners: Vec<usize> = groups.into_values().map(|mut members| {
+		// Same ordering (and tie-break) as the unsorted filter, computed once per doc
+		members.sort_by_cached_key(|idx| sort_keys.entry(*idx).or_insert_with(|| {
+			(extract_sortkey(&docs[*idx], config).unwrap(), docs[*idx].to_string())
+		}).clone());
+		if config.keep_idx == 0 { members[0] } else { *members.last().unwrap() }
+	}).collect();
+	winners.sort_unstable();
+	winners.dedup();
+	docs_kept += winners.len();
+	for idx in winners {
+		writer.write_value(&docs[idx]).unwrap();
+	}
 
+	if config.delete_after_read {
+		for p in input_path_chunk {
+			remove_file(p).unwrap();
+		}
+	}
+	writer.finish().unwrap();
 	Ok((docs_seen, docs_kept))
 }
 
-fn extract_sortkey(obj: &Value, sort_keys: &[Vec<String>]) -> Result<Vec<String>, Error> {
-    Ok(sort_keys
+fn chunk_bucket(path: &Path) -> Result<usize, Error> {
+	// Bucket id from a group step output name, chunk_{bucket:08}.{file_idx:08}.{subext}.jsonl[.zst]
+	let filename = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+	if let Some(caps) = Regex::new(r"^chunk_(\d{8})\.")?.captures(filename) {
+		Ok(caps[1].parse::<usize>()?)
+	} else {
+		bail!("Can't read a bucket id from {:?}", path)
+	}
+}
+
+fn extract_sortkey(obj: &Value, config: &GroupFilterConfig) -> Result<Vec<SortKeyPart>, Error> {
+    Ok(config.sort_keys
         .iter()
         .map(|key_group| {
             // Find the first available key in this group
@@ -389,32 +818,120 @@ fn extract_sortkey(obj: &Value, sort_keys: &[Vec<String>]) -> Result<Vec<String>
                 .find_map(|key| json_get(&obj, &key))
                 .expect(&format!("No keys from group {:?} found in object", key_group));
             
-            // Convert the value to a string, handling both String and Number types
-            match value {
-                Value::String(s) => s.clone(),
-                Value::Number(n) => n.to_string(),
-                Value::Bool(b) => b.to_string(),
-                Value::Null => "null".to_string(),
-                _ => panic!("Unexpected value type for key group {:?}", key_group),
-            }                        
+            // Lists/objects only have a sensible order when coerced (as uncoercible values)
+            if config.sort_key_type == SortKeyType::String && (value.is_array() || value.is_object()) {
+                panic!("Unexpected value type for key group {:?}", key_group);
+            }
+            SortKeyPart::from_value(value, config.sort_key_type, config.keep_idx)
         })
         .collect())
 }
 
+/*============================================================
+=                            GROUP SORT                      =
+============================================================*/
+
+struct GroupSortDoc {
+	group: Option<usize>,
+	sortkey: Vec<SortKeyPart>,
+	line: String, // the doc as serialized by serde_json (also its tie-break, like in the unsorted filter)
+}
+
+pub fn group_sort(input_dir: &Path, output_dir: &PathBuf, config_path: &PathBuf, subext: Option<String>) -> Result<(), Error> {
+	/* Single-node group + sort in one pass: reads every input file into memory, buckets the docs by group hash
+	(same config, hash and bucket assignment as the group step) and writes each bucket as a single file in which
+	every group is contiguous and sorted by the sort keys, in the same order the unsorted filter sorts it.
+	Nothing is dropped, and there is no intermediate group directory to write and re-read.
+
+	The output has the group step's file names, so a group-filter with --prev-sorted (and --assert-grouped) can run
+	on it directly and keeps the same docs the unsorted filter would.
+	Groups are written in group hash order; docs without a group are spread over the buckets by content hash and
+	written after that bucket's groups.
+	Only for datasets that fit in RAM: for anything bigger use group + group-filter, which go through disk.
+	*/
+	let start_main = Instant::now();
+	println!("Starting group-sort operation");
+	let input_paths = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+	let config = load_config(config_path)?;
+	ensure!(!config.expand_lists, "group-sort doesn't support expand_lists (a doc would have to be in several groups); use group + group-filter");
+	let num_buckets = config.num_buckets;
+	ensure!(num_buckets > 0, "num_buckets must be positive");
+	let subext = subext.unwrap_or("group".to_string());
+
+	// Step 1: load and bucket every doc, with its sort key computed once
+	let buckets: Vec<Mutex<Vec<GroupSortDoc>>> = (0..num_buckets).map(|_| Mutex::new(Vec::new())).collect();
+	let pbar = build_pbar(input_paths.len(), "Paths");
+	input_paths.par_iter().try_for_each(|p| -> Result<(), Error> {
+		let mut local: HashMap<usize, Vec<GroupSortDoc>> = HashMap::new();
+		for line in read_pathbuf_to_mem(p)?.lines() {
+			let value: Value = serde_json::from_str(&line?)?;
+			let group = get_group_hash(&value, &config.group_keys, &config.normalize_keys, config.hash_algo)?;
+			let sortkey = if group.is_some() { extract_sortkey(&value, &config)? } else { Vec::new() };
+			let line = value.to_string();
+			let bucket = group.unwrap_or_else(|| xxh3_64(line.as_bytes()) as usize) % num_buckets;
+			local.entry(bucket).or_default().push(GroupSortDoc { group, sortkey, line });
+		}
+		for (bucket, docs) in local {
+			buckets[bucket].lock().unwrap().extend(docs);
+		}
+		pbar.inc(1);
+		Ok(())
+	})?;
+
+	// Step 2: sort each bucket (groups by hash, then each group by sort key) and write it
+	let docs_seen = AtomicUsize::new(0);
+	let groups_seen = AtomicUsize::new(0);
+	buckets.into_par_iter().enumerate().try_for_each(|(bucket, docs)| -> Result<(), Error> {
+		let mut docs = docs.into_inner().unwrap();
+		if docs.is_empty() {
+			return Ok(());
+		}
+		docs.sort_unstable_by(|a, b| {
+			(a.group.is_none(), a.group, &a.sortkey, &a.line).cmp(&(b.group.is_none(), b.group, &b.sortkey, &b.line))
+		});
+		let mut writer = JsonlWriter::create(&GenWriter::get_filename(output_dir, bucket, 0, &subext))?;
+		let mut prev_group: Option<usize> = None;
+		for doc in &docs {
+			if doc.group.is_some() && doc.group != prev_group {
+				groups_seen.fetch_add(1, atomic::Ordering::SeqCst);
+				prev_group = doc.group;
+			}
+			writer.write_line(doc.line.as_bytes()).unwrap();
+		}
+		docs_seen.fetch_add(docs.len(), atomic::Ordering::SeqCst);
+		writer.finish()
+	})?;
+
+	if config.delete_after_read {
+		for p in &input_paths {
+			remove_file(p).unwrap();
+		}
+	}
+	println!("Finished group-sort in {:?} secs", start_main.elapsed().as_secs());
+	println!("Wrote {:?} docs in {:?} groups", docs_seen.into_inner(), groups_seen.into_inner());
+	Ok(())
+}
+
 /*==========================================================
 =                        GEN WRITER STUFF                  =
 ==========================================================*/
 
+// Default for how many bytes a BufferedGenWriter collects per bucket before taking the bucket's lock.
+// Big enough that the lock is taken rarely, small enough that (num_buckets x threads) buffers fit in RAM
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 1 << 20;
+
 pub struct GenWriter<'a> {
 	pub writer: DashMap<usize, Arc<Mutex<WriterInfo<'a>>>>,
 	#[allow(dead_code)]
 	storage_loc: PathBuf,	
 	num_chunks: usize,
-	max_len: usize
+	max_len: usize,
+	buffer_size: usize,
+	template: Option<FilenameTemplate>, // replaces the get_filename names if set
 }
 
 pub struct WriterInfo<'a> {
-	encoder: Option<Encoder<'a, File>>,
+	encoder: Option<OutputEncoder<'a, AtomicFile>>, // zstd unless --no-compress; moved into place when finished
 	bytes_written: usize,
 	file_idx: usize,
 	subext: String,
@@ -422,57 +939,70 @@ pub struct WriterInfo<'a> {
 	
 
 impl<'a> GenWriter<'a> {
-	pub fn new(storage_loc: &PathBuf, num_chunks: usize, subext: &str, max_len: usize) -> Self {
+	pub fn new(storage_loc: &PathBuf, num_chunks: usize, subext: &str, max_len: usize, buffer_size: usize) -> Self {
+		GenWriter::open(storage_loc, num_chunks, subext, max_len, buffer_size, 0, None)
+	}
+
+	pub fn new_from_idx(storage_loc: &PathBuf, num_chunks: usize, subext: &str, max_len: usize, buffer_size: usize, first_file_idx: usize) -> Self {
+		// Every bucket's first file is numbered first_file_idx (then counts up as files fill), so that files below it are left alone
+		GenWriter::open(storage_loc, num_chunks, subext, max_len, buffer_size, first_file_idx, None)
+	}
+
+	pub fn new_with_template(storage_loc: &PathBuf, num_chunks: usize, subext: &str, max_len: usize, buffer_size: usize, template: Option<FilenameTemplate>) -> Self {
+		// Files are named by template ({bucket} is the 8-digit chunk number, {ext} is output_ext()) instead of get_filename
+		GenWriter::open(storage_loc, num_chunks, subext, max_len, buffer_size, 0, template)
+	}
+
+	fn open(storage_loc: &PathBuf, num_chunks: usize, subext: &str, max_len: usize, buffer_size: usize, first_file_idx: usize, template: Option<FilenameTemplate>) -> Self {
 		let writer : DashMap<usize, Arc<Mutex<WriterInfo<'a>>>> = DashMap::new();
 		// Create writers
 		println!("Opening {:?} writer files", num_chunks);
 		for chunk in 0..num_chunks {
-			let filename = GenWriter::get_filename(storage_loc, chunk, 0, subext);
-			if let Some(parent_dir) = filename.parent() {
-		        if !parent_dir.exists() {
-		            create_dir_all(parent_dir).unwrap()
-		         }
-		    }		    
+			let filename = GenWriter::templated_filename(storage_loc, chunk, first_file_idx, subext, template.as_ref());
             let writer_info = WriterInfo {
-                encoder: Some(Encoder::new(
-                    OpenOptions::new()
-                    .append(true)
-                    .create(true)
-                    .mode(0o644)
-                    .open(filename)
-                    .unwrap(),
-                3).unwrap()),
+                encoder: Some(OutputEncoder::new(AtomicFile::create(&filename).unwrap()).unwrap()),
                 bytes_written: 0,
-                file_idx: 0,
+                file_idx: first_file_idx,
                 subext: subext.to_string(),
             };
 			writer.insert(chunk, Arc::new(Mutex::new(writer_info)));
 		}
-		GenWriter { writer, storage_loc: storage_loc.clone(), num_chunks, max_len }
+		GenWriter { writer, storage_loc: storage_loc.clone(), num_chunks, max_len, buffer_size, template }
 	}
 
 
 	pub fn get_filename(storage_loc: &PathBuf, chunk: usize, file_idx: usize, subext: &str) -> PathBuf {
 		storage_loc.clone()
-			.join(format!("chunk_{:08}.{:08}.{}.jsonl.zst", chunk, file_idx, subext))
+			.join(format!("chunk_{:08}.{:08}.{}.{}", chunk, file_idx, subext, output_ext()))
 	}
 
-    fn create_new_encoder(&self, key: usize, file_idx: usize, subext: &str) -> Encoder<'a, File> {
-        let new_filename = GenWriter::get_filename(&self.storage_loc, key, file_idx, subext);
-        if let Some(parent_dir) = new_filename.parent() {
-            if !parent_dir.exists() {
-                create_dir_all(parent_dir).unwrap()
-            }
-        }
-        
-        Encoder::new(
-            OpenOptions::new()
-            .append(true)
-            .create(true)
-            .mode(0o644)
-            .open(new_filename)
-            .unwrap(),
-        3).unwrap()
+	fn templated_filename(storage_loc: &PathBuf, chunk: usize, file_idx: usize, subext: &str, template: Option<&FilenameTemplate>) -> PathBuf {
+		match template {
+			Some(template) => storage_loc.join(template.render(&format!("{:08}", chunk), file_idx, output_ext())),
+			None => GenWriter::get_filename(storage_loc, chunk, file_idx, subext),
+		}
+	}
+
+	pub fn next_file_idx(storage_loc: &Path, subext: &str) -> Result<usize, Error> {
+		// One past the highest file index of any existing get_filename file with this subext (0 if there are none),
+		// compressed or not, so that a run with a different --no-compress doesn't reuse an index
+		if !storage_loc.exists() {
+			return Ok(0);
+		}
+		let re = Regex::new(&format!(r"^chunk_\d{{8}}\.(\d{{8}})\.{}\.jsonl(\.zst)?$", regex::escape(subext)))?;
+		let mut next_idx = 0;
+		for entry in std::fs::read_dir(storage_loc)? {
+			let filename = entry?.file_name();
+			if let Some(caps) = re.captures(&filename.to_string_lossy()) {
+				next_idx = next_idx.max(caps[1].parse::<usize>()? + 1);
+			}
+		}
+		Ok(next_idx)
+	}
+
+    fn create_new_encoder(&self, key: usize, file_idx: usize, subext: &str) -> OutputEncoder<'a, AtomicFile> {
+        let new_filename = GenWriter::templated_filename(&self.storage_loc, key, file_idx, subext, self.template.as_ref());
+        OutputEncoder::new(AtomicFile::create(&new_filename).unwrap()).unwrap()
     }	
 
     pub fn write_batch(&self, key: usize, contents: Vec<u8>) -> Result<(), Error> {
@@ -488,7 +1018,7 @@ impl<'a> GenWriter<'a> {
             if writer_info.bytes_written >= self.max_len {
                 let mut old_encoder = writer_info.encoder.take().unwrap();
                 old_encoder.flush()?;
-                old_encoder.finish()?;
+                old_encoder.finish()?.commit()?;
                 writer_info.file_idx += 1;
                 let new_encoder = self.create_new_encoder(key, writer_info.file_idx, &writer_info.subext);
                 writer_info.encoder = Some(new_encoder);
@@ -501,26 +1031,12 @@ impl<'a> GenWriter<'a> {
 
 
 	pub fn write_line(&self, key: usize, contents: &Vec<u8>) -> Result<(), Error> {
-		// hash the key and take mod num_chunks to get location
-
-		let binding = self.writer.get(&key).unwrap();
-		let mut writer_info = binding.lock().unwrap();
-		writer_info.bytes_written += contents.len();		
-		if let Some(encoder) = &mut writer_info.encoder {
-			encoder.write_all(contents).unwrap();
-			if writer_info.bytes_written >= self.max_len {
-				let mut old_encoder = writer_info.encoder.take().unwrap();
-				old_encoder.flush().unwrap();
-				old_encoder.finish().unwrap();
-				writer_info.file_idx += 1;
-				let new_encoder = self.create_new_encoder(key, writer_info.file_idx, &writer_info.subext);
-				writer_info.encoder = Some(new_encoder);
-				writer_info.bytes_written = 0;
-			}
-		}
-		
-		Ok(())
+		// Unbuffered: takes the bucket's lock for every line. Prefer buffered() for line-at-a-time writes
+		self.write_batch(key, contents.clone())
+	}
 
+	pub fn buffered(&self) -> BufferedGenWriter<'_, 'a> {
+		BufferedGenWriter { writer: self, buffers: HashMap::new() }
 	}
 
 	pub fn finish(self) -> Result<(), Error> {
@@ -530,10 +1046,13 @@ impl<'a> GenWriter<'a> {
 				match Arc::try_unwrap(value) {
 					Ok(mutex) => {
 						let mut writer_info = mutex.into_inner().unwrap();
+						let mut encoder = writer_info.encoder.take().unwrap();
 						if writer_info.bytes_written > 0 {
-							let mut encoder = writer_info.encoder.take().unwrap();
 							encoder.flush().unwrap();
-							encoder.finish().unwrap();
+							encoder.finish().unwrap().commit().unwrap();
+						} else {
+							// Buckets that never got a doc leave no (empty) file behind
+							encoder.finish().unwrap().discard().unwrap();
 						}
 					},
 					_ => panic!("WHAT?")
@@ -543,3 +1062,45 @@ impl<'a> GenWriter<'a> {
 	}
 }
 
+
+pub struct BufferedGenWriter<'w, 'a> {
+	/* Per-caller (e.g. per input file) front for a GenWriter: lines are collected per bucket and only handed to
+	write_batch once a bucket holds buffer_size bytes, so threads contend for the bucket locks once per batch
+	rather than once per line. Whatever is left is written on flush(), which callers must call (and check) once
+	they're done: dropping an unflushed BufferedGenWriter still tries to write the rest, but can only log a failure.
+	*/
+	writer: &'w GenWriter<'a>,
+	buffers: HashMap<usize, Vec<u8>>,
+}
+
+impl BufferedGenWriter<'_, '_> {
+	pub fn write_line(&mut self, key: usize, line: &[u8]) -> Result<(), Error> {
+		// Appends line (plus a newline) to the bucket's buffer
+		let buffer = self.buffers.entry(key).or_default();
+		buffer.extend_from_slice(line);
+		buffer.push(b'\n');
+		if buffer.len() >= self.writer.buffer_size {
+			let contents = std::mem::take(buffer);
+			self.writer.write_batch(key, contents)?;
+		}
+		Ok(())
+	}
+
+	pub fn flush(&mut self) -> Result<(), Error> {
+		for (key, contents) in self.buffers.drain() {
+			if !contents.is_empty() {
+				self.writer.write_batch(key, contents)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Drop for BufferedGenWriter<'_, '_> {
+	fn drop(&mut self) {
+		// Panicking here could abort mid-unwind, so a failed last-ditch write is only reported
+		if let Err(e) = self.flush() {
+			eprintln!("BufferedGenWriter dropped without flush() and failed to write its buffered lines: {:?}", e);
+		}
+	}
+}
@@ -0,0 +1,124 @@
+/*============================================================
+=                            HEAD                            =
+============================================================*/
+/*
+Pulls a small sample of raw lines out of a dataset for eyeballing (the `zcat | head` replacement).
+
+No pipeline runs and no fields are parsed: lines are copied as they are. By default this takes the first n lines,
+streaming the input files in sorted path order and stopping as soon as it has enough. With random, every file is read
+and a uniform sample of n lines is kept (reservoir sampling, seeded for reproducibility if a seed is given).
+The output is always written uncompressed, whatever its extension.
+*/
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{anyhow, Error, Result};
+use mj_io::read_pathbuf;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+
+use crate::utils::{build_pbar, expand_input_dirs, read_input_lines, AtomicFile};
+
+pub fn head(input_dir: &Path, output_file: &Path, n: usize, random: bool, seed: Option<u64>) -> Result<(), Error> {
+    let start_main = Instant::now();
+    let mut paths = expand_input_dirs(input_dir)?;
+    paths.sort();
+
+    let (sample, total_seen) = if random {
+        random_lines(&paths, n, seed)?
+    } else {
+        first_lines(&paths, n)?
+    };
+
+    let mut file = AtomicFile::create(output_file)
+        .map_err(|e| anyhow!("Unable to create output file {:?}: {}", output_file, e))?;
+    for line in &sample {
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+    }
+    file.commit().map_err(|e| anyhow!("Unable to write to {:?}: {}", output_file, e))?;
+
+    println!(
+        "Wrote {:?} of {:?} lines seen to {:?} in {:?} secs",
+        sample.len(),
+        total_seen,
+        output_file,
+        start_main.elapsed().as_secs()
+    );
+    Ok(())
+}
+
+fn first_lines(paths: &[PathBuf], n: usize) -> Result<(Vec<String>, usize), Error> {
+    // Files are streamed, so only the lines (and files) actually needed get read and decompressed
+    let mut sample: Vec<String> = Vec::new();
+    for p in paths {
+        if sample.len() >= n {
+            break;
+        }
+        let mut reader = read_pathbuf(p, true)?;
+        if starts_with_array(reader.as_buf_read())? {
+            // A json array of docs has to be parsed whole anyway
+            sample.extend(read_input_lines(p)?.into_iter().take(n - sample.len()));
+            continue;
+        }
+        for line in reader.lines() {
+            if sample.len() >= n {
+                break;
+            }
+            let line = line.map_err(|e| anyhow!("Unable to read {:?}: {}", p, e))?;
+            if !line.trim().is_empty() {
+                sample.push(line);
+            }
+        }
+    }
+    let total_seen = sample.len();
+    Ok((sample, total_seen))
+}
+
+fn starts_with_array(reader: &mut dyn BufRead) -> Result<bool, Error> {
+    // Same test as read_input_lines: is the first non-whitespace byte a '['? Consumes only the leading whitespace
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(false);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(idx) => {
+                let is_array = buf[idx] == b'[';
+                reader.consume(idx);
+                return Ok(is_array);
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+fn random_lines(paths: &[PathBuf], n: usize, seed: Option<u64>) -> Result<(Vec<String>, usize), Error> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_os_rng(),
+    };
+    let mut sample: Vec<String> = Vec::new();
+    let mut total_seen = 0;
+    let pbar = build_pbar(paths.len(), "Paths");
+    for p in paths {
+        for line in read_input_lines(p)? {
+            if sample.len() < n {
+                sample.push(line);
+            } else {
+                let idx = rng.random_range(0..=total_seen);
+                if idx < n {
+                    sample[idx] = line;
+                }
+            }
+            total_seen += 1;
+        }
+        pbar.inc(1);
+    }
+    Ok((sample, total_seen))
+}
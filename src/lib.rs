@@ -6,4 +6,12 @@ pub mod partition;
 pub mod groupfilter; 
 pub mod reservoir_sample;
 pub mod percentile_finder;
-pub mod shuffle;
\ No newline at end of file
+pub mod shuffle;
+pub mod merge;
+pub mod check_unique;
+pub mod percentilize;
+pub mod diff;
+pub mod head;
+pub mod bloom;
+pub mod config;
+pub mod map_run;
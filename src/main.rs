@@ -5,21 +5,21 @@ use std::fs;
 use serde_json::Value;
 use dashmap::DashMap;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
 
-use anyhow::{Error, Result};
-use clap::{Parser, Subcommand};
+use anyhow::{anyhow, ensure, Error, Result};
+use clap::{ArgAction, Parser, Subcommand};
 use rayon::prelude::*;
+use rand::seq::SliceRandom;
 use serde_json;
-use serde_yaml;
 use gjson;
+use xxhash_rust::xxh3::xxh3_64;
 
 use mj_io::{
-    build_pbar, expand_dirs, get_output_filename, read_pathbuf_to_mem, write_mem_to_pathbuf,
+    expand_dirs, get_output_filename, read_pathbuf_to_mem,
 };
 pub mod map_fxn;
 pub mod partition;
@@ -27,13 +27,25 @@ pub mod utils;
 pub mod groupfilter;
 pub mod reservoir_sample;
 pub use map_fxn::DataProcessor;
-use datamap_rs::map_fxn::PipelineProcessor;
-use datamap_rs::partition::{discrete_partition, range_partition};
-use datamap_rs::reshard::reshard;
-use datamap_rs::groupfilter::{group, group_filter};
-use datamap_rs::reservoir_sample::reservoir_sample;
+use datamap_rs::map_fxn::{parse_lines, PipelineProcessor, RoutedPipeline};
+use datamap_rs::partition::{discrete_partition, language_partition, range_partition};
+use datamap_rs::reshard::{coalesce, reshard};
+use datamap_rs::groupfilter::{group, group_filter, group_sort, DEFAULT_WRITE_BUFFER_SIZE};
+use datamap_rs::reservoir_sample::{reservoir_sample, stratified_sample};
 use datamap_rs::shuffle::shuffle; 
 use datamap_rs::percentile_finder::percentile_finder;
+use datamap_rs::merge::{merge_jsonl, ConflictStrategy};
+use datamap_rs::check_unique::check_unique;
+use datamap_rs::bloom::value_id;
+use datamap_rs::diff::diff;
+use datamap_rs::head::head;
+use datamap_rs::config::ConfigSource;
+use datamap_rs::map_run::{
+    check_drop_fraction, file_report, read_map_input, rejected_output_dir, run_stats, sample_pipeline, stamp_provenance,
+    PipelineSample, SeenBloom,
+};
+use datamap_rs::percentilize::percentilize;
+use datamap_rs::utils::{build_pbar, check_output_dir, set_compress_outputs, set_quiet_progress, expand_input_dirs, mirrored_output_filename, read_input_lines, write_mem_atomic, DocLimit, JsonlWriter, RuntimeBudget};
 
 /*
 Map Config layout:
@@ -56,26 +68,95 @@ struct ArgParser {
 
     #[arg(long, default_value_t = 0)]
     threads: usize,
+
+    #[arg(long, global = true)] // Field holding the document text, for every command that looks at text (default: "text")
+    text_key: Option<String>,
+
+    #[arg(long, global = true)] // Plain-text progress lines instead of progress bars (automatic when stderr isn't a terminal)
+    quiet: bool,
+
+    #[arg(long, global = true)] // Write plain .jsonl: self-named shards aren't .jsonl.zst, and outputs named after .zst/.gz inputs drop that extension
+    no_compress: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     #[clap(arg_required_else_help = true)]
     Map {
-        #[arg(required = true, long)]
-        input_dir: PathBuf,
+        #[arg(required_unless_present = "stdin", long)]
+        input_dir: Option<PathBuf>,
 
-        #[arg(required = true, long)]
-        output_dir: PathBuf,
+        #[arg(required_unless_present = "stdin", long)]
+        output_dir: Option<PathBuf>,
 
-        #[arg(required = true, long)]
-        config: PathBuf,
+        #[arg(required_unless_present = "config_inline", long)] // Config file, or a http(s):// url to fetch it from
+        config: Option<PathBuf>,
+
+        #[arg(long, conflicts_with = "config")] // The config itself, as a json (or yaml) string
+        config_inline: Option<String>,
 
         #[arg(long)]
         err_dir: Option<PathBuf>,
 
         #[arg(long)]
         delete_after_read: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long, default_value_t=false)] // Sort docs within each output file (by id, then content hash) for reproducible shards
+        deterministic: bool,
+
+        #[arg(long, default_value = "id")] // Id field --deterministic sorts docs by
+        id_field: String,
+
+        #[arg(long, default_value_t=false, conflicts_with = "deterministic")] // Guarantee docs keep their input order within each output file
+        preserve_order: bool,
+
+        #[arg(long, default_value_t=false)] // Survivors mirror the input layout directly in output_dir (no step_* subdirs)
+        flat_output: bool,
+
+        #[arg(long, default_value_t=false, conflicts_with_all = ["input_dir", "output_dir"])] // Read jsonl from stdin, write survivors to stdout
+        stdin: bool,
+
+        #[arg(long, conflicts_with = "stdin")] // Field to store each surviving doc's input file path (relative to input_dir) in
+        provenance_field: Option<String>,
+
+        #[arg(long, default_value_t=true, action=ArgAction::Set)] // `--keep-rejected false` only writes survivors
+        keep_rejected: bool,
+
+        #[arg(long, conflicts_with = "stdin")] // Write filtered docs to rejected_dir/step_XX instead
+        rejected_dir: Option<PathBuf>,
+
+        #[arg(long, default_value_t=false)] // Replace invalid UTF-8 bytes with U+FFFD instead of failing (alters content!)
+        lossy_utf8: bool,
+
+        #[arg(long, conflicts_with = "stdin")] // Stop starting new files after this many seconds (in-flight files and writers finish cleanly)
+        max_runtime: Option<u64>,
+
+        #[arg(long, conflicts_with = "stdin")] // Bloom filter of already-emitted ids: skip docs that hit it, add this run's survivors, save at the end
+        seen_bloom: Option<PathBuf>,
+
+        #[arg(long, default_value = "id")] // Id field checked against/added to --seen-bloom
+        seen_id_field: String,
+
+        #[arg(long, default_value_t=100_000_000)] // Number of ids a new --seen-bloom filter is sized for (existing filters keep their size)
+        seen_bloom_capacity: u64,
+
+        #[arg(long, default_value_t=0.001)] // False-positive rate of a new --seen-bloom filter at capacity (= fraction of unseen docs wrongly skipped)
+        seen_bloom_fpr: f64,
+
+        #[arg(long, conflicts_with = "stdin")] // Write a <input file>.report.json per input file (docs in/out, per-step removals/time, errors) here
+        per_file_reports: Option<PathBuf>,
+
+        #[arg(long, conflicts_with_all = ["stdin", "delete_after_read"])] // Stop once this many docs were written to the final output (quick end-to-end smoke tests)
+        limit: Option<usize>,
+
+        #[arg(long, conflicts_with = "stdin")] // Fail the run if more than this fraction of docs was filtered out (outputs are still written)
+        max_drop_fraction: Option<f64>,
+
+        #[arg(long, requires = "max_drop_fraction")] // Check --max-drop-fraction on this many docs first, and abort before the full run if it fails
+        sample_check: Option<usize>,
     },
 
     Reshard {
@@ -99,6 +180,32 @@ enum Commands {
 
         #[arg(long)]
         delete_after_read: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long, conflicts_with = "subsample")] // Never split the docs sharing this key's value across shards
+        respect_groups: Option<String>,
+    },
+
+    Coalesce {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        output_dir: PathBuf,
+
+        #[arg(required = true, long)] // Max (on-disk) input bytes packed into each output shard
+        max_size: usize,
+
+        #[arg(long)]
+        keep_dirs: bool,
+
+        #[arg(long)]
+        delete_after_read: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
     },
 
     ReservoirSample {
@@ -117,8 +224,34 @@ enum Commands {
         #[arg(long)]
         token_weighted: bool,
 
-        #[arg(long, default_value_t=String::from("text"))]
-        text_key: String,
+        #[arg(long, default_value_t=false)] // Write the sampled docs (jsonl) instead of their key values, gathering them in a second pass
+        sample_docs: bool,
+    },
+
+    StratifiedSample {
+        #[arg(required=true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required=true, long)]
+        output_file: PathBuf,
+
+        #[arg(required=true, long)]
+        value: String,
+
+        #[arg(long, value_delimiter = ',', required_unless_present = "num_buckets", conflicts_with = "num_buckets")]
+        range_groups: Option<Vec<f64>>,
+
+        #[arg(long)] // Evenly sized buckets; bounds come from --reservoir_path, or from a value sample taken first
+        num_buckets: Option<usize>,
+
+        #[arg(long, requires = "num_buckets")]
+        reservoir_path: Option<PathBuf>,
+
+        #[arg(required=true, long)]
+        per_bucket: usize,
+
+        #[arg(long)] // Value for docs without one (skipped if not set)
+        default_value: Option<f64>,
     },
 
     PercentileFinder {
@@ -131,9 +264,6 @@ enum Commands {
         #[arg(required=true, long)]
         score_key: String,
 
-        #[arg(long, default_value_t=String::from("text"))]
-        text_key: String,
-
         #[arg(long, default_value_t=String::from("cl100k"))]
         tokenizer: String,
 
@@ -155,7 +285,16 @@ enum Commands {
         config: Option<PathBuf>,
 
         #[arg(long)]
-        partition_key: Option<String>  // Use this as the partition key if no config specified
+        partition_key: Option<String>,  // Use this as the partition key if no config specified
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long)] // Stop starting new files after this many seconds (in-flight files and writers finish cleanly)
+        max_runtime: Option<u64>,
+
+        #[arg(long)] // Output file names, with {bucket}, {index} and {ext} placeholders (default chunk_{index}.{ext})
+        filename_template: Option<String>,
     },
 
     RangePartition {
@@ -188,6 +327,44 @@ enum Commands {
 
         #[arg(long)]
         bucket_name: Option<String>,
+
+        #[arg(long, default_value_t=false)] // Docs with a missing/non-numeric value go to {bucket_name}_missing instead of default_value
+        missing_bucket: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long)] // Stop starting new files after this many seconds (in-flight files and writers finish cleanly)
+        max_runtime: Option<u64>,
+
+        #[arg(long)] // Output file names, with {bucket}, {index} and {ext} placeholders (default shard_{index}.{ext})
+        filename_template: Option<String>,
+    },
+
+    LanguagePartition {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        output_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        fast_text_file: PathBuf,
+
+        #[arg(long, default_value_t=0.0)] // Docs whose top language is below this go to lang_unknown
+        min_prob: f32,
+
+        #[arg(long, default_value_t=256_000_000)]
+        max_file_size: usize,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long)] // Stop starting new files after this many seconds (in-flight files and writers finish cleanly)
+        max_runtime: Option<u64>,
+
+        #[arg(long)] // Output file names, with {bucket}, {index} and {ext} placeholders (default shard_{index}.{ext})
+        filename_template: Option<String>,
     },
 
     Group {
@@ -202,6 +379,32 @@ enum Commands {
 
         #[arg(long)]
         subext: Option<String>,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long)] // Stop starting new files after this many seconds (in-flight files and writers finish cleanly)
+        max_runtime: Option<u64>,
+
+        #[arg(long, default_value_t=false)] // Add to an existing group_dir, skipping input files its manifest says are already grouped
+        incremental: bool,
+    },
+
+    GroupSort {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        output_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        config: PathBuf,
+
+        #[arg(long)]
+        subext: Option<String>,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
     },
 
     GroupFilter {
@@ -215,7 +418,13 @@ enum Commands {
         config: PathBuf,   
 
         #[arg(long, default_value_t=false)]             
-        prev_sorted: bool
+        prev_sorted: bool,
+
+        #[arg(long, default_value_t=false, requires="prev_sorted")] // Fail if a group isn't contiguous in the pre-sorted input
+        assert_grouped: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
     },
 
     Shuffle {
@@ -232,7 +441,16 @@ enum Commands {
         max_len: usize,
 
         #[arg(long, default_value_t=false)]
-        delete_after_read: bool
+        delete_after_read: bool,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+
+        #[arg(long, default_value_t=DEFAULT_WRITE_BUFFER_SIZE)] // Bytes buffered per output chunk (per input file) before locking its writer
+        write_buffer_size: usize,
+
+        #[arg(long)] // Output file names, with {bucket}, {index} and {ext} placeholders (default chunk_{bucket}.{index}.shuffled.{ext})
+        filename_template: Option<String>,
     },
 
     Count {
@@ -242,7 +460,7 @@ enum Commands {
         #[arg(required=true, long)]
         output_file: PathBuf,
 
-        #[arg(long)] // If not None, points to string of key where we count total size
+        #[arg(long)] // If not None, points to string of key where we count total size (falls back to --text-key)
         count_bytes: Option<String>,
 
         #[arg(long)] // If not None, is a vec of keys pointing to usizes that we want to sum
@@ -253,6 +471,137 @@ enum Commands {
 
         #[arg(long, default_value_t=false)]
         split_by_dir: bool,
+
+        #[arg(long, default_value_t=false)] // Also write byte/char/word length distributions of the text field
+        lengths: bool,
+    },
+
+    CheckUnique {
+        #[arg(required=true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required=true, long)]
+        output_file: PathBuf,
+
+        #[arg(long, default_value_t=String::from("id"))]
+        id_field: String,
+
+        #[arg(long, default_value_t=100)] // Max number of colliding ids to list in the report
+        sample_size: usize,
+    },
+
+    Diff {
+        #[arg(required=true, long)]
+        old_dir: PathBuf,
+
+        #[arg(required=true, long)]
+        new_dir: PathBuf,
+
+        #[arg(required=true, long)]
+        output_file: PathBuf,
+
+        #[arg(long, default_value_t=String::from("id"))]
+        id_field: String,
+
+        #[arg(long, default_value_t=100)] // Max number of ids to list per category in the report
+        sample_size: usize,
+    },
+
+    Head {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required = true, long)] // Always written uncompressed
+        output_file: PathBuf,
+
+        #[arg(long, default_value_t=100)]
+        n: usize,
+
+        #[arg(long, default_value_t=false)] // Uniform sample of n lines over the whole input instead of the first n
+        random: bool,
+
+        #[arg(long, requires = "random")]
+        seed: Option<u64>,
+    },
+
+    MergeJsonl {
+        #[arg(required = true, long)]
+        left_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        right_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        output_dir: PathBuf,
+
+        #[arg(long, default_value_t=String::from("id"))]
+        join_key: String,
+
+        #[arg(long, value_delimiter = ',')] // If present, only these fields of the right side are kept (saves memory)
+        fields: Option<Vec<String>>,
+
+        #[arg(long, default_value_t=String::from("keep_left"))] // keep_left | prefer_right | error_on_conflict
+        conflict_strategy: String,
+
+        #[arg(long, default_value_t=false, conflicts_with="conflict_strategy")] // Shorthand for --conflict-strategy prefer_right
+        overwrite: bool,
+
+        #[arg(long)] // If present, the right doc's fields are placed under this (dotted) key of the left doc
+        nest_under: Option<String>,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+    },
+
+    Percentilize {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required = true, long)]
+        output_dir: PathBuf,
+
+        #[arg(required = true, long)] // Numeric field to rank
+        value: String,
+
+        #[arg(required = true, long)]
+        output_field: String,
+
+        #[arg(long, default_value_t=100_000)]
+        reservoir_size: usize,
+
+        #[arg(long, default_value_t=100)]
+        num_buckets: usize,
+
+        #[arg(long)] // Value used for docs missing the field; if absent those docs are left unannotated
+        default_value: Option<f64>,
+
+        #[arg(long, default_value_t=false)] // Write into a non-empty output dir anyway
+        force: bool,
+    },
+
+    Profile {
+        #[arg(required = true, long)]
+        input_dir: PathBuf,
+
+        #[arg(required_unless_present = "config_inline", long)] // Config file, or a http(s):// url to fetch it from
+        config: Option<PathBuf>,
+
+        #[arg(long, conflicts_with = "config")] // The config itself, as a json (or yaml) string
+        config_inline: Option<String>,
+
+        #[arg(long, default_value_t=10_000)] // Number of docs to run the pipeline on
+        sample: usize,
+
+        #[arg(long)] // Corpus size to project onto; if absent, every input file is read once to count it
+        total_docs: Option<usize>,
+    },
+
+    CheckConfig {
+        #[arg(required_unless_present = "config_inline", long)] // Config file, or a http(s):// url to fetch it from
+        config: Option<PathBuf>,
+
+        #[arg(long, conflicts_with = "config")] // The config itself, as a json (or yaml) string
+        config_inline: Option<String>,
     },
 
 
@@ -263,44 +612,69 @@ enum Commands {
 =                            UTILITIES                       =
 ============================================================*/
 
-fn parse_config(config: &PathBuf) -> Result<serde_json::Value, Error> {
-    // Handle either .yaml or .json config and return a Json value
-
-    let file = File::open(config).unwrap();
-    let reader = BufReader::new(file);
+fn load_pipeline(config: &ConfigSource, text_key: Option<String>) -> Result<PipelineProcessor, Error> {
+    let json_config = config.parse_with_text_key(text_key)?;
+    ensure!(
+        !RoutedPipeline::is_routed(&json_config),
+        "Routed configs (with 'routes') can only be used by map and check-config"
+    );
+    PipelineProcessor::new(&json_config)
+}
 
-    let ext = config.extension().unwrap().to_str().unwrap();
-    let parsed_config: serde_json::Value = match ext {
-        "json" => serde_json::from_reader(reader).unwrap(),
-        "yaml" => {
-            let yaml_value: serde_yaml::Value = serde_yaml::from_reader(reader).unwrap();
-            serde_json::to_value(yaml_value).unwrap()
-        }
-        _ => {
-            return Err(Error::msg(format!("Weird config format: {:?}", config)));
+fn check_config(config: &ConfigSource, text_key: Option<String>) -> Result<(), Error> {
+    /* Parses the config and builds its pipeline, without reading any data.
+    Building the pipeline is what loads banlists/models/tokenizers, so this catches bad processor names, bad kwargs
+    and missing auxiliary files up front instead of as a panic in a rayon worker hours into a run.
+    */
+    let json_config = config.parse_with_text_key(text_key)?;
+    if RoutedPipeline::is_routed(&json_config) {
+        let router = RoutedPipeline::new(&json_config).map_err(|e| anyhow!("Config {} is invalid: {}", config, e))?;
+        println!("Config {} is valid: {:?} routes", config, router.routes.len());
+        for ((route, processor), route_config) in router.routes.iter().zip(&router.pipelines).zip(json_config["routes"].as_array().unwrap()) {
+            println!("Route {} | {:?} steps, text_field {:?}", route.name, processor.steps.len(), processor.text_field);
+            print_steps(processor, &route_config["pipeline"]);
         }
-    };
-    Ok(parsed_config.into())
+        return Ok(());
+    }
+    let processor = PipelineProcessor::new(&json_config).map_err(|e| anyhow!("Config {} is invalid: {}", config, e))?;
+    println!("Config {} is valid: {:?} steps, text_field {:?}", config, processor.steps.len(), processor.text_field);
+    print_steps(&processor, &json_config["pipeline"]);
+    Ok(())
 }
 
-fn write_output_lines(output_values: Vec<Value>, output_file: &PathBuf) -> Result<(), Error> {
+fn print_steps(processor: &PipelineProcessor, pipeline_config: &Value) {
+    for (step, subconfig) in processor.steps.iter().zip(pipeline_config.as_array().unwrap()) {
+        println!("\t{} | {}", step, subconfig["name"].as_str().unwrap());
+    }
+}
+
+fn write_output_lines(output_values: Vec<Value>, output_file: &Path) -> Result<(), Error> {
     if output_values.len() == 0 {
         return Ok(());
     }
 
-    let mut output_bytes: Vec<u8> = Vec::new();
-    output_values.into_iter().for_each(|v| {
-        output_bytes.extend(serde_json::to_vec(&v).unwrap());
-        output_bytes.push(b'\n')
-    });
+    let mut writer = JsonlWriter::create(output_file)?;
+    for v in output_values {
+        writer.write_value(&v)?;
+    }
+    writer.finish()
+}
 
-    write_mem_to_pathbuf(&output_bytes, output_file)
+fn sort_deterministic(values: &mut [Value], id_field: &str) {
+    // Stable ordering for reproducible outputs: by id_field (if present), then by a hash of the full doc
+    values.sort_by_cached_key(|v| {
+        let id = value_id(v, id_field);
+        (id, xxh3_64(&serde_json::to_vec(v).unwrap()))
+    });
 }
 
 fn print_global_stats_stuff(
     start_time: Instant,
     global_timer: DashMap<usize, AtomicUsize>,
     global_filter: DashMap<usize, usize>,
+    global_text_bytes: DashMap<usize, (usize, usize)>,
+    global_filter_groups: DashMap<(usize, String), usize>,
+    limit_cut: usize,
     processor: &PipelineProcessor,
 ) -> () {
     // Timing info
@@ -316,7 +690,8 @@ fn print_global_stats_stuff(
         .collect();
 
     // Filtering info
-    let total_docs: usize = global_filter.iter().map(|e| *e.value()).sum::<usize>();
+    // global_filter's survivors are the ones written; limit_cut more survived the pipeline but were cut by --limit
+    let total_docs: usize = global_filter.iter().map(|e| *e.value()).sum::<usize>() + limit_cut;
     let mut remaining_docs: usize = total_docs;
 
     // Print things
@@ -344,8 +719,21 @@ fn print_global_stats_stuff(
             "\t Removed {:?} docs | {:.2}% of remaining | {:.2}% of pool",
             removed_in_this_step, remaining_remove_pct, total_remove_pct
         );
+
+        let (bytes_in, bytes_out) = *global_text_bytes.get(&i).unwrap().value();
+        if bytes_in != bytes_out {
+            let delta = bytes_out as i64 - bytes_in as i64;
+            println!(
+                "\t Text bytes (kept docs): {:?} in -> {:?} out | {:+} bytes ({:+.2}%)",
+                bytes_in, bytes_out, delta, delta as f64 / f64::max(1.0, bytes_in as f64) * 100.0
+            );
+        }
     }
 
+    if limit_cut > 0 {
+        remaining_docs -= limit_cut;
+        println!("Cut {:?} surviving docs to stay within --limit", limit_cut);
+    }
     println!("FINAL:");
     println!(
         "\t {:?} docs survived | {:.2}% of pool",
@@ -353,149 +741,686 @@ fn print_global_stats_stuff(
         remaining_docs as f32 / f32::max(0.0, total_docs as f32) * 100.0
     );
 
+    if let Some(group_field) = &processor.stats_group_by {
+        print_grouped_filter_stats(group_field, global_filter_groups, processor);
+    }
+
     ()
 }
 
+fn print_grouped_filter_stats(
+    group_field: &str,
+    global_filter_groups: DashMap<(usize, String), usize>,
+    processor: &PipelineProcessor,
+) {
+    // The removed/survived counts above, broken down by the stats_group_by value of each doc (largest groups first)
+    let mut groups: HashMap<String, HashMap<usize, usize>> = HashMap::new();
+    for ((step, group), count) in global_filter_groups.into_iter() {
+        *groups.entry(group).or_default().entry(step).or_insert(0) += count;
+    }
+    let mut groups: Vec<(String, HashMap<usize, usize>, usize)> = groups
+        .into_iter()
+        .map(|(group, counts)| {
+            let total = counts.values().sum::<usize>();
+            (group, counts, total)
+        })
+        .collect();
+    groups.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+    println!("-------------------------------------------");
+    println!("BY {}:", group_field);
+    for (group, counts, total) in groups {
+        println!("{} | {:?} docs", group, total);
+        let pct = |n: usize| n as f32 / f32::max(1.0, total as f32) * 100.0;
+        for (i, step) in processor.steps.iter().enumerate() {
+            let removed = counts.get(&i).copied().unwrap_or(0);
+            if removed > 0 {
+                println!("\t Step {:?} removed {:?} docs | {:.2}% of group", step, removed, pct(removed));
+            }
+        }
+        let survived = counts.get(&usize::MAX).copied().unwrap_or(0);
+        println!("\t {:?} docs survived | {:.2}% of group", survived, pct(survived));
+    }
+}
+
 
 /*============================================================
 =                            GENERAL MAP                     =
 ============================================================*/
 
+struct MapOptions {
+    /* How a map run treats its docs and where the extras go: everything Commands::Map sets besides the input, the
+    output and the config, built once in main and handed down to every file (see gen_map for what each one does)
+    */
+    err_dir: Option<PathBuf>,
+    rejected_dir: Option<PathBuf>, // where removed docs go (see rejected_output_dir), None if they aren't written
+    delete_after_read: bool,
+    deterministic: Option<String>, // the id field to sort each output file by, if sorting
+    preserve_order: bool,
+    flat_output: bool,
+    text_key: Option<String>,
+    provenance_field: Option<String>,
+    lossy_utf8: bool,
+    max_runtime: Option<u64>,
+    report_dir: Option<PathBuf>,
+    limit: Option<usize>,
+    max_drop_fraction: Option<f64>,
+    sample_check: Option<usize>,
+}
+
 fn gen_map(
     input_dir: &PathBuf,
     output_dir: &PathBuf,
-    config: &PathBuf,
-    err_dir: Option<PathBuf>,
-    delete_after_read: bool,
+    config: &ConfigSource,
+    seen_bloom: Option<&SeenBloom>,
+    options: &MapOptions,
 ) -> Result<(), Error> {
     /* Generic mapping/filtration function.
 
     Processes each *.jsonl.* in input_dir and makes an identically named copy in output_dir
-    with the changes specified in the config applied
+    with the changes specified in the config applied. Apart from seen_bloom, the settings below are options' fields
+
+    By default survivors go in output_dir/step_final and docs removed at step XX go in rejected_dir/step_XX
+    (rejected_dir is output_dir unless overridden; None means removed docs aren't written at all).
+    With flat_output, survivors go directly in output_dir
+
+    If a provenance_field is given, each survivor gets its input file's path (relative to input_dir) there
+
+    With lossy_utf8, invalid UTF-8 bytes in the input are replaced with U+FFFD instead of failing the whole file
+
+    With max_runtime, no new input files are started after that many seconds (see RuntimeBudget)
+
+    With preserve_order, every output file lists its docs in the same relative order as in its input file
+    (incompatible with deterministic, the id field to re-sort them by)
+
+    With seen_bloom, docs whose id (probably) was emitted before -- by an earlier run, or by an earlier file
+    in this run -- are dropped before the pipeline runs, and the ids of this run's survivors are added to the
+    filter, which is saved once all files are done
+
+    With report_dir, each input file also gets a small json report there (same relative path plus .report.json) with
+    its own doc counts, per-step removals/timing and error count, to spot anomalous shards
+
+    With limit, processing stops once that many survivors were written (see DocLimit), for quick end-to-end runs
+
+    With max_drop_fraction, the run fails after everything was written if the pipeline removed more than that fraction
+    of the docs it processed. With sample_check too, the same test runs first on that many docs (from a random order of
+    the input files, nothing written) and a failing config aborts before the full run starts
     */
 
     // Setup data handlers
     let start_main = Instant::now();
-    let all_files = expand_dirs(vec![input_dir.clone()], None).unwrap();
-    let json_config = parse_config(config).unwrap();
-    let processor = PipelineProcessor::new(&json_config).unwrap();
+    let all_files = expand_input_dirs(input_dir).unwrap();
+    let (targets, router) = load_map_targets(config, output_dir, options)?;
+    if let Some(max_drop_fraction) = options.max_drop_fraction {
+        ensure!(
+            (0.0..=1.0).contains(&max_drop_fraction),
+            "--max-drop-fraction must be in [0, 1], not {:?}",
+            max_drop_fraction
+        );
+    }
+    if let (Some(max_drop_fraction), Some(sample_check)) = (options.max_drop_fraction, options.sample_check) {
+        ensure!(router.is_none(), "--sample-check doesn't support routed configs");
+        let mut sample_files = all_files.clone();
+        sample_files.shuffle(&mut rand::rng());
+        let sample = sample_pipeline(&targets[0].processor, &sample_files, sample_check, options.lossy_utf8, seen_bloom)?;
+        check_drop_fraction(sample.docs, sample.survived(), max_drop_fraction, "--sample-check")?;
+        println!("--sample-check passed on {:?} docs", sample.docs);
+    }
 
     // Setup logging utils
-    let global_timer: DashMap<usize, AtomicUsize> = DashMap::new();
-    let global_filter: DashMap<usize, usize> = DashMap::new();
-    for i in 0..processor.pipeline.len() {
-        global_timer.insert(i, AtomicUsize::new(0));
-        global_filter.insert(i, 0);
-    }
-    global_filter.insert(usize::MAX, 0);
     let err_count: AtomicUsize = AtomicUsize::new(0);
+    let seen_skipped: AtomicUsize = AtomicUsize::new(0);
+    let unrouted: AtomicUsize = AtomicUsize::new(0);
 
     // Loop over input files
+    let budget = RuntimeBudget::new(options.max_runtime);
+    let doc_limit = options.limit.map(DocLimit::new);
     let pbar = build_pbar(all_files.len(), "Files");
     all_files.par_iter().for_each(|p| {
-        //let output_file = get_output_filename(p, input_dir, output_dir).unwrap();
-        let err_file: Option<PathBuf> = if let Some(err_dir_real) = &err_dir {
-            Some(get_output_filename(p, input_dir, &err_dir_real).unwrap())
-        } else {
-            None
-        };
-        let processor_clone = &processor;
-        gen_map_single(
-            p,
-            input_dir,
-            output_dir,
-            err_file,
-            &processor_clone,
-            &global_timer,
-            &global_filter,
-            &err_count,
-        )
-        .unwrap();
-        if delete_after_read {
+        if doc_limit.as_ref().is_some_and(|doc_limit| doc_limit.reached()) {
+            return;
+        }
+        if budget.out_of_time(p) {
+            return;
+        }
+        let (lines, num_lines) = read_map_input(p, options.lossy_utf8, seen_bloom, &seen_skipped).unwrap();
+        let doc_limit = doc_limit.as_ref();
+        match &router {
+            None => {
+                let skipped_seen = num_lines - lines.len();
+                let docs = parse_lines(lines, p);
+                gen_map_single(p, input_dir, docs, skipped_seen, &targets[0], &err_count, options, seen_bloom, doc_limit)
+                    .unwrap();
+            }
+            Some(router) => {
+                // Every route sees just its own docs; a route that got none from this file writes nothing for it
+                let (routed_docs, invalid_lines, file_unrouted) = router.split_lines(lines, p);
+                unrouted.fetch_add(file_unrouted, Ordering::SeqCst);
+                // Lines that aren't json belong to no route, so they go to the top of the run's err_dir
+                err_count.fetch_add(invalid_lines.len(), Ordering::SeqCst);
+                if let Some(err_dir) = &options.err_dir {
+                    write_err_lines(invalid_lines, p, input_dir, err_dir).unwrap();
+                }
+                for (target, docs) in targets.iter().zip(routed_docs) {
+                    if !docs.is_empty() {
+                        let docs = docs.into_iter().map(Ok);
+                        gen_map_single(p, input_dir, docs, 0, target, &err_count, options, seen_bloom, doc_limit).unwrap();
+                    }
+                }
+            }
+        }
+        if options.delete_after_read {
             fs::remove_file(p).unwrap();
         }
         pbar.inc(1);
     });
 
-    print_global_stats_stuff(start_main, global_timer, global_filter, &processor);
+    let unrouted = unrouted.into_inner();
+    let mut docs_processed: usize = unrouted;
+    let mut docs_survived: usize = 0;
+    for target in targets {
+        // Docs --limit cut survived the pipeline, so they count as survivors here
+        let limit_cut = target.limit_cut.into_inner();
+        docs_processed += target.global_filter.iter().map(|e| *e.value()).sum::<usize>() + limit_cut;
+        docs_survived += *target.global_filter.get(&usize::MAX).unwrap().value() + limit_cut;
+        if let Some(stats_file) = &target.stats_file {
+            let stats = run_stats(
+                &target.processor,
+                start_main.elapsed().as_secs(),
+                &target.global_timer.iter().map(|e| (*e.key(), e.value().load(Ordering::SeqCst))).collect(),
+                &target.global_filter.iter().map(|e| (*e.key(), *e.value())).collect(),
+                &target.global_text_bytes.iter().map(|e| (*e.key(), *e.value())).collect(),
+                &target.global_filter_groups.iter().map(|e| (e.key().clone(), *e.value())).collect(),
+                limit_cut,
+            );
+            write_mem_atomic(&serde_json::to_vec_pretty(&stats)?, stats_file)?;
+        }
+        if let Some(name) = &target.name {
+            println!("===========================================");
+            println!("ROUTE {}", name);
+        }
+        print_global_stats_stuff(
+            start_main,
+            target.global_timer,
+            target.global_filter,
+            target.global_text_bytes,
+            target.global_filter_groups,
+            limit_cut,
+            &target.processor,
+        );
+    }
+    if router.is_some() {
+        println!("===========================================");
+        println!("Dropped {:?} docs that matched no route", unrouted);
+    }
+    if let Some(seen_bloom) = seen_bloom {
+        seen_bloom.filter.save(&seen_bloom.path)?;
+        println!(
+            "Skipped {:?} already-seen docs | seen-bloom now holds ~{:?} ids (capacity {:?}, est. false-positive rate {:.2e})",
+            seen_skipped.into_inner(),
+            seen_bloom.filter.len(),
+            seen_bloom.filter.capacity,
+            seen_bloom.filter.estimated_fpr()
+        );
+    }
+    if let Some(doc_limit) = doc_limit {
+        doc_limit.finish();
+    }
+    if let Some(max_drop_fraction) = options.max_drop_fraction {
+        check_drop_fraction(docs_processed, docs_survived, max_drop_fraction, "This run")?;
+    }
+    budget.finish()
+}
+
+fn gen_map_stdin(config: &ConfigSource, text_key: Option<String>, lossy_utf8: bool) -> Result<(), Error> {
+    /* Stdin -> stdout version of the map, for piping/debugging.
+
+    Reads jsonl from stdin and writes only the docs that survive the whole pipeline to stdout.
+    No per-step outputs; bad lines are reported on stderr and skipped. Stats also go to stderr, as do processors'
+    diagnostics, so stdout carries nothing but the jsonl
+    */
+    let processor = load_pipeline(config, text_key)?;
+    let mut timing_info = HashMap::new();
+    let mut filter_info = HashMap::new();
+    let mut text_bytes_info = HashMap::new();
+    let mut err_count = 0;
+
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    for (line_num, line) in stdin.lock().split(b'\n').enumerate() {
+        let line = line?;
+        let line = if lossy_utf8 {
+            String::from_utf8_lossy(&line).into_owned()
+        } else {
+            String::from_utf8(line)?
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let parsed = serde_json::from_str::<Value>(&line)
+            .map_err(Error::from)
+            .and_then(|value| processor.process(value, &mut timing_info, &mut filter_info, &mut text_bytes_info));
+        match parsed {
+            Ok((usize::MAX, Some(value))) => {
+                serde_json::to_writer(&mut stdout, &value)?;
+                stdout.write_all(b"\n")?;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Error processing stdin line {:?}: {:?}", line_num, e);
+                err_count += 1;
+            }
+        }
+    }
+    stdout.flush()?;
+
+    let total_docs: usize = filter_info.values().sum();
+    let survived = filter_info.get(&usize::MAX).copied().unwrap_or(0);
+    eprintln!(
+        "Processed {:?} docs | {:?} survived | {:?} errors",
+        total_docs, survived, err_count
+    );
     Ok(())
 }
 
+struct MapTarget {
+    /* One pipeline of a map run, plus where its outputs go and its global stats.
+    A plain config is a single target writing to the run's own directories. A routed config (see RoutedPipeline)
+    has one per route, each writing to a subdirectory named after the route in the output, rejected, err and
+    report dirs alike (e.g. output_dir/web/step_final, or output_dir/web with flat_output).
+    */
+    name: Option<String>, // the route, if routed
+    processor: PipelineProcessor,
+    final_dir: PathBuf,
+    filtered_dir: Option<PathBuf>,
+    err_dir: Option<PathBuf>,
+    report_dir: Option<PathBuf>,
+    stats_file: Option<PathBuf>, // map_stats.json, the end-of-run stats (see run_stats); None with flat_output
+    global_timer: DashMap<usize, AtomicUsize>,
+    global_filter: DashMap<usize, usize>,
+    global_text_bytes: DashMap<usize, (usize, usize)>,
+    global_filter_groups: DashMap<(usize, String), usize>, // (step, stats_group_by value) -> docs, if grouping
+    limit_cut: AtomicUsize, // survivors not written because of --limit (not in global_filter)
+}
+
+impl MapTarget {
+    fn new(name: Option<String>, processor: PipelineProcessor, output_dir: &PathBuf, options: &MapOptions) -> Self {
+        let sub_dir = |dir: &PathBuf| match &name {
+            Some(name) => dir.join(name),
+            None => dir.clone(),
+        };
+        let final_dir = if options.flat_output {
+            sub_dir(output_dir)
+        } else {
+            sub_dir(output_dir).join("step_final")
+        };
+        // A flat output_dir only holds the survivors, and later commands would take a .json there for input
+        let stats_file = (!options.flat_output).then(|| sub_dir(output_dir).join("map_stats.json"));
+        let global_timer: DashMap<usize, AtomicUsize> = DashMap::new();
+        let global_filter: DashMap<usize, usize> = DashMap::new();
+        let global_text_bytes: DashMap<usize, (usize, usize)> = DashMap::new();
+        for i in 0..processor.pipeline.len() {
+            global_timer.insert(i, AtomicUsize::new(0));
+            global_filter.insert(i, 0);
+            global_text_bytes.insert(i, (0, 0));
+        }
+        global_filter.insert(usize::MAX, 0);
+        Self {
+            filtered_dir: options.rejected_dir.as_ref().map(sub_dir),
+            err_dir: options.err_dir.as_ref().map(sub_dir),
+            report_dir: options.report_dir.as_ref().map(sub_dir),
+            name,
+            processor,
+            final_dir,
+            stats_file,
+            global_timer,
+            global_filter,
+            global_text_bytes,
+            global_filter_groups: DashMap::new(),
+            limit_cut: AtomicUsize::new(0),
+        }
+    }
+}
+
+fn load_map_targets(
+    config: &ConfigSource,
+    output_dir: &PathBuf,
+    options: &MapOptions,
+) -> Result<(Vec<MapTarget>, Option<RoutedPipeline>), Error> {
+    // The targets of a map run: one per route for a routed config (which also returns its router), else just one
+    let json_config = config.parse_with_text_key(options.text_key.clone())?;
+    if !RoutedPipeline::is_routed(&json_config) {
+        let processor = PipelineProcessor::new(&json_config)?;
+        let target = MapTarget::new(None, processor, output_dir, options);
+        return Ok((vec![target], None));
+    }
+    let mut router = RoutedPipeline::new(&json_config)?;
+    // The pipelines move into the targets; the router only needs the selectors
+    let targets = std::mem::take(&mut router.pipelines)
+        .into_iter()
+        .zip(&router.routes)
+        .map(|(processor, route)| {
+            MapTarget::new(Some(route.name.clone()), processor, output_dir, options)
+        })
+        .collect();
+    Ok((targets, Some(router)))
+}
+
+fn write_err_lines(err_lines: Vec<String>, input_file: &Path, input_dir: &Path, err_dir: &Path) -> Result<(), Error> {
+    // The lines of input_file that errored, into its file under err_dir (none if there are no such lines)
+    if err_lines.is_empty() {
+        return Ok(());
+    }
+    let mut writer = JsonlWriter::create(&mirrored_output_filename(input_file, input_dir, err_dir)?)?;
+    for line in err_lines {
+        writer.write_line(line.as_bytes())?;
+    }
+    writer.finish()
+}
 
+#[allow(clippy::too_many_arguments)]
 fn gen_map_single(
     input_file: &PathBuf,
     input_dir: &PathBuf,
-    output_dir: &PathBuf,
-    err_file: Option<PathBuf>,
-    processor: &PipelineProcessor,
-    global_timer: &DashMap<usize, AtomicUsize>,
-    global_filter: &DashMap<usize, usize>,
+    docs: impl ExactSizeIterator<Item = Result<Value, String>>,
+    skipped_seen: usize,
+    target: &MapTarget,
     err_count: &AtomicUsize,
+    options: &MapOptions,
+    seen_bloom: Option<&SeenBloom>,
+    doc_limit: Option<&DocLimit>,
 ) -> Result<(), Error> {
     /* Single-file mapping/filtration function
 
-    Processes the contents of a single file, using file-centric mappers specified in the config and writes to output file
+    Processes the docs of a single file (what was left after seen_bloom dropped skipped_seen of them, see parse_lines)
+    through the target's pipeline and writes them to the target's outputs
     */
+    let processor = &target.processor;
+    let docs_in = docs.len() + skipped_seen;
 
-    // Setup for processing
-    let data = read_pathbuf_to_mem(input_file).unwrap();
-
-    let lines: Vec<_> = data.lines().filter_map(|el| el.ok()).collect();
-
-    // Process data
-    let (output_lines, err_lines, timing_info, filter_info) =
-        processor.process_lines(lines, input_file).unwrap();
+    // Process data (process_docs keeps input order within each output, which is what preserve_order promises)
+    let (output_lines, err_lines, timing_info, mut filter_info, text_bytes_info, mut grouped_filter_info, error_info) =
+        processor.process_docs(docs).unwrap();
+    let mut limit_cut = 0;
     let err_lines_len = err_lines.len();
 
-    output_lines.into_iter().for_each(|(k, v)| {
+    output_lines.into_iter().for_each(|(k, mut v)| {
+        if let (Some(provenance_field), usize::MAX) = (&options.provenance_field, k) {
+            stamp_provenance(&mut v, provenance_field, input_file, input_dir).unwrap();
+        }
+        if let (Some(id_field), false) = (&options.deterministic, options.preserve_order) {
+            sort_deterministic(&mut v, id_field);
+        }
+        if let (Some(doc_limit), usize::MAX) = (doc_limit, k) {
+            // Only survivors that actually get written count as survivors in the stats and report
+            let cut = doc_limit.truncate(&mut v);
+            limit_cut = cut.len();
+            *filter_info.get_mut(&usize::MAX).unwrap() -= limit_cut;
+            cut.iter().filter_map(|doc| processor.stats_group(doc)).for_each(|group| {
+                *grouped_filter_info.get_mut(&(usize::MAX, group)).unwrap() -= 1;
+            });
+        }
+        // Only survivors that actually get written are recorded as seen
+        if let (Some(seen_bloom), usize::MAX) = (seen_bloom, k) {
+            v.iter().filter_map(|doc| value_id(doc, &seen_bloom.id_field)).for_each(|id| {
+                seen_bloom.filter.insert(id.as_bytes());
+            });
+        }
         let step_output_dir = if k < usize::MAX {
-            output_dir.clone().join(processor.steps[k].to_string())
+            match &target.filtered_dir {
+                Some(filtered_dir) => filtered_dir.clone().join(processor.steps[k].to_string()),
+                None => return,
+            }
         } else {
-            output_dir.clone().join("step_final")
+            target.final_dir.clone()
         };
-        let output_file = get_output_filename(input_file, input_dir, &step_output_dir).unwrap();
+        let output_file = mirrored_output_filename(input_file, input_dir, &step_output_dir).unwrap();
         write_output_lines(v, &output_file).unwrap();
     });
 
-    if let Some(err_file_real) = err_file {
-        let mut err_bytes: Vec<u8> = Vec::new();
-        err_lines.into_iter().for_each(|line| {
-            err_bytes.extend(line.as_bytes());
-            err_bytes.push(b'\n');
-        });
-        if err_bytes.len() > 0 {
-            write_mem_to_pathbuf(&err_bytes, &err_file_real).unwrap();
-        }
+    if let Some(err_dir) = &target.err_dir {
+        write_err_lines(err_lines, input_file, input_dir, err_dir).unwrap();
+    }
+
+    if let Some(report_dir) = &target.report_dir {
+        let report = file_report(
+            input_file,
+            input_dir,
+            processor,
+            docs_in,
+            skipped_seen,
+            err_lines_len,
+            &timing_info,
+            &filter_info,
+            &text_bytes_info,
+            &error_info,
+            limit_cut,
+        );
+        let report_file = get_output_filename(input_file, input_dir, report_dir)?;
+        let report_file = PathBuf::from(format!("{}.report.json", report_file.display()));
+        write_mem_atomic(&serde_json::to_vec_pretty(&report)?, &report_file)?;
     }
 
     // Do logging stuff
     let _ = err_count.fetch_add(err_lines_len, Ordering::SeqCst);
+    target.limit_cut.fetch_add(limit_cut, Ordering::SeqCst);
     timing_info.iter().for_each(|(k, v)| {
-        global_timer
+        target
+            .global_timer
             .get(k)
             .unwrap()
             .fetch_add(*v as usize, Ordering::SeqCst);
     });
 
     filter_info.iter().for_each(|(k, v)| {
-        global_filter.entry(*k).and_modify(|gv| *gv += v);
+        target.global_filter.entry(*k).and_modify(|gv| *gv += v);
+    });
+
+    grouped_filter_info.into_iter().for_each(|(k, v)| {
+        *target.global_filter_groups.entry(k).or_insert(0) += v;
+    });
+
+    text_bytes_info.iter().for_each(|(k, (bytes_in, bytes_out))| {
+        target.global_text_bytes.entry(*k).and_modify(|gv| {
+            gv.0 += bytes_in;
+            gv.1 += bytes_out;
+        });
     });
 
     Ok(())
 }
 
-pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<String>, count_per_doc: bool, sum_keys_opt: Option<Vec<String>>, split_by_dir: bool) -> Result<(), Error> {
+/*============================================================
+=                            PROFILE                         =
+============================================================*/
+
+fn profile(
+    input_dir: &PathBuf,
+    config: &ConfigSource,
+    sample: usize,
+    total_docs: Option<usize>,
+    text_key: Option<String>,
+) -> Result<(), Error> {
+    /* Dry run of a map config to estimate what a full run will cost.
+
+    Runs the pipeline over `sample` docs (taken from a random order of the input files, nothing is written)
+    and reports per step how many sampled docs reached it and the average ns/doc spent there.
+    The per-step cost is then projected onto the full corpus: a step only pays for the docs that survive
+    the steps before it, so a cheap filter early on makes every later step cheaper.
+    The corpus size comes from count_docs unless total_docs is given (counting means reading every file once).
+    */
+    ensure!(sample > 0, "Need a positive --sample, got {:?}", sample);
+    let start_main = Instant::now();
+    let processor = load_pipeline(config, text_key)?;
+    let mut all_files = expand_input_dirs(input_dir)?;
+    all_files.shuffle(&mut rand::rng());
+
+    let PipelineSample { timing_info, filter_info, docs: sampled, errors: err_count, .. } =
+        sample_pipeline(&processor, &all_files, sample, false, None)?;
+    ensure!(sampled > 0, "No documents could be processed from {:?}", input_dir);
+    let sample_secs = start_main.elapsed().as_secs_f64();
+
+    let total_docs = match total_docs {
+        Some(total_docs) => total_docs,
+        None => count_docs(&all_files)?,
+    };
+    let threads = rayon::current_num_threads();
+    let scale = total_docs as f64 / sampled as f64;
+
+    // Print things
+    println!("Profiled {:?} docs in {:.2} seconds ({:?} errored)", sampled, sample_secs, err_count);
+    println!("Projecting onto {:?} total documents over {:?} threads", total_docs, threads);
+    println!("-------------------------------------------");
+    let mut reached = sampled;
+    let mut total_cpu_secs = 0.0;
+    for (i, el) in processor.pipeline.iter().enumerate() {
+        println!("Step {:?} | {:?}", processor.steps[i], el);
+        let step_nanos = *timing_info.get(&i).unwrap_or(&0) as f64;
+        let avg_nanos = step_nanos / f64::max(1.0, reached as f64);
+        let cpu_secs = step_nanos * scale / 1e9;
+        total_cpu_secs += cpu_secs;
+        println!(
+            "\t {:?} sampled docs reached this step | {:.0} ns/doc",
+            reached, avg_nanos
+        );
+        println!(
+            "\t Projected: ~{:.0} docs | {:.1} CPU-secs | ~{:.1} secs wall-clock",
+            reached as f64 * scale,
+            cpu_secs,
+            cpu_secs / threads as f64
+        );
+        reached -= filter_info.get(&i).unwrap_or(&0);
+    }
+
+    println!("FINAL:");
+    println!(
+        "\t ~{:.0} docs projected to survive | {:.2}% of pool",
+        reached as f64 * scale,
+        reached as f64 / sampled as f64 * 100.0
+    );
+    println!(
+        "\t Projected pipeline time: {:.1} CPU-secs | ~{:.1} secs wall-clock (excludes IO)",
+        total_cpu_secs,
+        total_cpu_secs / threads as f64
+    );
+    Ok(())
+}
+
+fn count_docs(paths: &[PathBuf]) -> Result<usize, Error> {
+    // Number of docs across all paths (same parsing as the map, so json array files count per element)
+    let pbar = build_pbar(paths.len(), "Counting");
+    let total = paths
+        .par_iter()
+        .map(|p| {
+            let n = read_input_lines(p).map(|lines| lines.len());
+            pbar.inc(1);
+            n
+        })
+        .collect::<Result<Vec<usize>, Error>>()?;
+    Ok(total.into_iter().sum())
+}
+
+#[derive(Default)]
+struct LengthStats {
+    // Running min/max/sum plus a log2 histogram (bucket k holds lengths in [2^(k-1), 2^k), bucket 0 is length 0)
+    count: usize,
+    min: usize,
+    max: usize,
+    sum: usize,
+    hist: Vec<usize>,
+}
+
+impl LengthStats {
+    fn add(&mut self, len: usize) {
+        self.min = if self.count == 0 { len } else { self.min.min(len) };
+        self.max = self.max.max(len);
+        self.count += 1;
+        self.sum += len;
+        let bucket = (usize::BITS - len.leading_zeros()) as usize;
+        if self.hist.len() <= bucket {
+            self.hist.resize(bucket + 1, 0);
+        }
+        self.hist[bucket] += 1;
+    }
+
+    fn merge(&mut self, other: &LengthStats) {
+        if other.count == 0 {
+            return;
+        }
+        self.min = if self.count == 0 { other.min } else { self.min.min(other.min) };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.sum += other.sum;
+        if self.hist.len() < other.hist.len() {
+            self.hist.resize(other.hist.len(), 0);
+        }
+        for (bucket, n) in other.hist.iter().enumerate() {
+            self.hist[bucket] += n;
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let histogram: Vec<Value> = self
+            .hist
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(bucket, n)| {
+                let (lo, hi) = if bucket == 0 { (0, 0) } else { (1usize << (bucket - 1), (1usize << bucket) - 1) };
+                json!({"min": lo, "max": hi, "count": n})
+            })
+            .collect();
+        json!({
+            "docs": self.count,
+            "min": self.min,
+            "max": self.max,
+            "mean": self.sum as f64 / f64::max(1.0, self.count as f64),
+            "histogram": histogram,
+        })
+    }
+}
+
+#[derive(Default)]
+struct TextLengths {
+    bytes: LengthStats,
+    chars: LengthStats,
+    words: LengthStats,
+}
+
+impl TextLengths {
+    fn add(&mut self, text: &str) {
+        self.bytes.add(text.len());
+        self.chars.add(text.chars().count());
+        self.words.add(text.split_whitespace().count());
+    }
+
+    fn merge(&mut self, other: &TextLengths) {
+        self.bytes.merge(&other.bytes);
+        self.chars.merge(&other.chars);
+        self.words.merge(&other.words);
+    }
+
+    fn to_json(&self) -> Value {
+        json!({"bytes": self.bytes.to_json(), "chars": self.chars.to_json(), "words": self.words.to_json()})
+    }
+}
+
+pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<String>, count_per_doc: bool, sum_keys_opt: Option<Vec<String>>, split_by_dir: bool, lengths: bool) -> Result<(), Error> {
     let start_main = Instant::now();
     let all_files = expand_dirs(vec![input_dir.clone()], None).unwrap();
 
     let total_doc_count : DashMap<String, usize> = DashMap::new();
     let total_file_sizes : DashMap<String, usize> = DashMap::new();  // uncompressed file sizes
     let total_text_bytes : DashMap<String, usize> = DashMap::new(); 
+    // With --lengths we need a text field to measure, so fall back to the default one
     let text_key: String = if let Some(text_key) = count_bytes {
         text_key
+    } else if lengths {
+        String::from("text")
     } else {
         String::from("")
     };
+    let total_lengths: DashMap<String, TextLengths> = DashMap::new();
 
     let sum_key_counter : DashMap<String, DashMap<String, usize>> = DashMap::new();
     let count_per_doc_opt: Option<DashMap<String, DashMap<PathBuf, usize>>> = if count_per_doc {
@@ -518,6 +1443,7 @@ pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<Str
         let mut file_len = 0;
         let mut file_size = 0;
         let mut text_bytes = 0;
+        let mut file_lengths = TextLengths::default();
         let mut sum_key_path_counter: HashMap<String, usize> = if let Some(sum_keys) = &sum_keys_opt {
             sum_keys.iter().map(|el| (el.clone(), 0)).collect()
         } else {
@@ -532,6 +1458,9 @@ pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<Str
                 let value = gjson::get(&line, &text_key);
                 if value.exists() {
                     text_bytes += value.str().len();
+                    if lengths {
+                        file_lengths.add(value.str());
+                    }
                 }
             }            
             for (k, v) in sum_key_path_counter.iter_mut() {
@@ -548,6 +1477,9 @@ pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<Str
         total_doc_count.entry(dirname.clone()).and_modify(|og_v| *og_v += file_len).or_insert(file_len);
         total_file_sizes.entry(dirname.clone()).and_modify(|og_v| *og_v += file_size).or_insert(file_size);
         total_text_bytes.entry(dirname.clone()).and_modify(|og_v| *og_v += text_bytes).or_insert(text_bytes);
+        if lengths {
+            total_lengths.entry(dirname.clone()).or_default().merge(&file_lengths);
+        }
         if let Some(doc_counts) = &count_per_doc_opt {
             doc_counts.entry(dirname).or_default().entry(p).or_insert(text_bytes);
         }
@@ -589,7 +1521,14 @@ pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<Str
             }
             acc
         });
-    let output_json = if let Some(doc_counts) = count_per_doc_opt {
+    let total_lengths: HashMap<String, TextLengths> = total_lengths
+        .into_iter()
+        .fold(HashMap::new(), |mut acc, (k, v)| {
+            acc.entry(agg_key(k)).or_default().merge(&v);
+            acc
+        });
+
+    let mut output_json = if let Some(doc_counts) = count_per_doc_opt {
         let doc_counts: HashMap<PathBuf, usize> = doc_counts
             .into_iter()
             .flat_map(|(_, inner)| inner.into_iter())
@@ -609,9 +1548,13 @@ pub fn count(input_dir: &PathBuf, output_file: &PathBuf, count_bytes: Option<Str
             "sum_keys": sum_key_counter
         })
     };
+    if lengths {
+        let lengths_json: HashMap<&String, Value> = total_lengths.iter().map(|(k, v)| (k, v.to_json())).collect();
+        output_json["text_lengths"] = json!(lengths_json);
+    }
 
     let output_contents = serde_json::to_vec(&output_json).unwrap();
-    write_mem_to_pathbuf(&output_contents, output_file).unwrap();
+    write_mem_atomic(&output_contents, output_file).unwrap();
 
     let total_docs: usize = total_doc_count.values().sum();
     let total_bytes: usize = total_file_sizes.values().sum();
@@ -635,16 +1578,78 @@ fn main() {
     if threads != 0 {
         std::env::set_var("RAYON_NUM_THREADS", threads.to_string());
     }
+    let text_key = args.text_key.clone().unwrap_or(String::from("text"));
+    set_quiet_progress(args.quiet);
+    set_compress_outputs(!args.no_compress);
 
     let result = match &args.command {
         Commands::Map {
             input_dir,
             output_dir,
             config,
+            config_inline,
             err_dir,
             delete_after_read,
-
-        } => gen_map(input_dir, output_dir, config, err_dir.clone(), *delete_after_read),
+            force,
+            deterministic,
+            id_field,
+            preserve_order,
+            flat_output,
+            stdin,
+            provenance_field,
+            keep_rejected,
+            rejected_dir,
+            lossy_utf8,
+            max_runtime,
+            seen_bloom,
+            seen_id_field,
+            seen_bloom_capacity,
+            seen_bloom_fpr,
+            per_file_reports,
+            limit,
+            max_drop_fraction,
+            sample_check,
+        } => if *stdin {
+            ConfigSource::new(config, config_inline)
+                .and_then(|config| gen_map_stdin(&config, args.text_key.clone(), *lossy_utf8))
+        } else {
+            let input_dir = input_dir.as_ref().unwrap();
+            let output_dir = output_dir.as_ref().unwrap();
+            let config = rejected_output_dir(output_dir, rejected_dir.as_ref(), *keep_rejected, *flat_output, err_dir.as_ref())
+                .and_then(|rejected_dir_used| ConfigSource::new(config, config_inline).map(|config| (config, rejected_dir_used)));
+            config.and_then(|(config, rejected_dir_used)| check_output_dir(output_dir, *force, None)
+                .and_then(|_| match rejected_dir {
+                    Some(rejected_dir) => check_output_dir(rejected_dir, *force, None),
+                    None => Ok(()),
+                })
+                .and_then(|_| match per_file_reports {
+                    Some(report_dir) => check_output_dir(report_dir, *force, None),
+                    None => Ok(()),
+                })
+                .and_then(|_| match seen_bloom {
+                    Some(path) => SeenBloom::load(path, seen_id_field, *seen_bloom_capacity, *seen_bloom_fpr).map(Some),
+                    None => Ok(None),
+                })
+                .and_then(|seen_bloom| {
+                    let options = MapOptions {
+                        err_dir: err_dir.clone(),
+                        rejected_dir: rejected_dir_used,
+                        delete_after_read: *delete_after_read,
+                        deterministic: deterministic.then(|| id_field.clone()),
+                        preserve_order: *preserve_order,
+                        flat_output: *flat_output,
+                        text_key: args.text_key.clone(),
+                        provenance_field: provenance_field.clone(),
+                        lossy_utf8: *lossy_utf8,
+                        max_runtime: *max_runtime,
+                        report_dir: per_file_reports.clone(),
+                        limit: *limit,
+                        max_drop_fraction: *max_drop_fraction,
+                        sample_check: *sample_check,
+                    };
+                    gen_map(input_dir, output_dir, &config, seen_bloom.as_ref(), &options)
+                }))
+        },
         Commands::Reshard {
             input_dir,
             output_dir,
@@ -653,7 +1658,9 @@ fn main() {
             subsample,
             keep_dirs,
             delete_after_read,
-        } => reshard(
+            force,
+            respect_groups,
+        } => check_output_dir(output_dir, *force, None).and_then(|_| reshard(
             input_dir,
             output_dir,
             *max_lines,
@@ -661,15 +1668,28 @@ fn main() {
             *subsample,
             *keep_dirs,
             *delete_after_read,
-        ),
+            respect_groups.as_deref(),
+        )),
+        Commands::Coalesce {
+            input_dir,
+            output_dir,
+            max_size,
+            keep_dirs,
+            delete_after_read,
+            force,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| coalesce(input_dir, output_dir, *max_size, *keep_dirs, *delete_after_read)),
         Commands::ReservoirSample {
             input_dir,
             output_file,
             key,
             reservoir_size,
             token_weighted,
-            text_key
-        } => reservoir_sample(input_dir, output_file, key, *reservoir_size, *token_weighted, &text_key.clone()),
+            sample_docs,
+        } => reservoir_sample(input_dir, output_file, key, *reservoir_size, *token_weighted, &text_key, *sample_docs),
+        Commands::StratifiedSample {
+            input_dir, output_file, value, range_groups, num_buckets, reservoir_path, per_bucket, default_value,
+        } => stratified_sample(input_dir, output_file, value, range_groups, reservoir_path, *num_buckets, *per_bucket, *default_value),
 
 
         Commands::PercentileFinder {
@@ -677,45 +1697,107 @@ fn main() {
             output_file,
             score_key,
             tokenizer,
-            text_key,
             num_buckets,
             subsample_rate
-        } => percentile_finder(input_dir, output_file, score_key, text_key, tokenizer, *num_buckets, *subsample_rate),
+        } => percentile_finder(input_dir, output_file, score_key, &text_key, tokenizer, *num_buckets, *subsample_rate),
 
         Commands::DiscretePartition {
             input_dir,
             output_dir,
             config,
-            partition_key
-        } => discrete_partition(input_dir, output_dir, config, partition_key),
+            partition_key,
+            force,
+            max_runtime,
+            filename_template,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| discrete_partition(input_dir, output_dir, config, partition_key, *max_runtime, filename_template)),
 
         Commands::RangePartition {
             input_dir,
             output_dir,
             config,
-            value, default_value, range_groups, reservoir_path, num_buckets, max_file_size, bucket_name
-
-        } => range_partition(input_dir, output_dir, config, value, default_value, range_groups, reservoir_path, num_buckets, max_file_size, bucket_name),
+            value, default_value, range_groups, reservoir_path, num_buckets, max_file_size, bucket_name, missing_bucket,
+            force,
+            max_runtime,
+            filename_template,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| range_partition(input_dir, output_dir, config, value, default_value, range_groups, reservoir_path, num_buckets, max_file_size, bucket_name, *missing_bucket, *max_runtime, filename_template)),
+        Commands::LanguagePartition {
+            input_dir, output_dir, fast_text_file, min_prob, max_file_size, force, max_runtime, filename_template,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| language_partition(input_dir, output_dir, fast_text_file, &text_key, *min_prob, *max_file_size, *max_runtime, filename_template)),
         Commands::Group {
             input_dir,
             group_dir,
             config,
-            subext
-        } => group(input_dir, group_dir, config, subext.clone()),
+            subext,
+            force,
+            max_runtime,
+            incremental,
+        } => {
+            // Several group runs can share a group_dir with distinct subexts, so only those files clash.
+            // An incremental run is meant to add to an existing group_dir
+            let subext_filter = format!(".{}.", subext.as_deref().unwrap_or("group"));
+            check_output_dir(group_dir, *force || *incremental, Some(&subext_filter))
+                .and_then(|_| group(input_dir, group_dir, config, subext.clone(), *max_runtime, *incremental))
+        },
+        Commands::GroupSort {
+            input_dir,
+            output_dir,
+            config,
+            subext,
+            force,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| group_sort(input_dir, output_dir, config, subext.clone())),
         Commands::GroupFilter {
             input_dir,
             output_dir,
             config,
             prev_sorted,
-        } => group_filter(input_dir, output_dir, config, *prev_sorted),
+            assert_grouped,
+            force,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| group_filter(input_dir, output_dir, config, *prev_sorted, *assert_grouped)),
 
         Commands::Shuffle {
-            input_dir, output_dir, num_outputs, max_len, delete_after_read
-        } => shuffle(input_dir, output_dir, *num_outputs, *max_len, *delete_after_read),
+            input_dir, output_dir, num_outputs, max_len, delete_after_read, force, write_buffer_size, filename_template,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| shuffle(input_dir, output_dir, *num_outputs, *max_len, *delete_after_read, *write_buffer_size, filename_template)),
 
         Commands::Count {
-            input_dir, output_file, count_bytes, count_per_doc, sum_keys, split_by_dir,
-        } => count(input_dir, output_file, count_bytes.clone(), *count_per_doc, sum_keys.clone(), *split_by_dir),
+            input_dir, output_file, count_bytes, count_per_doc, sum_keys, split_by_dir, lengths,
+        } => count(input_dir, output_file, count_bytes.clone().or(args.text_key.clone()), *count_per_doc, sum_keys.clone(), *split_by_dir, *lengths),
+
+        Commands::CheckUnique {
+            input_dir, output_file, id_field, sample_size,
+        } => check_unique(input_dir, output_file, id_field, *sample_size),
+
+        Commands::Diff {
+            old_dir, new_dir, output_file, id_field, sample_size,
+        } => diff(old_dir, new_dir, output_file, id_field, &text_key, *sample_size),
+
+        Commands::Head {
+            input_dir, output_file, n, random, seed,
+        } => head(input_dir, output_file, *n, *random, *seed),
+
+        Commands::MergeJsonl {
+            left_dir, output_dir, right_dir, join_key, fields, conflict_strategy, overwrite, nest_under, force,
+        } => ConflictStrategy::from_name(if *overwrite { "prefer_right" } else { conflict_strategy })
+            .and_then(|strategy| check_output_dir(output_dir, *force, None).map(|_| strategy))
+            .and_then(|strategy| merge_jsonl(left_dir, right_dir, output_dir, join_key, fields, strategy, nest_under)),
+
+        Commands::Percentilize {
+            input_dir, output_dir, value, output_field, reservoir_size, num_buckets, default_value, force,
+        } => check_output_dir(output_dir, *force, None)
+            .and_then(|_| percentilize(input_dir, output_dir, value, output_field, *reservoir_size, *num_buckets, *default_value)),
+
+        Commands::Profile {
+            input_dir, config, config_inline, sample, total_docs,
+        } => ConfigSource::new(config, config_inline)
+            .and_then(|config| profile(input_dir, &config, *sample, *total_docs, args.text_key.clone())),
+
+        Commands::CheckConfig { config, config_inline } => ConfigSource::new(config, config_inline)
+            .and_then(|config| check_config(&config, args.text_key.clone())),
 
         _ => Ok(()),
     };
@@ -1,35 +1,41 @@
 
+use std::fs;
 use std::io::Write;
+use std::cell::RefCell;
 use std::cmp;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Instant;
-use crate::utils::{extract_subdomain, get_default, json_get, json_set, json_remove};
+use crate::utils::{ceil_char_boundary, extract_subdomain, floor_char_boundary, get_default, get_required_str, json_get, json_set, json_remove, load_fasttext_model, truncate_at_byte_boundary};
 use aho_corasick::AhoCorasick;
-use anyhow::{anyhow, ensure, Error, Result};
+use ahash::RandomState;
+use anyhow::{anyhow, bail, ensure, Error, Result};
 use once_cell::sync::Lazy;
 use rand::rng;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde::Serialize;
 use serde_json;
 use serde_json::{json, Value};
+use std::borrow::{Borrow, Cow};
 use std::collections::VecDeque;
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::io::BufRead;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 use fasttext::FastText;
 use fxhash::{FxHasher, FxHashMap};
 use mj_io::read_pathbuf_to_mem;
-use regex::Regex;
+use regex::{Regex, RegexBuilder};
 use unicode_segmentation::UnicodeSegmentation;
 use url::Url;
-use xxhash_rust::xxh3::{xxh3_128, xxh3_64};
+use xxhash_rust::xxh3::{xxh3_128, xxh3_64, xxh3_64_with_seed};
 use once_cell::sync::OnceCell;
 use derivative::Derivative;
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use tiktoken_rs::{cl100k_base, p50k_base, CoreBPE};
+use tiktoken_rs::{cl100k_base, o200k_base, p50k_base, CoreBPE};
 use tokenizers::Tokenizer;
 use unicode_normalization::UnicodeNormalization;
 
@@ -40,13 +46,32 @@ use unicode_normalization::UnicodeNormalization;
 ================================================================================*/
 type TimingInfo = HashMap<usize, u128>;
 type FilterInfo = HashMap<usize, usize>;
+type TextBytesInfo = HashMap<usize, (usize, usize)>; // step -> (text bytes in, text bytes out) over docs surviving the step
+type GroupedFilterInfo = HashMap<(usize, String), usize>; // (step, stats_group_by value) -> docs, like FilterInfo
+type ErrorInfo = HashMap<usize, usize>; // step -> docs that errored in it (lines that aren't json never reach a step)
+type ProcessedLines = (HashMap<usize, Vec<Value>>, Vec<String>, TimingInfo, FilterInfo, TextBytesInfo, GroupedFilterInfo, ErrorInfo);
+
+#[derive(Debug)]
+pub struct StepError {
+    // What PipelineProcessor::process fails with: the error of the step it failed in
+    pub step: usize,
+    pub error: Error,
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Pipeline step {}: {}", self.step, self.error)
+    }
+}
+
+impl std::error::Error for StepError {}
 
 type ProcessorConstructor = fn(&Value) -> Result<Box<dyn AnyDataProcessor>, Error>;
 
 macro_rules! register_processor {
     ($map:expr, $name:expr, $processor_type:ty) => {
         $map.insert($name, |config| {
-            let processor = <$processor_type>::new(config).unwrap();
+            let processor = <$processor_type>::new(config)?;
             Ok(Box::new(processor) as Box<dyn AnyDataProcessor>)
         });
     };
@@ -57,27 +82,55 @@ static PROCESSOR_CONSTRUCTORS: Lazy<HashMap<&'static str, ProcessorConstructor>>
     Lazy::new(|| {
         let mut m: HashMap<&'static str, ProcessorConstructor> = HashMap::new();
         register_processor!(m, "non_null_filter", NonNullFilter);
+        register_processor!(m, "any_field_present_filter", AnyFieldPresentFilter);
         register_processor!(m, "text_len_filter", TextLenFilter);
         register_processor!(m, "subsample", SubsampleFilter);
+        register_processor!(m, "weighted_subsample_filter", WeightedSubsampleFilter);
         register_processor!(m, "add_id", AddIdModifier);
         register_processor!(m, "url_substring_filter", UrlSubstringFilter);
+        register_processor!(m, "keyword_presence_filter", KeywordPresenceFilter);
         register_processor!(m, "newline_removal_modifier", NewlineRemovalModifier);
+        register_processor!(m, "whitespace_normalize_modifier", WhitespaceNormalizeModifier);
+        register_processor!(m, "fix_encoding_modifier", FixEncodingModifier);
         register_processor!(m, "fasttext_annotator", FastTextAnnotator);
+        register_processor!(m, "language_agreement_filter", LanguageAgreementFilter);
         register_processor!(m, "float_filter", FloatFilter);
         register_processor!(m, "string_eq_filter", StringEqFilter);
+        register_processor!(m, "numeric_allow_list_filter", NumericAllowListFilter);
+        register_processor!(m, "numeric_deny_list_filter", NumericDenyListFilter);
+        register_processor!(m, "regex_filter", RegexFilter);
         register_processor!(m, "regex_text_filter", RegexTextFilter);
         register_processor!(m, "page_len_filter", PageLenFilter);
+        register_processor!(m, "non_empty_line_filter", NonEmptyLineFilter);
+        register_processor!(m, "sentence_count_filter", SentenceCountFilter);
+        register_processor!(m, "avg_sentence_length_filter", AvgSentenceLengthFilter);
+        register_processor!(m, "non_ascii_ratio_filter", NonAsciiRatioFilter);
+        register_processor!(m, "uppercase_ratio_filter", UppercaseRatioFilter);
         register_processor!(m, "word_len_filter", WordLenFilter);
+        register_processor!(m, "unique_word_ratio_filter", UniqueWordRatioFilter);
+        register_processor!(m, "dictionary_word_ratio_filter", DictionaryWordRatioFilter);
         register_processor!(m, "symbol_ratio_filter", SymbolRatioFilter);
+        register_processor!(m, "binary_content_filter", BinaryContentFilter);
         register_processor!(m, "bullet_filter", BulletFilter);
+        register_processor!(m, "code_density_annotator", CodeDensityAnnotator);
+        register_processor!(m, "code_density_filter", CodeDensityFilter);
         register_processor!(m, "ellipsis_line_ratio_filter", EllipsisLineRatioFilter);
         register_processor!(m, "alphabetic_word_ratio_filter", AlphabeticWordRatioFilter);
+        register_processor!(m, "min_alpha_chars_filter", MinAlphaCharsFilter);
         register_processor!(m, "stop_word_filter", StopWordFilter);
         register_processor!(
             m,
             "massive_web_repetition_filter",
             MassiveWebRepetitionFilter
         );
+        register_processor!(
+            m,
+            "massive_web_repetition_annotator",
+            MassiveWebRepetitionAnnotator
+        );
+        register_processor!(m, "duplicate_line_ratio_filter", DuplicateLineRatioFilter);
+        register_processor!(m, "short_line_ratio_filter", ShortLineRatioFilter);
+        register_processor!(m, "numeric_line_ratio_filter", NumericLineRatioFilter);
         register_processor!(m, "word_count_adder", WordCountAdder);
         register_processor!(m, "ratio_line_modifier", RatioLineModifier);
         register_processor!(m, "regex_line_modifier", RegexLineModifier);
@@ -92,10 +145,19 @@ static PROCESSOR_CONSTRUCTORS: Lazy<HashMap<&'static str, ProcessorConstructor>>
         register_processor!(m, "hash_annotator", HashAnnotator);
         register_processor!(m, "max_extractor", MaxExtractor);
         register_processor!(m, "constant_annotator", ConstantAnnotator);
+        register_processor!(m, "template_annotator", TemplateAnnotator);
         register_processor!(m, "rename_modifier", RenameModifier);
+        register_processor!(m, "parse_json_field_modifier", ParseJsonFieldModifier);
+        register_processor!(m, "url_parse_modifier", UrlParseModifier);
+        register_processor!(m, "split_field_modifier", SplitFieldModifier);
+        register_processor!(m, "concat_fields_modifier", ConcatFieldsModifier);
+        register_processor!(m, "array_join_modifier", ArrayJoinModifier);
+        register_processor!(m, "numeric_normalize_modifier", NumericNormalizeModifier);
         register_processor!(m, "sa_byte_modifier", SAByteModifier);
         register_processor!(m, "gzip_annotator", GzipAnnotator);
+        register_processor!(m, "compression_ratio_filter", CompressionRatioFilter);
         register_processor!(m, "token_count_annotator", TokenCountAnnotator);
+        register_processor!(m, "token_len_filter", TokenLenFilter);
         register_processor!(m, "ngram_repetition_filter", NgramRepetitionFilter);
         register_processor!(m, "ultrafineweb_annotator", UltrafinewebAnnotator);
         m
@@ -119,6 +181,8 @@ where
 pub struct PipelineProcessor {
     pub pipeline: Vec<Box<dyn AnyDataProcessor>>,
     pub steps: Vec<String>,
+    pub text_field: String,
+    pub stats_group_by: Option<String>, // if set, filter counts are also broken down by the value of this doc field
 }
 
 impl PipelineProcessor {
@@ -127,26 +191,35 @@ impl PipelineProcessor {
         let mut pipeline: Vec<Box<dyn AnyDataProcessor>> = Vec::<Box<dyn AnyDataProcessor>>::new();
         let mut steps: Vec<String> = Vec::<String>::new();
         let text_field = get_default(&config, "text_field", String::from("text"));
+        let stats_group_by = config.get("stats_group_by").and_then(|v| v.as_str()).map(String::from);
 
-        let pipeline_configs = config.get("pipeline").unwrap().as_array().unwrap();
+        let pipeline_configs = config
+            .get("pipeline")
+            .and_then(|p| p.as_array())
+            .ok_or_else(|| anyhow!("Config needs a 'pipeline' list"))?;
         for (step_num, subconfig) in pipeline_configs.iter().enumerate() {
-            let subconfig_name = subconfig.get("name").unwrap().as_str().unwrap();
+            let subconfig_name = subconfig
+                .get("name")
+                .and_then(|n| n.as_str())
+                .ok_or_else(|| anyhow!("Pipeline step {} has no 'name'", step_num))?;
             let default_json = json!({});
             let mut subconfig_kwargs: Value = subconfig
                 .get("kwargs")
-                .or(Some(&default_json))
-                .unwrap()
+                .unwrap_or(&default_json)
                 .clone();
             if json_get(&mut subconfig_kwargs, &String::from("text_field")).is_none() {
                 json_set(
                     &mut subconfig_kwargs,
                     &String::from("text_field"),
                     serde_json::Value::String(text_field.clone()),
-                )
-                .unwrap();
+                )?;
             }
-            let constructor = PROCESSOR_CONSTRUCTORS[subconfig_name];
-            pipeline.push(constructor(&subconfig_kwargs).unwrap());
+            let constructor = PROCESSOR_CONSTRUCTORS
+                .get(subconfig_name)
+                .ok_or_else(|| anyhow!("Pipeline step {}: unknown processor {:?}", step_num, subconfig_name))?;
+            let processor = constructor(&subconfig_kwargs)
+                .map_err(|e| anyhow!("Pipeline step {} ({}): {}", step_num, subconfig_name, e))?;
+            pipeline.push(processor);
 
             match subconfig.get("step") {
                 Some(step) => {
@@ -171,33 +244,57 @@ impl PipelineProcessor {
             return Err(Error::msg("Step names must be unique"));
         }
 
-        Ok(Self { pipeline, steps })
+        Ok(Self { pipeline, steps, text_field, stats_group_by })
     }
 
     pub fn process(
+        &self,
+        data: Value,
+        timing_info: &mut TimingInfo,
+        filter_info: &mut FilterInfo,
+        text_bytes_info: &mut TextBytesInfo,
+    ) -> Result<(usize, Option<Value>), Error> {
+        self.process_owned(data, timing_info, filter_info, text_bytes_info).map_err(|(error, _)| error)
+    }
+
+    fn process_owned(
         &self,
         data: Value,
         _timing_info: &mut TimingInfo,
         _filter_info: &mut FilterInfo,
-    ) -> Result<(usize, Option<Value>), Error> {
+        _text_bytes_info: &mut TextBytesInfo,
+    ) -> Result<(usize, Option<Value>), (Error, Value)> {
         /*
         General data processor for the pipeline:
             Takes in a Value and some extra logging info. Will maybe modify the json and then spit it back out with a (usize, .) prefixing it
             If the usize is less than usize::MAX, then this document got filtered and should not be included in outputs
             else, the thing that gets output passes the map and should be included in outputs
+        Also tracks how many bytes of text_field go into/come out of each step (for docs that survive the step)
+        An error comes back with the input doc, for callers that still need it (the error output, routing)
         */
 
         let og_copy = data.clone();
         let mut current_data = data;
+        let mut text_bytes = self.text_bytes(&current_data);
 
         let mut filter_step = 0;
         for processor in &self.pipeline {
             let start_step = Instant::now();
-            let proc_result = processor.process(current_data)?;
+            let proc_result = match processor.process(current_data) {
+                Ok(proc_result) => proc_result,
+                Err(error) => return Err((Error::new(StepError { step: filter_step, error }), og_copy)),
+            };
             *_timing_info.entry(filter_step).or_insert(0 as u128) += start_step.elapsed().as_nanos();
 
             match proc_result {
-                Some(data_value) => current_data = data_value,
+                Some(data_value) => {
+                    let new_text_bytes = self.text_bytes(&data_value);
+                    let entry = _text_bytes_info.entry(filter_step).or_insert((0, 0));
+                    entry.0 += text_bytes;
+                    entry.1 += new_text_bytes;
+                    text_bytes = new_text_bytes;
+                    current_data = data_value
+                },
                 None => {
                     *_filter_info.entry(filter_step).or_insert(0 as usize) += 1;
                     return Ok((filter_step, Some(og_copy)));
@@ -210,48 +307,257 @@ impl PipelineProcessor {
         Ok((usize::MAX, Some(current_data)))
     }
 
-    pub fn process_lines(
-        &self,
-        lines: Vec<String>,
-        filename: &PathBuf,
-    ) -> Result<
-        (
-            HashMap<usize, Vec<Value>>,
-            Vec<String>,
-            TimingInfo,
-            FilterInfo,
-        ),
-        Error,
-    > {
+    pub fn stats_group(&self, data: &Value) -> Option<String> {
+        // The stats_group_by value of a doc (non-strings by their json text, missing as "null"), None if not grouping
+        let field = self.stats_group_by.as_ref()?;
+        Some(match json_get(data, field) {
+            Some(Value::String(s)) => s.clone(),
+            Some(other) => other.to_string(),
+            None => String::from("null"),
+        })
+    }
+
+    fn text_bytes(&self, data: &Value) -> usize {
+        match json_get(data, &self.text_field) {
+            Some(Value::String(text)) => text.len(),
+            _ => 0,
+        }
+    }
+
+    pub fn process_lines(&self, lines: Vec<String>, filename: &Path) -> Result<ProcessedLines, Error> {
+        self.process_docs(parse_lines(lines, filename))
+    }
+
+    pub fn process_docs(&self, docs: impl IntoIterator<Item = Result<Value, String>>) -> Result<ProcessedLines, Error> {
+        /* Runs the pipeline over all docs of one file (see parse_lines; lines that aren't json go straight to the
+        error lines). Docs that error in a step are written to the error lines as json again.
+        Ordering contract (Map's --preserve-order relies on this): docs are processed in input order, so the docs
+        in each output Vec (survivors under usize::MAX, rejects under their step) keep their relative input order.
+        Any future within-file parallelism has to keep this (or be disabled when order must be preserved).
+        */
         let mut timing_info = TimingInfo::new();
         let mut filter_info = FilterInfo::new();
+        let mut text_bytes_info = TextBytesInfo::new();
+        let mut grouped_filter_info = GroupedFilterInfo::new();
+        let mut error_info = ErrorInfo::new();
         let mut output_lines: HashMap<usize, Vec<Value>> = HashMap::new();
         let mut err_lines: Vec<String> = Vec::new();
-        for (line_num, line) in lines.into_iter().enumerate() {
-            let json_parse_result = serde_json::from_str(&line);
-            match json_parse_result {
-                Ok(json_line) => {
-                    let process_out = self.process(json_line, &mut timing_info, &mut filter_info);
-                    match process_out {
-                        Ok((step_out, json_result)) => {
-                            if let Some(json_out) = json_result {
-                                output_lines
-                                    .entry(step_out)
-                                    .or_insert_with(Vec::new)
-                                    .push(json_out);
-                            }
-                        }
-                        Err(_e) => err_lines.push(line.clone()),
-                    };
-                },
-                Err(_e) => {
-                    println!("Error parsing json in {:?}:{:?}", filename, line_num);
-                    err_lines.push(line.clone())
+        for doc in docs {
+            let json_line = match doc {
+                Ok(json_line) => json_line,
+                Err(line) => {
+                    err_lines.push(line);
+                    continue;
+                }
+            };
+            // Grouped by the input doc, so a step that rewrites the field doesn't move docs between groups
+            let group = self.stats_group(&json_line);
+            match self.process_owned(json_line, &mut timing_info, &mut filter_info, &mut text_bytes_info) {
+                Ok((step_out, json_result)) => {
+                    if let Some(group) = group {
+                        *grouped_filter_info.entry((step_out, group)).or_insert(0) += 1;
+                    }
+                    if let Some(json_out) = json_result {
+                        output_lines
+                            .entry(step_out)
+                            .or_insert_with(Vec::new)
+                            .push(json_out);
+                    }
+                }
+                Err((e, json_line)) => {
+                    if let Some(step_error) = e.downcast_ref::<StepError>() {
+                        *error_info.entry(step_error.step).or_insert(0) += 1;
+                    }
+                    err_lines.push(json_line.to_string())
                 }
             };
         }
 
-        Ok((output_lines, err_lines, timing_info, filter_info))
+        Ok((output_lines, err_lines, timing_info, filter_info, text_bytes_info, grouped_filter_info, error_info))
+    }
+}
+
+pub fn parse_lines(lines: Vec<String>, filename: &Path) -> impl ExactSizeIterator<Item = Result<Value, String>> + '_ {
+    // One file's jsonl lines as docs, parsed as they're taken; a line that isn't valid json comes back as is
+    lines.into_iter().enumerate().map(move |(line_num, line)| match serde_json::from_str(&line) {
+        Ok(json_line) => Ok(json_line),
+        Err(_e) => {
+            eprintln!("Error parsing json in {:?}:{:?}", filename, line_num);
+            Err(line)
+        }
+    })
+}
+
+/*================================================================================
+=                            ROUTED PIPELINES                                    =
+================================================================================*/
+/*
+A routed config runs several independent pipelines in one map pass, each over its own slice of the docs:
+
+    text_field: text
+    route_field: metadata.source      # only needed by routes that match on values
+    routes:
+      - name: web
+        values: [common_crawl, web]   # docs whose route_field is one of these
+        pipeline: [...]
+      - name: long
+        when: {name: text_len_filter, kwargs: {lower_bound: 10000}}   # docs this processor keeps
+        pipeline: [...]
+      - name: rest
+        default: true                 # everything the routes above didn't take
+        pipeline: [...]
+
+Routes are tried in order and each doc goes to the first one that matches. Docs that no route matches are dropped
+(counted as unrouted). Lines that aren't valid json belong to no route: split_lines hands them back separately, for
+the map to write to its error output. A 'when' step passes the doc on to its route, so it should be a filter.
+Each route is a full PipelineProcessor with its own step names; its text_field defaults to the top-level one.
+*/
+
+#[derive(Debug)]
+pub struct Route {
+    pub name: String,
+    pub values: Option<HashSet<String>>, // route_field values this route takes (non-strings by their json text)
+    pub when: Option<PipelineProcessor>, // or: the docs this one-step pipeline keeps
+    pub default: bool,                   // or: every doc
+}
+
+#[derive(Debug)]
+pub struct RoutedPipeline {
+    pub route_field: Option<String>,
+    pub routes: Vec<Route>,
+    pub pipelines: Vec<PipelineProcessor>, // pipelines[i] runs on the docs routes[i] takes
+}
+
+impl RoutedPipeline {
+    pub fn is_routed(config: &Value) -> bool {
+        config.get("routes").is_some()
+    }
+
+    pub fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let stats_group_by = config.get("stats_group_by").cloned().unwrap_or(Value::Null);
+        let route_field = config.get("route_field").and_then(|v| v.as_str()).map(String::from);
+        let route_configs = config
+            .get("routes")
+            .and_then(|r| r.as_array())
+            .ok_or_else(|| anyhow!("Config needs a 'routes' list"))?;
+        ensure!(!route_configs.is_empty(), "Config needs at least one route");
+
+        let mut routes: Vec<Route> = Vec::new();
+        let mut pipelines: Vec<PipelineProcessor> = Vec::new();
+        for (route_num, route_config) in route_configs.iter().enumerate() {
+            let name = route_config
+                .get("name")
+                .and_then(|n| n.as_str())
+                .ok_or_else(|| anyhow!("Route {} has no 'name'", route_num))?
+                .to_string();
+            // Route names become output subdirectories
+            ensure!(
+                !name.is_empty() && !name.contains('/') && name != "." && name != "..",
+                "Route {} has an invalid name {:?}",
+                route_num,
+                name
+            );
+            ensure!(routes.iter().all(|r| r.name != name), "Route names must be unique, {:?} is repeated", name);
+            let route_text_field = get_default(route_config, "text_field", text_field.clone());
+
+            let values = match route_config.get("values") {
+                Some(values) => Some(
+                    values
+                        .as_array()
+                        .ok_or_else(|| anyhow!("Route {:?}: 'values' must be a list", name))?
+                        .iter()
+                        .map(|v| match v {
+                            Value::String(s) => s.clone(),
+                            other => other.to_string(),
+                        })
+                        .collect::<HashSet<String>>(),
+                ),
+                None => None,
+            };
+            let when = match route_config.get("when") {
+                Some(when) => Some(
+                    PipelineProcessor::new(&json!({"text_field": route_text_field, "pipeline": [when]}))
+                        .map_err(|e| anyhow!("Route {:?} 'when': {}", name, e))?,
+                ),
+                None => None,
+            };
+            let default = get_default(route_config, "default", false);
+            let num_selectors = values.is_some() as usize + when.is_some() as usize + default as usize;
+            ensure!(
+                num_selectors == 1,
+                "Route {:?} needs exactly one of 'values', 'when' or 'default: true'",
+                name
+            );
+            ensure!(
+                values.is_none() || route_field.is_some(),
+                "Route {:?} matches on values, which needs a top-level 'route_field'",
+                name
+            );
+            ensure!(
+                !default || route_num == route_configs.len() - 1,
+                "The default route ({:?}) has to be the last one",
+                name
+            );
+
+            let pipeline = PipelineProcessor::new(&json!({
+                "text_field": route_text_field,
+                "stats_group_by": route_config.get("stats_group_by").cloned().unwrap_or(stats_group_by.clone()),
+                "pipeline": route_config.get("pipeline").cloned().unwrap_or(Value::Null),
+            }))
+            .map_err(|e| anyhow!("Route {:?}: {}", name, e))?;
+            routes.push(Route { name, values, when, default });
+            pipelines.push(pipeline);
+        }
+        Ok(Self { route_field, routes, pipelines })
+    }
+
+    pub fn route(&self, doc: Value) -> (Option<usize>, Value) {
+        // Index of the route this doc goes to (None: no route takes it), and the doc, as the 'when' steps hand it back
+        let field_value = self.route_field.as_ref().and_then(|route_field| match json_get(&doc, route_field) {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(Value::Null) | None => None,
+            Some(other) => Some(other.to_string()),
+        });
+        let mut doc = doc;
+        for (i, route) in self.routes.iter().enumerate() {
+            if route.default {
+                return (Some(i), doc);
+            }
+            if let Some(values) = &route.values {
+                if field_value.as_ref().is_some_and(|v| values.contains(v)) {
+                    return (Some(i), doc);
+                }
+                continue;
+            }
+            if let Some(when) = &route.when {
+                // A 'when' step that errors on a doc just doesn't match it
+                match when.process_owned(doc, &mut TimingInfo::new(), &mut FilterInfo::new(), &mut TextBytesInfo::new()) {
+                    Ok((usize::MAX, Some(kept))) => return (Some(i), kept),
+                    Ok((_, Some(rejected))) => doc = rejected,
+                    Ok((_, None)) => unreachable!("PipelineProcessor::process always returns the doc"),
+                    Err((_, input)) => doc = input,
+                }
+            }
+        }
+        (None, doc)
+    }
+
+    pub fn split_lines(&self, lines: Vec<String>, filename: &Path) -> (Vec<Vec<Value>>, Vec<String>, usize) {
+        /* Parses one file's lines and groups the docs by route (keeping their relative order). Also returns the
+        lines that aren't valid json, which no route gets, and how many docs no route took
+        */
+        let mut routed: Vec<Vec<Value>> = vec![Vec::new(); self.routes.len()];
+        let mut invalid: Vec<String> = Vec::new();
+        let mut unrouted = 0;
+        for doc in parse_lines(lines, filename) {
+            match doc.map(|doc| self.route(doc)) {
+                Ok((Some(i), doc)) => routed[i].push(doc),
+                Ok((None, _)) => unrouted += 1,
+                Err(line) => invalid.push(line),
+            }
+        }
+        (routed, invalid, unrouted)
     }
 }
 
@@ -265,6 +571,7 @@ New plan:
         (precached data is nice for things that need to be loaded like banlists or a fasttext classifier)
     - this is specified in the pipeline with the kwargs argument in the config yaml
     - signatures are always a (json, config) -> Result<Option<Value>, Error>
+    - diagnostics go to stderr (eprintln!): map --stdin writes its jsonl to stdout
 */
 
 pub trait DataProcessor {
@@ -300,6 +607,56 @@ impl DataProcessor for NonNullFilter {
 }
 
 
+#[derive(Serialize, Debug)]
+pub struct AnyFieldPresentFilter {
+    /* Keeps docs where any (mode: "any", default) or all (mode: "all") of fields are present.
+    A field counts as present if json_get finds it and it isn't null; with nonempty (default true),
+    strings that are empty or whitespace-only count as missing too.
+    e.g. fields: [text, content, body] drops docs that have no body under any of those names.
+    */
+    pub fields: Vec<String>,
+    pub mode: String,
+    pub nonempty: bool,
+}
+
+impl DataProcessor for AnyFieldPresentFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let fields: Vec<String> = json_get(config, "fields")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow!("any_field_present_filter requires a list of 'fields'"))?
+            .iter()
+            .map(|v| v.as_str().map(String::from).ok_or_else(|| anyhow!("fields must be strings, got {:?}", v)))
+            .collect::<Result<Vec<String>, Error>>()?;
+        ensure!(!fields.is_empty(), "any_field_present_filter requires at least one field");
+        let mode = get_default(config, "mode", String::from("any"));
+        ensure!(
+            ["any", "all"].contains(&mode.as_str()),
+            format!("mode must be one of {{any, all}} and not {:?}", mode)
+        );
+        let nonempty = get_default(config, "nonempty", true);
+        Ok(Self { fields, mode, nonempty })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let is_present = |field: &String| match json_get(&data, field) {
+            None | Some(Value::Null) => false,
+            Some(Value::String(s)) => !(self.nonempty && s.trim().is_empty()),
+            Some(_) => true,
+        };
+        let keep = if self.mode == "all" {
+            self.fields.iter().all(is_present)
+        } else {
+            self.fields.iter().any(is_present)
+        };
+        if keep {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+
 #[derive(Serialize, Debug)]
 pub struct TextLenFilter {
     // Filters to only keep docs that have text length in range [lower_bound, upper_bound]
@@ -332,36 +689,121 @@ impl DataProcessor for TextLenFilter {
 
 #[derive(Serialize, Debug)]
 pub struct AddIdModifier {
-    // Adds a uuidv4 value to the id_key field
+    /* Adds an id to the id_key field. id_type picks how it's made:
+        - uuid4: (default) a random uuid
+        - content_hash: xxh3-128 of source_field (same value as hash_annotator), so identical content gets identical ids across runs
+        - uuid5: a name-based uuid of source_field under namespace ("dns", "url", "oid", "x500" or any uuid string), also stable across runs
+    source_field defaults to the text field
+    */
     pub id_key: String,
+    pub id_type: String,
+    pub source_field: String,
+    #[serde(skip)]
+    pub namespace: Uuid,
 }
 impl DataProcessor for AddIdModifier {
     fn new(config: &Value) -> Result<Self, Error> {
         let id_key = get_default(config, "id_key", String::from("id"));
-        Ok(Self { id_key })
+        let id_type = get_default(config, "id_type", String::from("uuid4"));
+        ensure!(
+            ["uuid4", "content_hash", "uuid5"].contains(&id_type.as_str()),
+            format!("id_type must be one of {{uuid4, content_hash, uuid5}} and not {:?}", id_type)
+        );
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let source_field = get_default(config, "source_field", text_field);
+        let namespace = match get_default(config, "namespace", String::from("url")).as_str() {
+            "dns" => Uuid::NAMESPACE_DNS,
+            "url" => Uuid::NAMESPACE_URL,
+            "oid" => Uuid::NAMESPACE_OID,
+            "x500" => Uuid::NAMESPACE_X500,
+            other => Uuid::parse_str(other).map_err(|e| anyhow!("Bad uuid5 namespace {:?}: {}", other, e))?,
+        };
+        Ok(Self { id_key, id_type, source_field, namespace })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let id = Uuid::new_v4().to_string();
-        json_set(&mut data, &self.id_key, Value::String(id)).unwrap();
+        let id = match self.id_type.as_str() {
+            "uuid4" => Value::String(Uuid::new_v4().to_string()),
+            _ => {
+                let source = match json_get(&data, &self.source_field) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Null) | None => {
+                        return Err(anyhow!("Source field '{}' not found for id_type {}", self.source_field, self.id_type))
+                    }
+                    Some(other) => other.to_string(),
+                };
+                if self.id_type == "content_hash" {
+                    HashAnnotator::hash_text(&source, 128)
+                } else {
+                    Value::String(Uuid::new_v5(&self.namespace, source.as_bytes()).to_string())
+                }
+            }
+        };
+        json_set(&mut data, &self.id_key, id).unwrap();
         Ok(Some(data))
     }
 }
 
 
 #[derive(Serialize, Debug)]
-struct SubsampleFilter {
-    // Keeps a random subsample_rate fraction of the documens
-    subsample_rate: f64,
+pub struct SubsampleFilter {
+    /* Keeps a random subsample_rate fraction of the documents.
+    Without a seed, every draw comes from the thread-local rng, so the sample changes from run to run.
+    With a seed, seed_mode picks how the draws are made reproducible:
+    - per_doc: (default) the draw is a hash of (seed, id_field), like weighted_subsample_filter. Each doc's
+      fate depends only on its id, so re-runs keep exactly the same docs with any thread count.
+      Docs without an id hash their full json instead.
+    - per_worker: each rayon worker gets its own rng, seeded from (seed, worker index). Cheaper than hashing
+      ids and works without ids, but the result is only reproducible when every worker sees the same docs
+      in the same order -- i.e. at the same thread count *and* the same file-to-worker assignment, which
+      rayon's work stealing only guarantees with a single thread. Use per_doc if that matters.
+    Both modes are unbiased: each doc is kept independently with probability subsample_rate.
+    */
+    pub subsample_rate: f64,
+    pub seed: Option<u64>,
+    pub seed_mode: String,
+    pub id_field: String,
+    #[serde(skip)]
+    instance_id: u64, // keys this filter's per-worker rngs (several subsample steps may share a worker)
 }
+
+static SUBSAMPLE_INSTANCES: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static SUBSAMPLE_WORKER_RNGS: RefCell<HashMap<u64, StdRng>> = RefCell::new(HashMap::new());
+}
+
 impl DataProcessor for SubsampleFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let subsample_rate = get_default(config, "subsample_rate", 1.0 as f64);
-        Ok(Self { subsample_rate })
+        let seed = json_get(config, "seed").and_then(|v| v.as_u64());
+        let seed_mode = get_default(config, "seed_mode", String::from("per_doc"));
+        let id_field = get_default(config, "id_field", String::from("id"));
+        ensure!(
+            ["per_doc", "per_worker"].contains(&seed_mode.as_str()),
+            format!("seed_mode must be per_doc or per_worker, not {:?}", seed_mode)
+        );
+        let instance_id = SUBSAMPLE_INSTANCES.fetch_add(1, Ordering::Relaxed);
+        Ok(Self { subsample_rate, seed, seed_mode, id_field, instance_id })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
-        let random_float = rng().random::<f64>();
+        let random_float = match (self.seed, self.seed_mode.as_str()) {
+            (None, _) => rng().random::<f64>(),
+            (Some(seed), "per_doc") => {
+                let key = match json_get(&data, &self.id_field) {
+                    Some(id) => id.to_string(),
+                    None => data.to_string(),
+                };
+                xxh3_64_with_seed(key.as_bytes(), seed) as f64 / u64::MAX as f64
+            }
+            (Some(seed), _) => SUBSAMPLE_WORKER_RNGS.with(|rngs| {
+                rngs.borrow_mut()
+                    .entry(self.instance_id)
+                    .or_insert_with(|| SubsampleFilter::worker_rng(seed))
+                    .random::<f64>()
+            }),
+        };
         if random_float <= self.subsample_rate {
             Ok(Some(data))
         } else {
@@ -370,6 +812,67 @@ impl DataProcessor for SubsampleFilter {
     }
 }
 
+impl SubsampleFilter {
+    pub fn worker_rng(seed: u64) -> StdRng {
+        // Threads outside the rayon pool (e.g. map --stdin) count as worker 0.
+        // The worker index is hashed with the seed so neighbouring seeds don't give overlapping streams
+        let worker_idx = rayon::current_thread_index().unwrap_or(0) as u64;
+        StdRng::seed_from_u64(xxh3_64_with_seed(&worker_idx.to_le_bytes(), seed))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct WeightedSubsampleFilter {
+    /* Importance sampling: keeps each doc with probability min(1.0, weight * scale), where weight is read from weight_field.
+    Docs missing the weight use default_weight (so by default they are dropped).
+    If a seed is given, the keep/drop draw is a hash of (seed, id_field) instead of a random number,
+    so re-runs (with any thread count) keep exactly the same docs. Docs without an id hash their full json instead.
+    */
+    pub weight_field: String,
+    pub scale: f64,
+    pub default_weight: f64,
+    pub seed: Option<u64>,
+    pub id_field: String,
+}
+
+impl DataProcessor for WeightedSubsampleFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let weight_field = get_default(config, "weight_field", String::from("weight"));
+        let scale = get_default(config, "scale", 1.0);
+        let default_weight = get_default(config, "default_weight", 0.0);
+        let seed = json_get(config, "seed").and_then(|v| v.as_u64());
+        let id_field = get_default(config, "id_field", String::from("id"));
+        ensure!(scale >= 0.0, "scale must be >= 0");
+        Ok(Self { weight_field, scale, default_weight, seed, id_field })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let weight = match json_get(&data, &self.weight_field) {
+            Some(Value::Null) | None => self.default_weight,
+            Some(val) => val
+                .as_f64()
+                .ok_or_else(|| anyhow!("Weight field '{}' is not a number: {:?}", self.weight_field, val))?,
+        };
+        let keep_prob = (weight * self.scale).min(1.0);
+
+        let random_float = match self.seed {
+            Some(seed) => {
+                let key = match json_get(&data, &self.id_field) {
+                    Some(id) => id.to_string(),
+                    None => data.to_string(),
+                };
+                xxh3_64_with_seed(key.as_bytes(), seed) as f64 / u64::MAX as f64
+            }
+            None => rng().random::<f64>(),
+        };
+        if random_float < keep_prob {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 #[derive(Serialize)]
@@ -419,18 +922,12 @@ impl DataProcessor for UrlSubstringFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let case_sensitive = get_default(config, "case_sensitive", false);
 
-        let banlist_file = PathBuf::from(config.get("banlist_file").unwrap().as_str().unwrap());
-        let banlist_data = read_pathbuf_to_mem(&banlist_file).unwrap();
+        let banlist_file = PathBuf::from(get_required_str(config, "banlist_file")?);
+        let banlist_data = read_pathbuf_to_mem(&banlist_file)?;
         let banlist: HashSet<String> = banlist_data
             .lines()
-            .map(|line| {
-                if case_sensitive {
-                    line.unwrap().to_lowercase()
-                } else {
-                    line.unwrap()
-                }
-            })
-            .collect();
+            .map(|line| line.map(|line| if case_sensitive { line.to_lowercase() } else { line }))
+            .collect::<Result<_, _>>()?;
 
         UrlSubstringFilter::construct_w_explicit_banlist(config, banlist)
     }
@@ -541,12 +1038,12 @@ impl UrlSubstringFilter {
         config: &Value,
         banlist: HashSet<String>,
     ) -> Result<Self, Error> {
-        let url_key = config.get("url_key").unwrap().as_str().unwrap().to_string();
+        let url_key = get_required_str(config, "url_key")?;
         let alt_url_key = get_default(config, "alt_url_key", String::from("ALT_URL_KEY"));
         let ignore_chars = get_default(config, "ignore_chars", Vec::new())
             .into_iter()
-            .map(|el| el.as_str().unwrap().to_string())
-            .collect();
+            .map(|el| el.as_str().map(String::from).ok_or_else(|| anyhow!("ignore_chars must be strings")))
+            .collect::<Result<_, _>>()?;
         let num_banned_substrs = get_default(config, "num_banned_substrs", 1);
         let exact_domain_match = get_default(config, "exact_domain_match", false);
         let exact_subdomain_match = get_default(config, "exact_subdomain_match", false);
@@ -560,7 +1057,7 @@ impl UrlSubstringFilter {
                 None
             } else {
                 let banlist_vec: Vec<String> = banlist.clone().into_iter().map(|v| v).collect();
-                Some(AhoCorasick::new(banlist_vec).unwrap())
+                Some(AhoCorasick::new(banlist_vec)?)
             };
 
         let part_splitter = if exact_part_match {
@@ -587,19 +1084,124 @@ impl UrlSubstringFilter {
     }
 }
 
+
+#[derive(Derivative, Serialize)]
+#[derivative(Debug)]
+pub struct KeywordPresenceFilter {
+    /* Keeps docs whose text_field contains at least min_keywords distinct keywords (e.g. from a list of medical
+    terms, to build a topical subset). The inverse of a banlist: matching is what keeps a doc.
+    Keywords come from the inline list keywords and/or keywords_file (one per line, may be compressed); blank lines
+    are skipped and repeated keywords count once. A keyword occurring many times still counts once.
+    case_sensitive (default false) -- When false, both keywords and text are lowercased before matching
+    match_substrings (default false) -- When false, keywords must sit between word boundaries (as in
+        url_substring_filter's non-exact mode), so "cardio" doesn't match inside "cardiology"
+    */
+    pub text_field: String,
+    pub min_keywords: usize,
+    pub case_sensitive: bool,
+    pub match_substrings: bool,
+    #[derivative(Debug = "ignore")]
+    pub keywords: Vec<String>,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub ac_keywords: AhoCorasick,
+}
+
+impl DataProcessor for KeywordPresenceFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let min_keywords = get_default(config, "min_keywords", 1);
+        ensure!(min_keywords > 0, "keyword_presence_filter needs min_keywords >= 1");
+        let case_sensitive = get_default(config, "case_sensitive", false);
+        let match_substrings = get_default(config, "match_substrings", false);
+
+        let mut raw_keywords: Vec<String> = Vec::new();
+        if let Some(keywords) = json_get(config, "keywords") {
+            for keyword in keywords.as_array().ok_or_else(|| anyhow!("keywords must be a list of strings"))? {
+                let keyword = keyword.as_str().ok_or_else(|| anyhow!("keywords must be strings, got {:?}", keyword))?;
+                raw_keywords.push(keyword.to_string());
+            }
+        }
+        if let Some(keywords_file) = json_get(config, "keywords_file") {
+            let keywords_file = PathBuf::from(
+                keywords_file.as_str().ok_or_else(|| anyhow!("keywords_file must be a string"))?,
+            );
+            for line in read_pathbuf_to_mem(&keywords_file)?.lines() {
+                raw_keywords.push(line?);
+            }
+        }
+        let mut keywords: Vec<String> = raw_keywords
+            .into_iter()
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .map(|k| if case_sensitive { k } else { k.to_lowercase() })
+            .collect();
+        keywords.sort();
+        keywords.dedup();
+        ensure!(
+            !keywords.is_empty(),
+            "keyword_presence_filter needs at least one keyword (from keywords and/or keywords_file)"
+        );
+        ensure!(
+            min_keywords <= keywords.len(),
+            format!("min_keywords is {} but only {} distinct keywords were given", min_keywords, keywords.len())
+        );
+        let ac_keywords = AhoCorasick::new(&keywords)?;
+        Ok(Self { text_field, min_keywords, case_sensitive, match_substrings, keywords, ac_keywords })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field).and_then(|v| v.as_str()).unwrap_or("");
+        if self.count_keywords(text) >= self.min_keywords {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl KeywordPresenceFilter {
+    pub fn count_keywords(&self, text: &str) -> usize {
+        // Distinct keywords found, stopping early once min_keywords is reached
+        let text: Cow<str> = if self.case_sensitive { Cow::Borrowed(text) } else { Cow::Owned(text.to_lowercase()) };
+        let mut found: HashSet<usize> = HashSet::new();
+        // Overlapping search, so a keyword inside a longer matched one (e.g. "heart" in "heart attack") is found too
+        for mat in self.ac_keywords.find_overlapping_iter(text.as_ref()) {
+            if !self.match_substrings {
+                let is_start_boundary = !text[..mat.start()].chars().last().is_some_and(|c| c.is_alphanumeric());
+                let is_end_boundary = !text[mat.end()..].chars().next().is_some_and(|c| c.is_alphanumeric());
+                if !(is_start_boundary && is_end_boundary) {
+                    continue;
+                }
+            }
+            found.insert(mat.pattern().as_usize());
+            if found.len() >= self.min_keywords {
+                break;
+            }
+        }
+        found.len()
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct NewlineRemovalModifier {
     // Modifies the doc by controlling for maximum number of consecutive newlines
     pub text_field: String,
     pub max_consecutive: usize,
+    pub trim_leading: bool, // drop blank (whitespace-only) lines at the start of the doc, defaults to false
+    pub trim_trailing: bool, // drop blank lines (and the final newline) at the end of the doc, defaults to false
 }
 impl DataProcessor for NewlineRemovalModifier {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
         let max_consecutive = get_default(config, "max_consecutive", 2);
+        let trim_leading = get_default(config, "trim_leading", false);
+        let trim_trailing = get_default(config, "trim_trailing", false);
         Ok(Self {
             text_field,
             max_consecutive,
+            trim_leading,
+            trim_trailing,
         })
     }
 
@@ -612,6 +1214,7 @@ impl DataProcessor for NewlineRemovalModifier {
         let pattern = Regex::new(&format!(r"\n{{{},}}", self.max_consecutive + 1)).unwrap();
         let replacement = "\n".repeat(self.max_consecutive);
         let new_text = pattern.replace_all(&text, replacement.as_str()).to_string();
+        let new_text = NewlineRemovalModifier::trim_blank_lines(&new_text, self.trim_leading, self.trim_trailing).to_string();
         json_set(
             &mut data,
             &self.text_field,
@@ -623,69 +1226,307 @@ impl DataProcessor for NewlineRemovalModifier {
     }
 }
 
+impl NewlineRemovalModifier {
+    pub fn trim_blank_lines(text: &str, leading: bool, trailing: bool) -> &str {
+        // Whole lines only: indentation of the first kept line and trailing spaces of the last one are left alone
+        let mut text = text;
+        if leading {
+            while let Some(idx) = text.find('\n') {
+                if !text[..idx].trim().is_empty() {
+                    break;
+                }
+                text = &text[idx + 1..];
+            }
+        }
+        if trailing {
+            while let Some(idx) = text.rfind('\n') {
+                if !text[idx + 1..].trim().is_empty() {
+                    break;
+                }
+                text = &text[..idx];
+            }
+        }
+        if (leading || trailing) && text.trim().is_empty() {
+            return "";
+        }
+        text
+    }
+}
+
 #[derive(Serialize, Debug)]
-pub struct FastTextAnnotator {
-    // Enriches the data with the top k predictions from a fast text classifier
-    pub fast_text_file: String,
+pub struct WhitespaceNormalizeModifier {
+    // Normalizes whitespace in the doc:
+    // collapse_spaces: runs of spaces/tabs (any non-newline whitespace) -> a single space
+    // trim_lines: strips leading/trailing whitespace from each line
+    // collapse_blank_lines: runs of blank (whitespace-only) lines -> a single blank line
     pub text_field: String,
-    pub output_field: String,
-    pub k: i32,
-    pub threshold: f32,
+    pub collapse_spaces: bool, // defaults to true
+    pub trim_lines: bool, // defaults to true
+    pub collapse_blank_lines: bool, // defaults to true
     #[serde(skip)]
-    pub model: FastText,
-    pub max_text_length: usize,
+    pub space_regex: Regex,
 }
-
-impl DataProcessor for FastTextAnnotator {
+impl DataProcessor for WhitespaceNormalizeModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let fast_text_file = config
-            .get("fast_text_file")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
         let text_field = get_default(config, "text_field", String::from("text"));
-        let output_field = get_default(config, "output_field", String::from("metadata.fasttext"));
-        let k = get_default(config, "k", 10 as usize) as i32;
-        let threshold = get_default(config, "threshold", 0.0) as f32;
-        let mut model = FastText::new();
-        model.load_model(&fast_text_file).unwrap();
-        let max_text_length: usize = get_default(config, "max_text_length", 0);
+        let collapse_spaces = get_default(config, "collapse_spaces", true);
+        let trim_lines = get_default(config, "trim_lines", true);
+        let collapse_blank_lines = get_default(config, "collapse_blank_lines", true);
+        let space_regex = Regex::new(r"[^\S\n]+").unwrap();
         Ok(Self {
-            fast_text_file,
             text_field,
-            output_field,
-            k,
-            threshold,
-            model,
-            max_text_length,
+            collapse_spaces,
+            trim_lines,
+            collapse_blank_lines,
+            space_regex,
         })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let mut text = json_get(&data, &self.text_field)
+        let text = json_get(&data, &self.text_field)
             .unwrap()
             .as_str()
-            .unwrap()
-            .to_string()
-            .replace("\n", " ");
+            .unwrap();
 
-        // Trim text if max_text_length is set, avoiding cutting on multi-byte characters
-        if self.max_text_length > 0 && text.len() > self.max_text_length {
-            let mut end = self.max_text_length;
-            while end > 0 && !text.is_char_boundary(end) {
-                end -= 1;
+        let mut lines: Vec<String> = Vec::new();
+        let mut prev_blank = false;
+        for line in text.split('\n') {
+            let mut line = if self.collapse_spaces {
+                self.space_regex.replace_all(line, " ").to_string()
+            } else {
+                line.to_string()
+            };
+            if self.trim_lines {
+                line = line.trim().to_string();
             }
-            text.truncate(end);
+            let is_blank = line.trim().is_empty();
+            if self.collapse_blank_lines && is_blank && prev_blank {
+                continue;
+            }
+            prev_blank = is_blank;
+            lines.push(line);
         }
+        let new_text = lines.join("\n");
+        json_set(
+            &mut data,
+            &self.text_field,
+            serde_json::Value::String(new_text),
+        )
+        .unwrap();
 
-        text.push_str("\n");
+        Ok(Some(data))
+    }
+}
 
-        let predictions = match self.model.predict(&text, self.k, self.threshold) {
-            Ok(preds) => preds,
-            Err(_e) => {
-                // If prediction fails, drop this document by returning None, this can happen for some bad utf bytes etc that happen very rarely
-                return Ok(None);
+
+// Windows-1252 chars for bytes 0x80..=0x9F ('\0' = undefined in cp1252, usually passed through as the C1 control)
+const CP1252_HIGH: [char; 32] = [
+    '€', '\0', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\0', 'Ž', '\0',
+    '\0', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\0', 'ž', 'Ÿ',
+];
+
+#[derive(Serialize, Debug)]
+pub struct FixEncodingModifier {
+    // Repairs common encoding damage in the text (ftfy-style):
+    // fix_mojibake: UTF-8 that was decoded as Windows-1252/Latin-1, e.g. "â€™" -> "’" (repeated for double encodings)
+    // fix_c1_controls: leftover C1 control chars (U+0080..U+009F) -> the Windows-1252 char they stand for
+    // remove_bom: drops byte order marks (U+FEFF)
+    // fix_line_breaks: \r\n, \r, U+0085, U+2028 and U+2029 -> \n
+    // Only complete, valid UTF-8 sequences are repaired, so clean text is left alone
+    pub text_field: String,
+    pub fix_mojibake: bool, // defaults to true
+    pub fix_c1_controls: bool, // defaults to true
+    pub remove_bom: bool, // defaults to true
+    pub fix_line_breaks: bool, // defaults to false
+}
+
+impl DataProcessor for FixEncodingModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let fix_mojibake = get_default(config, "fix_mojibake", true);
+        let fix_c1_controls = get_default(config, "fix_c1_controls", true);
+        let remove_bom = get_default(config, "remove_bom", true);
+        let fix_line_breaks = get_default(config, "fix_line_breaks", false);
+        Ok(Self {
+            text_field,
+            fix_mojibake,
+            fix_c1_controls,
+            remove_bom,
+            fix_line_breaks,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+
+        let new_text = self.fix_text(text);
+        json_set(&mut data, &self.text_field, Value::String(new_text)).unwrap();
+        Ok(Some(data))
+    }
+}
+
+impl FixEncodingModifier {
+    pub fn fix_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.remove_bom {
+            text = text.replace('\u{FEFF}', "");
+        }
+        if self.fix_mojibake {
+            // A few passes handle text that was mis-decoded more than once
+            for _ in 0..3 {
+                let fixed = Self::fix_mojibake_once(&text);
+                if fixed == text {
+                    break;
+                }
+                text = fixed;
+            }
+        }
+        if self.fix_c1_controls {
+            text = text
+                .chars()
+                .map(|c| match c as u32 {
+                    0x80..=0x9F => match CP1252_HIGH[c as usize - 0x80] {
+                        '\0' => c,
+                        mapped => mapped,
+                    },
+                    _ => c,
+                })
+                .collect();
+        }
+        if self.fix_line_breaks {
+            text = text
+                .replace("\r\n", "\n")
+                .replace(['\r', '\u{0085}', '\u{2028}', '\u{2029}'], "\n");
+        }
+        text
+    }
+
+    fn mojibake_byte(c: char) -> Option<u8> {
+        // The byte that c would have come from if it was decoded as Windows-1252 (or Latin-1); non-ASCII only
+        match c as u32 {
+            0x80..=0xFF => Some(c as u8),
+            _ => CP1252_HIGH
+                .iter()
+                .position(|&high| high != '\0' && high == c)
+                .map(|i| 0x80 + i as u8),
+        }
+    }
+
+    fn fix_mojibake_once(text: &str) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut output = String::with_capacity(text.len());
+        let mut i = 0;
+        while i < chars.len() {
+            // Look for a lead byte followed by the right number of continuation bytes that decode as UTF-8
+            let seq_len = match Self::mojibake_byte(chars[i]) {
+                Some(0xC2..=0xDF) => 2,
+                Some(0xE0..=0xEF) => 3,
+                Some(0xF0..=0xF4) => 4,
+                _ => 0,
+            };
+            if seq_len > 0 && i + seq_len <= chars.len() {
+                let bytes: Option<Vec<u8>> = chars[i..i + seq_len].iter().map(|&c| Self::mojibake_byte(c)).collect();
+                if let Some(Ok(decoded)) = bytes.as_deref().map(std::str::from_utf8) {
+                    output.push_str(decoded);
+                    i += seq_len;
+                    continue;
+                }
+            }
+            output.push(chars[i]);
+            i += 1;
+        }
+        output
+    }
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct FastTextAnnotator {
+    // Enriches the data with the top k predictions from a fast text classifier
+    pub fast_text_file: String,
+    pub text_field: String,
+    pub output_field: String,
+    pub k: i32,
+    pub threshold: f32,
+    #[serde(skip)]
+    pub model: FastText,
+    pub max_text_length: usize, // max bytes of text to score (0 = no limit)
+    pub max_words: usize, // only score the first max_words words (0 = no limit)
+    pub max_chars: usize, // only score the first max_chars characters (0 = no limit)
+    pub on_error: String, // what to do when prediction fails: drop | keep | annotate_empty | route_err
+}
+
+impl DataProcessor for FastTextAnnotator {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let fast_text_file = get_required_str(config, "fast_text_file")?;
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let output_field = get_default(config, "output_field", String::from("metadata.fasttext"));
+        let k = get_default(config, "k", 10 as usize) as i32;
+        let threshold = get_default(config, "threshold", 0.0) as f32;
+        let model = load_fasttext_model(&fast_text_file)?;
+        let max_text_length: usize = get_default(config, "max_text_length", 0);
+        let max_words: usize = get_default(config, "max_words", 0);
+        let max_chars: usize = get_default(config, "max_chars", 0);
+        let on_error = get_default(config, "on_error", String::from("drop"));
+        ensure!(
+            ["drop", "keep", "annotate_empty", "route_err"].contains(&on_error.as_str()),
+            format!("on_error must be one of {{drop, keep, annotate_empty, route_err}} and not {:?}", on_error)
+        );
+        Ok(Self {
+            fast_text_file,
+            text_field,
+            output_field,
+            k,
+            threshold,
+            model,
+            max_text_length,
+            max_words,
+            max_chars,
+            on_error,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let mut text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string()
+            .replace("\n", " ");
+
+        // Only the head of the text is scored; the stored text field is left untouched
+        if self.max_words > 0 {
+            text = text.split_whitespace().take(self.max_words).collect::<Vec<_>>().join(" ");
+        }
+        if self.max_chars > 0 {
+            if let Some((end, _)) = text.char_indices().nth(self.max_chars) {
+                text.truncate(end);
+            }
+        }
+
+        // Trim text if max_text_length is set, avoiding cutting on multi-byte characters
+        if self.max_text_length > 0 {
+            let end = truncate_at_byte_boundary(&text, self.max_text_length).len();
+            text.truncate(end);
+        }
+
+        text.push_str("\n");
+
+        let predictions = match self.model.predict(&text, self.k, self.threshold) {
+            Ok(preds) => preds,
+            Err(e) => {
+                // Prediction fails very rarely (e.g. on NUL/bad bytes in the text); on_error decides what happens to the doc
+                return match self.on_error.as_str() {
+                    "keep" => Ok(Some(data)),
+                    "annotate_empty" => {
+                        json_set(&mut data, &self.output_field, json!({})).unwrap();
+                        Ok(Some(data))
+                    }
+                    "route_err" => Err(anyhow!("FastText prediction failed: {}", e)),
+                    _ => Ok(None),
+                };
             }
         };
 
@@ -699,6 +1540,89 @@ impl DataProcessor for FastTextAnnotator {
     }
 }
 
+pub fn fasttext_top1(model: &FastText, text: &str) -> Result<Option<(String, f32)>, Error> {
+    // Top-1 (label, prob) of a fasttext model over text (newlines flattened), or None if it predicts nothing
+    let preds = model
+        .predict(&text.replace("\n", " "), 1, 0.0)
+        .map_err(|e| anyhow!("FastText prediction failed: {}", e))?;
+    Ok(preds
+        .into_iter()
+        .max_by(|a, b| a.prob.partial_cmp(&b.prob).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|pred| (pred.label, pred.prob)))
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct LanguageAgreementFilter {
+    /* Drops docs whose text is confidently predicted to be in a different language than the one
+    declared in declared_lang_field (e.g. crawl metadata or an html lang attribute).
+    Labels are compared without fasttext's "__label__" prefix and case-insensitively, so a
+    declared "EN" agrees with a predicted "__label__en".
+    */
+    pub fast_text_file: String,
+    pub text_field: String,
+    pub declared_lang_field: String,
+    pub threshold: f32, // mismatches are only dropped if the predicted language has at least this probability
+    pub on_missing: String, // what to do with docs without a declared language: keep | remove
+    #[serde(skip)]
+    pub model: FastText,
+}
+
+impl DataProcessor for LanguageAgreementFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let fast_text_file = config
+            .get("fast_text_file")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("language_agreement_filter requires a fast_text_file"))?
+            .to_string();
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let declared_lang_field = get_default(config, "declared_lang_field", String::from("metadata.language"));
+        let threshold = get_default(config, "threshold", 0.5) as f32;
+        ensure!(
+            (0.0..=1.0).contains(&threshold),
+            format!("threshold must be in [0, 1], not {:?}", threshold)
+        );
+        let on_missing = get_default(config, "on_missing", String::from("keep"));
+        ensure!(
+            ["keep", "remove"].contains(&on_missing.as_str()),
+            format!("on_missing must be one of {{keep, remove}} and not {:?}", on_missing)
+        );
+        let model = load_fasttext_model(&fast_text_file)?;
+        Ok(Self {
+            fast_text_file,
+            text_field,
+            declared_lang_field,
+            threshold,
+            on_missing,
+            model,
+        })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let declared = match json_get(&data, &self.declared_lang_field).and_then(|v| v.as_str()) {
+            Some(lang) if !lang.trim().is_empty() => lang.to_string(),
+            _ => {
+                return Ok(if self.on_missing == "keep" { Some(data) } else { None });
+            }
+        };
+        let text = json_get(&data, &self.text_field).and_then(|v| v.as_str()).unwrap_or("");
+        let Some((predicted, prob)) = fasttext_top1(&self.model, text)? else {
+            return Ok(Some(data));
+        };
+        if prob >= self.threshold && !Self::languages_agree(&declared, &predicted) {
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+}
+
+impl LanguageAgreementFilter {
+    pub fn languages_agree(declared: &str, predicted: &str) -> bool {
+        let normalize = |lang: &str| lang.trim().trim_start_matches("__label__").to_lowercase();
+        normalize(declared) == normalize(predicted)
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct FloatFilter {
     // Filters to only keep docs that have float in doc.float_field in range [lower_bound, upper_bound] (or ![lower_bound, upper_bound])
@@ -711,12 +1635,7 @@ pub struct FloatFilter {
 
 impl DataProcessor for FloatFilter {
     fn new(config: &Value) -> Result<Self, Error> {
-        let float_field = config
-            .get("float_field")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
+        let float_field = get_required_str(config, "float_field")?;
         let lower_bound = get_default(config, "lower_bound", 0.0 as f64) as f32;
         let upper_bound = get_default(config, "upper_bound", f32::MAX as f64) as f32;
         let negate = get_default(config, "negate", false);
@@ -765,18 +1684,8 @@ pub struct StringEqFilter {
 
 impl DataProcessor for StringEqFilter {
     fn new(config: &Value) -> Result<Self, Error> {
-        let str_field = config
-            .get("str_field")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let eq = config
-            .get("eq")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
+        let str_field = get_required_str(config, "str_field")?;
+        let eq = get_required_str(config, "eq")?;
         let keep_matches = get_default(config, "keep_matches", true);
 
         Ok(Self {str_field, eq, keep_matches})
@@ -793,6 +1702,183 @@ impl DataProcessor for StringEqFilter {
 }
 
 
+#[derive(Default)]
+pub struct NumericIdSet {
+    // Integer ids, kept as native ints (no string conversion). Non-negative ids live in a u64 set so that
+    // full-range u64 ids (e.g. 64-bit hashes) fit, negative ones in an i64 set
+    pub unsigned: HashSet<u64>,
+    pub signed: HashSet<i64>,
+}
+
+impl NumericIdSet {
+    pub fn from_file(path: &PathBuf) -> Result<Self, Error> {
+        // One integer per line (blank lines skipped); the file may be compressed
+        let mut ids = NumericIdSet::default();
+        for (line_num, line) in read_pathbuf_to_mem(path)?.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Ok(id) = line.parse::<u64>() {
+                ids.unsigned.insert(id);
+            } else if let Ok(id) = line.parse::<i64>() {
+                ids.signed.insert(id);
+            } else {
+                bail!("Line {} of {:?} is not an integer: {:?}", line_num + 1, path, line);
+            }
+        }
+        Ok(ids)
+    }
+
+    pub fn len(&self) -> usize {
+        self.unsigned.len() + self.signed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, val: &Value) -> Option<bool> {
+        // None if the value isn't an integer at all
+        if let Some(id) = val.as_u64() {
+            Some(self.unsigned.contains(&id))
+        } else {
+            val.as_i64().map(|id| self.signed.contains(&id))
+        }
+    }
+}
+
+fn numeric_list_config(config: &Value, filter_name: &str, default_on_null: &str) -> Result<(String, PathBuf, String), Error> {
+    let field = json_get(config, "field")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("{} requires a string 'field'", filter_name))?
+        .to_string();
+    let list_file = json_get(config, "list_file")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("{} requires a 'list_file'", filter_name))?;
+    let on_null = get_default(config, "on_null", String::from(default_on_null));
+    ensure!(
+        ["keep", "remove"].contains(&on_null.as_str()),
+        format!("on_null must be one of {{keep, remove}} and not {:?}", on_null)
+    );
+    Ok((field, PathBuf::from(list_file), on_null))
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct NumericAllowListFilter {
+    /* Keeps only docs whose integer field is in the set of ids loaded from list_file (one integer per line).
+    on_null decides what happens to docs where the field is missing, null or not an integer:
+    remove (default) or keep.
+    */
+    pub field: String,
+    pub on_null: String,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub ids: NumericIdSet, // Key for this is list_file
+}
+
+impl DataProcessor for NumericAllowListFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let (field, list_file, on_null) = numeric_list_config(config, "numeric_allow_list_filter", "remove")?;
+        let ids = NumericIdSet::from_file(&list_file)?;
+        Ok(Self { field, on_null, ids })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let keep = match json_get(&data, &self.field).and_then(|v| self.ids.contains(v)) {
+            Some(in_list) => in_list,
+            None => self.on_null == "keep",
+        };
+        if keep {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct NumericDenyListFilter {
+    /* Removes docs whose integer field is in the set of ids loaded from list_file (one integer per line).
+    on_null decides what happens to docs where the field is missing, null or not an integer:
+    keep (default) or remove.
+    */
+    pub field: String,
+    pub on_null: String,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub ids: NumericIdSet, // Key for this is list_file
+}
+
+impl DataProcessor for NumericDenyListFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let (field, list_file, on_null) = numeric_list_config(config, "numeric_deny_list_filter", "keep")?;
+        let ids = NumericIdSet::from_file(&list_file)?;
+        Ok(Self { field, on_null, ids })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let keep = match json_get(&data, &self.field).and_then(|v| self.ids.contains(v)) {
+            Some(in_list) => !in_list,
+            None => self.on_null == "keep",
+        };
+        if keep {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct RegexFilter {
+    // Document-level regex analog of string_eq_filter: keeps/drops docs based on whether the regex
+    // matches anywhere in the field. Missing or non-string fields count as "no match"
+    pub field: String, // defaults to text_field (which defaults to text)
+    pub regex_string: String,
+    pub keep_matches: bool, // defaults to true, which means we keep docs that match; o/w docs that don't
+    pub case_insensitive: bool, // defaults to false
+    #[serde(skip)]
+    pub regex: Regex,
+}
+
+impl DataProcessor for RegexFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let field = get_default(config, "field", text_field);
+        let regex_string = json_get(config, "regex_string")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("regex_filter requires a regex_string"))?
+            .to_string();
+        let keep_matches = get_default(config, "keep_matches", true);
+        let case_insensitive = get_default(config, "case_insensitive", false);
+        let regex = RegexBuilder::new(&regex_string)
+            .case_insensitive(case_insensitive)
+            .build()?;
+
+        Ok(Self { field, regex_string, keep_matches, case_insensitive, regex })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let is_match = match json_get(&data, &self.field) {
+            Some(Value::String(val)) => self.regex.is_match(val),
+            _ => false,
+        };
+
+        if is_match == self.keep_matches {
+            return Ok(Some(data));
+        }
+        Ok(None)
+    }
+}
+
+
 #[derive(Serialize, Debug)]
 pub struct RegexTextFilter {
     // Filter lines to only keep lines that match the regex
@@ -809,7 +1895,7 @@ impl DataProcessor for RegexTextFilter {
         let text_field = get_default(config, "text_field", String::from("text"));
         let regex_string = get_default(config, "regex_string", String::from(""));
         let remove_matches = get_default(config, "remove_matches", true);
-        let regex = Regex::new(&regex_string).unwrap();
+        let regex = Regex::new(&regex_string)?;
 
         Ok(Self {
             text_field,
@@ -993,36 +2079,29 @@ impl PageLenFilter {
 
 
 #[derive(Serialize, Debug)]
-pub struct WordLenFilter {
-    // Filters according to average word length
+pub struct SentenceCountFilter {
+    // Filters to only keep docs whose number of sentences is in [lower_bound, upper_bound].
+    // Unlike page_len_filter's sentence mode (which counts .!? characters), sentences come from split_sentences
     pub text_field: String,
-    pub lower_bound: f32,
-    pub upper_bound: f32,
+    pub lower_bound: usize, // defaults to 1
+    pub upper_bound: usize, // defaults to usize::MAX
 }
 
-impl DataProcessor for WordLenFilter {
+impl DataProcessor for SentenceCountFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let lower_bound = get_default(config, "lower_bound", 0.0 as f64) as f32;
-        let upper_bound = get_default(config, "upper_bound", f32::MAX as f64) as f32;
-        Ok(Self {
-            text_field,
-            lower_bound,
-            upper_bound,
-        })
+        let lower_bound = get_default(config, "lower_bound", 1_usize);
+        let upper_bound = get_default(config, "upper_bound", usize::MAX);
+        ensure!(lower_bound <= upper_bound, format!("lower_bound ({}) must be <= upper_bound ({})", lower_bound, upper_bound));
+        Ok(Self { text_field, lower_bound, upper_bound })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let word_lens: Vec<usize> = text.split_whitespace().map(|v| v.len()).collect();
-
-        let avg_word_len = word_lens.iter().sum::<usize>() as f32 / word_lens.len() as f32;
-
-        if self.lower_bound <= avg_word_len && avg_word_len <= self.upper_bound {
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        let num_sentences = split_sentences(text).len();
+        if self.lower_bound <= num_sentences && num_sentences <= self.upper_bound {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1030,44 +2109,42 @@ impl DataProcessor for WordLenFilter {
     }
 }
 
+
 #[derive(Serialize, Debug)]
-pub struct SymbolRatioFilter {
-    // Filters the doc by how many symbols (see symbols var) appear relative to other words
+pub struct AvgSentenceLengthFilter {
+    /* Keeps docs whose average sentence length (whitespace-separated words per sentence) is in [lower_bound, upper_bound].
+    Sentences come from madlad_split_sentences. Very short averages flag fragment/list-like text, very long ones run-ons.
+    on_empty decides what happens to docs with no sentences at all (empty or whitespace-only text): remove (default) or keep
+    */
     pub text_field: String,
-    pub max_symbol_to_word_ratio: f32,
+    pub lower_bound: f64, // defaults to 0
+    pub upper_bound: f64, // defaults to f64::MAX
+    pub on_empty: String,
 }
 
-impl DataProcessor for SymbolRatioFilter {
+impl DataProcessor for AvgSentenceLengthFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let max_symbol_to_word_ratio =
-            get_default(config, "max_symbol_to_word_ratio", f32::MAX as f64) as f32;
-        Ok(Self {
-            text_field,
-            max_symbol_to_word_ratio,
-        })
+        let lower_bound = get_default(config, "lower_bound", 0.0);
+        let upper_bound = get_default(config, "upper_bound", f64::MAX);
+        ensure!(lower_bound <= upper_bound, format!("lower_bound ({}) must be <= upper_bound ({})", lower_bound, upper_bound));
+        let on_empty = get_default(config, "on_empty", String::from("remove"));
+        ensure!(
+            ["keep", "remove"].contains(&on_empty.as_str()),
+            format!("on_empty must be one of {{keep, remove}} and not {:?}", on_empty)
+        );
+        Ok(Self { text_field, lower_bound, upper_bound, on_empty })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let symbols = vec!["#", "...", ". . .", "\u{2026}"];
-        let mut num_symbols = 0;
-        for symbol in symbols.iter() {
-            num_symbols += text.matches(symbol).count();
-        }
-
-        let num_words = text
-            .replace(". . .", "...")
-            .split_whitespace()
-            .collect::<Vec<_>>()
-            .len();
-        let symbol_to_word_ratio = num_symbols as f32 / std::cmp::max(num_words, 1) as f32;
-
-        if symbol_to_word_ratio <= self.max_symbol_to_word_ratio {
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        let keep = match AvgSentenceLengthFilter::avg_sentence_length(text) {
+            Some(avg) => self.lower_bound <= avg && avg <= self.upper_bound,
+            None => self.on_empty == "keep",
+        };
+        if keep {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1075,81 +2152,114 @@ impl DataProcessor for SymbolRatioFilter {
     }
 }
 
-#[derive(Serialize, Debug)]
-pub struct BulletFilter {
-    // Filters the doc by how many lines starting with bullets appear relative to other lines
-    pub text_field: String,
-    pub max_bullet_ratio: f32,
+impl AvgSentenceLengthFilter {
+    pub fn avg_sentence_length(text: &str) -> Option<f64> {
+        // None if the text has no sentences
+        let sentences = madlad_split_sentences(text);
+        if sentences.is_empty() {
+            return None;
+        }
+        let num_words: usize = sentences.iter().map(|s| s.split_whitespace().count()).sum();
+        Some(num_words as f64 / sentences.len() as f64)
+    }
 }
 
-impl DataProcessor for BulletFilter {
-    fn new(config: &Value) -> Result<Self, Error> {
-        let text_field = get_default(config, "text_field", String::from("text"));
-        let max_bullet_ratio = get_default(config, "max_bullet_ratio", f32::MAX as f64) as f32;
-        Ok(Self {
-            text_field,
-            max_bullet_ratio,
-        })
+// Lowercased, without the final period. A sentence break right after one of these (or after a single capital
+// letter, i.e. an initial) is not a sentence end. Sentence-final "etc." is common, so it's not in here
+const SENTENCE_ABBREVIATIONS: &[&str] = &[
+    // Only words that (practically) never end a sentence: "no", "co", "ed", "al", "mar", "dec", ... often do
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "mt", "hon", "gov", "sen", "rep", "lt", "sgt", "capt", "vs",
+    "fig", "figs", "eq", "vol", "pp", "eds", "approx", "dept", "univ", "inc", "ltd", "corp", "jan", "feb",
+    "apr", "jun", "jul", "aug", "sept", "oct", "nov", "e.g", "i.e", "cf",
+];
+
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    /* Splits text into (trimmed, non-empty) sentences.
+    Uses unicode (UAX #29) sentence boundaries, which already handle decimals ("$3.50"), lowercase continuations
+    and CJK sentence punctuation, and then re-joins boundaries that directly follow an abbreviation or an initial,
+    so "Dr. Smith paid $3.50." is one sentence (but "The answer is no. We left." and "So did I. Then we left." are two)
+    */
+    let mut sentences: Vec<&str> = Vec::new();
+    let mut start = 0;
+    for (idx, segment) in text.split_sentence_bound_indices() {
+        let end = idx + segment.len();
+        let candidate = text[start..end].trim();
+        if end < text.len() && ends_with_abbreviation(candidate, &text[end..]) {
+            continue;
+        }
+        if !candidate.is_empty() {
+            sentences.push(candidate);
+        }
+        start = end;
     }
+    sentences
+}
 
-    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
-        let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let lines: Vec<&str> = text.split('\n').collect();
-        let bullet_count = lines
-            .iter()
-            .filter(|line| {
-                line.starts_with('●')
-                    || line.starts_with('•')
-                    || line.starts_with('*')
-                    || line.starts_with('-')
-            })
-            .count();
-        if bullet_count as f32 / lines.len() as f32 > self.max_bullet_ratio {
-            Ok(None)
-        } else {
-            Ok(Some(data))
+static MADLAD_SENTENCE_SPLITTER: Lazy<Regex> = Lazy::new(|| Regex::new(r"[.!?]+\s+").unwrap());
+
+pub fn madlad_split_sentences(text: &str) -> Vec<&str> {
+    // The cruder splitter from the Madlad-400 paper: breaks after runs of .!? followed by whitespace (dropping both),
+    // and skips empty pieces. Kept as is for the Madlad annotator, whose rule thresholds assume it
+    MADLAD_SENTENCE_SPLITTER
+        .split(text)
+        .filter(|s| !s.trim().is_empty())
+        .collect()
+}
+
+fn ends_with_abbreviation(sentence: &str, rest: &str) -> bool {
+    // Whether the sentence's final period belongs to an abbreviation (given the text that follows it)
+    let Some(head) = sentence.strip_suffix('.') else {
+        return false;
+    };
+    let last_word = head
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or("")
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    let next_char = rest.trim_start().chars().next();
+    let mut chars = last_word.chars();
+    match (chars.next(), chars.next()) {
+        // An initial ("J. Smith"), but not the pronoun
+        (Some(c), None) => c.is_uppercase() && c != 'I',
+        (Some(_), Some(_)) => {
+            let word = last_word.to_lowercase();
+            match word.as_str() {
+                // Only before what they abbreviate: "No. 5", "St. Louis"
+                "no" | "nos" => next_char.is_some_and(|c| c.is_ascii_digit()),
+                "st" => next_char.is_some_and(|c| c.is_uppercase()),
+                _ => SENTENCE_ABBREVIATIONS.contains(&word.as_str()),
+            }
         }
+        _ => false,
     }
 }
 
+
 #[derive(Serialize, Debug)]
-pub struct EllipsisLineRatioFilter {
-    // Filters the doc by what fraction of lines end with an ellipsis
+pub struct NonEmptyLineFilter {
+    // Filters to only keep docs whose number of non-blank lines (lines with any non-whitespace) is in [lower_bound, upper_bound]
+    // (page_len_filter's line mode counts blank lines too, so mostly-blank docs can slip through)
     pub text_field: String,
-    pub max_ratio: f32,
+    pub lower_bound: usize, // defaults to 1
+    pub upper_bound: usize, // defaults to usize::MAX
 }
 
-impl DataProcessor for EllipsisLineRatioFilter {
+impl DataProcessor for NonEmptyLineFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let max_ratio = get_default(config, "max_ratio", f32::MAX as f64) as f32;
-        Ok(Self {
-            text_field,
-            max_ratio,
-        })
+        let lower_bound = get_default(config, "lower_bound", 1_usize);
+        let upper_bound = get_default(config, "upper_bound", usize::MAX);
+        ensure!(lower_bound <= upper_bound, "lower_bound must be <= upper_bound");
+        Ok(Self { text_field, lower_bound, upper_bound })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let lines: Vec<&str> = text.lines().filter(|line| line.len() > 0).collect();
-
-        let ellipsis_count = lines
-            .iter()
-            .filter(|line| {
-                line.ends_with("...") || line.ends_with(". . .") || line.ends_with("\u{2026}")
-            })
-            .count();
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
 
-        let ratio = ellipsis_count as f32 / std::cmp::max(lines.len(), 1) as f32;
-        if ratio <= self.max_ratio {
+        let num_lines = text.lines().filter(|line| !line.trim().is_empty()).count();
+        if self.lower_bound <= num_lines && num_lines <= self.upper_bound {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1157,43 +2267,32 @@ impl DataProcessor for EllipsisLineRatioFilter {
     }
 }
 
+
 #[derive(Serialize, Debug)]
-pub struct AlphabeticWordRatioFilter {
-    // Filters the doc by what fraction of words are NOT alphanumeric
+pub struct NonAsciiRatioFilter {
+    // Cheap first-pass language signal: keeps docs whose fraction of non-ASCII chars is in [min_ratio, max_ratio]
+    // (e.g. max_ratio 0.1 for an English-heavy subset, min_ratio 0.5 for CJK). Empty text has a ratio of 0
     pub text_field: String,
-    pub max_ratio: f32,
+    pub min_ratio: f32, // defaults to 0.0
+    pub max_ratio: f32, // defaults to 1.0
 }
 
-impl DataProcessor for AlphabeticWordRatioFilter {
+impl DataProcessor for NonAsciiRatioFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let max_ratio = get_default(config, "max_ratio", f32::MAX as f64) as f32;
-        Ok(Self {
-            text_field,
-            max_ratio,
-        })
+        let min_ratio = get_default(config, "min_ratio", 0.0) as f32;
+        let max_ratio = get_default(config, "max_ratio", 1.0) as f32;
+        ensure!(min_ratio <= max_ratio, "min_ratio must be <= max_ratio");
+        Ok(Self { text_field, min_ratio, max_ratio })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let words = text.split_whitespace().collect::<Vec<_>>();
-        if words.len() == 1 {
-            return Ok(None);
-        }
-        let total_words = words.len() as f32;
-        let non_alpha_words = words
-            .into_iter()
-            .filter(|w| !w.chars().any(|c| c.is_alphabetic()))
-            .collect::<Vec<_>>()
-            .len();
-
-        let ratio = non_alpha_words as f32 / total_words;
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
 
-        if ratio <= self.max_ratio {
+        let ratio = NonAsciiRatioFilter::non_ascii_ratio(text);
+        if self.min_ratio <= ratio && ratio <= self.max_ratio {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1201,51 +2300,46 @@ impl DataProcessor for AlphabeticWordRatioFilter {
     }
 }
 
+impl NonAsciiRatioFilter {
+    pub fn non_ascii_ratio(text: &str) -> f32 {
+        let (total, non_ascii) = text
+            .chars()
+            .fold((0, 0), |(total, non_ascii), c| (total + 1, non_ascii + !c.is_ascii() as usize));
+        if total == 0 {
+            0.0
+        } else {
+            non_ascii as f32 / total as f32
+        }
+    }
+}
+
 
 #[derive(Serialize, Debug)]
-pub struct StopWordFilter {
+pub struct UppercaseRatioFilter {
+    // Whole-doc gate for SHOUTING spam and all-caps navigation dumps: removes docs where uppercase letters make up more
+    // than max_ratio of all cased letters (so caseless scripts like CJK don't count either way).
+    // Docs with fewer than min_letters (or no) cased letters are always kept, so short strings like "FAQ" aren't penalized
     pub text_field: String,
-    pub count_unique: bool,
-    pub min_stop_word: usize,
-    // Use &'static str for better performance
-    pub stop_words: HashSet<&'static str>,
+    pub max_ratio: f32,     // defaults to 0.5
+    pub min_letters: usize, // defaults to 20
 }
 
-impl DataProcessor for StopWordFilter {
+impl DataProcessor for UppercaseRatioFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let count_unique = get_default(config, "count_unique", false);
-        let min_stop_word = get_default(config, "min_stop_word", 2);
-
-        // Use &'static str to avoid String allocations
-        let stop_words: HashSet<&'static str> =
-            ["the", "be", "to", "of", "and", "that", "have", "with"]
-            .into_iter()
-            .collect();
-
-        Ok(Self {
-            text_field,
-            count_unique,
-            min_stop_word,
-            stop_words,
-        })
+        let max_ratio = get_default(config, "max_ratio", 0.5) as f32;
+        let min_letters = get_default(config, "min_letters", 20);
+        ensure!((0.0..=1.0).contains(&max_ratio), "max_ratio must be in [0, 1], got {:?}", max_ratio);
+        Ok(Self { text_field, max_ratio, min_letters })
     }
 
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
-        // Early return optimization
-        if self.min_stop_word == 0 {
-            return Ok(Some(data));
-        }
-
-        let text = json_get(&data, &self.text_field).unwrap().as_str().unwrap();
-
-        let meets_threshold = if self.count_unique {
-            self.has_unique_stop_words(text)
-        } else {
-            self.has_enough_stop_words(text)
-        };
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
 
-        if meets_threshold {
+        let (letters, uppercase) = UppercaseRatioFilter::letter_counts(text);
+        if letters == 0 || letters < self.min_letters || uppercase as f32 / letters as f32 <= self.max_ratio {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1253,577 +2347,515 @@ impl DataProcessor for StopWordFilter {
     }
 }
 
-impl StopWordFilter {
-    // Return boolean instead of moving data
-    fn has_unique_stop_words(&self, text: &str) -> bool {
-        let mut unique_stop_words = HashSet::new();
-
-        // Avoid collecting into Vec, process words as iterator
-        for word in text.split_whitespace() {
-            let word_lower = word.to_lowercase();
-            if self.stop_words.contains(word_lower.as_str()) {
-                unique_stop_words.insert(word_lower);
-                if unique_stop_words.len() >= self.min_stop_word {
-                    return true;
-                }
-            }
-        }
-        false
-    }
-
-    fn has_enough_stop_words(&self, text: &str) -> bool {
-        let mut count = 0;
-
-        // Process words as iterator without collecting
-        for word in text.split_whitespace() {
-            let word_lower = word.to_lowercase();
-            if self.stop_words.contains(word_lower.as_str()) {
-                count += 1;
-                if count >= self.min_stop_word {
-                    return true;
-                }
+impl UppercaseRatioFilter {
+    pub fn letter_counts(text: &str) -> (usize, usize) {
+        // (cased letters, uppercase letters)
+        text.chars().fold((0, 0), |(letters, uppercase), c| {
+            if c.is_uppercase() {
+                (letters + 1, uppercase + 1)
+            } else if c.is_lowercase() {
+                (letters + 1, uppercase)
+            } else {
+                (letters, uppercase)
             }
-        }
-        false
+        })
     }
 }
 
 
-
 #[derive(Serialize, Debug)]
-pub struct MassiveWebRepetitionFilter {
-    // Fancy repetition thing from Gopher
+pub struct WordLenFilter {
+    // Filters according to average word length
     pub text_field: String,
+    pub lower_bound: f32,
+    pub upper_bound: f32,
 }
 
-impl DataProcessor for MassiveWebRepetitionFilter {
+impl DataProcessor for WordLenFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        Ok(Self { text_field })
+        let lower_bound = get_default(config, "lower_bound", 0.0 as f64) as f32;
+        let upper_bound = get_default(config, "upper_bound", f32::MAX as f64) as f32;
+        Ok(Self {
+            text_field,
+            lower_bound,
+            upper_bound,
+        })
     }
+
     fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
             .unwrap()
             .as_str()
             .unwrap()
             .to_string();
-        let lines: Vec<&str> = text.split('\n').filter(|w| w.len() > 0).collect();
-        let pars: Vec<&str> = text.split("\n\n").filter(|w| w.len() > 0).collect();
-        let words: Vec<&str> = text.unicode_words().collect();
+        let word_lens: Vec<usize> = text.split_whitespace().map(|v| v.len()).collect();
 
-        let flow_args = vec![
-            ((&lines, 1, false), 0.3),
-            ((&pars, 1, false), 0.3),
-            ((&lines, 1, true), 0.2),
-            ((&pars, 1, true), 0.2),
-            ((&words, 2, true), 0.2),
-            ((&words, 3, true), 0.18),
-            ((&words, 4, true), 0.16),
-            ((&words, 5, true), 0.15),
-            ((&words, 6, true), 0.14),
-            ((&words, 7, true), 0.13),
-            ((&words, 8, true), 0.12),
-            ((&words, 9, true), 0.11),
-            ((&words, 10, true), 0.10),
-        ];
-        for (arglist, upper_bound) in flow_args.into_iter() {
-            let rep_frac =
-                MassiveWebRepetitionFilter::_rep_counter_fraction(arglist.0, arglist.1, arglist.2)
-                    .unwrap();
-            if rep_frac > upper_bound {
-                return Ok(None);
-            }
-        }
+        let avg_word_len = word_lens.iter().sum::<usize>() as f32 / word_lens.len() as f32;
 
-        Ok(Some(data))
+        if self.lower_bound <= avg_word_len && avg_word_len <= self.upper_bound {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
     }
 }
 
-impl MassiveWebRepetitionFilter {
-    pub fn _rep_counter_fraction<'a>(
-        elements: &'a Vec<&'a str>,
-        ngram_size: usize,
-        weighted: bool,
-    ) -> Result<f32, Error> {
-        let mut rolling_hash = CompatibleRollingHash::new(ngram_size);
-        let mut ngram_counts: FxHashMap<(u64, usize), Vec<usize>> = FxHashMap::default(); //(ngram_hash, ngram_char_len) -> [idxs where this ngram starts, ...]
-        let total_elements = elements.len();
-        let mut total_ngrams = 0;
-        let total_charlen = elements.iter().map(|v| v.len()).sum::<usize>();
-
-
-        for (idx, &element) in elements.iter().enumerate() {
-            rolling_hash.roll(element);
-
-            if rolling_hash.is_full() {
-                let hash_val = rolling_hash.get_hash();
-                let char_len = rolling_hash.get_char_length();
-
-                ngram_counts
-                    .entry((hash_val, char_len))
-                    .or_insert_with(Vec::new)
-                    .push(idx + 1 - ngram_size);
+#[derive(Serialize, Debug)]
+pub struct UniqueWordRatioFilter {
+    // Catches low-diversity spam (the same few words over and over): drops docs where unique_words / total_words < min_ratio
+    // or that have fewer than min_unique_words distinct words. Words are unicode words, lowercased. Empty docs have a ratio of 0
+    pub text_field: String,
+    pub min_ratio: f32, // defaults to 0.0
+    pub min_unique_words: usize, // defaults to 0
+}
 
-                total_ngrams += 1;
-            }
-        }
+impl DataProcessor for UniqueWordRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let min_ratio = get_default(config, "min_ratio", 0.0) as f32;
+        let min_unique_words = get_default(config, "min_unique_words", 0_usize);
+        Ok(Self { text_field, min_ratio, min_unique_words })
+    }
 
-        // Special cases: either 0 or 1 ngrams
-        if total_ngrams == 0 {
-            if ngram_size == 1 {
-                return Ok(1.0);
-            } else {
-                return Ok(0.0);
-            }
-        } else if total_ngrams == 1 {
-            return Ok(0.0);
-        }
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
 
-        let repeat_frac = if ngram_size == 1 {
-            // Single ngram case:
-            if weighted {
-                // no ngrams, weighted => get total charlen of elements repeated > 1x, divide by total charlen
-                let total_repeat_len = ngram_counts
-                    .iter()
-                    .filter_map(|(k, v)| {
-                        if v.len() > 1 {
-                            Some(k.1 * v.len())
-                        } else {
-                            None
-                        }
-                    })
-                    .sum::<usize>();
-                total_repeat_len as f32 / total_charlen as f32
-            } else {
-                // no ngrams, unweighted => get total repeated elements >1x, divide by total elements
-                let total_repeats = ngram_counts
-                    .iter()
-                    .filter_map(|(_k, v)| if v.len() > 1 { Some(v.len()) } else { None })
-                    .sum::<usize>();
-                total_repeats as f32 / total_elements as f32
-            }
+        let (unique_words, total_words) = UniqueWordRatioFilter::word_counts(text);
+        let ratio = if total_words == 0 { 0.0 } else { unique_words as f32 / total_words as f32 };
+        if ratio < self.min_ratio || unique_words < self.min_unique_words {
+            Ok(None)
         } else {
-            // Ngram size > 1 case:
-            // If ngram size is >= 4, juts find the ngram that occurs most-often and use this to generate indexes
-            // otherwise, find ALL ngrams that occur > 1
-            // Use these to generate element indices that are repeated and then count charlen / total_charlen
-
-            let repeated_start_idxs: Vec<usize> = if ngram_size <= 4 {
-                let most_common = ngram_counts
-                    .iter()
-                    .filter(|(_k, v)| v.len() > 1) // only select ngrams that repeat
-                    .max_by(|a, b| {
-                        // take max of (#repeats, ngramCharLen)
-                        let value_cmp = a.1.len().cmp(&b.1.len());
-                        if value_cmp == std::cmp::Ordering::Equal {
-                            a.0 .1.cmp(&b.0 .1)
-                        } else {
-                            value_cmp
-                        }
-                    });
-                if let Some(most_common_pair) = most_common {
-                    most_common_pair.1.to_vec()
-                } else {
-                    Vec::new()
-                }
-            } else {
-                ngram_counts
-                    .into_values()
-                    .filter(|v| v.len() > 1)
-                    .flat_map(|v| v)
-                    .collect()
-            };
-            let repeat_element_idxs: HashSet<usize> = repeated_start_idxs
-                .iter()
-                .flat_map(|v| (*v..(v + ngram_size)).collect::<Vec<usize>>())
-                .collect();
-
-            let repeat_len = repeat_element_idxs
-                .iter()
-                .map(|idx| elements[*idx].len())
-                .sum::<usize>();
-            repeat_len as f32 / total_charlen as f32
-        };
+            Ok(Some(data))
+        }
+    }
+}
 
-        Ok(repeat_frac)
+impl UniqueWordRatioFilter {
+    pub fn word_counts(text: &str) -> (usize, usize) {
+        // (number of distinct lowercased words, total number of words)
+        let words: Vec<String> = text.unicode_words().map(|w| w.to_lowercase()).collect();
+        let unique: HashSet<&String> = words.iter().collect();
+        (unique.len(), words.len())
     }
 }
 
-/// Alternative: True rolling hash that matches original hash values
-/// This version computes the same hash as the original but still optimizes other aspects
-struct CompatibleRollingHash<'a> {
-    window: VecDeque<&'a str>,
-    window_size: usize,
-    char_length: usize,
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct DictionaryWordRatioFilter {
+    /* Catches gibberish/OCR garbage: keeps docs where the fraction of (lowercased, unicode) words found in a lexicon is
+    in [min_ratio, max_ratio], and that have at least min_words words.
+    dictionary_file (required) has one word per line (may be compressed); words are lowercased and blank lines skipped.
+    */
+    pub text_field: String,
+    pub dictionary_file: String,
+    pub min_ratio: f64, // defaults to 0.0
+    pub max_ratio: f64, // defaults to 1.0
+    pub min_words: usize, // defaults to 0
+
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub dictionary: HashSet<String>,
 }
 
-impl<'a> CompatibleRollingHash<'a> {
-    fn new(window_size: usize) -> Self {
-        Self {
-            window: VecDeque::with_capacity(window_size),
-            window_size,
-            char_length: 0,
+impl DataProcessor for DictionaryWordRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let dictionary_file = json_get(config, "dictionary_file")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("dictionary_word_ratio_filter needs a dictionary_file"))?
+            .to_string();
+        let min_ratio = get_default(config, "min_ratio", 0.0);
+        let max_ratio = get_default(config, "max_ratio", 1.0);
+        let min_words = get_default(config, "min_words", 0_usize);
+
+        let mut dictionary: HashSet<String> = HashSet::new();
+        for line in read_pathbuf_to_mem(&PathBuf::from(&dictionary_file))?.lines() {
+            let word = line?.trim().to_lowercase();
+            if !word.is_empty() {
+                dictionary.insert(word);
+            }
         }
+        ensure!(!dictionary.is_empty(), format!("Dictionary file {:?} has no words", dictionary_file));
+        ensure!(min_ratio <= max_ratio, format!("min_ratio {:?} is above max_ratio {:?}", min_ratio, max_ratio));
+
+        Ok(Self { text_field, dictionary_file, min_ratio, max_ratio, min_words, dictionary })
     }
 
-    fn roll(&mut self, new_element: &'a str) -> Option<&'a str> {
-        // Add new element
-        self.window.push_back(new_element);
-        self.char_length += new_element.len();
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
 
-        // Remove oldest if window is full
-        if self.window.len() > self.window_size {
-            let removed = self.window.pop_front().unwrap();
-            self.char_length -= removed.len();
-            Some(removed)
+        let (found, total_words) = self.dictionary_counts(text);
+        if total_words < self.min_words {
+            return Ok(None);
+        }
+        let ratio = if total_words == 0 { 0.0 } else { found as f64 / total_words as f64 };
+        if ratio < self.min_ratio || ratio > self.max_ratio {
+            Ok(None)
         } else {
-            None
+            Ok(Some(data))
         }
     }
+}
 
-    fn get_hash(&self) -> u64 {
-        // Hash the entire VecDeque to match original
-        let mut hasher = FxHasher::default();
-        self.window.hash(&mut hasher);
-        hasher.finish()
-    }
-
-    fn get_char_length(&self) -> usize {
-        self.char_length
-    }
-
-    fn is_full(&self) -> bool {
-        self.window.len() >= self.window_size
+impl DictionaryWordRatioFilter {
+    pub fn dictionary_counts(&self, text: &str) -> (usize, usize) {
+        // (number of words found in the dictionary, total number of words)
+        let mut found = 0;
+        let mut total = 0;
+        for word in text.unicode_words() {
+            total += 1;
+            if self.dictionary.contains(&word.to_lowercase()) {
+                found += 1;
+            }
+        }
+        (found, total)
     }
 }
 
 
-
-
 #[derive(Serialize, Debug)]
-pub struct WordCountAdder {
-    // Adds a field which is the count of how many words are in the text_field
+pub struct SymbolRatioFilter {
+    // Filters the doc by how many symbols (see symbols var) appear relative to other words
     pub text_field: String,
-    pub word_count_field: String,
+    pub max_symbol_to_word_ratio: f32,
 }
-impl DataProcessor for WordCountAdder {
+
+impl DataProcessor for SymbolRatioFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let word_count_field = get_default(
-            config,
-            "word_count_field",
-            String::from("original_word_count"),
-        );
-
+        let max_symbol_to_word_ratio =
+            get_default(config, "max_symbol_to_word_ratio", f32::MAX as f64) as f32;
         Ok(Self {
             text_field,
-            word_count_field,
+            max_symbol_to_word_ratio,
         })
     }
 
-    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
             .unwrap()
             .as_str()
-            .unwrap();
-        let word_count = text.unicode_words().count();
-        json_set(&mut data, &self.word_count_field, word_count.into()).unwrap();
+            .unwrap()
+            .to_string();
+        let symbols = vec!["#", "...", ". . .", "\u{2026}"];
+        let mut num_symbols = 0;
+        for symbol in symbols.iter() {
+            num_symbols += text.matches(symbol).count();
+        }
 
-        Ok(Some(data))
+        let num_words = text
+            .replace(". . .", "...")
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .len();
+        let symbol_to_word_ratio = num_symbols as f32 / std::cmp::max(num_words, 1) as f32;
+
+        if symbol_to_word_ratio <= self.max_symbol_to_word_ratio {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
     }
 }
 
+
 #[derive(Serialize, Debug)]
-pub struct RatioLineModifier {
-    // Modifies docs to keep only lines that have not-too-many uppercase chars or digits
+pub struct BinaryContentFilter {
+    /* Drops docs whose text_field looks like binary or encoded data rather than text (base64 images, dumped
+    binaries, mojibake). A doc is dropped if any of these trips:
+    max_non_printable_ratio (default 0.1) -- fraction of chars that are control chars (other than \n, \r, \t)
+        or U+FFFD replacement chars
+    max_base64_run (default 256) -- longest run of base64 chars ([A-Za-z0-9+/=_-]) without anything else in between;
+        no word gets near this, an embedded data:image/...;base64 blob does
+    min_whitespace_ratio (default 0.02) -- fraction of whitespace chars, only checked for texts of at least
+        min_whitespace_chars (default 1000) chars, since short strings legitimately have little whitespace.
+        Chars of scripts written without spaces between words (CJK, kana, Thai, ...) count as separators here,
+        so long Chinese/Japanese/Thai prose isn't mistaken for a blob
+    */
     pub text_field: String,
-    pub upper_bound: f32,
-    pub check: String,
+    pub max_non_printable_ratio: f64,
+    pub max_base64_run: usize,
+    pub min_whitespace_ratio: f64,
+    pub min_whitespace_chars: usize,
 }
 
-impl DataProcessor for RatioLineModifier {
+impl DataProcessor for BinaryContentFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let upper_bound = json_get(config, "upper_bound").unwrap().as_f64().unwrap() as f32;
-        let check = json_get(config, "check")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
+        let max_non_printable_ratio = get_default(config, "max_non_printable_ratio", 0.1);
+        let max_base64_run = get_default(config, "max_base64_run", 256);
+        let min_whitespace_ratio = get_default(config, "min_whitespace_ratio", 0.02);
+        let min_whitespace_chars = get_default(config, "min_whitespace_chars", 1000);
         ensure!(
-            ["uppercase", "numeric"].contains(&&check.as_str()),
-            format!(
-                "Check must be one of {{uppercase, numeric}} and not {:?}",
-                check
-            )
+            (0.0..=1.0).contains(&max_non_printable_ratio) && (0.0..=1.0).contains(&min_whitespace_ratio),
+            "binary_content_filter ratios must be in [0, 1]"
         );
-
-        Ok(Self {
-            text_field,
-            upper_bound,
-            check,
-        })
+        Ok(Self { text_field, max_non_printable_ratio, max_base64_run, min_whitespace_ratio, min_whitespace_chars })
     }
 
-    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let lines: Vec<&str> = text.split('\n').collect();
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field).and_then(|v| v.as_str()).unwrap_or("");
+        if self.binary_reason(text).is_some() {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+}
 
-        let mut passing_lines: Vec<&str> = Vec::new();
-        for line in lines {
-            if line.len() == 0 {
-                passing_lines.push(line);
-                continue;
+impl BinaryContentFilter {
+    pub fn binary_reason(&self, text: &str) -> Option<&'static str> {
+        // Which check (if any) flags this text as non-text content
+        let mut num_chars = 0;
+        let mut non_printable = 0;
+        let mut whitespace = 0;
+        let mut run = 0;
+        let mut longest_run = 0;
+        for c in text.chars() {
+            num_chars += 1;
+            if c.is_whitespace() || is_unspaced_script_char(c) {
+                whitespace += 1;
+            } else if c.is_control() || c == '\u{FFFD}' {
+                non_printable += 1;
             }
-            let line_len = std::cmp::max(line.len(), 1) as f32;
-            let count = if &self.check == "uppercase" {
-                line.chars()
-                    .filter(|v| v.is_uppercase())
-                    .collect::<Vec<_>>()
-                    .len() as f32
+            if c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '=' | '_' | '-') {
+                run += 1;
+                longest_run = longest_run.max(run);
             } else {
-                line.chars()
-                    .filter(|v| v.is_digit(10))
-                    .collect::<Vec<_>>()
-                    .len() as f32
-            };
-            if count / line_len <= self.upper_bound {
-                passing_lines.push(line)
+                run = 0;
             }
         }
-
-        json_set(
-            &mut data,
-            &self.text_field,
-            serde_json::Value::String(passing_lines.join("\n")),
-        )
-        .unwrap();
-
-        Ok(Some(data))
+        if num_chars == 0 {
+            return None;
+        }
+        if non_printable as f64 / num_chars as f64 > self.max_non_printable_ratio {
+            Some("non_printable_ratio")
+        } else if longest_run > self.max_base64_run {
+            Some("base64_run")
+        } else if num_chars >= self.min_whitespace_chars && (whitespace as f64 / num_chars as f64) < self.min_whitespace_ratio {
+            Some("whitespace_ratio")
+        } else {
+            None
+        }
     }
 }
 
+fn is_unspaced_script_char(c: char) -> bool {
+    // Scripts that don't put spaces between words: CJK ideographs and punctuation, kana, Thai, Lao, Tibetan,
+    // Myanmar and Khmer (Hangul is left out, Korean uses spaces)
+    matches!(c,
+        '\u{0E00}'..='\u{0EFF}' // Thai, Lao
+        | '\u{0F00}'..='\u{0FFF}' // Tibetan
+        | '\u{1000}'..='\u{109F}' // Myanmar
+        | '\u{1780}'..='\u{17FF}' // Khmer
+        | '\u{3000}'..='\u{30FF}' // CJK symbols and punctuation, hiragana, katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK unified ideographs
+        | '\u{F900}'..='\u{FAFF}' // CJK compatibility ideographs
+        | '\u{FF00}'..='\u{FFEF}' // halfwidth and fullwidth forms
+        | '\u{20000}'..='\u{2FFFF}' // CJK extensions B and up
+    )
+}
+
 #[derive(Serialize, Debug)]
-pub struct RegexLineModifier {
-    // Modifies lines to only keep those that don't have any regex matches
-    // Note that we automatically lowercase the text we query!
+pub struct BulletFilter {
+    // Filters the doc by how many lines starting with bullets appear relative to other lines
+    // Docs with fewer than min_lines lines are never judged. With require_low_prose, a high bullet ratio only
+    // removes the doc if it also has little prose (fewer than min_prose_words_per_line words per non-empty line),
+    // so structured docs like recipes survive while link farms don't
     pub text_field: String,
-    pub regex_string: String, //
-    #[serde(skip)]
-    pub regex: Regex,
+    pub max_bullet_ratio: f32,
+    pub min_lines: usize, // defaults to 0
+    pub require_low_prose: bool, // defaults to false (plain ratio check)
+    pub min_prose_words_per_line: f32, // defaults to 5.0
 }
 
-impl DataProcessor for RegexLineModifier {
+impl DataProcessor for BulletFilter {
     fn new(config: &Value) -> Result<Self, Error> {
-        let counter_regex = r"^\W*\d(?:,|\.|\d)*(?:K|k|M|m|B|b)?\s+(?:likes|shares|comments|retweets|reposts|quotes|bookmarks|upvotes|downvotes|downloads|views|followers)\W*$".to_string();
         let text_field = get_default(config, "text_field", String::from("text"));
-        let regex_string = get_default(config, "regex", counter_regex);
-        let regex = Regex::new(&regex_string).unwrap();
-
+        let max_bullet_ratio = get_default(config, "max_bullet_ratio", f32::MAX as f64) as f32;
+        let min_lines = get_default(config, "min_lines", 0_usize);
+        let require_low_prose = get_default(config, "require_low_prose", false);
+        let min_prose_words_per_line = get_default(config, "min_prose_words_per_line", 5.0) as f32;
         Ok(Self {
             text_field,
-            regex_string,
-            regex,
+            max_bullet_ratio,
+            min_lines,
+            require_low_prose,
+            min_prose_words_per_line,
         })
     }
 
-    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
             .unwrap()
             .as_str()
             .unwrap()
             .to_string();
         let lines: Vec<&str> = text.split('\n').collect();
-
-        let passing_lines: Vec<_> = lines
+        if lines.len() < self.min_lines {
+            return Ok(Some(data));
+        }
+        let bullet_count = lines
             .iter()
-            .filter(|line| !self.regex.is_match(&line.to_lowercase()))
-            .map(|&l| l)
-            .collect();
-        if passing_lines.len() == 0 {
-            return Ok(None);
+            .filter(|line| {
+                line.starts_with('●')
+                    || line.starts_with('•')
+                    || line.starts_with('*')
+                    || line.starts_with('-')
+            })
+            .count();
+        if bullet_count as f32 / lines.len() as f32 <= self.max_bullet_ratio {
+            return Ok(Some(data));
+        }
+        if self.require_low_prose && self.words_per_line(&lines) >= self.min_prose_words_per_line {
+            return Ok(Some(data));
         }
+        Ok(None)
+    }
+}
 
-        json_set(
-            &mut data,
-            &self.text_field,
-            serde_json::Value::String(passing_lines.join("\n")),
-        )
-        .unwrap();
+impl BulletFilter {
+    pub fn words_per_line(&self, lines: &[&str]) -> f32 {
+        let non_empty: Vec<&&str> = lines.iter().filter(|line| !line.trim().is_empty()).collect();
+        if non_empty.is_empty() {
+            return 0.0;
+        }
+        let words: usize = non_empty.iter().map(|line| line.split_whitespace().count()).sum();
+        words as f32 / non_empty.len() as f32
+    }
+}
 
-        Ok(Some(data))
+/*
+Code density: a single code-likeness score in [0, 1] for routing code vs. prose, the mean of
+- code_line_frac: the fraction of non-blank lines that are ``` fences, inside a fenced block, or indented
+  (by a tab or at least 4 spaces), and
+- symbol_frac: the fraction of non-whitespace chars that are code punctuation (CODE_SYMBOLS), scaled so that
+  CODE_SYMBOL_SATURATION (typical of source code) or more counts as 1.
+Prose scores near 0, fenced or indented code with ordinary syntax near 1.
+*/
+const CODE_SYMBOLS: &str = "{}[]()<>;=+*/%&|^~#$@\\`_";
+const CODE_SYMBOL_SATURATION: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CodeDensity {
+    pub code_line_frac: f64,
+    pub symbol_frac: f64,
+    pub score: f64,
+}
+
+pub fn code_density(text: &str) -> CodeDensity {
+    let mut in_fence = false;
+    let mut lines = 0;
+    let mut code_lines = 0;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        lines += 1;
+        let is_fence = line.trim_start().starts_with("```");
+        if is_fence || in_fence || line.starts_with('\t') || line.starts_with("    ") {
+            code_lines += 1;
+        }
+        if is_fence {
+            in_fence = !in_fence;
+        }
     }
+    let mut chars = 0;
+    let mut symbols = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        chars += 1;
+        if CODE_SYMBOLS.contains(c) {
+            symbols += 1;
+        }
+    }
+    let code_line_frac = if lines == 0 { 0.0 } else { code_lines as f64 / lines as f64 };
+    let symbol_frac = if chars == 0 { 0.0 } else { symbols as f64 / chars as f64 };
+    let score = (code_line_frac + f64::min(1.0, symbol_frac / CODE_SYMBOL_SATURATION)) / 2.0;
+    CodeDensity { code_line_frac, symbol_frac, score }
 }
 
 #[derive(Serialize, Debug)]
-pub struct LineLenModifier {
-    // Modifes lines to only keep those that have >= lower_bound words
+pub struct CodeDensityAnnotator {
+    // Writes code_density's score to output_field, e.g. to range-partition on; components_field also gets its parts
     pub text_field: String,
-    pub lower_bound: usize,
+    pub output_field: String, // defaults to metadata.code_density
+    pub components_field: Option<String>, // {code_line_frac, symbol_frac} if set
 }
 
-impl DataProcessor for LineLenModifier {
+impl DataProcessor for CodeDensityAnnotator {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let lower_bound = get_default(config, "lower_bound", 0);
-
-        Ok(Self {
-            text_field,
-            lower_bound,
-        })
+        let output_field = get_default(config, "output_field", String::from("metadata.code_density"));
+        let components_field = json_get(config, "components_field").and_then(|v| v.as_str()).map(String::from);
+        Ok(Self { text_field, output_field, components_field })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let lines: Vec<&str> = text.split('\n').collect();
-
-        let passing_lines: Vec<_> = lines
-            .iter()
-            .filter(|line| line.unicode_words().collect::<Vec<_>>().len() >= self.lower_bound || line.len() == 0)
-            .map(|&l| l)
-            .collect();
-        if passing_lines.iter().map(|v| v.len()).sum::<usize>() == 0 {
-            return Ok(None);
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        let density = code_density(text);
+        json_set(&mut data, &self.output_field, json!(density.score))?;
+        if let Some(components_field) = &self.components_field {
+            let components = json!({"code_line_frac": density.code_line_frac, "symbol_frac": density.symbol_frac});
+            json_set(&mut data, components_field, components)?;
         }
-
-        json_set(
-            &mut data,
-            &self.text_field,
-            serde_json::Value::String(passing_lines.join("\n")),
-        )
-        .unwrap();
-
         Ok(Some(data))
     }
 }
 
 #[derive(Serialize, Debug)]
-pub struct SubstringLineModifier {
-    // Modifies lines to only keep those that don't have any words from the banlist (or just removes those words themselves)
+pub struct CodeDensityFilter {
+    // Keeps docs whose code_density score is in [min_density, max_density]
     pub text_field: String,
-    pub banlist: String,
-    pub max_len: usize,
-    pub remove_substring_only: bool,
-    pub location: String,
-    #[serde(skip)]
-    regex: OnceCell<Regex>
-
+    pub min_density: f64, // defaults to 0.0
+    pub max_density: f64, // defaults to 1.0
 }
 
-impl DataProcessor for SubstringLineModifier {
+impl DataProcessor for CodeDensityFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let banlist = config.get("banlist").unwrap().as_str().unwrap().to_string();
-        let max_len = get_default(config, "max_len", usize::MAX);
-        let remove_substring_only = get_default(config, "remove_substring_only", true);
-        let location = get_default(config, "location", String::from("any"));
-
-        Ok(Self {
-            text_field,
-            banlist,
-            max_len,
-            remove_substring_only,
-            location,
-            regex: OnceCell::new(),
-        })
+        let min_density = get_default(config, "min_density", 0.0);
+        let max_density = get_default(config, "max_density", 1.0);
+        ensure!(min_density <= max_density, format!("min_density {:?} is above max_density {:?}", min_density, max_density));
+        Ok(Self { text_field, min_density, max_density })
     }
 
-    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
-            .unwrap()
-            .as_str()
-            .unwrap();
-
-        // Get or compile regex once
-        let regex = self.regex.get_or_try_init(|| {
-            let (pattern, _) = match self.location.as_str() {
-                "prefix" => (format!(r"^(?:{})\s?", self.banlist), ""),
-                "suffix" => (format!(r"\s?(?:{})$", self.banlist), ""),
-                _ => (format!(r"\s?(?:{})\s?", self.banlist), " "),
-            };
-            Regex::new(&pattern)
-        })?;
-
-        let replacement = match self.location.as_str() {
-            "prefix" | "suffix" => "",
-            _ => " ",
-        };
-
-        // Use iterator with filter_map for better performance
-        let processed_lines: Vec<String> = text
-            .lines()
-            .filter_map(|line| {
-                // Skip empty lines processing if they should be kept as-is
-                if line.is_empty() {
-                    return Some(String::new());
-                }
-
-                // Check max_len constraint first (cheaper operation)
-                if self.max_len != usize::MAX {
-                    let word_count = line.unicode_words().count();
-                    if word_count > self.max_len {
-                        return Some(line.to_string());
-                    }
-                }
-
-                if self.remove_substring_only {
-                    let cleaned = regex.replace_all(line, replacement);
-                    // Only keep non-empty trimmed lines
-                    if !cleaned.trim().is_empty() {
-                        Some(cleaned.into_owned())
-                    } else {
-                        None
-                    }
-                } else {
-                    // If regex matches, skip the line (return None)
-                    if regex.is_match(line) {
-                        None
-                    } else {
-                        Some(line.to_string())
-                    }
-                }
-            })
-            .collect();
-
-        json_set(
-            &mut data,
-            &self.text_field,
-            serde_json::Value::String(processed_lines.join("\n")),
-        )?;
-
-        Ok(Some(data))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        let score = code_density(text).score;
+        if score < self.min_density || score > self.max_density {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
     }
 }
 
 #[derive(Serialize, Debug)]
-pub struct WordRemovalRatioFilter {
-    // Only keeps docs that haven't removed too many words (from a previous, old, word_count_field)
+pub struct EllipsisLineRatioFilter {
+    // Filters the doc by what fraction of lines end with an ellipsis
     pub text_field: String,
-    pub word_count_field: String,
-    pub upper_bound: f32,
+    pub max_ratio: f32,
 }
 
-impl DataProcessor for WordRemovalRatioFilter {
+impl DataProcessor for EllipsisLineRatioFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let word_count_field = get_default(
-            config,
-            "word_count_field",
-            String::from("original_word_count"),
-        );
-        let upper_bound = get_default(config, "upper_bound", 1.0) as f32;
+        let max_ratio = get_default(config, "max_ratio", f32::MAX as f64) as f32;
         Ok(Self {
             text_field,
-            word_count_field,
-            upper_bound,
+            max_ratio,
         })
     }
 
@@ -1833,14 +2865,17 @@ impl DataProcessor for WordRemovalRatioFilter {
             .as_str()
             .unwrap()
             .to_string();
-        let old_word_count: usize = json_get(&data, &self.word_count_field)
-            .unwrap()
-            .as_u64()
-            .unwrap() as usize;
-        let cur_word_count: usize = text.unicode_words().collect::<Vec<_>>().len();
+        let lines: Vec<&str> = text.lines().filter(|line| line.len() > 0).collect();
 
-        let removed_ratio = ((old_word_count - cur_word_count) as f32) / old_word_count as f32;
-        if removed_ratio <= self.upper_bound {
+        let ellipsis_count = lines
+            .iter()
+            .filter(|line| {
+                line.ends_with("...") || line.ends_with(". . .") || line.ends_with("\u{2026}")
+            })
+            .count();
+
+        let ratio = ellipsis_count as f32 / std::cmp::max(lines.len(), 1) as f32;
+        if ratio <= self.max_ratio {
             Ok(Some(data))
         } else {
             Ok(None)
@@ -1848,232 +2883,1232 @@ impl DataProcessor for WordRemovalRatioFilter {
     }
 }
 
-#[derive(Derivative)]
-#[derivative(Debug)]
-#[derive(Serialize)]
-pub struct Madlad400SentenceAnnotator {
-    // Does the madlad400 sec2.3 filter : https://openreview.net/pdf?id=Y45ZCxslFx
-    // But just annotates
+#[derive(Serialize, Debug)]
+pub struct AlphabeticWordRatioFilter {
+    // Filters the doc by what fraction of words are NOT alphanumeric
     pub text_field: String,
-    pub sentence_lower_bound: usize,        // defaults to 5
-    pub sentence_question_upper_bound: f32, // defaults to 20%
-    pub annotation_key: String, // defaults to metadata.madlad
-    pub rules_to_include: Vec<usize>, // If empty, includes ALL rules. Otherwise just counts the rules here
-
-    // document consistency
-    pub fast_text_file: String, // path to fasttext model
-    #[serde(skip)]
-    pub model: FastText,
-    pub langid_field: String, // field where the document level language is
-
-    // list case
-    pub case_upper_bound: f32,       // defaults to 0.50
-    pub case_tok_lower_bound: usize, // defaults to 12
-
-    // abnormal lengths
-    pub char_len_lower_bound: usize, // defaults to 20
-    pub char_len_upper_bound: usize, // defaults to 500
-
-    // technical chars
-    pub tech_lower_bound: f32, // defaults to 0.20
-    #[derivative(Debug = "ignore")]
-    #[serde(skip)]
-    pub tech_charset: HashSet<char>,
-
-    // cursed regxes
-    pub cursed_regex_file: String, // path to cursed strings // last 4 are regexes
-    #[derivative(Debug = "ignore")]
-    #[serde(skip)]
-    pub cursed_inclusions: AhoCorasick,
-    #[derivative(Debug = "ignore")]
-    #[serde(skip)]
-    pub cursed_regexes: Vec<Regex>,
+    pub max_ratio: f32,
 }
 
-impl DataProcessor for Madlad400SentenceAnnotator {
+impl DataProcessor for AlphabeticWordRatioFilter {
     fn new(config: &Value) -> Result<Self, Error> {
         let text_field = get_default(config, "text_field", String::from("text"));
-        let sentence_lower_bound = get_default(config, "sentence_lower_bound", 5);
-        let sentence_question_upper_bound =
-            get_default(config, "sentence_question_upper_bound", 0.20) as f32;
-
-        let annotation_key = get_default(config, "annotation_key", String::from("metadata.madlad"));
-        let rules_to_include: Vec<usize> = get_default(config, "rules_to_include", vec![])
-            .into_iter().map(|v| v.as_u64().unwrap() as usize).collect::<Vec<usize>>();
-        let fast_text_file = config
-            .get("fast_text_file")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let mut model = FastText::new();
-        model.load_model(&fast_text_file).unwrap();
-        let langid_field = config
-            .get("langid_field")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-
-        let case_upper_bound = get_default(config, "case_upper_bound", 0.50) as f32;
-        let case_tok_lower_bound = get_default(config, "case_tok_lower_bound", 12);
-
-        let char_len_lower_bound = get_default(config, "char_len_lower_bound", 20);
-        let char_len_upper_bound = get_default(config, "char_len_upper_bound", 500);
-
-        let tech_lower_bound = get_default(config, "tech_lower_bound", 0.20) as f32;
-        let tech_charset: HashSet<char> = [
-            '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '{', '}', '+', '/', '(', ')', '>',
-        ]
-        .into_iter()
-        .collect();
-
-        let cursed_regex_file = config
-            .get("cursed_regex_file")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        let cursed_regex_data =
-            read_pathbuf_to_mem(&PathBuf::from(cursed_regex_file.clone())).unwrap();
-        let cursed_regex_lines: Vec<_> = cursed_regex_data.lines().map(|l| l.unwrap()).collect();
-        let cursed_inclusions =
-            AhoCorasick::new(&cursed_regex_lines[..cursed_regex_lines.len() - 4]).unwrap();
-        let mut cursed_regexes: Vec<Regex> = Vec::new();
-        for el in &cursed_regex_lines[cursed_regex_lines.len() - 4..] {
-            cursed_regexes.push(Regex::new(el).unwrap());
-        }
+        let max_ratio = get_default(config, "max_ratio", f32::MAX as f64) as f32;
         Ok(Self {
             text_field,
-            sentence_lower_bound,
-            sentence_question_upper_bound,
-            annotation_key,
-            rules_to_include,
-            fast_text_file,
-            model,
-            langid_field,
-            case_upper_bound,
-            case_tok_lower_bound,
-            char_len_lower_bound,
-            char_len_upper_bound,
-            tech_lower_bound,
-            tech_charset,
-            cursed_regex_file,
-            cursed_inclusions,
-            cursed_regexes,
+            max_ratio,
         })
     }
 
-    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        // Setup for filtering
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
         let text = json_get(&data, &self.text_field)
             .unwrap()
             .as_str()
             .unwrap()
             .to_string();
-        let sentence_splitter = Regex::new(r"[.!?]+\s+").unwrap();
-
-        let rules_to_include: HashSet<usize> = if self.rules_to_include.len() == 0 {
-            vec![1,2,3,4,5].into_iter().map(|v| v).collect()
-        } else {
-            self.rules_to_include.iter().map(|v| *v).collect()
-        };
+        let words = text.split_whitespace().collect::<Vec<_>>();
+        if words.len() == 1 {
+            return Ok(None);
+        }
+        let total_words = words.len() as f32;
+        let non_alpha_words = words
+            .into_iter()
+            .filter(|w| !w.chars().any(|c| c.is_alphabetic()))
+            .collect::<Vec<_>>()
+            .len();
 
-        let sentences: Vec<_> = sentence_splitter
-            .split(&text)
-            .filter(|s| s.trim().len() > 0)
-            .collect();
-        let num_sentences = sentences.len();
-        let madlad_status = self.annotation_key.clone() + "_status";
-        let mut tracker: FxHashMap<&str, Vec<usize>> = FxHashMap::default();
-        tracker.entry("num_sentences").or_default().push(num_sentences);
+        let ratio = non_alpha_words as f32 / total_words;
 
-        if num_sentences < self.sentence_lower_bound {
-            json_set(&mut data, &madlad_status, json!("killed:too_short")).unwrap();
-            return Ok(Some(data));
+        if ratio <= self.max_ratio {
+            Ok(Some(data))
+        } else {
+            Ok(None)
         }
+    }
+}
 
-        let doc_lang = json_get(&data, &self.langid_field)
-            .unwrap()
-            .as_object()
-            .unwrap()
-            .iter()
-            .max_by(|(_, a), (_, b)| {
-                (&(a.as_f64().unwrap()))
-                    .partial_cmp(&(b.as_f64().unwrap()))
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            })
-            .unwrap()
-            .0;
 
-        // Tracker maps rule -> sentence ids for which this pops
+#[derive(Serialize, Debug)]
+pub struct MinAlphaCharsFilter {
+    // Drops docs with fewer than min_alpha alphabetic chars (any script) in text_field: a cheap backstop for
+    // empty, whitespace-only or symbol-only fragments, meant to run before the expensive steps
+    pub text_field: String,
+    pub min_alpha: usize, // defaults to 1
+}
 
-        let mut sus_sentences: HashSet<usize> = HashSet::new();
-        let sentence_threshold = num_sentences as f32 * self.sentence_question_upper_bound;
-        // Loop through sentences
+impl DataProcessor for MinAlphaCharsFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let min_alpha = get_default(config, "min_alpha", 1_usize);
+        Ok(Self { text_field, min_alpha })
+    }
 
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        // Stops counting as soon as there are enough
+        let alpha_chars = text.chars().filter(|c| c.is_alphabetic()).take(self.min_alpha).count();
+        if alpha_chars < self.min_alpha {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+}
 
-        for (sentence_num, sentence) in sentences.into_iter().enumerate() {
-            // And finally langid
-            if rules_to_include.contains(&1) && self.document_consistency(sentence, doc_lang).unwrap() {
-                tracker.entry("rule.1").or_default().push(sentence_num);
-                sus_sentences.insert(sentence_num);
-            }
 
-            // Then check case
-            if rules_to_include.contains(&2) && self.list_case(sentence).unwrap() {
-                tracker.entry("rule.2").or_default().push(sentence_num);
-                sus_sentences.insert(sentence_num);
+#[derive(Serialize, Debug)]
+pub struct StopWordFilter {
+    /* Keeps docs with at least min_stop_word stop words (distinct ones with count_unique).
+    By default that's a small built-in English list. With stop_word_dir, every file in it is a stop word list for the
+    language named by the file name up to the first dot (en.txt, es.txt.gz, ...; one word per line), and each doc
+    is checked against the list for its language in lang_field. Docs whose language has no list (or that have no
+    language) are handled by unknown_lang: keep (default, pass unchecked), remove, or default (use the English list).
+    */
+    pub text_field: String,
+    pub count_unique: bool,
+    pub min_stop_word: usize,
+    // Use &'static str for better performance
+    pub stop_words: HashSet<&'static str>,
+    pub lang_field: Option<String>, // required with stop_word_dir
+    pub lang_stop_words: HashMap<String, HashSet<String>>, // language -> its list, loaded from stop_word_dir
+    pub unknown_lang: String, // keep (default), remove or default
+}
 
-            }
+impl DataProcessor for StopWordFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let count_unique = get_default(config, "count_unique", false);
+        let min_stop_word = get_default(config, "min_stop_word", 2);
 
-            // Check abnormal len sentences
-            if rules_to_include.contains(&3) && self.abnormal_len_sentence(sentence).unwrap() {
-                tracker.entry("rule.3").or_default().push(sentence_num);
-                sus_sentences.insert(sentence_num);
+        // Use &'static str to avoid String allocations
+        let stop_words: HashSet<&'static str> =
+            ["the", "be", "to", "of", "and", "that", "have", "with"]
+            .into_iter()
+            .collect();
 
+        let lang_field = json_get(config, "lang_field").and_then(|v| v.as_str()).map(String::from);
+        let unknown_lang = get_default(config, "unknown_lang", String::from("keep"));
+        ensure!(
+            ["keep", "remove", "default"].contains(&unknown_lang.as_str()),
+            format!("unknown_lang must be one of {{keep, remove, default}} and not {:?}", unknown_lang)
+        );
+        let mut lang_stop_words: HashMap<String, HashSet<String>> = HashMap::new();
+        if let Some(stop_word_dir) = json_get(config, "stop_word_dir") {
+            let stop_word_dir = PathBuf::from(
+                stop_word_dir.as_str().ok_or_else(|| anyhow!("stop_word_dir must be a string"))?,
+            );
+            ensure!(lang_field.is_some(), "stop_word_dir needs a lang_field to pick each doc's list");
+            for entry in fs::read_dir(&stop_word_dir)? {
+                let path = entry?.path();
+                if !path.is_file() {
+                    continue;
+                }
+                let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+                let lang = file_name.split('.').next().unwrap().to_string();
+                let mut words: HashSet<String> = HashSet::new();
+                for line in read_pathbuf_to_mem(&path)?.lines() {
+                    let word = line?.trim().to_lowercase();
+                    if !word.is_empty() {
+                        words.insert(word);
+                    }
+                }
+                lang_stop_words.insert(lang, words);
             }
+            ensure!(!lang_stop_words.is_empty(), format!("No stop word lists found in {:?}", stop_word_dir));
+        }
 
+        Ok(Self {
+            text_field,
+            count_unique,
+            min_stop_word,
+            stop_words,
+            lang_field,
+            lang_stop_words,
+            unknown_lang,
+        })
+    }
 
-            // Then check technical character counts
-            if rules_to_include.contains(&4) && self.technical_characters(sentence).unwrap() {
-                tracker.entry("rule.4").or_default().push(sentence_num);
-                sus_sentences.insert(sentence_num);
-
-            }
-
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        // Early return optimization
+        if self.min_stop_word == 0 {
+            return Ok(Some(data));
+        }
 
-            // Then do cursed regex stuff
-            if rules_to_include.contains(&5) && self.check_cursed_regexes(sentence).unwrap() {
-                tracker.entry("rule.5").or_default().push(sentence_num);
-                sus_sentences.insert(sentence_num);
+        let text = json_get(&data, &self.text_field).unwrap().as_str().unwrap();
 
+        let meets_threshold = if self.lang_stop_words.is_empty() {
+            self.meets_threshold(text, &self.stop_words)
+        } else {
+            let lang = json_get(&data, self.lang_field.as_ref().unwrap()).and_then(|v| v.as_str());
+            match lang.and_then(|lang| self.lang_stop_words.get(lang)) {
+                Some(stop_words) => self.meets_threshold(text, stop_words),
+                None => match self.unknown_lang.as_str() {
+                    "keep" => true,
+                    "remove" => false,
+                    _ => self.meets_threshold(text, &self.stop_words),
+                },
             }
-        }
+        };
 
-        let tracker_json: Value = json!(tracker);
-        if sus_sentences.len() as f32 > sentence_threshold {
-            json_set(&mut data, &madlad_status, json!("killed:too_many_sus_sentences")).unwrap();
+        if meets_threshold {
+            Ok(Some(data))
         } else {
-            json_set(&mut data, &madlad_status, json!("survived")).unwrap();
+            Ok(None)
         }
-        json_set(&mut data, &self.annotation_key, tracker_json).unwrap();
-        Ok(Some(data))
-
     }
 }
 
-impl Madlad400SentenceAnnotator {
-    // Individual checks. Returns True if the sentence IS questionable!
-    pub fn abnormal_len_sentence(&self, sentence: &str) -> Result<bool, Error> {
-        Ok(
-            sentence.len() < self.char_len_lower_bound
-                || sentence.len() > self.char_len_upper_bound,
-        )
+impl StopWordFilter {
+    fn meets_threshold<S: Borrow<str> + Eq + Hash>(&self, text: &str, stop_words: &HashSet<S>) -> bool {
+        if self.count_unique {
+            self.has_unique_stop_words(text, stop_words)
+        } else {
+            self.has_enough_stop_words(text, stop_words)
+        }
     }
 
-    pub fn technical_characters(&self, sentence: &str) -> Result<bool, Error> {
-        let technical_chars = sentence
+    // Return boolean instead of moving data
+    fn has_unique_stop_words<S: Borrow<str> + Eq + Hash>(&self, text: &str, stop_words: &HashSet<S>) -> bool {
+        let mut unique_stop_words = HashSet::new();
+
+        // Avoid collecting into Vec, process words as iterator
+        for word in text.split_whitespace() {
+            let word_lower = word.to_lowercase();
+            if stop_words.contains(word_lower.as_str()) {
+                unique_stop_words.insert(word_lower);
+                if unique_stop_words.len() >= self.min_stop_word {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn has_enough_stop_words<S: Borrow<str> + Eq + Hash>(&self, text: &str, stop_words: &HashSet<S>) -> bool {
+        let mut count = 0;
+
+        // Process words as iterator without collecting
+        for word in text.split_whitespace() {
+            let word_lower = word.to_lowercase();
+            if stop_words.contains(word_lower.as_str()) {
+                count += 1;
+                if count >= self.min_stop_word {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+
+
+#[derive(Serialize, Debug)]
+pub struct MassiveWebRepetitionFilter {
+    // Fancy repetition thing from Gopher
+    pub text_field: String,
+}
+
+impl DataProcessor for MassiveWebRepetitionFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        Ok(Self { text_field })
+    }
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let units = RepetitionUnits::new(&text);
+
+        // Stops at the first fraction over its bound
+        for check in MASSIVE_WEB_REPETITION_CHECKS.iter() {
+            if units.fraction(check)? > check.upper_bound {
+                return Ok(None);
+            }
+        }
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepetitionUnit {
+    Line,
+    Paragraph,
+    Word,
+}
+
+#[derive(Debug)]
+pub struct RepetitionCheck {
+    // One of the Gopher repetition fractions: _rep_counter_fraction(unit, ngram_size, weighted), dropped if > upper_bound
+    pub name: &'static str,
+    pub unit: RepetitionUnit,
+    pub ngram_size: usize,
+    pub weighted: bool,
+    pub upper_bound: f32,
+}
+
+pub const MASSIVE_WEB_REPETITION_CHECKS: [RepetitionCheck; 13] = [
+    RepetitionCheck { name: "dup_line_frac", unit: RepetitionUnit::Line, ngram_size: 1, weighted: false, upper_bound: 0.3 },
+    RepetitionCheck { name: "dup_para_frac", unit: RepetitionUnit::Paragraph, ngram_size: 1, weighted: false, upper_bound: 0.3 },
+    RepetitionCheck { name: "dup_line_char_frac", unit: RepetitionUnit::Line, ngram_size: 1, weighted: true, upper_bound: 0.2 },
+    RepetitionCheck { name: "dup_para_char_frac", unit: RepetitionUnit::Paragraph, ngram_size: 1, weighted: true, upper_bound: 0.2 },
+    RepetitionCheck { name: "top_2gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 2, weighted: true, upper_bound: 0.2 },
+    RepetitionCheck { name: "top_3gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 3, weighted: true, upper_bound: 0.18 },
+    RepetitionCheck { name: "top_4gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 4, weighted: true, upper_bound: 0.16 },
+    RepetitionCheck { name: "dup_5gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 5, weighted: true, upper_bound: 0.15 },
+    RepetitionCheck { name: "dup_6gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 6, weighted: true, upper_bound: 0.14 },
+    RepetitionCheck { name: "dup_7gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 7, weighted: true, upper_bound: 0.13 },
+    RepetitionCheck { name: "dup_8gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 8, weighted: true, upper_bound: 0.12 },
+    RepetitionCheck { name: "dup_9gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 9, weighted: true, upper_bound: 0.11 },
+    RepetitionCheck { name: "dup_10gram_char_frac", unit: RepetitionUnit::Word, ngram_size: 10, weighted: true, upper_bound: 0.10 },
+];
+
+pub struct RepetitionUnits<'a> {
+    // The text split the way the repetition checks count it
+    lines: Vec<&'a str>,
+    pars: Vec<&'a str>,
+    words: Vec<&'a str>,
+}
+
+impl<'a> RepetitionUnits<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let lines: Vec<&str> = text.split('\n').filter(|w| !w.is_empty()).collect();
+        let pars: Vec<&str> = text.split("\n\n").filter(|w| !w.is_empty()).collect();
+        let words: Vec<&str> = text.unicode_words().collect();
+        Self { lines, pars, words }
+    }
+
+    pub fn fraction(&self, check: &RepetitionCheck) -> Result<f32, Error> {
+        let elements = match check.unit {
+            RepetitionUnit::Line => &self.lines,
+            RepetitionUnit::Paragraph => &self.pars,
+            RepetitionUnit::Word => &self.words,
+        };
+        MassiveWebRepetitionFilter::_rep_counter_fraction(elements, check.ngram_size, check.weighted)
+    }
+}
+
+impl MassiveWebRepetitionFilter {
+    pub fn _rep_counter_fraction<'a>(
+        elements: &'a Vec<&'a str>,
+        ngram_size: usize,
+        weighted: bool,
+    ) -> Result<f32, Error> {
+        let mut rolling_hash = CompatibleRollingHash::new(ngram_size);
+        let mut ngram_counts: FxHashMap<(u64, usize), Vec<usize>> = FxHashMap::default(); //(ngram_hash, ngram_char_len) -> [idxs where this ngram starts, ...]
+        let total_elements = elements.len();
+        let mut total_ngrams = 0;
+        let total_charlen = elements.iter().map(|v| v.len()).sum::<usize>();
+
+
+        for (idx, &element) in elements.iter().enumerate() {
+            rolling_hash.roll(element);
+
+            if rolling_hash.is_full() {
+                let hash_val = rolling_hash.get_hash();
+                let char_len = rolling_hash.get_char_length();
+
+                ngram_counts
+                    .entry((hash_val, char_len))
+                    .or_insert_with(Vec::new)
+                    .push(idx + 1 - ngram_size);
+
+                total_ngrams += 1;
+            }
+        }
+
+        // Special cases: either 0 or 1 ngrams
+        if total_ngrams == 0 {
+            if ngram_size == 1 {
+                return Ok(1.0);
+            } else {
+                return Ok(0.0);
+            }
+        } else if total_ngrams == 1 {
+            return Ok(0.0);
+        }
+
+        let repeat_frac = if ngram_size == 1 {
+            // Single ngram case:
+            if weighted {
+                // no ngrams, weighted => get total charlen of elements repeated > 1x, divide by total charlen
+                let total_repeat_len = ngram_counts
+                    .iter()
+                    .filter_map(|(k, v)| {
+                        if v.len() > 1 {
+                            Some(k.1 * v.len())
+                        } else {
+                            None
+                        }
+                    })
+                    .sum::<usize>();
+                total_repeat_len as f32 / total_charlen as f32
+            } else {
+                // no ngrams, unweighted => get total repeated elements >1x, divide by total elements
+                let total_repeats = ngram_counts
+                    .iter()
+                    .filter_map(|(_k, v)| if v.len() > 1 { Some(v.len()) } else { None })
+                    .sum::<usize>();
+                total_repeats as f32 / total_elements as f32
+            }
+        } else {
+            // Ngram size > 1 case:
+            // If ngram size is >= 4, juts find the ngram that occurs most-often and use this to generate indexes
+            // otherwise, find ALL ngrams that occur > 1
+            // Use these to generate element indices that are repeated and then count charlen / total_charlen
+
+            let repeated_start_idxs: Vec<usize> = if ngram_size <= 4 {
+                let most_common = ngram_counts
+                    .iter()
+                    .filter(|(_k, v)| v.len() > 1) // only select ngrams that repeat
+                    .max_by(|a, b| {
+                        // take max of (#repeats, ngramCharLen)
+                        let value_cmp = a.1.len().cmp(&b.1.len());
+                        if value_cmp == std::cmp::Ordering::Equal {
+                            a.0 .1.cmp(&b.0 .1)
+                        } else {
+                            value_cmp
+                        }
+                    });
+                if let Some(most_common_pair) = most_common {
+                    most_common_pair.1.to_vec()
+                } else {
+                    Vec::new()
+                }
+            } else {
+                ngram_counts
+                    .into_values()
+                    .filter(|v| v.len() > 1)
+                    .flat_map(|v| v)
+                    .collect()
+            };
+            let repeat_element_idxs: HashSet<usize> = repeated_start_idxs
+                .iter()
+                .flat_map(|v| (*v..(v + ngram_size)).collect::<Vec<usize>>())
+                .collect();
+
+            let repeat_len = repeat_element_idxs
+                .iter()
+                .map(|idx| elements[*idx].len())
+                .sum::<usize>();
+            repeat_len as f32 / total_charlen as f32
+        };
+
+        Ok(repeat_frac)
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MassiveWebRepetitionAnnotator {
+    /* Computes the same repetition fractions as massive_web_repetition_filter, without filtering, and writes them
+    all to annotation_key as {name: fraction} (names as in MASSIVE_WEB_REPETITION_CHECKS, e.g. dup_line_frac,
+    top_2gram_char_frac, dup_10gram_char_frac). With include_bounds, annotation_key.exceeded also lists the names of
+    the fractions over the filter's bounds, so an empty list means massive_web_repetition_filter would keep the doc.
+    */
+    pub text_field: String,
+    pub annotation_key: String,
+    pub include_bounds: bool,
+}
+
+impl DataProcessor for MassiveWebRepetitionAnnotator {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let annotation_key = get_default(config, "annotation_key", String::from("metadata.repetition"));
+        let include_bounds = get_default(config, "include_bounds", false);
+        Ok(Self { text_field, annotation_key, include_bounds })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+        let units = RepetitionUnits::new(text);
+
+        let mut annotation = serde_json::Map::new();
+        let mut exceeded: Vec<&str> = Vec::new();
+        for check in MASSIVE_WEB_REPETITION_CHECKS.iter() {
+            let rep_frac = units.fraction(check)?;
+            if rep_frac > check.upper_bound {
+                exceeded.push(check.name);
+            }
+            annotation.insert(check.name.to_string(), json!(rep_frac));
+        }
+        if self.include_bounds {
+            annotation.insert(String::from("exceeded"), json!(exceeded));
+        }
+        json_set(&mut data, &self.annotation_key, Value::Object(annotation))?;
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct DuplicateLineRatioFilter {
+    // Simple alternative to the Gopher repetition filter: drops docs where the fraction of lines that exactly
+    // repeat an earlier line is > max_ratio. Blank lines are ignored.
+    // With count_chars, the fraction is over characters (chars in repeated lines / chars in all lines)
+    pub text_field: String,
+    pub max_ratio: f32,
+    pub count_chars: bool,
+}
+
+impl DataProcessor for DuplicateLineRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let max_ratio = get_default(config, "max_ratio", 1.0) as f32;
+        let count_chars = get_default(config, "count_chars", false);
+        ensure!((0.0..=1.0).contains(&max_ratio), "max_ratio must be in [0, 1]");
+        Ok(Self { text_field, max_ratio, count_chars })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+
+        if self.duplicate_line_ratio(text) > self.max_ratio {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+}
+
+impl DuplicateLineRatioFilter {
+    pub fn duplicate_line_ratio(&self, text: &str) -> f32 {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let (mut total, mut duplicated) = (0, 0);
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let weight = if self.count_chars { line.chars().count() } else { 1 };
+            total += weight;
+            if !seen.insert(line) {
+                duplicated += weight;
+            }
+        }
+        if total == 0 {
+            0.0
+        } else {
+            duplicated as f32 / total as f32
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ShortLineRatioFilter {
+    // Drops docs where the fraction of non-empty lines with fewer than min_line_chars chars (after trimming) is > max_ratio.
+    // Navigation menus, link lists and footers are mostly short lines; unlike line_len_modifier, which strips such
+    // lines, this judges the doc as a whole.
+    pub text_field: String,
+    pub min_line_chars: usize,
+    pub max_ratio: f32,
+}
+
+impl DataProcessor for ShortLineRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let min_line_chars = get_default(config, "min_line_chars", 30);
+        let max_ratio = get_default(config, "max_ratio", 1.0) as f32;
+        ensure!((0.0..=1.0).contains(&max_ratio), "max_ratio must be in [0, 1]");
+        Ok(Self { text_field, min_line_chars, max_ratio })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+
+        if self.short_line_ratio(text) > self.max_ratio {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+}
+
+impl ShortLineRatioFilter {
+    pub fn short_line_ratio(&self, text: &str) -> f32 {
+        let (mut total, mut short) = (0, 0);
+        for line in text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            total += 1;
+            if line.chars().count() < self.min_line_chars {
+                short += 1;
+            }
+        }
+        if total == 0 {
+            0.0
+        } else {
+            short as f32 / total as f32
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct NumericLineRatioFilter {
+    // Drops docs where the fraction of non-empty lines that are mostly digits (more than line_numeric_threshold of
+    // their non-whitespace chars) is > max_ratio. Catches pages dominated by data tables; ratio_line_modifier with
+    // check: numeric strips such lines instead. Docs with fewer than min_lines non-empty lines are never dropped.
+    pub text_field: String,
+    pub line_numeric_threshold: f32,
+    pub max_ratio: f32,
+    pub min_lines: usize,
+}
+
+impl DataProcessor for NumericLineRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let line_numeric_threshold = get_default(config, "line_numeric_threshold", 0.5) as f32;
+        ensure!((0.0..=1.0).contains(&line_numeric_threshold), "line_numeric_threshold must be in [0, 1]");
+        let max_ratio = get_default(config, "max_ratio", 1.0) as f32;
+        ensure!((0.0..=1.0).contains(&max_ratio), "max_ratio must be in [0, 1]");
+        let min_lines = get_default(config, "min_lines", 0_usize);
+        Ok(Self { text_field, line_numeric_threshold, max_ratio, min_lines })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Text field '{}' not found or not a string", self.text_field))?;
+
+        let (total, numeric) = self.count_numeric_lines(text);
+        if total >= self.min_lines && total > 0 && numeric as f32 / total as f32 > self.max_ratio {
+            Ok(None)
+        } else {
+            Ok(Some(data))
+        }
+    }
+}
+
+impl NumericLineRatioFilter {
+    pub fn count_numeric_lines(&self, text: &str) -> (usize, usize) {
+        // (non-empty lines, numeric lines)
+        let (mut total, mut numeric) = (0, 0);
+        for line in text.lines() {
+            let (chars, digits) = line
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .fold((0, 0), |(chars, digits), c| (chars + 1, digits + c.is_ascii_digit() as usize));
+            if chars == 0 {
+                continue;
+            }
+            total += 1;
+            if digits as f32 / chars as f32 > self.line_numeric_threshold {
+                numeric += 1;
+            }
+        }
+        (total, numeric)
+    }
+}
+
+/// Alternative: True rolling hash that matches original hash values
+/// This version computes the same hash as the original but still optimizes other aspects
+struct CompatibleRollingHash<'a> {
+    window: VecDeque<&'a str>,
+    window_size: usize,
+    char_length: usize,
+}
+
+impl<'a> CompatibleRollingHash<'a> {
+    fn new(window_size: usize) -> Self {
+        Self {
+            window: VecDeque::with_capacity(window_size),
+            window_size,
+            char_length: 0,
+        }
+    }
+
+    fn roll(&mut self, new_element: &'a str) -> Option<&'a str> {
+        // Add new element
+        self.window.push_back(new_element);
+        self.char_length += new_element.len();
+
+        // Remove oldest if window is full
+        if self.window.len() > self.window_size {
+            let removed = self.window.pop_front().unwrap();
+            self.char_length -= removed.len();
+            Some(removed)
+        } else {
+            None
+        }
+    }
+
+    fn get_hash(&self) -> u64 {
+        // Hash the entire VecDeque to match original
+        let mut hasher = FxHasher::default();
+        self.window.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get_char_length(&self) -> usize {
+        self.char_length
+    }
+
+    fn is_full(&self) -> bool {
+        self.window.len() >= self.window_size
+    }
+}
+
+
+
+
+#[derive(Serialize, Debug)]
+pub struct WordCountAdder {
+    // Adds a field which is the count of how many words are in the text_field
+    pub text_field: String,
+    pub word_count_field: String,
+}
+impl DataProcessor for WordCountAdder {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let word_count_field = get_default(
+            config,
+            "word_count_field",
+            String::from("original_word_count"),
+        );
+
+        Ok(Self {
+            text_field,
+            word_count_field,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap();
+        let word_count = text.unicode_words().count();
+        json_set(&mut data, &self.word_count_field, word_count.into()).unwrap();
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RatioLineModifier {
+    // Modifies docs to keep only lines that have not-too-many uppercase chars or digits
+    pub text_field: String,
+    pub upper_bound: f32,
+    pub check: String,
+}
+
+impl DataProcessor for RatioLineModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let upper_bound = json_get(config, "upper_bound")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| anyhow!("Config requires a number 'upper_bound'"))? as f32;
+        let check = get_required_str(config, "check")?;
+        ensure!(
+            ["uppercase", "numeric"].contains(&&check.as_str()),
+            format!(
+                "Check must be one of {{uppercase, numeric}} and not {:?}",
+                check
+            )
+        );
+
+        Ok(Self {
+            text_field,
+            upper_bound,
+            check,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        let mut passing_lines: Vec<&str> = Vec::new();
+        for line in lines {
+            if line.len() == 0 {
+                passing_lines.push(line);
+                continue;
+            }
+            let line_len = std::cmp::max(line.len(), 1) as f32;
+            let count = if &self.check == "uppercase" {
+                line.chars()
+                    .filter(|v| v.is_uppercase())
+                    .collect::<Vec<_>>()
+                    .len() as f32
+            } else {
+                line.chars()
+                    .filter(|v| v.is_digit(10))
+                    .collect::<Vec<_>>()
+                    .len() as f32
+            };
+            if count / line_len <= self.upper_bound {
+                passing_lines.push(line)
+            }
+        }
+
+        json_set(
+            &mut data,
+            &self.text_field,
+            serde_json::Value::String(passing_lines.join("\n")),
+        )
+        .unwrap();
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RegexLineModifier {
+    // Modifies lines to only keep those that don't have any regex matches
+    // Note that we automatically lowercase the text we query!
+    pub text_field: String,
+    pub regex_string: String, //
+    #[serde(skip)]
+    pub regex: Regex,
+}
+
+impl DataProcessor for RegexLineModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let counter_regex = r"^\W*\d(?:,|\.|\d)*(?:K|k|M|m|B|b)?\s+(?:likes|shares|comments|retweets|reposts|quotes|bookmarks|upvotes|downvotes|downloads|views|followers)\W*$".to_string();
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let regex_string = get_default(config, "regex", counter_regex);
+        let regex = Regex::new(&regex_string)?;
+
+        Ok(Self {
+            text_field,
+            regex_string,
+            regex,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        let passing_lines: Vec<_> = lines
+            .iter()
+            .filter(|line| !self.regex.is_match(&line.to_lowercase()))
+            .map(|&l| l)
+            .collect();
+        if passing_lines.len() == 0 {
+            return Ok(None);
+        }
+
+        json_set(
+            &mut data,
+            &self.text_field,
+            serde_json::Value::String(passing_lines.join("\n")),
+        )
+        .unwrap();
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct LineLenModifier {
+    // Modifes lines to only keep those that have >= lower_bound words
+    pub text_field: String,
+    pub lower_bound: usize,
+}
+
+impl DataProcessor for LineLenModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let lower_bound = get_default(config, "lower_bound", 0);
+
+        Ok(Self {
+            text_field,
+            lower_bound,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let lines: Vec<&str> = text.split('\n').collect();
+
+        let passing_lines: Vec<_> = lines
+            .iter()
+            .filter(|line| line.unicode_words().collect::<Vec<_>>().len() >= self.lower_bound || line.len() == 0)
+            .map(|&l| l)
+            .collect();
+        if passing_lines.iter().map(|v| v.len()).sum::<usize>() == 0 {
+            return Ok(None);
+        }
+
+        json_set(
+            &mut data,
+            &self.text_field,
+            serde_json::Value::String(passing_lines.join("\n")),
+        )
+        .unwrap();
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubstringLineModifier {
+    // Modifies lines to only keep those that don't have any words from the banlist (or just removes those words themselves)
+    pub text_field: String,
+    pub banlist: String,
+    pub max_len: usize,
+    pub remove_substring_only: bool,
+    pub location: String,
+    #[serde(skip)]
+    regex: OnceCell<Regex>
+
+}
+
+impl DataProcessor for SubstringLineModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let banlist = get_required_str(config, "banlist")?;
+        let max_len = get_default(config, "max_len", usize::MAX);
+        let remove_substring_only = get_default(config, "remove_substring_only", true);
+        let location = get_default(config, "location", String::from("any"));
+
+        Ok(Self {
+            text_field,
+            banlist,
+            max_len,
+            remove_substring_only,
+            location,
+            regex: OnceCell::new(),
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap();
+
+        // Get or compile regex once
+        let regex = self.regex.get_or_try_init(|| {
+            let (pattern, _) = match self.location.as_str() {
+                "prefix" => (format!(r"^(?:{})\s?", self.banlist), ""),
+                "suffix" => (format!(r"\s?(?:{})$", self.banlist), ""),
+                _ => (format!(r"\s?(?:{})\s?", self.banlist), " "),
+            };
+            Regex::new(&pattern)
+        })?;
+
+        let replacement = match self.location.as_str() {
+            "prefix" | "suffix" => "",
+            _ => " ",
+        };
+
+        // Use iterator with filter_map for better performance
+        let processed_lines: Vec<String> = text
+            .lines()
+            .filter_map(|line| {
+                // Skip empty lines processing if they should be kept as-is
+                if line.is_empty() {
+                    return Some(String::new());
+                }
+
+                // Check max_len constraint first (cheaper operation)
+                if self.max_len != usize::MAX {
+                    let word_count = line.unicode_words().count();
+                    if word_count > self.max_len {
+                        return Some(line.to_string());
+                    }
+                }
+
+                if self.remove_substring_only {
+                    let cleaned = regex.replace_all(line, replacement);
+                    // Only keep non-empty trimmed lines
+                    if !cleaned.trim().is_empty() {
+                        Some(cleaned.into_owned())
+                    } else {
+                        None
+                    }
+                } else {
+                    // If regex matches, skip the line (return None)
+                    if regex.is_match(line) {
+                        None
+                    } else {
+                        Some(line.to_string())
+                    }
+                }
+            })
+            .collect();
+
+        json_set(
+            &mut data,
+            &self.text_field,
+            serde_json::Value::String(processed_lines.join("\n")),
+        )?;
+
+        Ok(Some(data))
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct WordRemovalRatioFilter {
+    // Only keeps docs that haven't removed too many words (from a previous, old, word_count_field)
+    pub text_field: String,
+    pub word_count_field: String,
+    pub upper_bound: f32,
+}
+
+impl DataProcessor for WordRemovalRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let word_count_field = get_default(
+            config,
+            "word_count_field",
+            String::from("original_word_count"),
+        );
+        let upper_bound = get_default(config, "upper_bound", 1.0) as f32;
+        Ok(Self {
+            text_field,
+            word_count_field,
+            upper_bound,
+        })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+        let old_word_count: usize = json_get(&data, &self.word_count_field)
+            .unwrap()
+            .as_u64()
+            .unwrap() as usize;
+        let cur_word_count: usize = text.unicode_words().collect::<Vec<_>>().len();
+
+        let removed_ratio = ((old_word_count - cur_word_count) as f32) / old_word_count as f32;
+        if removed_ratio <= self.upper_bound {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct Madlad400SentenceAnnotator {
+    // Does the madlad400 sec2.3 filter : https://openreview.net/pdf?id=Y45ZCxslFx
+    // But just annotates
+    pub text_field: String,
+    pub sentence_lower_bound: usize,        // defaults to 5
+    pub sentence_question_upper_bound: f32, // defaults to 20%
+    pub annotation_key: String, // defaults to metadata.madlad
+    pub rules_to_include: Vec<usize>, // If empty, includes ALL rules. Otherwise just counts the rules here
+
+    // document consistency
+    pub fast_text_file: String, // path to fasttext model
+    #[serde(skip)]
+    pub model: FastText,
+    pub langid_field: String, // field where the document level language is
+
+    // list case
+    pub case_upper_bound: f32,       // defaults to 0.50
+    pub case_tok_lower_bound: usize, // defaults to 12
+
+    // abnormal lengths
+    pub char_len_lower_bound: usize, // defaults to 20
+    pub char_len_upper_bound: usize, // defaults to 500
+
+    // technical chars
+    pub tech_lower_bound: f32, // defaults to 0.20
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub tech_charset: HashSet<char>,
+
+    // cursed regxes
+    pub cursed_regex_file: String, // path to cursed strings // last 4 are regexes
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub cursed_inclusions: AhoCorasick,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub cursed_regexes: Vec<Regex>,
+}
+
+impl DataProcessor for Madlad400SentenceAnnotator {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let sentence_lower_bound = get_default(config, "sentence_lower_bound", 5);
+        let sentence_question_upper_bound =
+            get_default(config, "sentence_question_upper_bound", 0.20) as f32;
+
+        let annotation_key = get_default(config, "annotation_key", String::from("metadata.madlad"));
+        let rules_to_include: Vec<usize> = get_default(config, "rules_to_include", vec![])
+            .into_iter()
+            .map(|v| v.as_u64().map(|v| v as usize).ok_or_else(|| anyhow!("rules_to_include must be rule numbers")))
+            .collect::<Result<Vec<usize>, Error>>()?;
+        let fast_text_file = get_required_str(config, "fast_text_file")?;
+        let model = load_fasttext_model(&fast_text_file)?;
+        let langid_field = get_required_str(config, "langid_field")?;
+
+        let case_upper_bound = get_default(config, "case_upper_bound", 0.50) as f32;
+        let case_tok_lower_bound = get_default(config, "case_tok_lower_bound", 12);
+
+        let char_len_lower_bound = get_default(config, "char_len_lower_bound", 20);
+        let char_len_upper_bound = get_default(config, "char_len_upper_bound", 500);
+
+        let tech_lower_bound = get_default(config, "tech_lower_bound", 0.20) as f32;
+        let tech_charset: HashSet<char> = [
+            '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '{', '}', '+', '/', '(', ')', '>',
+        ]
+        .into_iter()
+        .collect();
+
+        let cursed_regex_file = get_required_str(config, "cursed_regex_file")?;
+        let cursed_regex_data = read_pathbuf_to_mem(&PathBuf::from(cursed_regex_file.clone()))?;
+        let cursed_regex_lines: Vec<_> = cursed_regex_data.lines().collect::<Result<_, _>>()?;
+        // The file is substrings followed by exactly 4 regexes
+        ensure!(cursed_regex_lines.len() >= 4, "cursed_regex_file {:?} needs at least 4 lines", cursed_regex_file);
+        let cursed_inclusions =
+            AhoCorasick::new(&cursed_regex_lines[..cursed_regex_lines.len() - 4])?;
+        let mut cursed_regexes: Vec<Regex> = Vec::new();
+        for el in &cursed_regex_lines[cursed_regex_lines.len() - 4..] {
+            cursed_regexes.push(Regex::new(el)?);
+        }
+        Ok(Self {
+            text_field,
+            sentence_lower_bound,
+            sentence_question_upper_bound,
+            annotation_key,
+            rules_to_include,
+            fast_text_file,
+            model,
+            langid_field,
+            case_upper_bound,
+            case_tok_lower_bound,
+            char_len_lower_bound,
+            char_len_upper_bound,
+            tech_lower_bound,
+            tech_charset,
+            cursed_regex_file,
+            cursed_inclusions,
+            cursed_regexes,
+        })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        // Setup for filtering
+        let text = json_get(&data, &self.text_field)
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let rules_to_include: HashSet<usize> = if self.rules_to_include.len() == 0 {
+            vec![1,2,3,4,5].into_iter().map(|v| v).collect()
+        } else {
+            self.rules_to_include.iter().map(|v| *v).collect()
+        };
+
+        let sentences = madlad_split_sentences(&text);
+        let num_sentences = sentences.len();
+        let madlad_status = self.annotation_key.clone() + "_status";
+        let mut tracker: FxHashMap<&str, Vec<usize>> = FxHashMap::default();
+        tracker.entry("num_sentences").or_default().push(num_sentences);
+
+        if num_sentences < self.sentence_lower_bound {
+            json_set(&mut data, &madlad_status, json!("killed:too_short")).unwrap();
+            return Ok(Some(data));
+        }
+
+        let doc_lang = json_get(&data, &self.langid_field)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .iter()
+            .max_by(|(_, a), (_, b)| {
+                (&(a.as_f64().unwrap()))
+                    .partial_cmp(&(b.as_f64().unwrap()))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap()
+            .0;
+
+        // Tracker maps rule -> sentence ids for which this pops
+
+        let mut sus_sentences: HashSet<usize> = HashSet::new();
+        let sentence_threshold = num_sentences as f32 * self.sentence_question_upper_bound;
+        // Loop through sentences
+
+
+        for (sentence_num, sentence) in sentences.into_iter().enumerate() {
+            // And finally langid
+            if rules_to_include.contains(&1) && self.document_consistency(sentence, doc_lang).unwrap() {
+                tracker.entry("rule.1").or_default().push(sentence_num);
+                sus_sentences.insert(sentence_num);
+            }
+
+            // Then check case
+            if rules_to_include.contains(&2) && self.list_case(sentence).unwrap() {
+                tracker.entry("rule.2").or_default().push(sentence_num);
+                sus_sentences.insert(sentence_num);
+
+            }
+
+            // Check abnormal len sentences
+            if rules_to_include.contains(&3) && self.abnormal_len_sentence(sentence).unwrap() {
+                tracker.entry("rule.3").or_default().push(sentence_num);
+                sus_sentences.insert(sentence_num);
+
+            }
+
+
+            // Then check technical character counts
+            if rules_to_include.contains(&4) && self.technical_characters(sentence).unwrap() {
+                tracker.entry("rule.4").or_default().push(sentence_num);
+                sus_sentences.insert(sentence_num);
+
+            }
+
+
+            // Then do cursed regex stuff
+            if rules_to_include.contains(&5) && self.check_cursed_regexes(sentence).unwrap() {
+                tracker.entry("rule.5").or_default().push(sentence_num);
+                sus_sentences.insert(sentence_num);
+
+            }
+        }
+
+        let tracker_json: Value = json!(tracker);
+        if sus_sentences.len() as f32 > sentence_threshold {
+            json_set(&mut data, &madlad_status, json!("killed:too_many_sus_sentences")).unwrap();
+        } else {
+            json_set(&mut data, &madlad_status, json!("survived")).unwrap();
+        }
+        json_set(&mut data, &self.annotation_key, tracker_json).unwrap();
+        Ok(Some(data))
+
+    }
+}
+
+impl Madlad400SentenceAnnotator {
+    // Individual checks. Returns True if the sentence IS questionable!
+    pub fn abnormal_len_sentence(&self, sentence: &str) -> Result<bool, Error> {
+        Ok(
+            sentence.len() < self.char_len_lower_bound
+                || sentence.len() > self.char_len_upper_bound,
+        )
+    }
+
+    pub fn technical_characters(&self, sentence: &str) -> Result<bool, Error> {
+        let technical_chars = sentence
             .chars()
             .filter(|c| self.tech_charset.contains(c))
             .count();
@@ -2097,485 +4132,1257 @@ impl Madlad400SentenceAnnotator {
             })
             .count();
 
-        Ok(cap_counts as f32 > words.len() as f32 * self.case_upper_bound)
+        Ok(cap_counts as f32 > words.len() as f32 * self.case_upper_bound)
+    }
+
+    pub fn check_cursed_regexes(&self, sentence: &str) -> Result<bool, Error> {
+        if let Some(_) = self.cursed_inclusions.find_iter(sentence).next() {
+            return Ok(true);
+        }
+        let has_curse = self.cursed_regexes.iter().any(|re| {
+            if let Some(_) = re.find(sentence) {
+                true
+            } else {
+                false
+            }
+        });
+        Ok(has_curse)
+    }
+
+    pub fn document_consistency(&self, sentence: &str, doc_lang: &str) -> Result<bool, Error> {
+        // Do langid
+        let Some((sentence_lang, _)) = fasttext_top1(&self.model, sentence)? else {
+            return Ok(true);
+        };
+        Ok(sentence_lang != doc_lang)
+    }
+}
+
+
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct Madlad400RuleFilter {
+    // Filters based on the madlad rules
+    // Removes if too_short OR if any of the rule filters applies
+    // A rule group applies if its suspicious sentences are >= threshold * num_sentences, or, when set,
+    // > max_sus_fraction * num_sentences (instead of threshold) or >= min_sus_sentences (in addition)
+    pub annotation_key: String, // defaults to metadata.madlad
+    pub status_key: String, // defaults to metadata.madlad_status
+    pub remove_too_short: bool, // remove if status is too short, defaults to false
+    pub rules_to_remove: Vec<Vec<usize>>,
+    pub threshold: f64, // defaults to 0.2
+    pub max_sus_fraction: Option<f64>, // replaces threshold (strictly above this fraction), can't set both
+    pub min_sus_sentences: Option<usize>, // absolute count that's too many regardless of doc length
+}
+
+impl DataProcessor for Madlad400RuleFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+
+        let annotation_key = get_default(config, "annotation_key", String::from("metadata.madlad"));
+        let status_key = get_default(config, "status_key", String::from("metadata.madlad_status"));
+        let remove_too_short = get_default(config, "remove_too_short", false);
+        let rules_to_remove = get_default(config, "rules_to_remove", Vec::new());
+        let rules_to_remove: Vec<Vec<usize>> = if rules_to_remove.len() == 0 {
+            Vec::new()
+        } else {
+            rules_to_remove
+                .into_iter()
+                .map(|v| {
+                    v.as_array()
+                        .and_then(|rules| rules.iter().map(|k| k.as_u64().map(|k| k as usize)).collect::<Option<Vec<usize>>>())
+                        .ok_or_else(|| anyhow!("rules_to_remove must be lists of rule numbers"))
+                })
+                .collect::<Result<Vec<Vec<usize>>, Error>>()?
+        };
+
+        let threshold = get_default(config, "threshold", 0.2);
+        let max_sus_fraction = json_get(config, "max_sus_fraction").and_then(|v| v.as_f64());
+        ensure!(
+            max_sus_fraction.is_none() || json_get(config, "threshold").is_none(),
+            "max_sus_fraction replaces threshold, set only one of them"
+        );
+        if let Some(max_sus_fraction) = max_sus_fraction {
+            ensure!(
+                (0.0..=1.0).contains(&max_sus_fraction),
+                format!("max_sus_fraction must be in [0, 1], not {:?}", max_sus_fraction)
+            );
+        }
+        let min_sus_sentences = json_get(config, "min_sus_sentences").and_then(|v| v.as_u64()).map(|v| v as usize);
+        ensure!(min_sus_sentences != Some(0), "min_sus_sentences must be positive");
+
+        Ok(Self {
+            annotation_key,
+            status_key,
+            remove_too_short,
+            rules_to_remove,
+            threshold,
+            max_sus_fraction,
+            min_sus_sentences,
+        })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+    	let status: String = json_get(&data, &self.status_key).unwrap().as_str().unwrap().to_string();
+
+    	if status == "killed:too_short" {
+    		if self.remove_too_short {
+    			return Ok(None);
+    		} else {
+    			return Ok(Some(data));
+    		}
+
+    	}
+
+
+        let annotation_data: HashMap<String, Vec<usize>> = serde_json::from_value(json_get(&data, &self.annotation_key).unwrap().clone()).unwrap();
+        let num_sentences = annotation_data.get("num_sentences").unwrap()[0];
+        for rule in &self.rules_to_remove {
+            let mut sus_sentences: HashSet<usize> = HashSet::new();
+            for subrule in rule {
+                let key = format!("rule.{:}", subrule);
+                if let Some(sentence_ids) = annotation_data.get(&key) {
+                    for sentence_id in sentence_ids {
+                        sus_sentences.insert(*sentence_id);
+                    }
+                }
+            }
+            if self.too_many_sus(sus_sentences.len(), num_sentences) {
+                return Ok(None);
+            }
+        }
+
+
+        Ok(Some(data))
+
+    }
+}
+
+impl Madlad400RuleFilter {
+    pub fn too_many_sus(&self, num_sus: usize, num_sentences: usize) -> bool {
+        let too_large_fraction = match self.max_sus_fraction {
+            Some(max_sus_fraction) => num_sus as f64 > num_sentences as f64 * max_sus_fraction,
+            None => num_sus as f64 >= num_sentences as f64 * self.threshold,
+        };
+        too_large_fraction || self.min_sus_sentences.is_some_and(|min_sus| num_sus >= min_sus)
+    }
+}
+
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+#[derive(Serialize)]
+pub struct IntervalFilter {
+    pub text_field: String, // defaults to global text field, or "text"
+    pub interval_field: String, // Required! If intervals don't exist, doc is left as is
+    pub fuzzy_merge: bool, // defaults to false
+
+    pub merge_fuzziness: f64, // only necessary if fuzzy_merge is true
+    pub output_text_field: String, // defaults to text field if not present
+    pub invert: bool, // defaults to false; if true, keep only the text inside the intervals instead of scrubbing it
+    pub separator: String, // with invert, joins the kept spans; defaults to "\n"
+    pub on_invalid_interval: String, // repair (default) | skip_interval | error | drop_doc
+}
+
+impl DataProcessor for IntervalFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text_field"));
+        let interval_field = get_required_str(config, "interval_field")?;
+        let fuzzy_merge = get_default(config, "fuzzy_merge", false);
+        let merge_fuzziness = get_default(config, "merge_fuzziness", 1.0 as f64);
+        let output_text_field = get_default(config, "output_text_field", text_field.clone());
+        let invert = get_default(config, "invert", false);
+        let separator = get_default(config, "separator", String::from("\n"));
+        let on_invalid_interval = get_default(config, "on_invalid_interval", String::from("repair"));
+        ensure!(
+            ["repair", "skip_interval", "error", "drop_doc"].contains(&on_invalid_interval.as_str()),
+            format!("on_invalid_interval must be one of {{repair, skip_interval, error, drop_doc}} and not {:?}", on_invalid_interval)
+        );
+        Ok(Self {text_field, interval_field, fuzzy_merge, merge_fuzziness, output_text_field, invert, separator, on_invalid_interval})
     }
 
-    pub fn check_cursed_regexes(&self, sentence: &str) -> Result<bool, Error> {
-        if let Some(_) = self.cursed_inclusions.find_iter(sentence).next() {
-            return Ok(true);
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+
+        // Collect things we need frorm the data
+        let text = json_get(&data, &self.text_field).unwrap().as_str().unwrap().to_string();
+        let base_intervals = match json_get(&data, &self.interval_field) {
+            Some(base_intervals) => base_intervals.as_array().ok_or_else(|| {
+                anyhow!("Interval field {:?} must be a list of [start, end] pairs, not {}", self.interval_field, base_intervals)
+            })?,
+            None => return Ok(Some(data)),
+        };
+
+        // Annotations computed on a differently-encoded copy of the text can point past its end or into the middle
+        // of a char; on_invalid_interval decides what happens to those instead of panicking on the slice
+        let mut intervals: Vec<(usize, usize)> = Vec::with_capacity(base_intervals.len());
+        for raw in base_intervals {
+            let parsed = parse_interval(raw);
+            match parsed {
+                Some(interval) if interval_is_valid(&text, interval) => intervals.push(interval),
+                _ => match self.on_invalid_interval.as_str() {
+                    "skip_interval" => (),
+                    "drop_doc" => return Ok(None),
+                    "error" => bail!(
+                        "Invalid interval {} in {:?} for text of {} bytes", raw, self.interval_field, text.len()
+                    ),
+                    // repair: anything that parsed is clamped/widened below, malformed entries can only be skipped
+                    _ => intervals.extend(parsed),
+                },
+            }
         }
-        let has_curse = self.cursed_regexes.iter().any(|re| {
-            if let Some(_) = re.find(sentence) {
-                true
-            } else {
-                false
+        let intervals = clamp_intervals(&text, intervals);
+
+        // Sort and union overlapping intervals (the fuzzy merge expects sorted, disjoint intervals),
+        // then fuzzy merge them if that's a thing we need to do
+        let intervals = merge_intervals(intervals, false);
+        let intervals = if self.fuzzy_merge {
+            fuzzy_interval_merge(intervals, self.merge_fuzziness)
+        } else {
+            intervals
+        };
+
+
+        let output = if self.invert {
+            // Keep only the interval data
+            intervals.iter().map(|(start, end)| &text[*start..*end]).collect::<Vec<&str>>().join(&self.separator)
+        } else {
+            // Scrub out the interval data from the text
+            let mut output = String::with_capacity(text.len());
+            let mut last_excluded = 0;
+            for interval in intervals {
+                let start = interval.0;
+                let end = interval.1;
+                output.push_str(&text[last_excluded..start]);
+                last_excluded = end;
             }
-        });
-        Ok(has_curse)
+            if last_excluded < text.len() {
+                output.push_str(&text[last_excluded..]);
+            }
+            output
+        };
+
+        if output.len() == 0 {
+            return Ok(None);
+        }
+
+        json_set(&mut data, &self.output_text_field, serde_json::Value::String(output)).unwrap();
+        Ok(Some(data))
     }
 
-    pub fn document_consistency(&self, sentence: &str, doc_lang: &str) -> Result<bool, Error> {
-        // Do langid
-        let sentence_lang_preds = &self
-            .model
-            .predict(&sentence.replace("\n", " "), 1, 0.0)
-            .unwrap();
-        if sentence_lang_preds.len() == 0 {
-            return Ok(true);
+}
+
+fn parse_interval(interval: &Value) -> Option<(usize, usize)> {
+    // A [start, end] pair of non-negative integers; anything else is malformed
+    match interval.as_array()?.as_slice() {
+        [start, end] => Some((start.as_u64()? as usize, end.as_u64()? as usize)),
+        _ => None,
+    }
+}
+
+fn interval_is_valid(text: &str, (start, end): (usize, usize)) -> bool {
+    // Empty intervals are fine (they just do nothing), reversed ones are not
+    start <= end && end <= text.len() && text.is_char_boundary(start) && text.is_char_boundary(end)
+}
+
+fn clamp_intervals(text: &str, intervals: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    /* Makes annotated byte intervals safe to slice text with (bad annotations used to panic):
+    ends past the text are clamped to its length, offsets inside a multi-byte char are widened to the whole char
+    (so a scrub removes it, and a keep keeps it), and intervals that end up empty or reversed are dropped.
+    */
+    intervals
+        .into_iter()
+        .map(|(start, end)| (floor_char_boundary(text, start), ceil_char_boundary(text, end)))
+        .filter(|(start, end)| start < end)
+        .collect()
+}
+
+fn fuzzy_interval_merge(intervals: Vec<(usize, usize)>, merge_fuzziness: f64) -> Vec<(usize, usize)> {
+    let forward = fuzzy_sandwich_intervals(&intervals, true, merge_fuzziness);
+    let backward = fuzzy_sandwich_intervals(&intervals, false, merge_fuzziness);
+    merge_sorted_interval_pair(forward, backward)
+}
+
+
+fn merge_intervals(mut v: Vec<(usize, usize)>, already_sorted: bool) -> Vec<(usize, usize)>{
+    if !already_sorted {
+        v.sort_by_key(|(key, _)| key.clone());
+    }
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (s, e) in v {
+        if merged.len() == 0 {
+            merged.push((s, e));
+        } else if merged.last().unwrap().1 >= s {
+            let (old_s, old_e) = merged.pop().unwrap();
+            merged.push((old_s, cmp::max(e, old_e)));
+        } else {
+            merged.push((s, e));
         }
-        let sentence_lang = &sentence_lang_preds
-            .iter()
-            .max_by(|a, b| {
-                (&a.prob)
-                    .partial_cmp(&b.prob)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-            })
-            .unwrap()
-            .label;
-        Ok(sentence_lang != doc_lang)
     }
+    merged
 }
 
+fn merge_sorted_interval_pair(u: Vec<(usize, usize)>, w: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    // Given two sorted lists of intervals, does a merge of the pairs, and then unions all intervals
+    let mut v : Vec<(usize, usize)> = Vec::new();
+    let mut ui = 0;
+    let mut wi = 0;
+    while ui < u.len() && wi < w.len() {
+        let (us, ue) = u[ui];
+        let (ws, we) = w[wi];
+        if us < ws || (us == ws && ue <= we){
+            v.push((us, ue));
+            ui += 1;
+        } else {
+            v.push((ws, we));
+            wi += 1
+        }
+    }
+    while ui < u.len() {
+        v.push(u[ui]);
+        ui += 1;
+    }
 
+    while wi < w.len() {
+        v.push(w[wi]);
+        wi += 1;
+    }
 
-#[derive(Derivative)]
-#[derivative(Debug)]
-#[derive(Serialize)]
-pub struct Madlad400RuleFilter {
-    // Filters based on the madlad rules
-    // Removes if too_short OR if any of the rule filters applies
-    pub annotation_key: String, // defaults to metadata.madlad
-    pub status_key: String, // defaults to metadata.madlad_status
-    pub remove_too_short: bool, // remove if status is too short, defaults to false
-    pub rules_to_remove: Vec<Vec<usize>>,
-    pub threshold: f64, // defaults to 0.2
+    merge_intervals(v, true)
 }
 
-impl DataProcessor for Madlad400RuleFilter {
-    fn new(config: &Value) -> Result<Self, Error> {
 
-        let annotation_key = get_default(config, "annotation_key", String::from("metadata.madlad"));
-        let status_key = get_default(config, "status_key", String::from("metadata.madlad_status"));
-        let remove_too_short = get_default(config, "remove_too_short", false);
-        let rules_to_remove = get_default(config, "rules_to_remove", Vec::new());
-        let rules_to_remove: Vec<Vec<usize>> = if rules_to_remove.len() == 0 {
-            Vec::new()
+fn fuzzy_sandwich_intervals(v: &Vec<(usize, usize)>, foward: bool, threshold: f64) -> Vec<(usize, usize)> {
+    // Given SORTED list of DISJOINT intervals, scans in the forward/!forward direction
+    // And collects all intervals that:
+    // 1. Start and end at an interval
+    // 2. Have >=threshold of the range contained in an input interval
+    // e.g. [(0,9), (10, 20)] -> [(0,20)] (when the threshold is <=0.95)
+
+    let n = v.len();
+    let iter_range : Vec<_> = if foward {
+        (0..n).collect()
+    } else {
+        (0..n).rev().collect()
+    };
+    let mut output : Vec<(i32, i32, i32)> = Vec::new();
+    for idx in iter_range {
+
+
+        let (next_s, next_e) = v[idx];
+        let next_s = next_s as i32;
+        let next_e = next_e as i32;
+
+        if output.len() == 0 {
+            output.push((next_s, next_e, next_e - next_s));
+            continue;
+        }
+        let (cur_s, cur_e, cur_w) = output.last().unwrap();
+        let new_interval = (cmp::min(next_s, *cur_s as i32),
+                            cmp::max(next_e, *cur_e as i32),
+                            *cur_w  as i32 + next_e - next_s);
+        if new_interval.2 as f64 >= (new_interval.1 - new_interval.0) as f64 * threshold {
+            output.pop().unwrap();
+            output.push(new_interval);
         } else {
-            rules_to_remove.into_iter().map(|v| v.as_array().unwrap().into_iter().map(|k| k.clone().as_u64().unwrap() as usize).collect::<Vec<usize>>()).collect::<Vec<Vec<usize>>>()
-        };
+            output.push((next_s, next_e, next_e - next_s));
+        }
+    }
 
-        let threshold = get_default(config, "threshold", 0.2);
+    output
+        .iter()
+        .map(|(a,b, _)| (*a as usize, *b as usize))
+        .collect()
+}
+
+
+
+
+#[derive(Serialize, Debug)]
+pub struct DDMaxGetter {
+    /* {attributes: {
+        <prefix>_KEY : [[val]]
+    }}
+    of attributes keys that start with prefix, returns the max KEY
+    */
+    pub main_attribute: String, // default to "attributes"
+    pub prefix: String,
+    pub output_attribute: String,  // where the max KEY goes
+}
 
+impl DataProcessor for DDMaxGetter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let main_attribute = get_default(config, "main_attribute", String::from("attributes"));
+
+        let prefix = get_required_str(config, "prefix")?;
+        let output_attribute = get_required_str(config, "output_attribute")?;
         Ok(Self {
-            annotation_key,
-            status_key,
-            remove_too_short,
-            rules_to_remove,
-            threshold
+            main_attribute,
+            prefix,
+            output_attribute
         })
+
     }
 
-    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
-    	let status: String = json_get(&data, &self.status_key).unwrap().as_str().unwrap().to_string();
 
-    	if status == "killed:too_short" {
-    		if self.remove_too_short {
-    			return Ok(None);
-    		} else {
-    			return Ok(Some(data));
-    		}
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let input_dict = json_get(&data, &self.main_attribute).unwrap();
+        // claude: loop over key,val pairs in input_dict
+        // and for keys that start with prefix, get their value as a [[f64]] (or just an f64)
+
+        let mut max_key = String::from("null");
+        let mut max_val: f64 = -1.0;
+
+        if let Value::Object(map) = input_dict {
+            for (key, value) in map {
+                if key.starts_with(&self.prefix) {
+
+					// if the value is an array, get the first element of the first element (jake format)
+					// if it is a siple float, just get the value; otherwise throw an error
+                    let parsed_val = match value {
+                        Value::Array(outer) => &outer[0][0].as_f64().unwrap(),
+						Value::Number(num) => &num.as_f64().unwrap(),
+						_ => panic!("Invalid value type: {:?}", value),
+                    };
+                    if *parsed_val > max_val {
+                        max_key = key.clone();
+                        max_val = *parsed_val;
+                    }
+                }
+            }
+        }
+
+        json_set(&mut data, &self.output_attribute, serde_json::Value::String(max_key)).unwrap();
+        Ok(Some(data))
+
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct MaxExtractor {
+    /*
+    - main_attribute points to a dict with str->key floats
+    - if the max value is >= lower bound (defaults to 0.0), sets the key to be the value of output_attribute
+    */
+
+    pub main_attribute: String,
+    pub lower_bound: f64, // defaults to 0.0
+    pub output_attribute: String,
+    pub keep_nulls: bool, // defaults to true
+}
 
-    	}
 
+impl DataProcessor for MaxExtractor {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let main_attribute = get_required_str(config, "main_attribute")?;
+        let lower_bound: f64 = get_default(config, "lower_bound", 0.0);
+        let output_attribute = get_required_str(config, "output_attribute")?;
+        let keep_nulls = get_default(config, "keep_nulls", true);
+        Ok(Self {main_attribute, lower_bound, output_attribute, keep_nulls})
+    }
 
-        let annotation_data: HashMap<String, Vec<usize>> = serde_json::from_value(json_get(&data, &self.annotation_key).unwrap().clone()).unwrap();
-        let num_sentences = annotation_data.get("num_sentences").unwrap()[0];
-        let sus_threshold = num_sentences as f64 * &self.threshold;
-        for rule in &self.rules_to_remove {
-            let mut sus_sentences: HashSet<usize> = HashSet::new();
-            for subrule in rule {
-                let key = format!("rule.{:}", subrule);
-                if let Some(sentence_ids) = annotation_data.get(&key) {
-                    for sentence_id in sentence_ids {
-                        sus_sentences.insert(*sentence_id);
-                    }
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let mut max_key = String::from("");
+        let mut max_val: f64 = f64::MIN;
+        let input_dict = json_get(&data, &self.main_attribute).unwrap();
+        if let Value::Object(map) = input_dict {
+            for (key, value) in map {
+                let value = value.as_f64().unwrap();
+                if value >= max_val && value >= self.lower_bound {
+                    max_key = key.to_string();
+                    max_val = value;
                 }
             }
-            if sus_sentences.len() as f64 >= sus_threshold {
-                return Ok(None);
-            }
         }
 
 
+        if max_key.len() > 0 {
+            json_set(&mut data, &self.output_attribute, serde_json::Value::String(max_key)).unwrap();
+        } else {
+            if !&self.keep_nulls {
+                return Ok(None);
+            }
+        }
         Ok(Some(data))
 
     }
 }
 
 
-#[derive(Derivative)]
-#[derivative(Debug)]
-#[derive(Serialize)]
-pub struct IntervalFilter {
-    pub text_field: String, // defaults to global text field, or "text"
-    pub interval_field: String, // Required! If intervals don't exist, doc is left as is
-    pub fuzzy_merge: bool, // defaults to false
 
-    pub merge_fuzziness: f64, // only necessary if fuzzy_merge is true
-    pub output_text_field: String, // defaults to text field if not present
+#[derive(Serialize, Debug)]
+pub struct HashAnnotator {
+    /* Adds a hash id to every doc.
+    hash_source is a field or a list of fields. With a list the fields are hashed together: their values (strings
+    as-is, other values as json, missing/null as "") are joined with separator (default "\u{1f}", the ascii unit
+    separator) in the given order, so ["url", "title"] and ["title", "url"] give different hashes.
+    A single field (or a one-element list) hashes exactly the same as before lists were supported.
+    normalize_for_hash lowercases the hashed text and collapses every whitespace run to a single space (trimming the
+    ends) before hashing, so docs that only differ in case or formatting get the same hash. The doc itself is untouched.
+    */
+    pub hash_source: Vec<String>, // field(s) that get hashed
+    pub separator: String, // only used when hashing several fields
+    pub normalize_for_hash: bool, // defaults to false
+    pub hash_destination: String, // where the target gets hashed and save
+    pub num_bits: usize, // defaults to 128
+    pub hash_algo: String, // xxh3 (default, stable across versions) or ahash (64 bit only, matches group's legacy hash_algo)
 }
 
-impl DataProcessor for IntervalFilter {
+impl DataProcessor for HashAnnotator {
     fn new(config: &Value) -> Result<Self, Error> {
-        let text_field = get_default(config, "text_field", String::from("text_field"));
-        let interval_field = json_get(config, "interval_field").unwrap().as_str().unwrap().to_string();
-        let fuzzy_merge = get_default(config, "fuzzy_merge", false);
-        let merge_fuzziness = get_default(config, "merge_fuzziness", 1.0 as f64);
-        let output_text_field = get_default(config, "output_text_field", text_field.clone());
-        Ok(Self {text_field, interval_field, fuzzy_merge, merge_fuzziness, output_text_field})
+        let hash_source: Vec<String> = match json_get(config, "hash_source") {
+            None => vec![String::from("text")],
+            Some(Value::String(field)) => vec![field.clone()],
+            Some(Value::Array(fields)) => fields
+                .iter()
+                .map(|v| v.as_str().map(String::from).ok_or_else(|| anyhow!("hash_source fields must be strings, got {:?}", v)))
+                .collect::<Result<Vec<String>, Error>>()?,
+            Some(other) => bail!("hash_source must be a field or a list of fields, not {:?}", other),
+        };
+        ensure!(!hash_source.is_empty(), "hash_source needs at least one field");
+        let separator = get_default(config, "separator", String::from("\u{1f}"));
+        let normalize_for_hash = get_default(config, "normalize_for_hash", false);
+        let hash_destination = get_default(config, "hash_destination", String::from("metadata.text_hash"));
+        let num_bits = get_default(config, "num_bits", 128);
+        let hash_algo = get_default(config, "hash_algo", String::from("xxh3"));
+
+        ensure!(num_bits == 64 || num_bits == 128, format!("num_bits must be 64 or 128, not {}", num_bits));
+        ensure!(["xxh3", "ahash"].contains(&hash_algo.as_str()), format!("hash_algo must be xxh3 or ahash, not {:?}", hash_algo));
+        ensure!(hash_algo == "xxh3" || num_bits == 64, "hash_algo ahash only supports num_bits: 64");
+
+        Ok(Self {
+            hash_source,
+            separator,
+            normalize_for_hash,
+            hash_destination,
+            num_bits,
+            hash_algo,
+        })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-
-        // Collect things we need frorm the data
-        let text = json_get(&data, &self.text_field).unwrap().as_str().unwrap().to_string();
-        let intervals: Vec<(usize, usize)> = if let Some(base_intervals) = json_get(&data, &self.interval_field) {
-            base_intervals.as_array().unwrap().iter().map(|interval| {
-                let interval = interval.as_array().unwrap();
-                (interval[0].as_u64().unwrap() as usize, interval[1].as_u64().unwrap() as usize)
-            }).collect::<Vec<(usize, usize)>>()
+        let text = if self.hash_source.len() == 1 {
+            json_get(&data, &self.hash_source[0])
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .to_string()
         } else {
-            return Ok(Some(data));
+            self.hash_source
+                .iter()
+                .map(|field| match json_get(&data, field) {
+                    Some(Value::String(s)) => s.clone(),
+                    Some(Value::Null) | None => String::new(),
+                    Some(other) => other.to_string(),
+                })
+                .collect::<Vec<String>>()
+                .join(&self.separator)
+        };
+        let text = if self.normalize_for_hash {
+            HashAnnotator::normalize_text(&text)
+        } else {
+            text
         };
 
-        // Merge the intervals if that's a thing we need to do
-        let intervals = if self.fuzzy_merge {
-            fuzzy_interval_merge(intervals, self.merge_fuzziness)
+        let hash_val = if self.hash_algo == "ahash" {
+            Value::from(RandomState::with_seeds(1, 2, 3, 4).hash_one(text.as_str()))
         } else {
-            intervals
+            HashAnnotator::hash_text(&text, self.num_bits)
         };
 
+        json_set(&mut data, &self.hash_destination, hash_val).unwrap();
+        Ok(Some(data))
+    }
+}
 
-        // Scrub out the interval data from the text
-        let mut output = String::with_capacity(text.len());
-        let mut last_excluded = 0;
-        for interval in intervals {
-            let start = interval.0;
-            let end = interval.1;
-            output.push_str(&text[last_excluded..start]);
-            last_excluded = end;
-        }
-        if last_excluded < text.len() {
-            output.push_str(&text[last_excluded..]);
+impl HashAnnotator {
+    pub fn hash_text(text: &str, num_bits: usize) -> Value {
+        // 128-bit hashes don't fit in a json number, so they're stored as a (decimal) string
+        if num_bits == 128 {
+            Value::from(HashAnnotator::hash_text_128(text).to_string())
+        } else {
+            Value::from(xxh3_64(text.as_bytes()))
         }
+    }
 
-        if output.len() == 0 {
-            return Ok(None);
-        }
+    pub fn hash_text_128(text: &str) -> u128 {
+        xxh3_128(text.as_bytes())
+    }
 
-        json_set(&mut data, &self.output_text_field, serde_json::Value::String(output)).unwrap();
+    pub fn normalize_text(text: &str) -> String {
+        // The view that normalize_for_hash hashes: lowercased, whitespace runs collapsed to one space, trimmed
+        text.split_whitespace()
+            .map(|w| w.to_lowercase())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+
+#[derive(Serialize, Debug)]
+pub struct ConstantAnnotator {
+    // Adds a string into every json in a directory
+    pub key: String, // location of where we save the constant
+    pub value: String, // what we save
+}
+
+impl DataProcessor for ConstantAnnotator {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let key = get_required_str(config, "key")?;
+        let value = get_required_str(config, "value")?;
+
+        Ok(Self { key, value })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        json_set(&mut data, &self.key, json!(&self.value)).unwrap();
         Ok(Some(data))
     }
+}
+
 
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum TemplatePart {
+    Literal(String),
+    Field(String),
 }
 
-fn fuzzy_interval_merge(intervals: Vec<(usize, usize)>, merge_fuzziness: f64) -> Vec<(usize, usize)> {
-    let forward = fuzzy_sandwich_intervals(&intervals, true, merge_fuzziness);
-    let backward = fuzzy_sandwich_intervals(&intervals, false, merge_fuzziness);
-    merge_sorted_interval_pair(forward, backward)
+#[derive(Serialize, Debug)]
+pub struct TemplateAnnotator {
+    /* Builds a string from other fields and saves it into output_field.
+    template is a string with {field.path} placeholders, e.g. "{source}/{metadata.date}"
+    ({{ and }} are literal braces). Strings are inserted as-is, other values as their json repr.
+    on_missing controls what happens if a placeholder is missing/null:
+        - "empty": substitute the empty string (default)
+        - "skip": leave the doc untouched (output_field is not written)
+        - "error": return an error (doc ends up in err_dir)
+    */
+    pub template: String,
+    pub output_field: String,
+    pub on_missing: String,
+    pub parts: Vec<TemplatePart>,
 }
 
+impl DataProcessor for TemplateAnnotator {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let template = get_required_str(config, "template")?;
+        let output_field = get_required_str(config, "output_field")?;
+        let on_missing = get_default(config, "on_missing", String::from("empty"));
+        ensure!(
+            ["empty", "skip", "error"].contains(&on_missing.as_str()),
+            format!("on_missing must be one of {{empty, skip, error}} and not {:?}", on_missing)
+        );
+        let parts = TemplateAnnotator::parse_template(&template)?;
 
-fn merge_intervals(mut v: Vec<(usize, usize)>, already_sorted: bool) -> Vec<(usize, usize)>{
-    if !already_sorted {
-        v.sort_by_key(|(key, _)| key.clone());
+        Ok(Self { template, output_field, on_missing, parts })
     }
-    let mut merged: Vec<(usize, usize)> = Vec::new();
-    for (s, e) in v {
-        if merged.len() == 0 {
-            merged.push((s, e));
-        } else if merged.last().unwrap().1 >= s {
-            let (old_s, old_e) = merged.pop().unwrap();
-            merged.push((old_s, cmp::max(e, old_e)));
-        } else {
-            merged.push((s, e));
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(s) => output.push_str(s),
+                TemplatePart::Field(field) => match json_get(&data, field) {
+                    Some(Value::String(s)) => output.push_str(s),
+                    Some(Value::Null) | None => match self.on_missing.as_str() {
+                        "skip" => return Ok(Some(data)),
+                        "error" => return Err(anyhow!("Template field {:?} is missing", field)),
+                        _ => {}
+                    },
+                    Some(other) => output.push_str(&other.to_string()),
+                },
+            }
         }
+
+        json_set(&mut data, &self.output_field, Value::String(output)).unwrap();
+        Ok(Some(data))
     }
-    merged
 }
 
-fn merge_sorted_interval_pair(u: Vec<(usize, usize)>, w: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
-    // Given two sorted lists of intervals, does a merge of the pairs, and then unions all intervals
-    let mut v : Vec<(usize, usize)> = Vec::new();
-    let mut ui = 0;
-    let mut wi = 0;
-    while ui < u.len() && wi < w.len() {
-        let (us, ue) = u[ui];
-        let (ws, we) = w[wi];
-        if us < ws || (us == ws && ue <= we){
-            v.push((us, ue));
-            ui += 1;
-        } else {
-            v.push((ws, we));
-            wi += 1
+impl TemplateAnnotator {
+    pub fn parse_template(template: &str) -> Result<Vec<TemplatePart>, Error> {
+        let mut parts: Vec<TemplatePart> = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(fc) => field.push(fc),
+                            None => return Err(anyhow!("Unclosed placeholder in template {:?}", template)),
+                        }
+                    }
+                    ensure!(!field.is_empty(), "Empty placeholder in template {:?}", template);
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(field));
+                }
+                '}' => return Err(anyhow!("Unmatched '}}' in template {:?}", template)),
+                _ => literal.push(c),
+            }
         }
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(parts)
     }
-    while ui < u.len() {
-        v.push(u[ui]);
-        ui += 1;
-    }
+}
 
-    while wi < w.len() {
-        v.push(w[wi]);
-        wi += 1;
-    }
 
-    merge_intervals(v, true)
+#[derive(Serialize, Debug)]
+pub struct RenameModifier {
+    /* Renames (moves) fields in the json; fields can be nested paths.
+    Either one old_field -> new_field, several at once with old_field/new_field as parallel lists, or a renames map
+    of old -> new. Several renames happen simultaneously: every old field is taken out first and then every new field
+    is written, so a swap (a -> b, b -> a) swaps and a chain (a -> b, b -> c) moves each value one step along.
+    */
+    pub renames: Vec<(String, String)>, // (old field, new field) pairs
+    pub prune_empty: bool, // defaults to false; if true, drop parents of old fields that the move left as empty objects
 }
 
+impl DataProcessor for RenameModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let renames = RenameModifier::rename_pairs(config)?;
+        let prune_empty = get_default(config, "prune_empty", false);
 
-fn fuzzy_sandwich_intervals(v: &Vec<(usize, usize)>, foward: bool, threshold: f64) -> Vec<(usize, usize)> {
-    // Given SORTED list of DISJOINT intervals, scans in the forward/!forward direction
-    // And collects all intervals that:
-    // 1. Start and end at an interval
-    // 2. Have >=threshold of the range contained in an input interval
-    // e.g. [(0,9), (10, 20)] -> [(0,20)] (when the threshold is <=0.95)
-
-    let n = v.len();
-    let iter_range : Vec<_> = if foward {
-        (0..n).collect()
-    } else {
-        (0..n).rev().collect()
-    };
-    let mut output : Vec<(i32, i32, i32)> = Vec::new();
-    for idx in iter_range {
+        Ok(Self { renames, prune_empty })
+    }
 
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        // Remove before setting, so moving a field into (a.b -> a) or out of (a -> a.b) its own parent works
+        let mut old_vals: Vec<Value> = Vec::with_capacity(self.renames.len());
+        for (old_field, _) in &self.renames {
+            let old_val = json_remove(&mut data, old_field)?
+                .ok_or_else(|| anyhow!("Field {:?} to rename not found", old_field))?;
+            old_vals.push(old_val);
+        }
+        if self.prune_empty {
+            for (old_field, _) in &self.renames {
+                RenameModifier::prune_empty_parents(&mut data, old_field)?;
+            }
+        }
+        for ((_, new_field), old_val) in self.renames.iter().zip(old_vals) {
+            json_set(&mut data, new_field, old_val)?;
+        }
 
-        let (next_s, next_e) = v[idx];
-        let next_s = next_s as i32;
-        let next_e = next_e as i32;
+        Ok(Some(data))
+    }
+}
 
-        if output.len() == 0 {
-            output.push((next_s, next_e, next_e - next_s));
-            continue;
+impl RenameModifier {
+    fn rename_pairs(config: &Value) -> Result<Vec<(String, String)>, Error> {
+        // The (old, new) pairs of the config, checked for renames that would step on each other
+        let as_str = |v: &Value, what: &str| {
+            v.as_str().map(String::from).ok_or_else(|| anyhow!("rename_modifier: {} must be strings, not {}", what, v))
+        };
+        let as_list = |key: &str| -> Result<Vec<String>, Error> {
+            match json_get(config, key) {
+                Some(Value::Array(fields)) => fields.iter().map(|v| as_str(v, key)).collect(),
+                Some(field) => Ok(vec![as_str(field, key)?]),
+                None => bail!("rename_modifier needs {:?} (or a renames map)", key),
+            }
+        };
+        let pairs: Vec<(String, String)> = match json_get(config, "renames") {
+            Some(renames) => {
+                ensure!(
+                    json_get(config, "old_field").is_none() && json_get(config, "new_field").is_none(),
+                    "rename_modifier takes either a renames map or old_field/new_field, not both"
+                );
+                let renames = renames
+                    .as_object()
+                    .ok_or_else(|| anyhow!("rename_modifier: renames must be a map of old field -> new field"))?;
+                renames
+                    .iter()
+                    .map(|(old_field, new_field)| -> Result<(String, String), Error> {
+                        Ok((old_field.clone(), as_str(new_field, "renames values")?))
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?
+            }
+            None => {
+                let old_fields = as_list("old_field")?;
+                let new_fields = as_list("new_field")?;
+                ensure!(
+                    old_fields.len() == new_fields.len(),
+                    "rename_modifier: old_field has {} fields but new_field has {}",
+                    old_fields.len(),
+                    new_fields.len()
+                );
+                old_fields.into_iter().zip(new_fields).collect()
+            }
+        };
+        ensure!(!pairs.is_empty(), "rename_modifier needs at least one field to rename");
+
+        // With all old fields taken out before any new one is written, the only ambiguous renames are overlapping
+        // old fields (a and a.b: the second isn't there anymore) or overlapping new fields (a and a.b: which one wins)
+        let overlaps = |a: &str, b: &str| a == b || a.starts_with(&format!("{}.", b)) || b.starts_with(&format!("{}.", a));
+        for (i, (old_a, new_a)) in pairs.iter().enumerate() {
+            for (old_b, new_b) in &pairs[i + 1..] {
+                ensure!(!overlaps(old_a, old_b), "rename_modifier: old fields {:?} and {:?} overlap", old_a, old_b);
+                ensure!(!overlaps(new_a, new_b), "rename_modifier: new fields {:?} and {:?} overlap", new_a, new_b);
+            }
         }
-        let (cur_s, cur_e, cur_w) = output.last().unwrap();
-        let new_interval = (cmp::min(next_s, *cur_s as i32),
-                            cmp::max(next_e, *cur_e as i32),
-                            *cur_w  as i32 + next_e - next_s);
-        if new_interval.2 as f64 >= (new_interval.1 - new_interval.0) as f64 * threshold {
-            output.pop().unwrap();
-            output.push(new_interval);
-        } else {
-            output.push((next_s, next_e, next_e - next_s));
+
+        // Renaming a field onto itself leaves the doc alone (even if the field is missing)
+        Ok(pairs.into_iter().filter(|(old_field, new_field)| old_field != new_field).collect())
+    }
+
+    pub fn prune_empty_parents(data: &mut Value, field: &str) -> Result<(), Error> {
+        // Walks up from the parent of field, removing each parent that is now an empty object
+        let parts: Vec<&str> = field.split('.').collect();
+        for depth in (1..parts.len()).rev() {
+            let parent = parts[..depth].join(".");
+            match json_get(data, &parent) {
+                Some(Value::Object(obj)) if obj.is_empty() => {
+                    json_remove(data, &parent)?;
+                }
+                _ => break,
+            }
         }
+        Ok(())
     }
+}
 
-    output
-        .iter()
-        .map(|(a,b, _)| (*a as usize, *b as usize))
-        .collect()
+
+#[derive(Serialize, Debug)]
+pub struct ParseJsonFieldModifier {
+    /* Parses a JSON-encoded string field (e.g. "metadata": "{\"lang\":\"en\"}") into actual JSON,
+    so later steps can use dotted paths into it.
+    The parsed value replaces the string, or goes to output_field if that's set.
+    Docs where the field is missing or isn't a string are passed through untouched.
+    on_error controls what happens if the string isn't valid JSON:
+        - keep: (default) pass the doc through unchanged
+        - remove: filter the doc out
+        - error: raise an error (doc goes to err_dir)
+    */
+    pub field: String,
+    pub output_field: Option<String>,
+    pub on_error: String,
+}
+
+impl DataProcessor for ParseJsonFieldModifier {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let field = json_get(config, "field")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("parse_json_field_modifier requires a string 'field'"))?
+            .to_string();
+        let output_field = json_get(config, "output_field").and_then(|v| v.as_str()).map(String::from);
+        let on_error = get_default(config, "on_error", String::from("keep"));
+        ensure!(
+            ["keep", "remove", "error"].contains(&on_error.as_str()),
+            format!("on_error must be one of {{keep, remove, error}} and not {:?}", on_error)
+        );
+        Ok(Self { field, output_field, on_error })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let raw = match json_get(&data, &self.field) {
+            Some(Value::String(raw)) => raw,
+            _ => return Ok(Some(data)),
+        };
+        let parsed: Value = match serde_json::from_str(raw) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                return match self.on_error.as_str() {
+                    "remove" => Ok(None),
+                    "error" => Err(anyhow!("Field '{}' is not valid JSON: {}", self.field, e)),
+                    _ => Ok(Some(data)),
+                };
+            }
+        };
+        let output_field = self.output_field.as_ref().unwrap_or(&self.field);
+        json_set(&mut data, output_field, parsed)?;
+        Ok(Some(data))
+    }
 }
 
 
-
-
 #[derive(Serialize, Debug)]
-pub struct DDMaxGetter {
-    /* {attributes: {
-        <prefix>_KEY : [[val]]
-    }}
-    of attributes keys that start with prefix, returns the max KEY
+pub struct UrlParseModifier {
+    /* Parses the url in url_key and writes structured/normalized versions of it back into the doc.
+    components_field gets {scheme, host, port, path, query} (port is null for the scheme's default port,
+    query is null if there is none).
+    normalized_field gets a canonical form of the url: lowercased scheme and host, default port stripped,
+    query params sorted (and dropped entirely if empty) and, with strip_fragment, no #fragment.
+    At least one of the two output fields must be set.
+    Docs without a string url are passed through untouched.
+    on_error controls what happens if the url can't be parsed (e.g. it has no scheme):
+        - keep: (default) pass the doc through unchanged
+        - remove: filter the doc out
+        - error: raise an error (doc goes to err_dir)
     */
-    pub main_attribute: String, // default to "attributes"
-    pub prefix: String,
-    pub output_attribute: String,  // where the max KEY goes
+    pub url_key: String,
+    pub components_field: Option<String>,
+    pub normalized_field: Option<String>,
+    pub strip_fragment: bool,
+    pub on_error: String,
 }
 
-impl DataProcessor for DDMaxGetter {
+impl DataProcessor for UrlParseModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let main_attribute = get_default(config, "main_attribute", String::from("attributes"));
-
-        let prefix = json_get(config, "prefix").unwrap().as_str().unwrap().to_string();
-        let output_attribute = json_get(config, "output_attribute").unwrap().as_str().unwrap().to_string();
-        Ok(Self {
-            main_attribute,
-            prefix,
-            output_attribute
-        })
-
+        let url_key = get_default(config, "url_key", String::from("url"));
+        let components_field = json_get(config, "components_field").and_then(|v| v.as_str()).map(String::from);
+        let normalized_field = json_get(config, "normalized_field").and_then(|v| v.as_str()).map(String::from);
+        ensure!(
+            components_field.is_some() || normalized_field.is_some(),
+            "url_parse_modifier needs at least one of components_field or normalized_field"
+        );
+        let strip_fragment = get_default(config, "strip_fragment", true);
+        let on_error = get_default(config, "on_error", String::from("keep"));
+        ensure!(
+            ["keep", "remove", "error"].contains(&on_error.as_str()),
+            format!("on_error must be one of {{keep, remove, error}} and not {:?}", on_error)
+        );
+        Ok(Self { url_key, components_field, normalized_field, strip_fragment, on_error })
     }
 
-
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let input_dict = json_get(&data, &self.main_attribute).unwrap();
-        // claude: loop over key,val pairs in input_dict
-        // and for keys that start with prefix, get their value as a [[f64]] (or just an f64)
-
-        let mut max_key = String::from("null");
-        let mut max_val: f64 = -1.0;
-
-        if let Value::Object(map) = input_dict {
-            for (key, value) in map {
-                if key.starts_with(&self.prefix) {
-
-					// if the value is an array, get the first element of the first element (jake format)
-					// if it is a siple float, just get the value; otherwise throw an error
-                    let parsed_val = match value {
-                        Value::Array(outer) => &outer[0][0].as_f64().unwrap(),
-						Value::Number(num) => &num.as_f64().unwrap(),
-						_ => panic!("Invalid value type: {:?}", value),
-                    };
-                    if *parsed_val > max_val {
-                        max_key = key.clone();
-                        max_val = *parsed_val;
-                    }
-                }
+        let raw = match json_get(&data, &self.url_key) {
+            Some(Value::String(raw)) => raw,
+            _ => return Ok(Some(data)),
+        };
+        let url = match Url::parse(raw.trim()) {
+            Ok(url) => url,
+            Err(e) => {
+                return match self.on_error.as_str() {
+                    "remove" => Ok(None),
+                    "error" => Err(anyhow!("Field '{}' is not a valid url: {}", self.url_key, e)),
+                    _ => Ok(Some(data)),
+                };
             }
-        }
+        };
 
-        json_set(&mut data, &self.output_attribute, serde_json::Value::String(max_key)).unwrap();
+        if let Some(components_field) = &self.components_field {
+            let components = json!({
+                "scheme": url.scheme(),
+                "host": url.host_str(),
+                "port": url.port(),
+                "path": url.path(),
+                "query": url.query(),
+            });
+            json_set(&mut data, components_field, components)?;
+        }
+        if let Some(normalized_field) = &self.normalized_field {
+            let normalized = UrlParseModifier::normalize(url, self.strip_fragment);
+            json_set(&mut data, normalized_field, Value::String(normalized))?;
+        }
         Ok(Some(data))
+    }
+}
 
+impl UrlParseModifier {
+    pub fn normalize(mut url: Url, strip_fragment: bool) -> String {
+        // Url::parse already lowercases the scheme/host and drops default ports, so only the query and fragment are left
+        let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        if pairs.is_empty() {
+            url.set_query(None);
+        } else {
+            pairs.sort();
+            url.query_pairs_mut().clear().extend_pairs(pairs);
+        }
+        if strip_fragment {
+            url.set_fragment(None);
+        }
+        url.to_string()
     }
 }
 
+
 #[derive(Serialize, Debug)]
-pub struct MaxExtractor {
-    /*
-    - main_attribute points to a dict with str->key floats
-    - if the max value is >= lower bound (defaults to 0.0), sets the key to be the value of output_attribute
+pub struct NumericNormalizeModifier {
+    /* Rescales and/or clamps the number in value_field, e.g. to put quality scores from sources with different
+    scales on a common [0, 1] scale before a single float_filter.
+    With source_min and source_max, the value is min-max normalized: (value - source_min) / (source_max - source_min).
+    With clamp_min and/or clamp_max, the (normalized) value is then clamped into [clamp_min, clamp_max].
+    At least one of the two must be configured. The result goes to output_field (default: value_field itself).
+    on_error controls docs where value_field is missing or not a number, and docs whose value falls outside
+    [source_min, source_max] when no clamp is configured (their normalized value would be outside [0, 1]):
+        - keep: (default) pass the doc through unchanged
+        - remove: filter the doc out
+        - error: raise an error (doc goes to err_dir)
     */
-
-    pub main_attribute: String,
-    pub lower_bound: f64, // defaults to 0.0
-    pub output_attribute: String,
-    pub keep_nulls: bool, // defaults to true
+    pub value_field: String,
+    pub output_field: String,
+    pub source_min: Option<f64>,
+    pub source_max: Option<f64>,
+    pub clamp_min: Option<f64>,
+    pub clamp_max: Option<f64>,
+    pub on_error: String,
 }
 
-
-impl DataProcessor for MaxExtractor {
+impl DataProcessor for NumericNormalizeModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let main_attribute = json_get(config, "main_attribute").unwrap().as_str().unwrap().to_string();
-        let lower_bound: f64 = get_default(config, "lower_bound", 0.0);
-        let output_attribute = json_get(config, "output_attribute").unwrap().as_str().unwrap().to_string();
-        let keep_nulls = get_default(config, "keep_nulls", true);
-        Ok(Self {main_attribute, lower_bound, output_attribute, keep_nulls})
+        let value_field = get_default(config, "value_field", String::new());
+        ensure!(!value_field.is_empty(), "numeric_normalize_modifier needs a value_field");
+        let output_field = get_default(config, "output_field", value_field.clone());
+        let source_min = json_get(config, "source_min").and_then(|v| v.as_f64());
+        let source_max = json_get(config, "source_max").and_then(|v| v.as_f64());
+        let clamp_min = json_get(config, "clamp_min").and_then(|v| v.as_f64());
+        let clamp_max = json_get(config, "clamp_max").and_then(|v| v.as_f64());
+        ensure!(
+            source_min.is_some() == source_max.is_some(),
+            "source_min and source_max must be given together"
+        );
+        if let (Some(source_min), Some(source_max)) = (source_min, source_max) {
+            ensure!(source_min < source_max, "source_min must be < source_max");
+        }
+        if let (Some(clamp_min), Some(clamp_max)) = (clamp_min, clamp_max) {
+            ensure!(clamp_min <= clamp_max, "clamp_min must be <= clamp_max");
+        }
+        ensure!(
+            source_min.is_some() || clamp_min.is_some() || clamp_max.is_some(),
+            "numeric_normalize_modifier needs source_min/source_max, clamp_min or clamp_max"
+        );
+        let on_error = get_default(config, "on_error", String::from("keep"));
+        ensure!(
+            ["keep", "remove", "error"].contains(&on_error.as_str()),
+            format!("on_error must be one of {{keep, remove, error}} and not {:?}", on_error)
+        );
+        Ok(Self { value_field, output_field, source_min, source_max, clamp_min, clamp_max, on_error })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let mut max_key = String::from("");
-        let mut max_val: f64 = f64::MIN;
-        let input_dict = json_get(&data, &self.main_attribute).unwrap();
-        if let Value::Object(map) = input_dict {
-            for (key, value) in map {
-                let value = value.as_f64().unwrap();
-                if value >= max_val && value >= self.lower_bound {
-                    max_key = key.to_string();
-                    max_val = value;
-                }
+        let value = match json_get(&data, &self.value_field).and_then(|v| v.as_f64()) {
+            Some(value) => value,
+            None => return self.handle_error(data, format!("Field '{}' is missing or not a number", self.value_field)),
+        };
+        let normalized = match self.normalize(value) {
+            Some(normalized) => normalized,
+            None => {
+                return self.handle_error(
+                    data,
+                    format!("Field '{}' = {} is outside [source_min, source_max]", self.value_field, value),
+                )
             }
-        }
-
+        };
+        json_set(&mut data, &self.output_field, json!(normalized))?;
+        Ok(Some(data))
+    }
+}
 
-        if max_key.len() > 0 {
-            json_set(&mut data, &self.output_attribute, serde_json::Value::String(max_key)).unwrap();
-        } else {
-            if !&self.keep_nulls {
-                return Ok(None);
+impl NumericNormalizeModifier {
+    pub fn normalize(&self, value: f64) -> Option<f64> {
+        // None if the value is out of the source range and there's no clamp to bring it back
+        let mut value = value;
+        if let (Some(source_min), Some(source_max)) = (self.source_min, self.source_max) {
+            let clamped = self.clamp_min.is_some() || self.clamp_max.is_some();
+            if !clamped && (value < source_min || value > source_max) {
+                return None;
             }
+            value = (value - source_min) / (source_max - source_min);
         }
-        Ok(Some(data))
+        if let Some(clamp_min) = self.clamp_min {
+            value = value.max(clamp_min);
+        }
+        if let Some(clamp_max) = self.clamp_max {
+            value = value.min(clamp_max);
+        }
+        Some(value)
+    }
 
+    fn handle_error(&self, data: Value, msg: String) -> Result<Option<Value>, Error> {
+        match self.on_error.as_str() {
+            "remove" => Ok(None),
+            "error" => Err(anyhow!(msg)),
+            _ => Ok(Some(data)),
+        }
     }
 }
 
 
-
 #[derive(Serialize, Debug)]
-pub struct HashAnnotator {
-    // Adds a hash id to
-    pub hash_source: String, // field that gets hashed
-    pub hash_destination: String, // where the target gets hashed and save
-    pub num_bits: usize // defaults to 128
+pub struct SplitFieldModifier {
+    /* Splits the string in text_field on delimiter and writes the parts as an array to output_field
+    (e.g. a field of concatenated sentences back into a list).
+    trim (default true) strips whitespace around each part, drop_empty (default true) drops empty parts (after trimming).
+    max_splits caps the number of splits, so there are at most max_splits + 1 parts and the last one holds the rest.
+    Docs where text_field is missing or isn't a string are passed through untouched.
+    */
+    pub text_field: String,
+    pub delimiter: String,
+    pub output_field: String,
+    pub trim: bool,
+    pub drop_empty: bool,
+    pub max_splits: Option<usize>,
 }
 
-impl DataProcessor for HashAnnotator {
+impl DataProcessor for SplitFieldModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let hash_source = get_default(config, "hash_source", String::from("text"));
-        let hash_destination = get_default(config, "hash_destination", String::from("metadata.text_hash"));
-        let num_bits = get_default(config, "num_bits", 128);
-
-        assert!(num_bits == 64 || num_bits == 128);
-
-        Ok(Self {
-            hash_source,
-            hash_destination,
-            num_bits
-        })
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let delimiter = get_default(config, "delimiter", String::from(""));
+        ensure!(!delimiter.is_empty(), "split_field_modifier requires a non-empty 'delimiter'");
+        let output_field = json_get(config, "output_field")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("split_field_modifier requires a string 'output_field'"))?
+            .to_string();
+        let trim = get_default(config, "trim", true);
+        let drop_empty = get_default(config, "drop_empty", true);
+        let max_splits = json_get(config, "max_splits").and_then(|v| v.as_u64()).map(|v| v as usize);
+        Ok(Self { text_field, delimiter, output_field, trim, drop_empty, max_splits })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let text = json_get(&data, &self.hash_source)
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-
-        let hash_val = if self.num_bits == 128 {
-            Value::from(xxh3_128(text.as_bytes()).to_string())
-        } else {
-            Value::from(xxh3_64(text.as_bytes()))
+        let text = match json_get(&data, &self.text_field) {
+            Some(Value::String(text)) => text,
+            _ => return Ok(Some(data)),
         };
-
-        json_set(&mut data, &self.hash_destination, hash_val).unwrap();
+        let parts: Vec<Value> = self
+            .split(text)
+            .into_iter()
+            .map(|part| Value::String(part.to_string()))
+            .collect();
+        json_set(&mut data, &self.output_field, Value::Array(parts))?;
         Ok(Some(data))
     }
 }
 
+impl SplitFieldModifier {
+    pub fn split<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let parts: Vec<&str> = match self.max_splits {
+            Some(max_splits) => text.splitn(max_splits + 1, self.delimiter.as_str()).collect(),
+            None => text.split(self.delimiter.as_str()).collect(),
+        };
+        parts
+            .into_iter()
+            .map(|part| if self.trim { part.trim() } else { part })
+            .filter(|part| !(self.drop_empty && part.is_empty()))
+            .collect()
+    }
+}
+
 
 #[derive(Serialize, Debug)]
-pub struct ConstantAnnotator {
-    // Adds a string into every json in a directory
-    pub key: String, // location of where we save the constant
-    pub value: String, // what we save
+pub struct ConcatFieldsModifier {
+    /* Joins the values of source_fields, in order, with separator into the string output_field
+    (e.g. title + abstract + body into a single text field). This works on a single doc, as opposed to
+    concatenating docs within a group.
+    Missing and null fields are skipped (as are empty strings, with skip_empty default true), so no stray separators are left.
+    labels (optional, one per source field) prefixes each part as "<label><label_separator><value>".
+    Strings are used as-is and other scalars by their json text; arrays and objects are an error.
+    */
+    pub source_fields: Vec<String>,
+    pub separator: String,
+    pub output_field: String,
+    pub labels: Option<Vec<String>>,
+    pub label_separator: String,
+    pub skip_empty: bool,
 }
 
-impl DataProcessor for ConstantAnnotator {
+impl DataProcessor for ConcatFieldsModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let key = json_get(config, "key").unwrap().as_str().unwrap().to_string();
-        let value = json_get(config, "value").unwrap().as_str().unwrap().to_string();
-
-        Ok(Self { key, value })
+        let as_strings = |key: &str| -> Result<Option<Vec<String>>, Error> {
+            json_get(config, key)
+                .map(|v| {
+                    v.as_array()
+                        .ok_or_else(|| anyhow!("{} must be a list of strings", key))?
+                        .iter()
+                        .map(|v| v.as_str().map(String::from).ok_or_else(|| anyhow!("{} must be strings, got {:?}", key, v)))
+                        .collect::<Result<Vec<String>, Error>>()
+                })
+                .transpose()
+        };
+        let source_fields = as_strings("source_fields")?
+            .ok_or_else(|| anyhow!("concat_fields_modifier requires a list of 'source_fields'"))?;
+        ensure!(!source_fields.is_empty(), "concat_fields_modifier requires at least one source field");
+        let labels = as_strings("labels")?;
+        if let Some(labels) = &labels {
+            ensure!(
+                labels.len() == source_fields.len(),
+                format!(
+                    "concat_fields_modifier needs one label per source field ({} labels for {} fields)",
+                    labels.len(),
+                    source_fields.len()
+                )
+            );
+        }
+        let separator = get_default(config, "separator", String::from("\n\n"));
+        let output_field = get_default(config, "output_field", String::from("text"));
+        let label_separator = get_default(config, "label_separator", String::from(": "));
+        let skip_empty = get_default(config, "skip_empty", true);
+        Ok(Self { source_fields, separator, output_field, labels, label_separator, skip_empty })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        json_set(&mut data, &self.key, json!(&self.value)).unwrap();
+        let mut parts: Vec<String> = Vec::new();
+        for (idx, field) in self.source_fields.iter().enumerate() {
+            let part = match json_get(&data, field) {
+                None | Some(Value::Null) => continue,
+                Some(Value::String(s)) => s.clone(),
+                Some(v @ (Value::Array(_) | Value::Object(_))) => {
+                    bail!("concat_fields_modifier can't concatenate non-scalar field {:?}: {}", field, v)
+                }
+                Some(v) => v.to_string(),
+            };
+            if self.skip_empty && part.is_empty() {
+                continue;
+            }
+            match &self.labels {
+                Some(labels) => parts.push(format!("{}{}{}", labels[idx], self.label_separator, part)),
+                None => parts.push(part),
+            }
+        }
+        json_set(&mut data, &self.output_field, Value::String(parts.join(&self.separator)))?;
         Ok(Some(data))
     }
 }
 
 
 #[derive(Serialize, Debug)]
-pub struct RenameModifier {
-    // Renames a field in the json
-    pub old_field: String, // old field name
-    pub new_field: String, // new field name
+pub struct ArrayJoinModifier {
+    /* Flattens text_field when it holds a list of text segments (e.g. "segments": ["para1", "para2"]) by joining
+    them with separator into the string output_field (defaults to text_field itself, i.e. in place), so the rest of
+    the pipeline, which expects a single string, can run on it.
+    Null elements are skipped (as are empty strings, with skip_empty default true), other scalars are used by their
+    json text and nested arrays or objects are an error.
+    Docs where text_field is missing or already a string are passed through untouched.
+    */
+    pub text_field: String,
+    pub separator: String,
+    pub output_field: String,
+    pub skip_empty: bool,
 }
 
-impl DataProcessor for RenameModifier {
+impl DataProcessor for ArrayJoinModifier {
     fn new(config: &Value) -> Result<Self, Error> {
-        let old_field = json_get(config, "old_field").unwrap().as_str().unwrap().to_string();
-        let new_field = json_get(config, "new_field").unwrap().as_str().unwrap().to_string();
-
-        Ok(Self { old_field, new_field })
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let separator = get_default(config, "separator", String::from("\n\n"));
+        let output_field = get_default(config, "output_field", text_field.clone());
+        let skip_empty = get_default(config, "skip_empty", true);
+        Ok(Self { text_field, separator, output_field, skip_empty })
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
-        let old_val = json_get(&data, &self.old_field).unwrap().clone();
-        json_set(&mut data, &self.new_field, old_val).unwrap();
-        json_remove(&mut data, &self.old_field).unwrap();
-
+        let segments = match json_get(&data, &self.text_field) {
+            Some(Value::Array(segments)) => segments,
+            _ => return Ok(Some(data)),
+        };
+        let mut parts: Vec<String> = Vec::new();
+        for segment in segments {
+            let part = match segment {
+                Value::Null => continue,
+                Value::String(s) => s.clone(),
+                v @ (Value::Array(_) | Value::Object(_)) => {
+                    bail!("array_join_modifier can't join non-scalar segment of {:?}: {}", self.text_field, v)
+                }
+                v => v.to_string(),
+            };
+            if self.skip_empty && part.is_empty() {
+                continue;
+            }
+            parts.push(part);
+        }
+        json_set(&mut data, &self.output_field, Value::String(parts.join(&self.separator)))?;
         Ok(Some(data))
     }
 }
@@ -2894,8 +5701,8 @@ pub struct GzipAnnotator {
 impl DataProcessor for GzipAnnotator {
     fn new(config: &Value) -> Result<Self, Error> {
 
-        let text_field = json_get(config, "text_field").unwrap().as_str().unwrap().to_string();
-        let anno_field = json_get(config, "anno_field").unwrap().as_str().unwrap().to_string();
+        let text_field = get_required_str(config, "text_field")?;
+        let anno_field = get_required_str(config, "anno_field")?;
 
         Ok(Self { text_field, anno_field })
     }
@@ -2912,6 +5719,54 @@ impl DataProcessor for GzipAnnotator {
     }
 }
 
+#[derive(Serialize, Debug)]
+pub struct CompressionRatioFilter {
+    // Keeps docs whose zstd compression ratio (compressed_len / original_len) is in [lower_bound, upper_bound]
+    // Repetitive/low-entropy text compresses very well, so has a low ratio
+    pub text_field: String, // defaults to text
+    pub lower_bound: f64, // defaults to 0.0
+    pub upper_bound: f64, // defaults to f64::MAX
+    pub anno_field: Option<String>, // if present, the ratio is also written here
+    pub keep_empty: bool, // empty text has no meaningful ratio: keep (with a null annotation) or drop. defaults to false
+}
+
+impl DataProcessor for CompressionRatioFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let lower_bound = get_default(config, "lower_bound", 0.0);
+        let upper_bound = get_default(config, "upper_bound", f64::MAX);
+        let anno_field = json_get(config, "anno_field")
+            .map(|v| v.as_str().map(String::from).ok_or_else(|| anyhow!("anno_field must be a string")))
+            .transpose()?;
+        let keep_empty = get_default(config, "keep_empty", false);
+        ensure!(lower_bound <= upper_bound, "lower_bound must be <= upper_bound");
+        Ok(Self { text_field, lower_bound, upper_bound, anno_field, keep_empty })
+    }
+
+    fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field).unwrap().as_str().unwrap();
+        if text.is_empty() {
+            if !self.keep_empty {
+                return Ok(None);
+            }
+            if let Some(anno_field) = &self.anno_field {
+                json_set(&mut data, anno_field, Value::Null).unwrap();
+            }
+            return Ok(Some(data));
+        }
+
+        let compressed = zstd::bulk::compress(text.as_bytes(), 3)?;
+        let ratio = compressed.len() as f64 / text.len() as f64;
+        if ratio < self.lower_bound || ratio > self.upper_bound {
+            return Ok(None);
+        }
+        if let Some(anno_field) = &self.anno_field {
+            json_set(&mut data, anno_field, ratio.into()).unwrap();
+        }
+        Ok(Some(data))
+    }
+}
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 #[derive(Serialize)]
@@ -2937,9 +5792,9 @@ impl DataProcessor for NgramRepetitionFilter {
         let skip_offsets = get_default(config, "skip_offsets", false);
 
         let tokenizer = match tokenizer_name.as_str() {
-            "cl100k" => cl100k_base().unwrap(),
-            "p50k" => p50k_base().unwrap(),
-            _ => panic!("Unsupported tokenizer: {}", tokenizer_name)
+            "cl100k" => cl100k_base()?,
+            "p50k" => p50k_base()?,
+            _ => bail!("Unsupported tokenizer: {}", tokenizer_name)
         };
         Ok(Self { text_field, tokenizer_name, period_lb, period_ub, rep_count, tokenizer, skip_offsets})
     }
@@ -3146,16 +6001,12 @@ pub struct TokenCountAnnotator {
 impl DataProcessor for TokenCountAnnotator {
     fn new(config: &Value) -> Result<Self, Error> {
 
-        let text_field = json_get(config, "text_field").unwrap().as_str().unwrap().to_string();
-        let tokenizer_name = json_get(config, "tokenizer_name").unwrap().as_str().unwrap();
-        let tokenizer = match tokenizer_name {
-            "cl100k" => cl100k_base().unwrap(),
-            "p50k" => p50k_base().unwrap(),
-            _ => panic!("Unsupported tokenizer: {}", tokenizer_name)
-        };
-        let output_field = json_get(config, "output_field").unwrap().as_str().unwrap().to_string();
+        let text_field = get_required_str(config, "text_field")?;
+        let tokenizer_name = get_required_str(config, "tokenizer_name")?;
+        let tokenizer = load_tiktoken(&tokenizer_name)?;
+        let output_field = get_required_str(config, "output_field")?;
 
-        Ok(Self { text_field, tokenizer_name: tokenizer_name.to_string(), output_field, tokenizer})
+        Ok(Self { text_field, tokenizer_name, output_field, tokenizer})
     }
 
     fn process(&self, mut data: Value) -> Result<Option<Value>, Error> {
@@ -3166,6 +6017,67 @@ impl DataProcessor for TokenCountAnnotator {
     }
 }
 
+pub fn load_tiktoken(tokenizer_name: &str) -> Result<CoreBPE, Error> {
+    match tokenizer_name {
+        "cl100k" => cl100k_base(),
+        "p50k" => p50k_base(),
+        "o200k" => o200k_base(),
+        _ => bail!("tokenizer_name must be one of {{cl100k, p50k, o200k}} and not {:?}", tokenizer_name),
+    }
+}
+
+
+#[derive(Derivative, Serialize)]
+#[derivative(Debug)]
+pub struct TokenLenFilter {
+    /* Keeps docs whose text_field encodes to a number of tiktoken tokens in [lower_bound, upper_bound]
+    (e.g. the min/max sequence length used in training). tokenizer_name is cl100k (default), p50k or o200k, and the
+    count matches token_count_annotator's. A missing text field counts as 0 tokens.
+    Every token spans at least one byte, so docs with fewer bytes than lower_bound are dropped without encoding them.
+    */
+    pub text_field: String,
+    pub tokenizer_name: String,
+    pub lower_bound: usize,
+    pub upper_bound: usize,
+    #[derivative(Debug = "ignore")]
+    #[serde(skip)]
+    pub tokenizer: CoreBPE,
+}
+
+impl DataProcessor for TokenLenFilter {
+    fn new(config: &Value) -> Result<Self, Error> {
+        let text_field = get_default(config, "text_field", String::from("text"));
+        let tokenizer_name = get_default(config, "tokenizer_name", String::from("cl100k"));
+        let lower_bound = get_default(config, "lower_bound", 0);
+        let upper_bound = get_default(config, "upper_bound", usize::MAX);
+        ensure!(
+            lower_bound <= upper_bound,
+            format!("token_len_filter needs lower_bound <= upper_bound, got [{}, {}]", lower_bound, upper_bound)
+        );
+        let tokenizer = load_tiktoken(&tokenizer_name)?;
+        Ok(Self { text_field, tokenizer_name, lower_bound, upper_bound, tokenizer })
+    }
+
+    fn process(&self, data: Value) -> Result<Option<Value>, Error> {
+        let text = json_get(&data, &self.text_field).and_then(|v| v.as_str()).unwrap_or("");
+        if text.len() < self.lower_bound {
+            return Ok(None);
+        }
+        let token_count = self.token_count(text);
+        if self.lower_bound <= token_count && token_count <= self.upper_bound {
+            Ok(Some(data))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl TokenLenFilter {
+    pub fn token_count(&self, text: &str) -> usize {
+        self.tokenizer.encode_with_special_tokens(text).len()
+    }
+}
+
 
 #[derive(Derivative)]
 #[derivative(Debug)]
@@ -3189,24 +6101,17 @@ pub struct UltrafinewebAnnotator {
 
 impl DataProcessor for UltrafinewebAnnotator {
     fn new(config: &Value) -> Result<Self, Error> {
-        let text_field = json_get(config, "text_field")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
+        let text_field = get_required_str(config, "text_field")?;
         let tokenizer_path = get_default(
             config,
             "tokenizer_path",
             String::from("tokenizers/deepseek_v2.json"),
         );
-        println!("TOKENIZER PATH {:?}", tokenizer_path);
-        let tokenizer = Tokenizer::from_file(&tokenizer_path).unwrap();
+        eprintln!("TOKENIZER PATH {:?}", tokenizer_path);
+        let tokenizer_bytes = read_pathbuf_to_mem(&PathBuf::from(&tokenizer_path))?.into_inner().into_inner();
+        let tokenizer = Tokenizer::from_bytes(tokenizer_bytes).map_err(|e| anyhow!("Bad tokenizer {:?}: {}", tokenizer_path, e))?;
 
-        let output_field = json_get(config, "output_field")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
+        let output_field = get_required_str(config, "output_field")?;
         let re_multinewline = Regex::new(r"\n{3,}").unwrap();
         let re_newline = Regex::new(r"\n").unwrap();
         let re_carriage = Regex::new(r"\r").unwrap();
@@ -3219,8 +6124,7 @@ impl DataProcessor for UltrafinewebAnnotator {
             "fast_text_file",
             String::from("ft_classifiers/ultrafineweb.bin"),
         );
-        let mut model = FastText::new();
-        model.load_model(&fast_text_file).unwrap();
+        let model = load_fasttext_model(&fast_text_file)?;
         let max_text_length: usize = get_default(config, "max_text_length", 0);
 
         Ok(Self {
@@ -3243,11 +6147,8 @@ impl DataProcessor for UltrafinewebAnnotator {
             .to_string();
 
         // Trim text if max_text_length is set, avoiding cutting on multi-byte characters
-        if self.max_text_length > 0 && text.len() > self.max_text_length {
-            let mut end = self.max_text_length;
-            while end > 0 && !text.is_char_boundary(end) {
-                end -= 1;
-            }
+        if self.max_text_length > 0 {
+            let end = truncate_at_byte_boundary(&text, self.max_text_length).len();
             text.truncate(end);
         }
 
@@ -0,0 +1,310 @@
+/*============================================================
+=                         MAP RUN HELPERS                    =
+============================================================*/
+/*
+The parts of a map run around the pipeline itself: what happens to one input file's outputs before they are
+written, and the stats that are reported about it.
+*/
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Error, Result};
+use serde_json::{json, Value};
+
+use crate::bloom::{line_id, BloomFilter};
+use crate::map_fxn::PipelineProcessor;
+use crate::utils::{json_set, read_input_lines, read_input_lines_lossy};
+
+pub struct SeenBloom {
+    // --seen-bloom state for the map: the filter, where to save it, and which field holds the id
+    pub filter: BloomFilter,
+    pub path: PathBuf,
+    pub id_field: String,
+}
+
+impl SeenBloom {
+    pub fn load(path: &PathBuf, id_field: &str, capacity: u64, fpr: f64) -> Result<Self, Error> {
+        let filter = BloomFilter::load_or_new(path, capacity, fpr)?;
+        println!(
+            "Using seen-bloom {:?} with ~{:?} ids (capacity {:?}, fpr {:?})",
+            path,
+            filter.len(),
+            filter.capacity,
+            filter.fpr
+        );
+        Ok(Self {
+            filter,
+            path: path.clone(),
+            id_field: id_field.to_string(),
+        })
+    }
+}
+
+pub fn read_map_input(
+    input_file: &PathBuf,
+    lossy_utf8: bool,
+    seen_bloom: Option<&SeenBloom>,
+    seen_skipped: &AtomicUsize,
+) -> Result<(Vec<String>, usize), Error> {
+    // One input file's lines for the map (minus those seen_bloom skips), and how many it had before skipping
+    let lines = if lossy_utf8 {
+        read_input_lines_lossy(input_file)?
+    } else {
+        read_input_lines(input_file)?
+    };
+    let num_lines = lines.len();
+    let lines = if let Some(seen_bloom) = seen_bloom {
+        // Docs without an id are never skipped (and never recorded)
+        let lines: Vec<String> = lines
+            .into_iter()
+            .filter(|line| {
+                line_id(line, &seen_bloom.id_field).is_none_or(|id| !seen_bloom.filter.contains(id.as_bytes()))
+            })
+            .collect();
+        seen_skipped.fetch_add(num_lines - lines.len(), Ordering::SeqCst);
+        lines
+    } else {
+        lines
+    };
+    Ok((lines, num_lines))
+}
+
+pub struct PipelineSample {
+    // Per-step stats of running the pipeline over the first docs of some files
+    pub timing_info: HashMap<usize, u128>,
+    pub filter_info: HashMap<usize, usize>,
+    pub docs: usize,         // docs processed without error
+    pub errors: usize,       // docs that failed to parse or process
+    pub skipped_seen: usize, // docs seen_bloom skipped before the pipeline (not part of the sample)
+}
+
+impl PipelineSample {
+    pub fn survived(&self) -> usize {
+        *self.filter_info.get(&usize::MAX).unwrap_or(&0)
+    }
+}
+
+pub fn sample_pipeline(
+    processor: &PipelineProcessor,
+    files: &[PathBuf],
+    sample: usize,
+    lossy_utf8: bool,
+    seen_bloom: Option<&SeenBloom>,
+) -> Result<PipelineSample, Error> {
+    /* Runs the pipeline over (up to) `sample` docs in file order, accumulating the per-step stats like the map does.
+    Files are read the way the map reads them, so docs that seen_bloom would skip aren't sampled either
+    */
+    let mut timing_info = HashMap::new();
+    let mut filter_info = HashMap::new();
+    let mut text_bytes_info = HashMap::new();
+    let mut docs = 0;
+    let mut errors = 0;
+    let skipped_seen = AtomicUsize::new(0);
+    for p in files {
+        if docs + errors >= sample {
+            break;
+        }
+        let (lines, _) = read_map_input(p, lossy_utf8, seen_bloom, &skipped_seen)?;
+        for line in lines.into_iter().take(sample - docs - errors) {
+            let parsed = serde_json::from_str::<Value>(&line)
+                .map_err(Error::from)
+                .and_then(|value| processor.process(value, &mut timing_info, &mut filter_info, &mut text_bytes_info));
+            match parsed {
+                Ok(_) => docs += 1,
+                Err(_) => errors += 1,
+            }
+        }
+    }
+    Ok(PipelineSample { timing_info, filter_info, docs, errors, skipped_seen: skipped_seen.into_inner() })
+}
+
+pub fn check_drop_fraction(docs: usize, survived: usize, max_drop_fraction: f64, what: &str) -> Result<(), Error> {
+    // The --max-drop-fraction guardrail: errors (loudly) if the pipeline removed too much
+    let drop_fraction = (docs - survived) as f64 / f64::max(1.0, docs as f64);
+    if drop_fraction > max_drop_fraction {
+        let msg = format!(
+            "{} filtered out {:.2}% of {:?} docs, more than --max-drop-fraction {:?} allows. Is the config right?",
+            what,
+            drop_fraction * 100.0,
+            docs,
+            max_drop_fraction
+        );
+        eprintln!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
+        eprintln!("!!! {}", msg);
+        eprintln!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
+        bail!(msg);
+    }
+    Ok(())
+}
+
+fn relative_input_path(input_file: &Path, input_dir: &Path) -> String {
+    // How an input file is named in map outputs: relative to input_dir (or in full, if it isn't under it)
+    input_file.strip_prefix(input_dir).unwrap_or(input_file).to_string_lossy().to_string()
+}
+
+pub fn stamp_provenance(docs: &mut [Value], provenance_field: &String, input_file: &Path, input_dir: &Path) -> Result<(), Error> {
+    // --provenance-field: records on each doc (overwriting whatever was there) the input file it came from
+    let provenance = json!(relative_input_path(input_file, input_dir));
+    for doc in docs.iter_mut() {
+        json_set(doc, provenance_field, provenance.clone())?;
+    }
+    Ok(())
+}
+
+pub fn rejected_output_dir(
+    output_dir: &Path,
+    rejected_dir: Option<&PathBuf>,
+    keep_rejected: bool,
+    flat_output: bool,
+    err_dir: Option<&PathBuf>,
+) -> Result<Option<PathBuf>, Error> {
+    /* Where the docs a pipeline step removes are written (under step_XX/), None if they aren't written at all:
+    rejected_dir if given, else output_dir -- or err_dir with flat_output, whose output_dir only holds survivors.
+    Asking for a rejected_dir while turning rejected outputs off is contradictory, so it's an error
+    */
+    if !keep_rejected {
+        if let Some(rejected_dir) = rejected_dir {
+            bail!("--rejected-dir {:?} can't be combined with --keep-rejected false", rejected_dir);
+        }
+        return Ok(None);
+    }
+    Ok(match rejected_dir {
+        Some(rejected_dir) => Some(rejected_dir.clone()),
+        None if flat_output => err_dir.cloned(),
+        None => Some(output_dir.to_path_buf()),
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn file_report(
+    input_file: &Path,
+    input_dir: &Path,
+    processor: &PipelineProcessor,
+    docs_in: usize,
+    skipped_seen: usize,
+    errors: usize,
+    timing_info: &HashMap<usize, u128>,
+    filter_info: &HashMap<usize, usize>,
+    text_bytes_info: &HashMap<usize, (usize, usize)>,
+    error_info: &HashMap<usize, usize>,
+    limit_cut: usize,
+) -> Value {
+    /* One input file's view of the stats the map prints for the whole run (the --per-file-reports json).
+    A doc that errors in step k was still seen by steps 0..=k, so it counts toward their docs_in and shows up as
+    one of step k's errors; lines that aren't valid json (the rest of errors) never reach step 0.
+    filter_info's survivors are the docs actually written: limit_cut more made it through the pipeline but were
+    cut by --limit
+    */
+    let step_errors: usize = error_info.values().sum();
+    let mut remaining = docs_in - skipped_seen - errors.saturating_sub(step_errors);
+    let steps: Vec<Value> = processor
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let removed = filter_info.get(&i).copied().unwrap_or(0);
+            let errored = error_info.get(&i).copied().unwrap_or(0);
+            let docs_before = remaining;
+            remaining -= removed + errored;
+            let (text_bytes_in, text_bytes_out) = text_bytes_info.get(&i).copied().unwrap_or((0, 0));
+            json!({
+                "step": step,
+                "docs_in": docs_before,
+                "removed": removed,
+                "removed_frac": if docs_before > 0 { removed as f64 / docs_before as f64 } else { 0.0 },
+                "errors": errored,
+                "time_ms": timing_info.get(&i).copied().unwrap_or(0) as f64 / 1e6,
+                "text_bytes_in": text_bytes_in,
+                "text_bytes_out": text_bytes_out,
+            })
+        })
+        .collect();
+    let docs_out = filter_info.get(&usize::MAX).copied().unwrap_or(0);
+    json!({
+        "input_file": relative_input_path(input_file, input_dir),
+        "docs_in": docs_in,
+        "docs_out": docs_out,
+        "kept_frac": if docs_in > 0 { docs_out as f64 / docs_in as f64 } else { 0.0 },
+        "skipped_seen": skipped_seen,
+        "errors": errors,
+        "limit_cut": limit_cut,
+        "steps": steps,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn run_stats(
+    processor: &PipelineProcessor,
+    total_time_secs: u64,
+    step_times: &HashMap<usize, usize>,
+    filter_info: &HashMap<usize, usize>,
+    text_bytes_info: &HashMap<usize, (usize, usize)>,
+    filter_groups: &HashMap<(usize, String), usize>,
+    limit_cut: usize,
+) -> Value {
+    /* The stats the map prints at the end of a run, as json (the map_stats.json written next to the outputs).
+    step_times are in ns, filter_info holds the docs removed per step and the survivors actually written (under
+    usize::MAX): limit_cut more made it through the pipeline but were cut by --limit. filter_groups is the same
+    breakdown per stats_group_by value, empty if the config doesn't group
+    */
+    let total_step_time: usize = step_times.values().sum();
+    let docs_in: usize = filter_info.values().sum::<usize>() + limit_cut;
+    let mut remaining = docs_in;
+    let steps: Vec<Value> = processor
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let removed = filter_info.get(&i).copied().unwrap_or(0);
+            let docs_before = remaining;
+            remaining -= removed;
+            let time_ns = step_times.get(&i).copied().unwrap_or(0);
+            let (text_bytes_in, text_bytes_out) = text_bytes_info.get(&i).copied().unwrap_or((0, 0));
+            json!({
+                "step": step,
+                "docs_in": docs_before,
+                "removed": removed,
+                "removed_frac": if docs_before > 0 { removed as f64 / docs_before as f64 } else { 0.0 },
+                "time_ms": time_ns as f64 / 1e6,
+                "time_frac": if total_step_time > 0 { time_ns as f64 / total_step_time as f64 } else { 0.0 },
+                "text_bytes_in": text_bytes_in,
+                "text_bytes_out": text_bytes_out,
+            })
+        })
+        .collect();
+    let docs_out = filter_info.get(&usize::MAX).copied().unwrap_or(0);
+
+    let mut stats = json!({
+        "total_time_secs": total_time_secs,
+        "docs_in": docs_in,
+        "docs_out": docs_out,
+        "kept_frac": if docs_in > 0 { docs_out as f64 / docs_in as f64 } else { 0.0 },
+        "limit_cut": limit_cut,
+        "steps": steps,
+    });
+    if let Some(group_field) = &processor.stats_group_by {
+        // Per group: docs seen, docs removed per step (steps that removed none are left out) and survivors
+        let mut groups: HashMap<&String, (usize, serde_json::Map<String, Value>, usize)> = HashMap::new();
+        for ((step, group), count) in filter_groups {
+            let entry = groups.entry(group).or_default();
+            entry.0 += count;
+            match processor.steps.get(*step) {
+                Some(step) => {
+                    entry.1.insert(step.to_string(), json!(count));
+                }
+                None => entry.2 += count,
+            }
+        }
+        let groups: serde_json::Map<String, Value> = groups
+            .into_iter()
+            .map(|(group, (docs_in, removed, docs_out))| {
+                (group.clone(), json!({"docs_in": docs_in, "removed": removed, "docs_out": docs_out}))
+            })
+            .collect();
+        stats["group_by"] = json!(group_field);
+        stats["groups"] = Value::Object(groups);
+    }
+    stats
+}
@@ -0,0 +1,230 @@
+/*============================================================
+=                            MERGE                           =
+============================================================*/
+/*
+Merges two jsonl datasets on a join key.
+
+The "right" dataset is loaded fully into memory as a lookup table (join key -> doc), and then every
+doc in the "left" dataset is enriched with the fields from its matching right doc. Output files mirror
+the left dataset's file layout.
+
+Since the whole right side lives in RAM, use the fields whitelist to only keep the fields you actually
+want to attach (e.g. a couple of annotation scores rather than the full text).
+
+Fields present on both sides are resolved with a ConflictStrategy, and nest_under places all of the
+right doc's fields under a single (possibly nested) key of the left doc instead of at the top level.
+*/
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Error, Result};
+use dashmap::DashMap;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::utils::{build_pbar, expand_input_dirs, json_get, json_set, mirrored_output_filename, read_input_lines, JsonlWriter};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictStrategy {
+    KeepLeft,        // the left doc's value wins (default)
+    PreferRight,     // the right doc's value wins
+    ErrorOnConflict, // differing values for the same field are an error
+}
+
+impl ConflictStrategy {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "keep_left" => Ok(ConflictStrategy::KeepLeft),
+            "prefer_right" => Ok(ConflictStrategy::PreferRight),
+            "error_on_conflict" => Ok(ConflictStrategy::ErrorOnConflict),
+            _ => bail!(
+                "conflict_strategy must be keep_left, prefer_right or error_on_conflict, not {:?}",
+                name
+            ),
+        }
+    }
+}
+
+pub fn merge_jsonl(
+    left_dir: &Path,
+    right_dir: &Path,
+    output_dir: &Path,
+    join_key: &str,
+    fields: &Option<Vec<String>>,
+    conflict_strategy: ConflictStrategy,
+    nest_under: &Option<String>,
+) -> Result<(), Error> {
+    let start_main = Instant::now();
+    println!("Starting merge...");
+
+    // Step 1: build the lookup from the right side
+    let lookup = build_lookup(right_dir, join_key, fields, nest_under)?;
+    println!(
+        "Loaded {:?} right-side docs in {:?} secs",
+        lookup.len(),
+        start_main.elapsed().as_secs()
+    );
+
+    // Step 2: merge into the left side
+    let left_paths = expand_input_dirs(left_dir).unwrap();
+    let pbar = build_pbar(left_paths.len(), "Paths");
+    let docs_seen = AtomicUsize::new(0);
+    let docs_matched = AtomicUsize::new(0);
+    left_paths.par_iter().try_for_each(|p| -> Result<(), Error> {
+        let output_file = mirrored_output_filename(p, left_dir, output_dir)?;
+        let (seen, matched) = merge_path(p, &output_file, &lookup, join_key, conflict_strategy)?;
+        docs_seen.fetch_add(seen, Ordering::SeqCst);
+        docs_matched.fetch_add(matched, Ordering::SeqCst);
+        pbar.inc(1);
+        Ok(())
+    })?;
+
+    println!("Finished merge in {:?} secs", start_main.elapsed().as_secs());
+    println!(
+        "Saw {:?} left docs | {:?} had a match on the right side",
+        docs_seen.into_inner(),
+        docs_matched.into_inner()
+    );
+    Ok(())
+}
+
+fn build_lookup(
+    right_dir: &Path,
+    join_key: &str,
+    fields: &Option<Vec<String>>,
+    nest_under: &Option<String>,
+) -> Result<DashMap<String, Value>, Error> {
+    let right_paths = expand_input_dirs(right_dir).unwrap();
+    let lookup: DashMap<String, Value> = DashMap::new();
+    let missing_keys = AtomicUsize::new(0);
+    let duplicate_keys = AtomicUsize::new(0);
+    let pbar = build_pbar(right_paths.len(), "Right paths");
+    right_paths.par_iter().for_each(|p| {
+        for line in read_input_lines(p).unwrap() {
+            let value: Value = serde_json::from_str(&line).unwrap();
+            let key = if let Some(key) = get_join_key(&value, join_key) {
+                key
+            } else {
+                missing_keys.fetch_add(1, Ordering::SeqCst);
+                continue;
+            };
+            let value = if let Some(fields) = fields {
+                select_fields(&value, fields).unwrap()
+            } else {
+                value
+            };
+            // Wrap once here rather than once per matched left doc
+            let value = if let Some(nest_under) = nest_under {
+                let mut nested = json!({});
+                json_set(&mut nested, nest_under, value).unwrap();
+                nested
+            } else {
+                value
+            };
+            if lookup.insert(key, value).is_some() {
+                duplicate_keys.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        pbar.inc(1);
+    });
+
+    let missing_keys = missing_keys.into_inner();
+    let duplicate_keys = duplicate_keys.into_inner();
+    if missing_keys > 0 {
+        println!("Skipped {:?} right-side docs without a join key", missing_keys);
+    }
+    if duplicate_keys > 0 {
+        println!("Saw {:?} duplicate join keys on the right side (arbitrary one kept)", duplicate_keys);
+    }
+    Ok(lookup)
+}
+
+fn merge_path(
+    input_path: &PathBuf,
+    output_path: &Path,
+    lookup: &DashMap<String, Value>,
+    join_key: &str,
+    conflict_strategy: ConflictStrategy,
+) -> Result<(usize, usize), Error> {
+    let mut writer = JsonlWriter::create(output_path)?;
+    let mut matched = 0;
+    for line in read_input_lines(input_path).unwrap() {
+        let mut value: Value = serde_json::from_str(&line).unwrap();
+        if let Some(key) = get_join_key(&value, join_key) {
+            if let Some(right) = lookup.get(&key) {
+                merge_records(&mut value, right.value(), conflict_strategy)
+                    .map_err(|e| anyhow!("Merging join key {:?} in {:?}: {}", key, input_path, e))?;
+                matched += 1;
+            }
+        }
+        writer.write_value(&value)?;
+    }
+    let seen = writer.lines();
+    if seen > 0 {
+        writer.finish()?;
+    } else {
+        writer.discard()?;
+    }
+    Ok((seen, matched))
+}
+
+fn get_join_key(value: &Value, join_key: &str) -> Option<String> {
+    match json_get(value, join_key) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(other.to_string()),
+    }
+}
+
+fn select_fields(value: &Value, fields: &[String]) -> Result<Value, Error> {
+    // Builds a new doc with only the whitelisted (possibly nested) fields
+    let mut selected = json!({});
+    for field in fields {
+        if let Some(field_val) = json_get(value, field) {
+            json_set(&mut selected, field, field_val.clone())?;
+        }
+    }
+    Ok(selected)
+}
+
+pub fn merge_records(left: &mut Value, right: &Value, conflict_strategy: ConflictStrategy) -> Result<(), Error> {
+    /* Merges the fields of right into left.
+    Fields missing from left are always added; nested objects are merged recursively so that e.g.
+    right.metadata.score is added to an existing left.metadata.
+    Fields present on both sides with different values are resolved by conflict_strategy
+    (identical values are never a conflict).
+    */
+    merge_records_at(left, right, conflict_strategy, "")
+}
+
+fn merge_records_at(left: &mut Value, right: &Value, conflict_strategy: ConflictStrategy, path: &str) -> Result<(), Error> {
+    let left_obj = left
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Can only merge into a json object"))?;
+    let right_obj = right
+        .as_object()
+        .ok_or_else(|| anyhow!("Can only merge from a json object"))?;
+
+    for (k, right_val) in right_obj {
+        let field_path = if path.is_empty() { k.clone() } else { format!("{}.{}", path, k) };
+        match left_obj.get_mut(k) {
+            Some(left_val) if left_val.is_object() && right_val.is_object() => {
+                merge_records_at(left_val, right_val, conflict_strategy, &field_path)?;
+            }
+            Some(left_val) if left_val != right_val => match conflict_strategy {
+                ConflictStrategy::KeepLeft => {}
+                ConflictStrategy::PreferRight => *left_val = right_val.clone(),
+                ConflictStrategy::ErrorOnConflict => {
+                    bail!("Conflicting values for field {:?}: {} vs {}", field_path, left_val, right_val)
+                }
+            },
+            Some(_) => {}
+            None => {
+                left_obj.insert(k.clone(), right_val.clone());
+            }
+        }
+    }
+    Ok(())
+}
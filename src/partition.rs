@@ -1,21 +1,20 @@
 use std::sync::atomic::Ordering;
 use std::sync::atomic::AtomicUsize;
 use std::collections::{HashMap, HashSet};
-use anyhow::{Error, Result};
+use anyhow::{ensure, Error, Result};
 use dashmap::DashMap;
 use std::{
-    fs::{create_dir_all, File, OpenOptions},
+    fs::create_dir_all,
     io::{Write, BufRead},
-    os::unix::fs::OpenOptionsExt,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Instant,
 };
 use serde_json;
 use rayon::prelude::*;
-use crate::utils::json_get;
-use mj_io::{expand_dirs, read_pathbuf_to_mem, build_pbar};
-use zstd::stream::Encoder;
+use crate::utils::{build_pbar, json_get, load_fasttext_model, output_ext, AtomicFile, FilenameTemplate, OutputEncoder, RuntimeBudget};
+use fasttext::FastText;
+use mj_io::{expand_dirs, read_pathbuf_to_mem};
 use serde::{Deserialize, Serialize};
 
 /*
@@ -27,7 +26,7 @@ Discrete (Categorical) Partitioning:
 - Takes an input dataset and the config holds the key we're partitioning on as well as optional known categories
 	- If categories are known beforehand, will bucket anything not matching these categories into a separate bucket
 	- If categories are not known beforehand, will create one bucket per category as it's seen
-- Output files are stored like chunk_{category}_{filenum}.jsonl.zst
+- Output files are stored like chunk_{category}_{filenum}.jsonl.zst (.jsonl with --no-compress, for all three)
 
 
 
@@ -37,6 +36,13 @@ Range Partitioning:
 
 - Output files are stored like 
 	bucket_{bucket_num}/shard_{:08}.jsonl.zst
+
+
+Language Partitioning:
+- Single-pass version of (fasttext langid map) -> (discrete partition)
+- Takes the top-1 fasttext prediction for each doc; docs with no prediction >= min_prob go to "unknown"
+- Output files are stored like
+	lang_{code}/shard_{:08}.jsonl.zst
 	
 */
 
@@ -53,6 +59,8 @@ struct DiscretePartitionConfig {
 	choices: Option<Vec<String>>,
 	#[serde(default="default_max_file_size")]
 	max_file_size: usize,
+	#[serde(default)]
+	max_files_per_category: Option<usize>, // files past this many go to overflow_XXXX subdirectories of the category
 }
 
 
@@ -63,10 +71,10 @@ fn default_max_file_size() -> usize {
 
 
 
-pub fn discrete_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &Option<PathBuf>, partition_key: &Option<String>) -> Result<(), Error> {
+pub fn discrete_partition(input_dir: &Path, output_dir: &PathBuf, config_opt: &Option<PathBuf>, partition_key: &Option<String>, max_runtime: Option<u64>, filename_template: &Option<String>) -> Result<(), Error> {
 	let start_main = Instant::now();
 	println!("Starting partition operation");
-	let input_paths = expand_dirs(vec![input_dir.clone()], None).unwrap();
+	let input_paths = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
 
 	let config: DiscretePartitionConfig = if let Some(config_path) = config_opt {
 		let config_contents = read_pathbuf_to_mem(config_path).unwrap();
@@ -75,20 +83,31 @@ pub fn discrete_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt:
 		DiscretePartitionConfig {name: String::from("Discrete partition"),
 							     partition_key: partition_key.clone().unwrap(), 
 							     choices: None,
-							 	 max_file_size: default_max_file_size()}
+							 	 max_file_size: default_max_file_size(),
+							 	 max_files_per_category: None}
 	};
+	ensure!(config.max_files_per_category != Some(0), "max_files_per_category must be at least 1");
 
 
-	let writer = GenWriter::new_category_writer(output_dir, &config.choices, config.max_file_size);
+	let template = FilenameTemplate::from_option(filename_template)?;
+	let writer = GenWriter::new_category_writer(output_dir, &config.choices, config.max_file_size, config.max_files_per_category, template);
 	let global_counts: DashMap<Option<String>, AtomicUsize> = DashMap::new();
+	let budget = RuntimeBudget::new(max_runtime);
 	let pbar = build_pbar(input_paths.len(), "Paths");
 	input_paths.par_iter().for_each(|p| {
+		if budget.out_of_time(p) {
+			return;
+		}
 		let local_counts = partition_single_path(p, &config, &writer).unwrap();
 		local_counts.into_iter().for_each(|(k, v)| {
 		    global_counts.entry(k).or_insert_with(|| AtomicUsize::new(0)).fetch_add(v, Ordering::Relaxed);
 		});
 		pbar.inc(1);
 	});
+	let file_counts: HashMap<Option<String>, usize> = writer.file_counts().into_iter().filter_map(|(k, v)| match k {
+		WriterKey::Category(choice) => Some((choice, v)),
+		WriterKey::Bucket(_) | WriterKey::Missing => None,
+	}).collect();
 	writer.finish().unwrap();
 	println!("Finished partition in {:?} secs", start_main.elapsed().as_secs());
 	let global_counts: HashMap<Option<String>, usize> = global_counts
@@ -99,15 +118,22 @@ pub fn discrete_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt:
 	let total_values: usize = global_counts.iter().map(|(_k,v)| *v).sum();
 	println!("Saw {:?} documents...", total_values);
 	global_counts.into_iter().for_each(|(k,v)| {
+		let num_files = file_counts.get(&k).copied().unwrap_or(0);
 		let printkey: String = if k.is_none() {
 			String::from("None")
 		} else {
 			k.unwrap()
 		};
-		println!("Saw {:?} documents with type {:?}", v, printkey);
+		println!("Saw {:?} documents with type {:?} ({:?} files)", v, printkey, num_files);
+		if let Some(max_files) = config.max_files_per_category {
+			if num_files > max_files {
+				println!("Warning: type {:?} needed {:?} files, more than max_files_per_category ({:?}); the rest are in its overflow_* subdirectories",
+						 printkey, num_files, max_files);
+			}
+		}
 	});
 
-	Ok(())
+	budget.finish()
 }
 
 
@@ -167,9 +193,9 @@ struct PercentilePartitionConfig {
 	#[serde(default="default_max_file_size")]
 	max_file_size: usize,
 	#[serde(default="default_bucket_name")]
-	bucket_name: String
-
-
+	bucket_name: String,
+	#[serde(default)]
+	missing_bucket: bool, // if true, docs with a missing, null or non-numeric value go to {bucket_name}_missing instead of getting default_value
 }
 
 
@@ -178,9 +204,10 @@ fn default_bucket_name() -> String {
 }
 
 
+#[allow(clippy::too_many_arguments)]
 pub fn range_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &Option<PathBuf>,
 					  value: &Option<String>, default_value: &Option<f64>, range_groups: &Option<Vec<f64>>, reservoir_path: &Option<PathBuf>, num_buckets: &Option<usize>, 
-					  max_file_size: &Option<usize>, bucket_name: &Option<String>) -> Result<(), Error> {
+					  max_file_size: &Option<usize>, bucket_name: &Option<String>, missing_bucket: bool, max_runtime: Option<u64>, filename_template: &Option<String>) -> Result<(), Error> {
 	println!("Starting partition...");
 	let start_time = Instant::now();
 
@@ -196,7 +223,8 @@ pub fn range_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &O
 							   	   reservoir_path: reservoir_path.clone(), 
 							   	   num_buckets: num_buckets.clone(),
 							   	   max_file_size: max_file_size.clone().unwrap_or(default_max_file_size()),
-							   	   bucket_name: bucket_name.clone().unwrap_or(default_bucket_name())}
+							   	   bucket_name: bucket_name.clone().unwrap_or(default_bucket_name()),
+							   	   missing_bucket}
 	};
 	
 	let input_paths = expand_dirs(vec![input_dir.clone()], None).unwrap();
@@ -205,18 +233,8 @@ pub fn range_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &O
 		range_groups.to_vec()
 	} else if let Some(ref res_path) = config.reservoir_path {
 		let reservoir_content = read_pathbuf_to_mem(&res_path).unwrap().into_inner().into_inner();
-		let mut reservoir_data: Vec<f64> = serde_json::from_slice(&reservoir_content).unwrap();
-		reservoir_data.sort_unstable_by(|a,b| a.total_cmp(b));
-		let num_buckets = config.num_buckets.unwrap();
-		(1..num_buckets).map(|i| {
-			let index = (i * reservoir_data.len()) / num_buckets;
-			if index < reservoir_data.len() {
-				reservoir_data[index] 				
-			} else {
-				reservoir_data[reservoir_data.len() - 1]
-			}
-		})
-		.collect()
+		let reservoir_data: Vec<f64> = serde_json::from_slice(&reservoir_content).unwrap();
+		reservoir_to_ranges(reservoir_data, config.num_buckets.unwrap())
 	} else {
 		panic!("Need either range groups or a reservoir");
 	};
@@ -224,11 +242,17 @@ pub fn range_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &O
 
 
 	let counter: DashMap<usize, usize> = DashMap::new(); // counts range group -> num docs
-	let writer = GenWriter::new_bucket_writer(output_dir, config.max_file_size, &config.bucket_name);
+	let missing_counter = AtomicUsize::new(0); // docs without a usable value (in their own bucket or defaulted)
+	let template = FilenameTemplate::from_option(filename_template)?;
+	let writer = GenWriter::new_bucket_writer(output_dir, config.max_file_size, &config.bucket_name, template);
+	let budget = RuntimeBudget::new(max_runtime);
 	let pbar = build_pbar(input_paths.len(), "Paths");
 
 	input_paths.par_iter().for_each(|p| {
-		percentile_partition_path(p, &writer, &ranges, &config, &counter).unwrap();
+		if budget.out_of_time(p) {
+			return;
+		}
+		percentile_partition_path(p, &writer, &ranges, &config, &counter, &missing_counter).unwrap();
 		pbar.inc(1);
 	});
 	writer.finish().unwrap();
@@ -248,20 +272,34 @@ pub fn range_partition(input_dir: &PathBuf, output_dir: &PathBuf, config_opt: &O
 			println!("[{:?}, {:?}) | {:?} docs", ranges[k-1], ranges[k], v);
 		}
 	});
-	Ok(())
+	let missing = missing_counter.into_inner();
+	if config.missing_bucket {
+		println!("missing | {:?} docs", missing);
+	} else if missing > 0 {
+		println!("{:?} docs had no value and were bucketed as {:?}", missing, config.default_value.unwrap_or(0.0));
+	}
+	budget.finish()
 }
 
-fn percentile_partition_path(input_path: &PathBuf, writer: &GenWriter, percentile_values: &Vec<f64>, config: &PercentilePartitionConfig, counter: &DashMap<usize, usize>) -> Result<(), Error> {
+fn percentile_partition_path(input_path: &PathBuf, writer: &GenWriter, percentile_values: &Vec<f64>, config: &PercentilePartitionConfig, counter: &DashMap<usize, usize>, missing_counter: &AtomicUsize) -> Result<(), Error> {
 	let mut subcounter: HashMap<usize, usize> = HashMap::new();
 	let mut partitioned_contents: HashMap<usize, Vec<u8>> = HashMap::new();
+	let mut missing_contents: Vec<u8> = Vec::new();
 	let contents = read_pathbuf_to_mem(input_path).unwrap();
 	for line in contents.lines() {		
 		let line = line.unwrap();
 		let value : serde_json::Value = serde_json::from_str(&line).unwrap();
 		let gathered_value = json_get(&value, &config.value);
+		if config.missing_bucket && gathered_value.and_then(|v| v.as_f64()).is_none() {
+			missing_counter.fetch_add(1, Ordering::SeqCst);
+			missing_contents.extend(line.as_bytes());
+			missing_contents.push(b'\n');
+			continue;
+		}
 		let res_value = if let Some(res_value) = gathered_value {
 			res_value.as_f64().unwrap() as f64
 		} else {
+			missing_counter.fetch_add(1, Ordering::SeqCst);
 			config.default_value.unwrap_or(0.0)
 		};
 		let bucket = f64_to_bucket(percentile_values, res_value);
@@ -275,12 +313,29 @@ fn percentile_partition_path(input_path: &PathBuf, writer: &GenWriter, percentil
 		writer.write_contents(WriterKey::Bucket(k), v).unwrap();
 		*counter.entry(k).or_insert(0) += subcounter.get(&k).unwrap();
 	});
-
+	if !missing_contents.is_empty() {
+		writer.write_contents(WriterKey::Missing, missing_contents).unwrap();
+	}
 
 	Ok(())
 }
 
-fn f64_to_bucket(bucket_bounds: &Vec<f64>, value: f64) -> usize {
+pub fn reservoir_to_ranges(mut reservoir_data: Vec<f64>, num_buckets: usize) -> Vec<f64> {
+	// Evenly spaced quantiles of the reservoir: the num_buckets - 1 inner bucket bounds
+	reservoir_data.sort_unstable_by(|a,b| a.total_cmp(b));
+	(1..num_buckets).map(|i| {
+		let index = (i * reservoir_data.len()) / num_buckets;
+		if index < reservoir_data.len() {
+			reservoir_data[index] 				
+		} else {
+			reservoir_data[reservoir_data.len() - 1]
+		}
+	})
+	.collect()
+}
+
+
+pub fn f64_to_bucket(bucket_bounds: &[f64], value: f64) -> usize {
 	// linear scan of percentile bounds to the right bucket index
 	if value < bucket_bounds[0] {
 		return 0;
@@ -295,6 +350,89 @@ fn f64_to_bucket(bucket_bounds: &Vec<f64>, value: f64) -> usize {
 
 
 
+/*=============================================================
+=                        LANGUAGE PARTITION                   =
+=============================================================*/
+
+#[allow(clippy::too_many_arguments)]
+pub fn language_partition(input_dir: &Path, output_dir: &Path, fast_text_file: &Path, text_key: &str,
+						  min_prob: f32, max_file_size: usize, max_runtime: Option<u64>, filename_template: &Option<String>) -> Result<(), Error> {
+	println!("Starting language partition...");
+	let start_time = Instant::now();
+	let input_paths = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+
+	let model = load_fasttext_model(fast_text_file.to_str().unwrap())?;
+
+	let template = FilenameTemplate::from_option(filename_template)?;
+	let writer = GenWriter::new_prefixed_writer(output_dir, max_file_size, "lang", template);
+	let global_counts: DashMap<String, AtomicUsize> = DashMap::new();
+	let budget = RuntimeBudget::new(max_runtime);
+	let pbar = build_pbar(input_paths.len(), "Paths");
+	input_paths.par_iter().for_each(|p| {
+		if budget.out_of_time(p) {
+			return;
+		}
+		let local_counts = language_partition_path(p, &model, text_key, min_prob, &writer).unwrap();
+		local_counts.into_iter().for_each(|(k, v)| {
+		    global_counts.entry(k).or_insert_with(|| AtomicUsize::new(0)).fetch_add(v, Ordering::Relaxed);
+		});
+		pbar.inc(1);
+	});
+	writer.finish().unwrap();
+	println!("Finished language partition in {:?} secs", start_time.elapsed().as_secs());
+
+	let mut global_counts: Vec<(String, usize)> = global_counts
+		.into_iter()
+		.map(|(k, v)| (k, v.into_inner()))
+		.collect();
+	global_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+	let total_values: usize = global_counts.iter().map(|(_k, v)| *v).sum();
+	println!("Saw {:?} documents...", total_values);
+	global_counts.into_iter().for_each(|(k, v)| {
+		println!("Saw {:?} documents with language {:?}", v, k);
+	});
+	budget.finish()
+}
+
+
+fn language_partition_path(path: &PathBuf, model: &FastText, text_key: &str, min_prob: f32,
+						   writer: &GenWriter) -> Result<HashMap<String, usize>, Error> {
+	let contents = read_pathbuf_to_mem(path).unwrap();
+	let mut partitioned_bytes: HashMap<String, Vec<u8>> = HashMap::new();
+	let mut counts: HashMap<String, usize> = HashMap::new();
+	for line in contents.lines() {
+		let line = line.unwrap();
+		let json_value: serde_json::Value = serde_json::from_str(&line).unwrap();
+		let text = json_get(&json_value, text_key).and_then(|v| v.as_str()).unwrap_or("");
+		let lang = predict_language(model, text, min_prob);
+
+		*counts.entry(lang.clone()).or_insert(0) += 1;
+		let append_vec = partitioned_bytes.entry(lang).or_default();
+		append_vec.extend(line.as_bytes());
+		append_vec.push(b'\n');
+	}
+	partitioned_bytes.into_iter().for_each(|(lang, val)| {
+		writer.write_contents(WriterKey::Category(Some(lang)), val).unwrap();
+	});
+
+	Ok(counts)
+}
+
+
+fn predict_language(model: &FastText, text: &str, min_prob: f32) -> String {
+	// Top-1 fasttext label (without the __label__ prefix), or "unknown" if nothing clears min_prob
+	let mut text = text.replace("\n", " ");
+	text.push('\n');
+	match model.predict(&text, 1, min_prob) {
+		Ok(preds) if !preds.is_empty() => {
+			let label = &preds[0].label;
+			label.strip_prefix("__label__").unwrap_or(label).to_string()
+		},
+		_ => String::from("unknown"),
+	}
+}
+
+
 /*==========================================================
 =                        GEN WRITER STUFF                  =
 ==========================================================*/
@@ -305,6 +443,7 @@ fn f64_to_bucket(bucket_bounds: &Vec<f64>, value: f64) -> usize {
 pub enum WriterKey {
     Category(Option<String>),
     Bucket(usize),
+    Missing, // range partition's bucket for docs without a usable value
 }
 
 pub struct GenWriter<'a> {
@@ -312,10 +451,11 @@ pub struct GenWriter<'a> {
     storage_loc: PathBuf,
     max_len: usize,
     config: WriterConfig,
+    template: Option<FilenameTemplate>, // replaces the default file names (not directories) if set
 }
 
 pub struct WriterInfo<'a> {
-    encoder: Option<Encoder<'a, File>>, // You'll need to import your Encoder type
+    encoder: Option<OutputEncoder<'a, AtomicFile>>, // zstd unless --no-compress; moved into place when finished
     bytes_written: usize,
     file_idx: usize,
 }
@@ -324,10 +464,14 @@ pub struct WriterInfo<'a> {
 pub enum WriterConfig {
     Category {
         full_choices: Option<HashSet<Option<String>>>,
+        max_files: Option<usize>, // max files directly in a category dir, see get_filename
     },
     Bucket {
         bucket_name: String,
     },
+    Prefixed {
+        prefix: String,
+    },
 }
 
 impl<'a> GenWriter<'a> {
@@ -335,11 +479,13 @@ impl<'a> GenWriter<'a> {
     pub fn new_category_writer(
         storage_loc: &PathBuf, 
         choices: &Option<Vec<String>>, 
-        max_len: usize
+        max_len: usize,
+        max_files: Option<usize>,
+        template: Option<FilenameTemplate>,
     ) -> Self {
         let writer = DashMap::new();
 
-        let fake_config = &WriterConfig::Category {full_choices: None};
+        let fake_config = &WriterConfig::Category {full_choices: None, max_files};
         let (full_choices, fc_len) = if let Some(choices) = choices {
         	let mut full_choices: HashSet<Option<String>> = HashSet::new();
         	for choice in choices {
@@ -351,14 +497,14 @@ impl<'a> GenWriter<'a> {
         	for choice in &full_choices {
         		let key = WriterKey::Category(choice.clone());
 				writer.entry(key.clone()).or_insert_with(|| {
-		            let filename = GenWriter::get_filename(fake_config, &key, 0, storage_loc);
+		            let filename = GenWriter::get_filename(fake_config, &key, 0, storage_loc, template.as_ref());
 		            if let Some(parent_dir) = filename.parent() {
 		                if !parent_dir.exists() {
 		                    create_dir_all(parent_dir).unwrap();
 		                }
 		            }
 		            let writer_info = WriterInfo {
-		                encoder: Some(Self::create_new_encoder(fake_config, &key, 0, storage_loc)),
+		                encoder: Some(Self::create_new_encoder(fake_config, &key, 0, storage_loc, template.as_ref())),
 		                bytes_written: 0,
 		                file_idx: 0,
 		            };
@@ -374,7 +520,8 @@ impl<'a> GenWriter<'a> {
             writer,
             storage_loc: storage_loc.clone(),
             max_len,
-            config: WriterConfig::Category { full_choices },
+            config: WriterConfig::Category { full_choices, max_files },
+            template,
         };
 
 
@@ -388,7 +535,8 @@ impl<'a> GenWriter<'a> {
     pub fn new_bucket_writer(
         storage_loc: &PathBuf,
         max_len: usize,
-        bucket_name: &String
+        bucket_name: &String,
+        template: Option<FilenameTemplate>,
     ) -> Self {
         let writer = DashMap::new();
         
@@ -399,54 +547,79 @@ impl<'a> GenWriter<'a> {
             config: WriterConfig::Bucket {
                 bucket_name: bucket_name.to_string(),
             },
+            template,
         }
     }
 
-    pub fn get_filename(config: &WriterConfig, key: &WriterKey, file_idx: usize, storage_loc: &PathBuf) -> PathBuf {
+    // Constructor for string-keyed writer with bucket-style shard names (e.g. lang_en/shard_00000000.jsonl.zst)
+    pub fn new_prefixed_writer(
+        storage_loc: &Path,
+        max_len: usize,
+        prefix: &str,
+        template: Option<FilenameTemplate>,
+    ) -> Self {
+        let writer = DashMap::new();
+
+        GenWriter {
+            writer,
+            storage_loc: storage_loc.to_path_buf(),
+            max_len,
+            config: WriterConfig::Prefixed {
+                prefix: prefix.to_string(),
+            },
+            template,
+        }
+    }
+
+    pub fn get_filename(config: &WriterConfig, key: &WriterKey, file_idx: usize, storage_loc: &Path, template: Option<&FilenameTemplate>) -> PathBuf {
+        // {bucket} in a template is the category (no_category for docs without one), the 4-digit bucket number (or
+        // "missing") or the prefixed writer's key
+        let name = |bucket: &str, default: String| match template {
+            Some(template) => template.render(bucket, file_idx, output_ext()),
+            None => default,
+        };
         match (config, key) {
-            (WriterConfig::Category { .. }, WriterKey::Category(choice)) => {
-                if choice.is_none() {
+            (WriterConfig::Category { max_files, .. }, WriterKey::Category(choice)) => {
+                let category_dir = if choice.is_none() {
                     storage_loc.join("no_category")
-                    	.join(format!("chunk_{:08}.jsonl.zst", file_idx))
                 } else {
 
                     storage_loc.join(format!("{}", choice.as_ref().unwrap()))
-                    	.join(format!("chunk_{:08}.jsonl.zst", file_idx))
-                }
+                };
+                // The first max_files files live in the category dir, each next max_files in overflow_0001/, overflow_0002/, ...
+                let category_dir = match max_files {
+                    Some(max_files) if file_idx >= *max_files => category_dir.join(format!("overflow_{:04}", file_idx / max_files)),
+                    _ => category_dir,
+                };
+                category_dir.join(name(choice.as_deref().unwrap_or("no_category"), format!("chunk_{:08}.{}", file_idx, output_ext())))
             }
             (WriterConfig::Bucket { bucket_name }, WriterKey::Bucket(bucket_num)) => {
                 storage_loc
                     .join(format!("{}_{:04}", bucket_name, bucket_num))
-                    .join(format!("shard_{:08}.jsonl.zst", file_idx))
+                    .join(name(&format!("{:04}", bucket_num), format!("shard_{:08}.{}", file_idx, output_ext())))
+            }
+            (WriterConfig::Bucket { bucket_name }, WriterKey::Missing) => {
+                storage_loc
+                    .join(format!("{}_missing", bucket_name))
+                    .join(name("missing", format!("shard_{:08}.{}", file_idx, output_ext())))
+            }
+            (WriterConfig::Prefixed { prefix }, WriterKey::Category(choice)) => {
+                storage_loc
+                    .join(format!("{}_{}", prefix, choice.as_deref().unwrap_or("unknown")))
+                    .join(name(choice.as_deref().unwrap_or("unknown"), format!("shard_{:08}.{}", file_idx, output_ext())))
             }
             _ => panic!("Mismatched writer config and key type"),
         }
     }
 
-    fn create_new_encoder(config: &WriterConfig, key: &WriterKey, file_idx: usize, storage_loc: &PathBuf) -> Encoder<'a, File> {
-        let new_filename = GenWriter::get_filename(config, key, file_idx, storage_loc);
-
-        if let Some(parent_dir) = new_filename.parent() {
-            if !parent_dir.exists() {
-                create_dir_all(parent_dir).unwrap();
-            }
-        }
-
-        Encoder::new(
-            OpenOptions::new()
-                .append(true)
-                .create(true)
-                .mode(0o644)
-                .open(new_filename)
-                .unwrap(),
-            3,
-        )
-        .unwrap()
+    fn create_new_encoder(config: &WriterConfig, key: &WriterKey, file_idx: usize, storage_loc: &Path, template: Option<&FilenameTemplate>) -> OutputEncoder<'a, AtomicFile> {
+        let new_filename = GenWriter::get_filename(config, key, file_idx, storage_loc, template);
+        OutputEncoder::new(AtomicFile::create(&new_filename).unwrap()).unwrap()
     }
 
     pub fn write_contents(&self, key: WriterKey, contents: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
     	let writer_arc = match (&self.config, &key) {
-    		(WriterConfig::Category { full_choices }, WriterKey::Category(choice)) => {
+    		(WriterConfig::Category { full_choices, .. }, WriterKey::Category(choice)) => {
     			if let Some(og_choices) = full_choices { // Choices are prespecified -- either we match or key=None
     				let proper_key = if og_choices.contains(&choice) {
     					key.clone()
@@ -456,14 +629,14 @@ impl<'a> GenWriter<'a> {
     				&self.writer.get_mut(&proper_key).unwrap()
     			} else { // Choices are not prespecified, always match, otherwise create a new thing
 					&self.writer.entry(key.clone()).or_insert_with(|| {
-			            let filename = GenWriter::get_filename(&self.config, &key, 0, &self.storage_loc);
+			            let filename = GenWriter::get_filename(&self.config, &key, 0, &self.storage_loc, self.template.as_ref());
 			            if let Some(parent_dir) = filename.parent() {
 			                if !parent_dir.exists() {
 			                    create_dir_all(parent_dir).unwrap();
 			                }
 			            }
 			            let writer_info = WriterInfo {
-			                encoder: Some(GenWriter::create_new_encoder(&self.config, &key, 0, &self.storage_loc)),
+			                encoder: Some(GenWriter::create_new_encoder(&self.config, &key, 0, &self.storage_loc, self.template.as_ref())),
 			                bytes_written: 0,
 			                file_idx: 0,
 			            };
@@ -471,16 +644,16 @@ impl<'a> GenWriter<'a> {
 			        })    				
     			}
     		},
-    		(WriterConfig::Bucket { .. }, WriterKey::Bucket(..)) => {
+    		(WriterConfig::Bucket { .. }, WriterKey::Bucket(..) | WriterKey::Missing) | (WriterConfig::Prefixed { .. }, WriterKey::Category(..)) => {
 				&self.writer.entry(key.clone()).or_insert_with(|| {
-		            let filename = GenWriter::get_filename(&self.config, &key, 0, &self.storage_loc);
+		            let filename = GenWriter::get_filename(&self.config, &key, 0, &self.storage_loc, self.template.as_ref());
 		            if let Some(parent_dir) = filename.parent() {
 		                if !parent_dir.exists() {
 		                    create_dir_all(parent_dir).unwrap();
 		                }
 		            }
 		            let writer_info = WriterInfo {
-		                encoder: Some(GenWriter::create_new_encoder(&self.config, &key, 0, &self.storage_loc)),
+		                encoder: Some(GenWriter::create_new_encoder(&self.config, &key, 0, &self.storage_loc, self.template.as_ref())),
 		                bytes_written: 0,
 		                file_idx: 0,
 		            };
@@ -495,7 +668,7 @@ impl<'a> GenWriter<'a> {
         writer_info.bytes_written += contents.len();
 
         if writer_info.encoder.is_none() {
-            writer_info.encoder = Some(GenWriter::create_new_encoder(&self.config, &key, writer_info.file_idx, &self.storage_loc));
+            writer_info.encoder = Some(GenWriter::create_new_encoder(&self.config, &key, writer_info.file_idx, &self.storage_loc, self.template.as_ref()));
         }
 
 
@@ -504,7 +677,7 @@ impl<'a> GenWriter<'a> {
             if writer_info.bytes_written >= self.max_len {
                 let mut old_encoder = writer_info.encoder.take().unwrap();
                 old_encoder.flush()?;
-                old_encoder.finish()?;
+                old_encoder.finish()?.commit()?;
                 writer_info.file_idx += 1;
                 writer_info.encoder = None;
                 writer_info.bytes_written = 0;
@@ -533,15 +706,26 @@ impl<'a> GenWriter<'a> {
         self.write_contents(WriterKey::Bucket(bucket), contents)
     }
 
+    pub fn file_counts(&self) -> Vec<(WriterKey, usize)> {
+        // Number of files created so far per key (after a roll-over the next file is only created on the next write)
+        self.writer.iter().map(|entry| {
+            let writer_info = entry.value().lock().unwrap();
+            (entry.key().clone(), writer_info.file_idx + writer_info.encoder.is_some() as usize)
+        }).collect()
+    }
+
     pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
         self.writer.into_par_iter().for_each(|(_, value)| {
             match Arc::try_unwrap(value) {
                 Ok(mutex) => {
                     let mut writer_info = mutex.into_inner().unwrap();
-                    if writer_info.bytes_written > 0 {
-                        if let Some(mut encoder) = writer_info.encoder.take() {
+                    if let Some(mut encoder) = writer_info.encoder.take() {
+                        if writer_info.bytes_written > 0 {
                             encoder.flush().unwrap();
-                            encoder.finish().unwrap();
+                            encoder.finish().unwrap().commit().unwrap();
+                        } else {
+                            // e.g. a prespecified category that never got a doc: no (empty) file at all
+                            encoder.finish().unwrap().discard().unwrap();
                         }
                     }
                 }
@@ -4,8 +4,9 @@ use serde_json::json;
 use std::io::BufRead;
 use anyhow::{Error, Result};
 use std::path::PathBuf;
+use crate::utils::{build_pbar, write_mem_atomic};
 use mj_io::{
-    build_pbar, expand_dirs, read_pathbuf_to_mem, write_mem_to_pathbuf,
+    expand_dirs, read_pathbuf_to_mem,
 };
 use rayon::prelude::*;
 use rand::prelude::*;
@@ -59,7 +60,7 @@ pub fn percentile_finder(input_dir: &PathBuf, output_file: &PathBuf, score_key:
 
     let output_json = json!(score_breaks);
     let output_contents = serde_json::to_vec(&output_json).unwrap();
-    write_mem_to_pathbuf(&output_contents, output_file).unwrap();
+    write_mem_atomic(&output_contents, output_file).unwrap();
     
     Ok(())
 
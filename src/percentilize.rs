@@ -0,0 +1,116 @@
+/*============================================================
+=                            PERCENTILIZE                    =
+============================================================*/
+/*
+Back-annotates every doc with the percentile rank of a numeric field across the whole dataset.
+
+Two passes:
+1. Reservoir sample the value field (same sampler as reservoir-sample) and turn the reservoir into
+   num_buckets - 1 evenly spaced quantile bounds (same math as range-partition with a reservoir)
+2. Stream every doc, bucket its value against those bounds and write bucket * 100 / num_buckets into
+   output_field. So with the default 100 buckets, output_field is the integer percentile in [0, 99]
+   and "top 10%" is output_field >= 90.
+
+Output files mirror the layout of the input directory.
+*/
+
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use anyhow::{ensure, Error, Result};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+use crate::partition::{f64_to_bucket, reservoir_to_ranges};
+use crate::reservoir_sample::sample_values;
+use crate::utils::{build_pbar, json_get, json_set, mirrored_output_filename, JsonlWriter};
+use mj_io::{expand_dirs, read_pathbuf_to_mem};
+
+#[allow(clippy::too_many_arguments)]
+pub fn percentilize(
+    input_dir: &Path,
+    output_dir: &Path,
+    value: &String,
+    output_field: &String,
+    reservoir_size: usize,
+    num_buckets: usize,
+    default_value: Option<f64>,
+) -> Result<(), Error> {
+    ensure!(num_buckets >= 2, "Need at least 2 buckets, got {:?}", num_buckets);
+    let start_main = Instant::now();
+    println!("Starting percentilize...");
+
+    // Pass 1: estimate the CDF from a reservoir
+    let (reservoir, total_seen) = sample_values(input_dir, value, reservoir_size).unwrap();
+    let reservoir: Vec<f64> = reservoir.iter().filter_map(|v| v.as_f64()).collect();
+    ensure!(!reservoir.is_empty(), "No numeric values found for {:?}", value);
+    println!(
+        "Made a reservoir of size {:?} from {:?} documents total",
+        reservoir.len(),
+        total_seen
+    );
+    let ranges = reservoir_to_ranges(reservoir, num_buckets);
+
+    // Pass 2: back-annotate
+    let all_files = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+    let pbar = build_pbar(all_files.len(), "Paths");
+    let docs_seen = AtomicUsize::new(0);
+    let docs_missing = AtomicUsize::new(0);
+    all_files.par_iter().for_each(|p| {
+        let output_file = mirrored_output_filename(p, input_dir, output_dir).unwrap();
+        let (seen, missing) = percentilize_path(
+            p, &output_file, &ranges, num_buckets, value, output_field, default_value,
+        )
+        .unwrap();
+        docs_seen.fetch_add(seen, Ordering::SeqCst);
+        docs_missing.fetch_add(missing, Ordering::SeqCst);
+        pbar.inc(1);
+    });
+
+    println!("Finished percentilize in {:?} secs", start_main.elapsed().as_secs());
+    println!(
+        "Saw {:?} docs | {:?} had no numeric {:?}",
+        docs_seen.into_inner(),
+        docs_missing.into_inner(),
+        value
+    );
+    Ok(())
+}
+
+fn percentilize_path(
+    input_path: &PathBuf,
+    output_path: &Path,
+    ranges: &[f64],
+    num_buckets: usize,
+    value: &str,
+    output_field: &String,
+    default_value: Option<f64>,
+) -> Result<(usize, usize), Error> {
+    let contents = read_pathbuf_to_mem(input_path).unwrap();
+    let mut writer = JsonlWriter::create(output_path)?;
+    let mut missing = 0;
+    for line in contents.lines() {
+        let line = line.unwrap();
+        let mut doc: Value = serde_json::from_str(&line).unwrap();
+        let doc_value = json_get(&doc, value).and_then(|v| v.as_f64());
+        if doc_value.is_none() {
+            missing += 1;
+        }
+        // Docs without a value get the default's percentile, or are passed through unannotated
+        if let Some(doc_value) = doc_value.or(default_value) {
+            let bucket = f64_to_bucket(ranges, doc_value);
+            let percentile = (bucket * 100) as f64 / num_buckets as f64;
+            json_set(&mut doc, output_field, json!(percentile))?;
+        }
+        writer.write_value(&doc)?;
+    }
+    let seen = writer.lines();
+    if seen > 0 {
+        writer.finish()?;
+    } else {
+        writer.discard()?;
+    }
+    Ok((seen, missing))
+}
@@ -1,28 +1,46 @@
-/* Reservoir sampling */
+/* Reservoir sampling
 
+With sample_docs, the output is the sampled documents themselves (jsonl) rather than the values of key.
+Holding reservoir_size full documents can take a lot of memory for big-text corpora, so in that mode the
+reservoirs only hold DocRefs (input file index + line number) and the documents are fetched in a final
+gather pass over the files that contributed to the sample. Peak memory is then the refs plus the few files
+being read at once, not the sampled documents.
+
+Stratified sampling buckets docs by a numeric value (the same bucketing as range_partition) and keeps a
+uniform sample of per_bucket docs from each bucket, e.g. for an eval set balanced across quality quintiles.
+*/
+
+use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::cmp::Ordering;
 use serde_json::json;
-use crate::utils::json_get;
+use crate::partition::{f64_to_bucket, reservoir_to_ranges};
+use crate::utils::{build_pbar, json_get, write_mem_atomic, AtomicFile};
 use serde_json::Value;
 use indicatif::ProgressBar;
 use std::io::BufRead;
-use anyhow::{Error, Result};
-use std::path::PathBuf;
+use anyhow::{ensure, Error, Result};
+use std::path::{Path, PathBuf};
 use mj_io::{
-    build_pbar, expand_dirs, read_pathbuf_to_mem, write_mem_to_pathbuf,
+    expand_dirs, read_pathbuf_to_mem,
 };
 use rayon::prelude::*;
 use rand::prelude::*;
 use rayon::current_num_threads;
 
 use binary_heap_plus::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use tiktoken_rs::cl100k_base;
+use zstd::stream::Encoder;
 
 
-pub fn reservoir_sample(input_dir: &PathBuf, output_file: &PathBuf, key: &String, reservoir_size: usize, token_weighted: bool, text_key: &String) -> Result<(), Error> {
+pub fn reservoir_sample(input_dir: &PathBuf, output_file: &PathBuf, key: &String, reservoir_size: usize, token_weighted: bool, text_key: &String, sample_docs: bool) -> Result<(), Error> {
 	println!("Starting reservoir sampling...");
-	if !token_weighted {
+	if sample_docs {
+		doc_reservoir(input_dir, key, text_key, reservoir_size, token_weighted, output_file)?;
+	} else if !token_weighted {
 		unweighted_reservoir(input_dir, key, reservoir_size, output_file).unwrap();
 	} else {
 		token_weighted_reservoir(input_dir, key, &text_key, reservoir_size, output_file).unwrap();
@@ -35,45 +53,62 @@ pub fn reservoir_sample(input_dir: &PathBuf, output_file: &PathBuf, key: &String
 =                           Unweighted Reservoir Sampling                  =
 ==========================================================================*/
 
-fn unweighted_reservoir(input_dir: &PathBuf, key: &String, reservoir_size: usize, output_file: &PathBuf) -> Result<(), Error> {
+fn unweighted_reservoir(input_dir: &Path, key: &str, reservoir_size: usize, output_file: &Path) -> Result<(), Error> {
 
 
-    let all_files = expand_dirs(vec![input_dir.clone()], None).unwrap();
+    let (full_res, total_seen) = sample_values(input_dir, key, reservoir_size).unwrap();
+    let json_res = json!(full_res);
+    let output_contents = serde_json::to_vec(&json_res).unwrap();
+    write_mem_atomic(&output_contents, output_file).unwrap();
+    println!("Made a reservoir of size {:?} from {:?} documents total", full_res.len(), total_seen);
+
+	Ok(())
+}
+
+
+pub fn sample_values(input_dir: &Path, key: &str, reservoir_size: usize) -> Result<(Vec<Value>, usize), Error> {
+    // Uniform sample of the values of key: returns (reservoir, total docs seen)
+    let all_files = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
     let num_files = all_files.len();
 
     let chunks_targets = get_chunks_targets(all_files, reservoir_size).unwrap();
     let pbar = build_pbar(num_files, "Paths");
 
     let full_res: Vec<(Vec<Value>, usize)> = chunks_targets.into_par_iter().map(|(pvec, target_size)| {
-        thread_res(&pvec, key, target_size, &pbar).unwrap()
+        thread_res(&pvec, key, target_size, &pbar, |item, _, _| item.clone()).unwrap()
     }).collect();
 
-
     let total_seen = full_res.par_iter().map(|k| k.1).sum::<usize>();
     let full_res: Vec<Value> = full_res.into_iter().flat_map(|k| k.0).collect();
-    let json_res = json!(full_res);
-    let output_contents = serde_json::to_vec(&json_res).unwrap();
-    write_mem_to_pathbuf(&output_contents, output_file).unwrap();
-    println!("Made a reservoir of size {:?} from {:?} documents total", full_res.len(), total_seen);
-
-	Ok(())
+    Ok((full_res, total_seen))
 }
 
 
-fn thread_res(input_paths: &Vec<PathBuf>, key: &String, reservoir_size: usize, pbar: &ProgressBar) -> Result<(Vec<Value>, usize), Error> {
-	let mut cur_res: Vec<Value> = Vec::new();
+fn thread_res<T>(
+	input_paths: &Vec<PathBuf>,
+	key: &str,
+	reservoir_size: usize,
+	pbar: &ProgressBar,
+	make_item: impl Fn(&Value, &PathBuf, usize) -> T, // (value of key, path, line number) -> what the reservoir keeps
+) -> Result<(Vec<T>, usize), Error> {
+	let mut cur_res: Vec<T> = Vec::new();
 	let mut total_seen: usize = 0;
 	let mut rng = rand::rng();
 	input_paths.into_iter().for_each(|p| {
 		let contents = read_pathbuf_to_mem(&p).unwrap();
-		for line in contents.lines() {
+		for (line_num, line) in contents.lines().enumerate() {
 			// Only process if we need to access this data 
 			total_seen += 1;
 			let rand_idx = rng.random_range(0..=total_seen);
 			if cur_res.len() < reservoir_size || rand_idx < reservoir_size {
 				let line = line.unwrap();
 				let json_line: Value = serde_json::from_str(&line).unwrap();
-				let item = json_get(&json_line, key).unwrap().clone();
+				// Docs without the key can't contribute a value
+				let item = if let Some(item) = json_get(&json_line, key) {
+					make_item(item, p, line_num)
+				} else {
+					continue;
+				};
 				if cur_res.len() < reservoir_size {
 					cur_res.push(item);
 				} else {
@@ -117,7 +152,7 @@ fn token_weighted_reservoir(input_dir: &PathBuf, score_key: &String, text_key: &
 
     let pbar = build_pbar(num_files, "Paths");
     let full_res: Vec<Vec<WeightedItem>> = chunks_targets.into_par_iter().map(|(pvec, res_size)| {
-        token_weighted_thread_res(&pvec, score_key, text_key, res_size, &pbar).unwrap()
+        token_weighted_thread_res(&pvec, score_key, text_key, res_size, &pbar, None).unwrap()
     }).collect();
 
     let mut full_res: Vec<WeightedItem> = full_res.into_iter().flat_map(|k| k).collect();
@@ -134,7 +169,7 @@ fn token_weighted_reservoir(input_dir: &PathBuf, score_key: &String, text_key: &
     println!("Made a reservoir of size {:?} from {:?} tokens total", percentiles.len(), total_weight);
 
     let output_contents = serde_json::to_vec(&percentiles).unwrap();
-    write_mem_to_pathbuf(&output_contents, output_file).unwrap();
+    write_mem_atomic(&output_contents, output_file).unwrap();
 
 	Ok(())
 }
@@ -145,6 +180,7 @@ struct WeightedItem {
     value: f64,
     log_key: f64,
     weight: usize,
+    doc: Option<DocRef>, // only kept with sample_docs
 }
 impl PartialEq for WeightedItem {
     fn eq(&self, other: &Self) -> bool {
@@ -171,7 +207,8 @@ fn token_weighted_thread_res(
     score_key: &String,
     text_key: &String,
     reservoir_size: usize, 
-    pbar: &ProgressBar
+    pbar: &ProgressBar,
+    path_ids: Option<&HashMap<PathBuf, usize>>,
 ) -> Result<Vec<WeightedItem>, Error> {
     // Create min-heap ordered by log_key using closure comparator
 
@@ -181,7 +218,8 @@ fn token_weighted_thread_res(
     let tokenizer = cl100k_base().unwrap();
     input_paths.into_iter().for_each(|p| {
         let contents = read_pathbuf_to_mem(&p).unwrap();
-        for line in contents.lines() {
+        let path_id = path_ids.map(|path_ids| path_ids[p]);
+        for (line_num, line) in contents.lines().enumerate() {
             let line = line.unwrap();
             let json_line: Value = serde_json::from_str(&line).unwrap();
             let value = json_get(&json_line, score_key).unwrap().as_f64().unwrap();
@@ -193,7 +231,8 @@ fn token_weighted_thread_res(
             let u: f64 = rng.random();
             let log_key = u.ln() / (weight as f64);
             
-            let weighted_item = WeightedItem { value, log_key, weight };
+            let doc = path_id.map(|path_id| DocRef { path_id, line_num });
+            let weighted_item = WeightedItem { value, log_key, weight, doc };
             
             if heap.len() < reservoir_size {
                 heap.push(weighted_item);
@@ -208,4 +247,272 @@ fn token_weighted_thread_res(
     });
         
     Ok(heap.into_vec())
-}
\ No newline at end of file
+}
+
+
+/*==================================================================
+=                      Document Reservoir Sampling                 =
+==================================================================*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct DocRef {
+    path_id: usize,  // index into the sorted input file list
+    line_num: usize, // line of the (decompressed) file
+}
+
+
+fn doc_reservoir(input_dir: &Path, key: &String, text_key: &String, reservoir_size: usize, token_weighted: bool, output_file: &PathBuf) -> Result<(), Error> {
+    let mut all_files = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+    all_files.sort();
+    let path_ids: HashMap<PathBuf, usize> = all_files.iter().enumerate().map(|(i, p)| (p.clone(), i)).collect();
+    let chunks_targets = get_chunks_targets(all_files.clone(), reservoir_size).unwrap();
+
+    // Pass 1: sample refs only
+    let pbar = build_pbar(all_files.len(), "Paths");
+    let (mut refs, total_seen): (Vec<DocRef>, usize) = if token_weighted {
+        let full_res: Vec<Vec<WeightedItem>> = chunks_targets.into_par_iter().map(|(pvec, res_size)| {
+            token_weighted_thread_res(&pvec, key, text_key, res_size, &pbar, Some(&path_ids)).unwrap()
+        }).collect();
+        let full_res: Vec<WeightedItem> = full_res.into_iter().flatten().collect();
+        let total_weight = full_res.iter().map(|w| w.weight).sum::<usize>();
+        (full_res.into_iter().map(|w| w.doc.unwrap()).collect(), total_weight)
+    } else {
+        let full_res: Vec<(Vec<DocRef>, usize)> = chunks_targets.into_par_iter().map(|(pvec, target_size)| {
+            thread_res(&pvec, key, target_size, &pbar, |_, p, line_num| DocRef { path_id: path_ids[p], line_num }).unwrap()
+        }).collect();
+        let total_seen = full_res.iter().map(|k| k.1).sum::<usize>();
+        (full_res.into_iter().flat_map(|k| k.0).collect(), total_seen)
+    };
+    refs.par_sort_unstable();
+    println!(
+        "Sampled {:?} documents from {:?} {} total, gathering them...",
+        refs.len(), total_seen, if token_weighted { "tokens" } else { "documents" }
+    );
+
+    let gathered = gather_docs(&all_files, &refs, output_file)?;
+    println!("Wrote {:?} sampled documents to {:?}", gathered, output_file);
+    Ok(())
+}
+
+
+fn gather_docs(all_files: &[PathBuf], refs: &[DocRef], output_file: &Path) -> Result<usize, Error> {
+    // Pass 2: gather (refs must be sorted), reading a thread's worth of files at a time so only those are ever in memory
+    let mut refs_by_path: Vec<(usize, Vec<usize>)> = Vec::new();
+    for doc_ref in refs {
+        match refs_by_path.last_mut() {
+            Some((path_id, line_nums)) if *path_id == doc_ref.path_id => line_nums.push(doc_ref.line_num),
+            _ => refs_by_path.push((doc_ref.path_id, vec![doc_ref.line_num])),
+        }
+    }
+    let mut writer = DocWriter::new(output_file)?;
+    let pbar = build_pbar(refs_by_path.len(), "Gather paths");
+    let mut gathered = 0;
+    for batch in refs_by_path.chunks(current_num_threads()) {
+        let batch_bytes: Vec<(usize, Vec<u8>)> = batch.par_iter().map(|(path_id, line_nums)| {
+            let docs = gather_lines(&all_files[*path_id], line_nums).unwrap();
+            pbar.inc(1);
+            docs
+        }).collect();
+        for (num_docs, bytes) in batch_bytes {
+            gathered += num_docs;
+            writer.write_all(&bytes)?;
+        }
+    }
+    writer.finish()?;
+    Ok(gathered)
+}
+
+
+fn gather_lines(path: &PathBuf, line_nums: &[usize]) -> Result<(usize, Vec<u8>), Error> {
+    // line_nums are sorted and unique; returns (num docs found, their bytes as jsonl)
+    let contents = read_pathbuf_to_mem(path).unwrap();
+    let mut wanted = line_nums.iter().peekable();
+    let mut output_bytes: Vec<u8> = Vec::new();
+    let mut found = 0;
+    for (line_num, line) in contents.lines().enumerate() {
+        match wanted.peek() {
+            None => break,
+            Some(&&wanted_num) if wanted_num == line_num => {
+                output_bytes.extend(line?.as_bytes());
+                output_bytes.push(b'\n');
+                found += 1;
+                wanted.next();
+            }
+            Some(_) => {}
+        }
+    }
+    Ok((found, output_bytes))
+}
+
+
+enum DocWriter {
+    // Streaming jsonl writer for the gather pass, compressed according to the output extension
+    Plain(AtomicFile),
+    Zstd(Encoder<'static, AtomicFile>),
+    Gzip(GzEncoder<AtomicFile>),
+}
+
+impl DocWriter {
+    fn new(output_file: &Path) -> Result<Self, Error> {
+        let file = AtomicFile::create(output_file)?;
+        Ok(match output_file.extension().and_then(|ext| ext.to_str()) {
+            Some("zst") | Some("zstd") => DocWriter::Zstd(Encoder::new(file, 3)?),
+            Some("gz") => DocWriter::Gzip(GzEncoder::new(file, Compression::default())),
+            _ => DocWriter::Plain(file),
+        })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        match self {
+            DocWriter::Plain(w) => w.write_all(bytes)?,
+            DocWriter::Zstd(w) => w.write_all(bytes)?,
+            DocWriter::Gzip(w) => w.write_all(bytes)?,
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            DocWriter::Plain(w) => w.commit()?,
+            DocWriter::Zstd(w) => w.finish()?.commit()?,
+            DocWriter::Gzip(w) => w.finish()?.commit()?,
+        }
+        Ok(())
+    }
+}
+
+
+/*==================================================================
+=                      Stratified Sampling                         =
+==================================================================*/
+/*
+Buckets docs by value with explicit range_groups, or with num_buckets evenly sized (quantile) buckets whose bounds
+come from reservoir_path (a reservoir-sample output) or, if that's not given, from a value-only reservoir sampled
+here first. Every doc in a bucket draws a random u64 priority and the bucket keeps the per_bucket lowest ones:
+that's a uniform sample without replacement, and per-file samples merge by simply keeping the lowest priorities
+again, so files are sampled fully in parallel. Like doc_reservoir, only DocRefs are held and the sampled docs are
+gathered in a second pass.
+*/
+
+const STRATIFIED_VALUE_RESERVOIR: usize = 100_000; // values sampled to find the bucket bounds when only num_buckets is given
+
+struct BucketSample {
+    samples: Vec<BinaryHeap<(u64, DocRef)>>, // per bucket: max-heap of the per_bucket lowest priorities
+    seen: Vec<usize>,                        // per bucket: docs that landed in it
+    missing: usize,                          // docs skipped for not having a numeric value
+}
+
+impl BucketSample {
+    fn new(num_buckets: usize) -> Self {
+        BucketSample {
+            samples: (0..num_buckets).map(|_| BinaryHeap::new()).collect(),
+            seen: vec![0; num_buckets],
+            missing: 0,
+        }
+    }
+
+    fn merge(mut self, other: BucketSample, per_bucket: usize) -> Self {
+        for (bucket, heap) in other.samples.into_iter().enumerate() {
+            for item in heap.into_vec() {
+                push_capped(&mut self.samples[bucket], item, per_bucket);
+            }
+        }
+        self.seen.iter_mut().zip(other.seen).for_each(|(a, b)| *a += b);
+        self.missing += other.missing;
+        self
+    }
+}
+
+fn push_capped(heap: &mut BinaryHeap<(u64, DocRef)>, item: (u64, DocRef), per_bucket: usize) {
+    if heap.len() < per_bucket {
+        heap.push(item);
+    } else if heap.peek().is_some_and(|top| item < *top) {
+        heap.pop();
+        heap.push(item);
+    }
+}
+
+
+#[allow(clippy::too_many_arguments)]
+pub fn stratified_sample(input_dir: &Path, output_file: &PathBuf, value: &String, range_groups: &Option<Vec<f64>>,
+                         reservoir_path: &Option<PathBuf>, num_buckets: Option<usize>, per_bucket: usize,
+                         default_value: Option<f64>) -> Result<(), Error> {
+    println!("Starting stratified sampling...");
+    ensure!(range_groups.is_some() != num_buckets.is_some(), "Need exactly one of range_groups or num_buckets");
+    ensure!(per_bucket > 0, "per_bucket must be positive");
+    let mut all_files = expand_dirs(vec![input_dir.to_path_buf()], None).unwrap();
+    all_files.sort();
+
+    let ranges: Vec<f64> = if let Some(range_groups) = range_groups {
+        range_groups.clone()
+    } else {
+        let num_buckets = num_buckets.unwrap();
+        ensure!(num_buckets >= 2, "num_buckets must be at least 2");
+        let reservoir: Vec<f64> = if let Some(res_path) = reservoir_path {
+            let reservoir_content = read_pathbuf_to_mem(res_path).unwrap().into_inner().into_inner();
+            serde_json::from_slice(&reservoir_content)?
+        } else {
+            let (values, _) = sample_values(input_dir, value, STRATIFIED_VALUE_RESERVOIR)?;
+            values.iter().filter_map(|v| v.as_f64()).collect()
+        };
+        ensure!(!reservoir.is_empty(), format!("No numeric values of {:?} to compute bucket bounds from", value));
+        reservoir_to_ranges(reservoir, num_buckets)
+    };
+    ensure!(!ranges.is_empty(), "Need at least one range group");
+    println!("Range groups are {:?}", ranges);
+
+    // Pass 1: sample refs per bucket
+    let pbar = build_pbar(all_files.len(), "Paths");
+    let sample = all_files
+        .par_iter()
+        .enumerate()
+        .map(|(path_id, p)| {
+            let sample = stratified_path(p, path_id, value, &ranges, per_bucket, default_value).unwrap();
+            pbar.inc(1);
+            sample
+        })
+        .reduce(|| BucketSample::new(ranges.len() + 1), |a, b| a.merge(b, per_bucket));
+
+    for (k, (heap, seen)) in sample.samples.iter().zip(&sample.seen).enumerate() {
+        let range = if k == 0 {
+            format!("(-∞, {:?})", ranges[0])
+        } else if k == ranges.len() {
+            format!("[{:?}, ∞)", ranges[k - 1])
+        } else {
+            format!("[{:?}, {:?})", ranges[k - 1], ranges[k])
+        };
+        println!("{} | sampled {:?} of {:?} docs", range, heap.len(), seen);
+    }
+    if sample.missing > 0 {
+        println!("Skipped {:?} docs without a numeric value", sample.missing);
+    }
+
+    let mut refs: Vec<DocRef> = sample.samples.into_iter().flat_map(|heap| heap.into_vec()).map(|(_, r)| r).collect();
+    refs.par_sort_unstable();
+    let gathered = gather_docs(&all_files, &refs, output_file)?;
+    println!("Wrote {:?} sampled documents to {:?}", gathered, output_file);
+    Ok(())
+}
+
+
+fn stratified_path(path: &PathBuf, path_id: usize, value: &str, ranges: &[f64], per_bucket: usize,
+                   default_value: Option<f64>) -> Result<BucketSample, Error> {
+    let mut sample = BucketSample::new(ranges.len() + 1);
+    let mut rng = rand::rng();
+    let contents = read_pathbuf_to_mem(path).unwrap();
+    for (line_num, line) in contents.lines().enumerate() {
+        let line = line?;
+        let json_line: Value = serde_json::from_str(&line)?;
+        let doc_value = match json_get(&json_line, value).and_then(|v| v.as_f64()).or(default_value) {
+            Some(doc_value) => doc_value,
+            None => {
+                sample.missing += 1;
+                continue;
+            }
+        };
+        let bucket = f64_to_bucket(ranges, doc_value);
+        sample.seen[bucket] += 1;
+        push_capped(&mut sample.samples[bucket], (rng.random::<u64>(), DocRef { path_id, line_num }), per_bucket);
+    }
+    Ok(sample)
+}
@@ -2,15 +2,12 @@
 /*============================================================
 =                            RESHARD                         =
 ============================================================*/
-use zstd::Encoder;
 use std::panic;
 use rand::Rng;
 use std::cmp::max;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::fs::{create_dir_all, File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::os::unix::fs::OpenOptionsExt;
+use std::io::Write;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Instant;
@@ -20,10 +17,13 @@ use rayon::current_num_threads;
 use rayon::prelude::*;
 
 use indicatif::ProgressBar;
+use crate::bloom::line_id;
+use crate::utils::{build_pbar, output_ext, AtomicFile, OutputEncoder};
 use mj_io::{
-    build_pbar, expand_dirs, get_output_filename, read_pathbuf
+    expand_dirs, get_output_filename, read_pathbuf
 };
 
+#[allow(clippy::too_many_arguments)]
 pub fn reshard(
     input_dir: &PathBuf,
     output_dir: &PathBuf,
@@ -32,13 +32,26 @@ pub fn reshard(
     subsample: f32,
     keep_dirs: bool,
     delete_after_read: bool,
+    respect_groups: Option<&str>,
 ) -> Result<(), Error> {
+    /* Rebalances input_dir into shards of at most max_lines docs / max_size bytes.
+
+    With respect_groups, docs are packed a whole group at a time (docs sharing that key's value), so a group never
+    spans two output shards and stays contiguous: a shard is closed early when the next group wouldn't fit.
+    This needs each group to already be contiguous within a single input file (e.g. group/group_filter outputs),
+    errors if that's visibly not the case, and errors on a group that alone exceeds the limits.
+    Docs without the key are groups of their own. Subsampling isn't supported in this mode
+    */
     let start_main = Instant::now();
 
     ensure!(
         max(max_lines, max_size) > 0,
         "Either max_lines or max_size must be provided!"
     );
+    ensure!(
+        respect_groups.is_none() || subsample == 0.0,
+        "Can't subsample while respecting groups"
+    );
     let max_lines = if max_lines == 0 {
         usize::MAX
     } else {
@@ -81,21 +94,34 @@ pub fn reshard(
         all_files.chunks(chunk_size).map(|c| c.to_vec()).collect()
     };
     let out_num = AtomicUsize::new(0);
-    chunks.par_iter().for_each(|chunk| {
-        reshard_chunk(
-            chunk,
-            input_dir,
-            output_dir,
-            &out_num,
-            max_lines,
-            max_size,
-            &pbar,
-            subsample,
-            keep_dirs,
-            delete_after_read,
-        )
-        .unwrap();
-    });
+    chunks.par_iter().try_for_each(|chunk| -> Result<(), Error> {
+        match respect_groups {
+            Some(group_key) => reshard_chunk_grouped(
+                chunk,
+                input_dir,
+                output_dir,
+                &out_num,
+                max_lines,
+                max_size,
+                &pbar,
+                keep_dirs,
+                delete_after_read,
+                group_key,
+            ),
+            None => reshard_chunk(
+                chunk,
+                input_dir,
+                output_dir,
+                &out_num,
+                max_lines,
+                max_size,
+                &pbar,
+                subsample,
+                keep_dirs,
+                delete_after_read,
+            ),
+        }
+    })?;
 
     println!(
         "Finished reshard in {:?} seconds | Wrote {:?} new shards",
@@ -117,26 +143,13 @@ fn reshard_chunk(
     keep_dirs: bool,
     delete_after_read: bool,
 ) -> Result<(), Error> {
-    // Quick assert: if keep dirs, all parents should be the same, and then we modify the output dir to be the "parent dir"
-    let output_dir: PathBuf = if keep_dirs {
-        let chunk_parents: Vec<Option<PathBuf>> = chunk
-            .iter()
-            .map(|file| file.parent().map(|p| p.to_path_buf()))
-            .collect();
-        let parent_example = &chunk_parents[0];
-        assert!(chunk_parents.iter().all(|x| x == parent_example));
-        get_output_filename(&parent_example.as_ref().unwrap(), input_dir, output_dir).unwrap()
-    } else {
-        output_dir.clone()
-    };
+    let output_dir = chunk_output_dir(chunk, input_dir, output_dir, keep_dirs);
 
     // faster strat: keep an open writer and append until full
-    let get_new_writer = |out_num: &AtomicUsize| -> Result<Box<dyn std::io::Write>, Error> {
+    let get_new_writer = |out_num: &AtomicUsize| -> Result<ShardWriter, Error> {
         let shard_id = out_num.fetch_add(1, Ordering::SeqCst);
         let shard = get_reshard_name(&output_dir, shard_id).unwrap();
-        let writer = make_shard_writer(shard).unwrap();
-        let auto_finisher = writer.auto_finish();
-        Ok(Box::new(auto_finisher))
+        make_shard_writer(shard)
     };
 
     let mut rng = rand::rng();
@@ -163,10 +176,9 @@ fn reshard_chunk(
                 cur_lines += 1;
                 cur_size += line.len();
                 writer.write_all(&line).unwrap();
-                writer.write(vec![b'\n'].as_slice()).unwrap();
+                writer.write_all(b"\n").unwrap();
                 if cur_lines >= max_lines || cur_size >= max_size {
-                    writer.flush().unwrap();
-                    drop(writer);
+                    finish_shard(writer).unwrap();
                     writer = get_new_writer(out_num).unwrap();
                     cur_lines = 0;
                     cur_size = 0;
@@ -174,8 +186,7 @@ fn reshard_chunk(
             }
         }
         if cur_lines >= max_lines || cur_size >= max_size {
-            writer.flush().unwrap();
-            drop(writer);
+            finish_shard(writer).unwrap();
             writer = get_new_writer(out_num).unwrap();
             cur_lines = 0;
             cur_size = 0;
@@ -187,35 +198,214 @@ fn reshard_chunk(
         }
     }
 
-    writer.flush().unwrap();
-    //writer.do_finish().unwrap();
+    finish_shard(writer).unwrap();
 
     Ok(())
 }
 
-fn get_reshard_name(output_dir: &PathBuf, shard_id: usize) -> Result<PathBuf, Error> {
-    let basename = PathBuf::from(format!("shard_{:08}.jsonl.zst", shard_id));
-    let output_file = output_dir.clone().join(basename);
+fn chunk_output_dir(chunk: &[PathBuf], input_dir: &PathBuf, output_dir: &PathBuf, keep_dirs: bool) -> PathBuf {
+    // Quick assert: if keep dirs, all parents should be the same, and then we modify the output dir to be the "parent dir"
+    if keep_dirs {
+        let chunk_parents: Vec<Option<PathBuf>> = chunk
+            .iter()
+            .map(|file| file.parent().map(|p| p.to_path_buf()))
+            .collect();
+        let parent_example = &chunk_parents[0];
+        assert!(chunk_parents.iter().all(|x| x == parent_example));
+        get_output_filename(parent_example.as_ref().unwrap(), input_dir, output_dir).unwrap()
+    } else {
+        output_dir.clone()
+    }
+}
 
-    Ok(output_file)
+#[derive(Default)]
+struct GroupBuffer {
+    // The docs of the group currently being read
+    key: Option<String>,
+    bytes: Vec<u8>,
+    lines: usize,
+    size: usize, // like reshard_chunk, the bytes of the docs without their newlines
 }
 
-fn make_shard_writer(shard_name: PathBuf) -> Result<Encoder<'static, BufWriter<File>>, Error> {
-    // Make parent dir if not exists
-    if let Some(parent_dir) = shard_name.parent() {
-        if !parent_dir.exists() {
-            create_dir_all(parent_dir).unwrap()
+#[allow(clippy::too_many_arguments)]
+fn reshard_chunk_grouped(
+    chunk: &Vec<PathBuf>,
+    input_dir: &PathBuf,
+    output_dir: &PathBuf,
+    out_num: &AtomicUsize,
+    max_lines: usize,
+    max_size: usize,
+    pbar: &ProgressBar,
+    keep_dirs: bool,
+    delete_after_read: bool,
+    group_key: &str,
+) -> Result<(), Error> {
+    // reshard_chunk, but moving whole groups at a time (see reshard)
+    let output_dir = chunk_output_dir(chunk, input_dir, output_dir, keep_dirs);
+    let get_new_writer = || -> Result<ShardWriter, Error> {
+        let shard_id = out_num.fetch_add(1, Ordering::SeqCst);
+        make_shard_writer(get_reshard_name(&output_dir, shard_id)?)
+    };
+
+    let mut writer = get_new_writer()?;
+    let mut cur_lines = 0;
+    let mut cur_size = 0;
+    let mut write_group = |group: GroupBuffer, path: &PathBuf| -> Result<(), Error> {
+        if group.lines == 0 {
+            return Ok(());
+        }
+        ensure!(
+            group.lines <= max_lines && group.size <= max_size,
+            "Group {:?} in {:?} has {:?} docs ({:?} bytes), more than fit in one shard",
+            group.key.unwrap_or_default(),
+            path,
+            group.lines,
+            group.size
+        );
+        if cur_lines > 0 && (cur_lines + group.lines > max_lines || cur_size + group.size > max_size) {
+            finish_shard(std::mem::replace(&mut writer, get_new_writer()?))?;
+            cur_lines = 0;
+            cur_size = 0;
+        }
+        writer.write_all(&group.bytes)?;
+        cur_lines += group.lines;
+        cur_size += group.size;
+        Ok(())
+    };
+
+    for path in chunk {
+        let data = read_pathbuf(path, true)?;
+        let mut group = GroupBuffer::default();
+        let mut done_keys: HashSet<String> = HashSet::new();
+        for line in data.lines() {
+            let line = line?;
+            let key = line_id(&line, group_key);
+            if group.lines > 0 && (key.is_none() || key != group.key) {
+                if let Some(done_key) = group.key.take() {
+                    done_keys.insert(done_key);
+                }
+                write_group(std::mem::take(&mut group), path)?;
+            }
+            if let Some(key) = &key {
+                ensure!(
+                    !done_keys.contains(key),
+                    "Group {:?} isn't contiguous in {:?}, so its docs can't be kept together",
+                    key,
+                    path
+                );
+            }
+            group.key = key;
+            group.lines += 1;
+            group.size += line.len();
+            group.bytes.extend(line.as_bytes());
+            group.bytes.push(b'\n');
+        }
+        // Groups don't continue into the next file
+        write_group(group, path)?;
+        pbar.inc(1);
+
+        if delete_after_read {
+            fs::remove_file(path)?;
         }
     }
-    let buf_writer = BufWriter::new(
-        OpenOptions::new()
-            .append(true)
-            .create(true)
-            .mode(0o644)
-            .open(shard_name)
-            .unwrap(),
+    finish_shard(writer)
+}
+
+/*============================================================
+=                            COALESCE                        =
+============================================================*/
+/*
+Opposite of reshard-by-count: concatenates whole input files (in sorted path order) into as few output
+shards of at most max_size bytes as possible. Files are never split or reordered, so adjacent small
+shards end up next to each other. Sizes are on-disk input sizes, so bins are planned up front.
+*/
+
+pub fn coalesce(
+    input_dir: &PathBuf,
+    output_dir: &PathBuf,
+    max_size: usize,
+    keep_dirs: bool,
+    delete_after_read: bool,
+) -> Result<(), Error> {
+    let start_main = Instant::now();
+    ensure!(max_size > 0, "max_size must be > 0");
+
+    let mut all_files = expand_dirs(vec![input_dir.clone()], None).unwrap();
+    all_files.sort();
+    let num_files = all_files.len();
+    let bins = plan_coalesce_bins(all_files, max_size, keep_dirs);
+    let pbar = build_pbar(num_files, "Files");
+
+    bins.par_iter().enumerate().for_each(|(shard_id, bin)| {
+        let bin_output_dir = if keep_dirs {
+            let parent = bin[0].parent().unwrap().to_path_buf();
+            get_output_filename(&parent, input_dir, output_dir).unwrap()
+        } else {
+            output_dir.clone()
+        };
+        let shard = get_reshard_name(&bin_output_dir, shard_id).unwrap();
+        let mut writer = make_shard_writer(shard).unwrap();
+        for path in bin {
+            let data = read_pathbuf(path, true).unwrap();
+            for line in data.lines() {
+                writer.write_all(line.unwrap().as_bytes()).unwrap();
+                writer.write_all(b"\n").unwrap();
+            }
+            pbar.inc(1);
+        }
+        finish_shard(writer).unwrap();
+        if delete_after_read {
+            bin.iter().for_each(|path| fs::remove_file(path).unwrap());
+        }
+    });
+
+    println!(
+        "Finished coalesce in {:?} seconds | Packed {:?} files into {:?} shards",
+        start_main.elapsed().as_secs(),
+        num_files,
+        bins.len()
     );
+    Ok(())
+}
 
-    let writer = Encoder::new(buf_writer, 3).unwrap();
-    Ok(writer)
+fn plan_coalesce_bins(all_files: Vec<PathBuf>, max_size: usize, keep_dirs: bool) -> Vec<Vec<PathBuf>> {
+    // Next-fit packing over the (sorted) files: start a new bin when the next file doesn't fit.
+    // A file bigger than max_size gets a bin of its own. With keep_dirs, bins never span directories
+    let mut bins: Vec<Vec<PathBuf>> = Vec::new();
+    let mut cur_bin: Vec<PathBuf> = Vec::new();
+    let mut cur_size = 0;
+    for path in all_files {
+        let size = fs::metadata(&path).unwrap().len() as usize;
+        let new_dir = keep_dirs && cur_bin.last().is_some_and(|last| last.parent() != path.parent());
+        if !cur_bin.is_empty() && (new_dir || cur_size + size > max_size) {
+            bins.push(std::mem::take(&mut cur_bin));
+            cur_size = 0;
+        }
+        cur_size += size;
+        cur_bin.push(path);
+    }
+    if !cur_bin.is_empty() {
+        bins.push(cur_bin);
+    }
+    bins
+}
+
+
+fn get_reshard_name(output_dir: &PathBuf, shard_id: usize) -> Result<PathBuf, Error> {
+    let basename = PathBuf::from(format!("shard_{:08}.{}", shard_id, output_ext()));
+    let output_file = output_dir.clone().join(basename);
+
+    Ok(output_file)
+}
+
+type ShardWriter = OutputEncoder<'static, AtomicFile>;
+
+fn make_shard_writer(shard_name: PathBuf) -> Result<ShardWriter, Error> {
+    // zstd, or plain jsonl with --no-compress. Only shows up under shard_name once finish_shard is called
+    Ok(OutputEncoder::new(AtomicFile::create(&shard_name)?)?)
+}
+
+fn finish_shard(writer: ShardWriter) -> Result<(), Error> {
+    writer.finish()?.commit()?;
+    Ok(())
 }
\ No newline at end of file
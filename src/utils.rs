@@ -1,6 +1,24 @@
-use anyhow::{anyhow, Error, Result};
+use anyhow::{anyhow, bail, ensure, Error, Result};
+use std::borrow::Cow;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufWriter, IsTerminal, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::ops::Deref;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use fasttext::FastText;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use indicatif::{ProgressBar, ProgressDrawTarget};
+use mj_io::{expand_dirs, get_output_filename, read_pathbuf_to_mem};
 use serde_json::{json, Value};
 use url::Url;
+use zstd::stream::Encoder;
 
 /*================================================================================
 =                            JSON GETTER METHODS                                 =
@@ -63,6 +81,15 @@ pub fn get_default<T: FromValue>(config: &Value, key: &str, default: T) -> T {
     }
 }
 
+pub fn get_required_str(config: &Value, key: &str) -> Result<String, Error> {
+    // Like get_default, but for string settings a processor can't run without
+    config
+        .get(key)
+        .and_then(|v| v.as_str())
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Config requires a string '{}'", key))
+}
+
 pub fn json_get<'a>(data: &'a serde_json::Value, key: &str) -> Option<&'a Value> {
     let keys: Vec<&str> = key.split('.').collect();
     let mut current = data;
@@ -110,7 +137,8 @@ pub fn json_remove(input: &mut Value, key: &str) -> Result<Option<Value>, Error>
         if i == parts.len() - 1 {
             // We're at the final key - remove it
             if let Some(obj) = current.as_object_mut() {
-                return Ok(obj.remove(part));
+                // shift_remove keeps the order of the remaining keys (plain remove swaps the last key into its place)
+                return Ok(obj.shift_remove(part));
             } else {
                 return Err(anyhow!("Cannot remove field from non-object"));
             }
@@ -133,6 +161,601 @@ pub fn json_remove(input: &mut Value, key: &str) -> Result<Option<Value>, Error>
 }
 
 
+/*====================================================================
+=                            FILESYSTEM HELPERS                      =
+====================================================================*/
+
+// Input files we pick up from an input dir: jsonl/ndjson (one doc per line) and json (either one doc per line,
+// or a single array of docs), each optionally gzip/zstd compressed
+pub const INPUT_EXTS: &[&str] = &[
+    ".jsonl", ".jsonl.gz", ".jsonl.zst", ".jsonl.zstd",
+    ".ndjson", ".ndjson.gz", ".ndjson.zst", ".ndjson.zstd",
+    ".json", ".json.gz", ".json.zst", ".json.zstd",
+];
+
+pub fn expand_input_dirs(input_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    expand_dirs(vec![input_dir.to_path_buf()], Some(INPUT_EXTS))
+}
+
+pub fn read_input_lines(path: &PathBuf) -> Result<Vec<String>, Error> {
+    /* Reads an input file into one json string per doc.
+    Files whose content starts with '[' are treated as a single json array of docs (e.g. plain .json exports),
+    anything else as jsonl. Blank lines are skipped. Invalid UTF-8 is an error.
+    */
+    read_input_lines_inner(path, false)
+}
+
+pub fn read_input_lines_lossy(path: &PathBuf) -> Result<Vec<String>, Error> {
+    /* Same as read_input_lines, but invalid UTF-8 bytes are replaced with U+FFFD instead of failing the file.
+    Note that this alters the content!
+    */
+    read_input_lines_inner(path, true)
+}
+
+fn read_input_lines_inner(path: &PathBuf, lossy_utf8: bool) -> Result<Vec<String>, Error> {
+    let mut contents = Vec::new();
+    read_pathbuf_to_mem(path)?.read_to_end(&mut contents)?;
+    if lossy_utf8 {
+        if let Cow::Owned(fixed) = String::from_utf8_lossy(&contents) {
+            contents = fixed.into_bytes();
+        }
+    }
+
+    let first_char = contents.iter().find(|b| !b.is_ascii_whitespace());
+    if first_char == Some(&b'[') {
+        let docs: Vec<Value> = serde_json::from_slice(&contents)
+            .map_err(|e| anyhow!("Unable to parse {:?} as a json array: {:?}", path, e))?;
+        return Ok(docs.iter().map(|doc| doc.to_string()).collect());
+    }
+
+    let mut lines = Vec::new();
+    for line in contents.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+// Auxiliary files (banlists, cursed regexes, tokenizers) are read with read_pathbuf_to_mem, which decompresses
+// these transparently. FastText models are the exception: fasttext reads them from disk itself
+pub const COMPRESSED_EXTS: &[&str] = &[".gz", ".zst", ".zstd"];
+
+pub fn load_fasttext_model(path: &str) -> Result<FastText, Error> {
+    ensure!(
+        !COMPRESSED_EXTS.iter().any(|ext| path.ends_with(ext)),
+        "FastText model {:?} must be uncompressed (fasttext reads it directly), decompress it first",
+        path
+    );
+    let mut model = FastText::new();
+    model
+        .load_model(path)
+        .map_err(|e| anyhow!("Unable to load fasttext model {:?}: {}", path, e))?;
+    Ok(model)
+}
+
+pub fn check_output_dir(output_dir: &Path, force: bool, name_filter: Option<&str>) -> Result<(), Error> {
+    /* Refuses to write into an output directory that already has files in it (unless force is set).
+    If name_filter is Some(...), only files whose name contains that string count as conflicts
+    (useful when several runs write distinctly-named files into the same directory).
+    */
+    if force || !output_dir.exists() {
+        return Ok(());
+    }
+    if let Some(existing) = find_existing_file(output_dir, name_filter)? {
+        bail!(
+            "Output directory {:?} is not empty (found {:?}); pass --force to overwrite",
+            output_dir,
+            existing
+        );
+    }
+    Ok(())
+}
+
+fn find_existing_file(dir: &Path, name_filter: Option<&str>) -> Result<Option<PathBuf>, Error> {
+    if !dir.is_dir() {
+        return Ok(Some(dir.to_path_buf()));
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if let Some(found) = find_existing_file(&path, name_filter)? {
+                return Ok(Some(found));
+            }
+            continue;
+        }
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if name_filter.is_none_or(|f| name.contains(f)) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+
+pub struct RuntimeBudget {
+    /* Wall-clock budget (--max-runtime) for commands that loop over input files.
+    Checked right before each file is started: once the budget is spent no new files are started, but files already
+    in flight finish normally, so the caller can still finish() its writers and leave only complete outputs behind.
+    Files that were never started are recorded and listed by finish().
+    */
+    start: Instant,
+    max_runtime: Option<Duration>,
+    skipped: Mutex<Vec<PathBuf>>,
+}
+
+impl RuntimeBudget {
+    pub fn new(max_runtime: Option<u64>) -> Self {
+        Self {
+            start: Instant::now(),
+            max_runtime: max_runtime.map(Duration::from_secs),
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn out_of_time(&self, path: &Path) -> bool {
+        match self.max_runtime {
+            Some(max_runtime) if self.start.elapsed() >= max_runtime => {
+                self.skipped.lock().unwrap().push(path.to_path_buf());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Error> {
+        // Call after the writers are finished: errors out (listing the files that weren't processed) if time ran out
+        let mut skipped = self.skipped.into_inner().unwrap();
+        if skipped.is_empty() {
+            return Ok(());
+        }
+        skipped.sort();
+        println!("Files not processed:");
+        for path in &skipped {
+            println!("\t{}", path.display());
+        }
+        bail!(
+            "Stopped after --max-runtime of {:?} secs: {:?} input files were not processed (listed above)",
+            self.max_runtime.unwrap().as_secs(),
+            skipped.len()
+        )
+    }
+}
+
+pub struct DocLimit {
+    /* --limit state for the map: a cap on the survivors written across all workers.
+    Files are only started while there is room left. A file that runs into the cap writes just the survivors that
+    still fit (the first ones in its output order); files already in flight finish and write their outputs normally.
+    */
+    pub limit: usize,
+    written: AtomicUsize,
+    cut: AtomicUsize,
+    files_not_started: AtomicUsize,
+}
+
+impl DocLimit {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, written: AtomicUsize::new(0), cut: AtomicUsize::new(0), files_not_started: AtomicUsize::new(0) }
+    }
+
+    pub fn reached(&self) -> bool {
+        // Checked right before a file is started; a file that isn't started because of it is counted
+        let reached = self.written.load(Ordering::SeqCst) >= self.limit;
+        if reached {
+            self.files_not_started.fetch_add(1, Ordering::SeqCst);
+        }
+        reached
+    }
+
+    pub fn truncate<T>(&self, survivors: &mut Vec<T>) -> Vec<T> {
+        // Keeps as many of a file's survivors as still fit under the limit and returns the rest (which aren't written)
+        let wanted = survivors.len();
+        let prev = self
+            .written
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |written| Some((written + wanted).min(self.limit).max(written)))
+            .unwrap();
+        let granted = (prev + wanted).min(self.limit).saturating_sub(prev);
+        self.cut.fetch_add(wanted - granted, Ordering::SeqCst);
+        survivors.split_off(granted)
+    }
+
+    pub fn finish(self) {
+        println!(
+            "--limit {:?}: wrote {:?} docs, cut {:?} more that survived the pipeline, {:?} input files were not started",
+            self.limit,
+            self.written.into_inner(),
+            self.cut.into_inner(),
+            self.files_not_started.into_inner()
+        );
+    }
+}
+
+/*====================================================================
+=                            PROGRESS BARS                           =
+====================================================================*/
+/*
+Drop-in replacement for mj_io::build_pbar that every command uses.
+A progress bar redraws itself in place, which turns into megabytes of carriage-return noise once the output is
+captured (cron, CI, cluster logs). In quiet mode (--quiet, or automatically when stderr isn't a terminal) the bar is
+hidden and a background thread prints a plain progress line instead, every 5% of the items or every
+PROGRESS_LINE_SECS seconds, whichever comes first. The bar comes wrapped in a Progress, which derefs to the
+ProgressBar; dropping it stops that thread, which first prints where the bar ended up if its last line didn't.
+*/
+
+static QUIET_PROGRESS: AtomicBool = AtomicBool::new(false);
+const PROGRESS_LINE_SECS: u64 = 60;
+const PROGRESS_POLL: Duration = Duration::from_millis(500);
+
+pub fn set_quiet_progress(quiet: bool) {
+    QUIET_PROGRESS.store(quiet, Ordering::Relaxed);
+}
+
+pub fn quiet_progress() -> bool {
+    QUIET_PROGRESS.load(Ordering::Relaxed) || !std::io::stderr().is_terminal()
+}
+
+pub struct Progress {
+    pbar: ProgressBar,
+    poller: Option<(Sender<()>, JoinHandle<()>)>, // quiet mode's progress line thread, and how to stop it
+}
+
+impl Deref for Progress {
+    type Target = ProgressBar;
+
+    fn deref(&self) -> &ProgressBar {
+        &self.pbar
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some((stop, poller)) = self.poller.take() {
+            // Hanging up the channel is the stop signal
+            drop(stop);
+            let _ = poller.join();
+        }
+    }
+}
+
+pub fn build_pbar(num_items: usize, units: &str) -> Progress {
+    if !quiet_progress() {
+        return Progress { pbar: mj_io::build_pbar(num_items, units), poller: None };
+    }
+    let pbar = ProgressBar::with_draw_target(Some(num_items as u64), ProgressDrawTarget::hidden());
+    if num_items == 0 {
+        return Progress { pbar, poller: None };
+    }
+    let (stop, stopped) = mpsc::channel::<()>();
+    let poller = {
+        let pbar = pbar.clone();
+        let units = units.to_string();
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            let mut last_line = Instant::now();
+            let mut last_pos = None;
+            let mut next_pct = 5;
+            loop {
+                let stopping = !matches!(stopped.recv_timeout(PROGRESS_POLL), Err(RecvTimeoutError::Timeout));
+                let pos = pbar.position();
+                let pct = pos * 100 / num_items as u64;
+                let final_line = stopping && last_pos != Some(pos);
+                if final_line || pct >= next_pct || last_line.elapsed().as_secs() >= PROGRESS_LINE_SECS {
+                    println!(
+                        "{} {}/{} ({}%) after {:?} secs",
+                        units,
+                        pos,
+                        num_items,
+                        pct,
+                        start.elapsed().as_secs()
+                    );
+                    next_pct = (pct / 5 + 1) * 5;
+                    last_line = Instant::now();
+                    last_pos = Some(pos);
+                }
+                if stopping || pos >= num_items as u64 {
+                    break;
+                }
+            }
+        })
+    };
+    Progress { pbar, poller: Some((stop, poller)) }
+}
+
+/*====================================================================
+=                            OUTPUT COMPRESSION                      =
+====================================================================*/
+/*
+Writers that pick their own output names (the partition commands' and group/shuffle's GenWriters, reshard and coalesce) write zstd
+compressed .jsonl.zst shards. --no-compress (every command accepts it) switches them to plain .jsonl instead, for
+eyeballing outputs without a decompression step. OutputEncoder is the pass-through-or-zstd stream they write to.
+Commands whose outputs mirror their input names (map, group-filter, merge-jsonl, percentilize) get them from
+mirrored_output_filename, which drops a compressed input's .zst/.zstd/.gz under --no-compress: JsonlWriter picks
+its compression from the extension, so the name is what decides.
+*/
+
+static COMPRESS_OUTPUTS: AtomicBool = AtomicBool::new(true);
+
+pub fn set_compress_outputs(compress: bool) {
+    COMPRESS_OUTPUTS.store(compress, Ordering::Relaxed);
+}
+
+pub fn compress_outputs() -> bool {
+    COMPRESS_OUTPUTS.load(Ordering::Relaxed)
+}
+
+pub fn mirrored_output_filename(input_file: &Path, input_dir: &Path, output_dir: &Path) -> Result<PathBuf, Error> {
+    // input_file's path under output_dir instead of input_dir (see get_output_filename), uncompressed with --no-compress
+    let output_file = get_output_filename(&input_file.to_path_buf(), &input_dir.to_path_buf(), &output_dir.to_path_buf())?;
+    let compressed = matches!(output_file.extension().and_then(|ext| ext.to_str()), Some("zst") | Some("zstd") | Some("gz"));
+    Ok(if compressed && !compress_outputs() { output_file.with_extension("") } else { output_file })
+}
+
+pub fn output_ext() -> &'static str {
+    // Extension (without the leading dot) for shards named by these writers
+    if compress_outputs() { "jsonl.zst" } else { "jsonl" }
+}
+
+/*
+Writers that pick their own output names use a fixed naming scheme per command (chunk_{bucket:08}.{index:08}.shuffled.jsonl.zst,
+shard_{index:08}.jsonl.zst, ...). --filename-template overrides the file name (not the directories a writer sorts
+files into) with a string where {bucket} is the writer's bucket (shuffle's output number, a partition's category or
+bucket), {index} the 8-digit file number within that bucket and {ext} the extension the writer would have used.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilenameTemplate {
+    template: String,
+}
+
+impl FilenameTemplate {
+    pub fn new(template: &str) -> Result<Self, Error> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}').ok_or_else(|| anyhow!("Unclosed '{{' in filename template {:?}", template))?;
+            let placeholder = &rest[start + 1..start + end];
+            ensure!(
+                ["bucket", "index", "ext"].contains(&placeholder),
+                "Unknown placeholder {{{}}} in filename template {:?} (expected {{bucket}}, {{index}} or {{ext}})",
+                placeholder,
+                template
+            );
+            rest = &rest[start + end + 1..];
+        }
+        // Without {index}, a bucket's second file would overwrite its first
+        ensure!(template.contains("{index}"), "Filename template {:?} needs an {{index}} placeholder", template);
+        ensure!(!template.contains('/'), "Filename template {:?} can't contain a '/'", template);
+        Ok(Self { template: template.to_string() })
+    }
+
+    pub fn from_option(template: &Option<String>) -> Result<Option<Self>, Error> {
+        template.as_deref().map(FilenameTemplate::new).transpose()
+    }
+
+    pub fn has_bucket(&self) -> bool {
+        self.template.contains("{bucket}")
+    }
+
+    pub fn render(&self, bucket: &str, index: usize, ext: &str) -> String {
+        self.template
+            .replace("{bucket}", bucket)
+            .replace("{index}", &format!("{:08}", index))
+            .replace("{ext}", ext)
+    }
+}
+
+pub enum OutputEncoder<'a, W: Write> {
+    Zstd(Encoder<'a, W>),
+    Plain(W),
+}
+
+impl<W: Write> OutputEncoder<'_, W> {
+    pub fn new(inner: W) -> std::io::Result<Self> {
+        Self::with_compression(inner, compress_outputs())
+    }
+
+    pub fn with_compression(inner: W, compress: bool) -> std::io::Result<Self> {
+        if compress {
+            Ok(OutputEncoder::Zstd(Encoder::new(inner, 3)?))
+        } else {
+            Ok(OutputEncoder::Plain(inner))
+        }
+    }
+
+    pub fn finish(self) -> std::io::Result<W> {
+        match self {
+            OutputEncoder::Zstd(encoder) => encoder.finish(),
+            OutputEncoder::Plain(mut inner) => {
+                inner.flush()?;
+                Ok(inner)
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for OutputEncoder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputEncoder::Zstd(encoder) => encoder.write(buf),
+            OutputEncoder::Plain(inner) => inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputEncoder::Zstd(encoder) => encoder.flush(),
+            OutputEncoder::Plain(inner) => inner.flush(),
+        }
+    }
+}
+
+/*====================================================================
+=                            ATOMIC WRITES                           =
+====================================================================*/
+/*
+Every output file is written to a sibling "<name>.tmp" first and only renamed into place once it is complete
+(all bytes written, compression stream finished). A worker that dies mid-write leaves a .tmp file behind, which
+expand_dirs won't pick up, instead of a truncated file that looks valid to the next stage.
+*/
+
+pub fn tmp_path(path: &Path) -> PathBuf {
+    PathBuf::from(format!("{}.tmp", path.display()))
+}
+
+pub struct AtomicFile {
+    writer: BufWriter<File>,
+    tmp_path: PathBuf,
+    path: PathBuf, // where the file ends up on commit
+}
+
+impl AtomicFile {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let tmp_path = tmp_path(path);
+        let file = OpenOptions::new().write(true).truncate(true).create(true).mode(0o644).open(&tmp_path)?;
+        Ok(Self { writer: BufWriter::new(file), tmp_path, path: path.to_path_buf() })
+    }
+
+    pub fn commit(mut self) -> std::io::Result<()> {
+        // Flushes and moves the file into place
+        self.writer.flush()?;
+        fs::rename(&self.tmp_path, &self.path)
+    }
+
+    pub fn discard(self) -> std::io::Result<()> {
+        // Removes the temp file (for writers that ended up with nothing to write)
+        drop(self.writer);
+        fs::remove_file(&self.tmp_path)
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub fn write_mem_atomic(contents: &[u8], output_file: &Path) -> Result<(), Error> {
+    // Atomic version of mj_io's write_mem_to_pathbuf: compressed by the output's extension ({zst, zstd} -> zstd,
+    // gz -> gzip, anything else -> as is)
+    let mut writer = JsonlWriter::create(output_file)?;
+    writer.write_raw(contents)?;
+    writer.finish()
+}
+
+/*
+JsonlWriter is the streaming counterpart of write_mem_atomic: docs are serialized into a small buffer that is
+handed to the compressor every JSONL_WRITER_CHUNK bytes, so writing a file never holds more than one chunk of
+output bytes on top of the docs themselves. The compressor buffers internally either way, so the bytes on disk
+are identical to collecting everything and calling write_mem_atomic.
+*/
+
+const JSONL_WRITER_CHUNK: usize = 1 << 20;
+
+enum JsonlEncoder {
+    Zstd(Encoder<'static, AtomicFile>),
+    Gzip(GzEncoder<AtomicFile>),
+    Plain(AtomicFile),
+}
+
+impl JsonlEncoder {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            JsonlEncoder::Zstd(encoder) => encoder,
+            JsonlEncoder::Gzip(encoder) => encoder,
+            JsonlEncoder::Plain(file) => file,
+        }
+    }
+
+    fn finish(self) -> std::io::Result<AtomicFile> {
+        match self {
+            JsonlEncoder::Zstd(encoder) => encoder.finish(),
+            JsonlEncoder::Gzip(encoder) => encoder.finish(),
+            JsonlEncoder::Plain(file) => Ok(file),
+        }
+    }
+}
+
+pub struct JsonlWriter {
+    encoder: JsonlEncoder,
+    buffer: Vec<u8>, // pending bytes, handed to the encoder once they reach JSONL_WRITER_CHUNK
+    path: PathBuf,
+    lines: usize,
+}
+
+impl JsonlWriter {
+    pub fn create(output_file: &Path) -> Result<Self, Error> {
+        let file = AtomicFile::create(output_file)
+            .map_err(|e| anyhow!("Unable to create output file {:?}: {}", output_file, e))?;
+        let encoder = match output_file.extension().and_then(|ext| ext.to_str()) {
+            Some("zst") | Some("zstd") => JsonlEncoder::Zstd(Encoder::new(file, 0)?),
+            Some("gz") => JsonlEncoder::Gzip(GzEncoder::new(file, Compression::default())),
+            _ => JsonlEncoder::Plain(file),
+        };
+        Ok(Self { encoder, buffer: Vec::new(), path: output_file.to_path_buf(), lines: 0 })
+    }
+
+    pub fn write_value(&mut self, value: &Value) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.buffer, value)?;
+        self.end_line()
+    }
+
+    pub fn write_line(&mut self, line: &[u8]) -> Result<(), Error> {
+        // A line that is already serialized (e.g. passed through from the input), without its newline
+        self.buffer.extend_from_slice(line);
+        self.end_line()
+    }
+
+    fn end_line(&mut self) -> Result<(), Error> {
+        self.buffer.push(b'\n');
+        self.lines += 1;
+        if self.buffer.len() >= JSONL_WRITER_CHUNK {
+            self.flush_buffer()?;
+        }
+        Ok(())
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.flush_buffer()?;
+        self.encoder.writer().write_all(bytes)?;
+        Ok(())
+    }
+
+    fn flush_buffer(&mut self) -> Result<(), Error> {
+        if !self.buffer.is_empty() {
+            self.encoder.writer().write_all(&self.buffer)?;
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn finish(mut self) -> Result<(), Error> {
+        // Finishes the compression stream and moves the file into place
+        self.flush_buffer()?;
+        let file = self.encoder.finish()?;
+        file.commit().map_err(|e| anyhow!("Unable to write to {:?}: {}", self.path, e))?;
+        Ok(())
+    }
+
+    pub fn discard(self) -> Result<(), Error> {
+        // Drops whatever was written (for outputs that ended up empty)
+        self.encoder.finish()?.discard()?;
+        Ok(())
+    }
+}
+
 /*====================================================================
 =                            URL HELPERS                             =
 ====================================================================*/
@@ -156,3 +779,37 @@ pub fn extract_subdomain(url_str: &str) -> Result<Option<String>, Error> {
         Ok(None) // No subdomain found
     }
 }
+
+
+/*====================================================================
+=                            TEXT HELPERS                            =
+====================================================================*/
+
+pub fn floor_char_boundary(s: &str, index: usize) -> usize {
+    // Largest char boundary <= index (index is clamped to the string's length)
+    if index >= s.len() {
+        return s.len();
+    }
+    let mut index = index;
+    while !s.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+pub fn ceil_char_boundary(s: &str, index: usize) -> usize {
+    // Smallest char boundary >= index (index is clamped to the string's length)
+    if index >= s.len() {
+        return s.len();
+    }
+    let mut index = index;
+    while !s.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+pub fn truncate_at_byte_boundary(s: &str, max_bytes: usize) -> &str {
+    // The longest prefix of s that is at most max_bytes long, without splitting a UTF-8 codepoint
+    &s[..floor_char_boundary(s, max_bytes)]
+}
@@ -0,0 +1,87 @@
+extern crate datamap_rs;
+use datamap_rs::bloom::{line_id, value_id, BloomFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_bloom_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_insert_and_contains() {
+        let filter = BloomFilter::new(1000, 0.01).unwrap();
+        assert_eq!(filter.num_bits % 64, 0);
+        assert!(filter.is_empty());
+        assert!(!filter.contains(b"doc_1"));
+        assert!(filter.insert(b"doc_1"));
+        assert!(filter.contains(b"doc_1"));
+        // Re-inserting is a no-op
+        assert!(!filter.insert(b"doc_1"));
+        assert_eq!(filter.len(), 1);
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let filter = BloomFilter::new(10_000, 0.01).unwrap();
+        for i in 0..10_000 {
+            filter.insert(format!("seen_{}", i).as_bytes());
+        }
+        for i in 0..10_000 {
+            assert!(filter.contains(format!("seen_{}", i).as_bytes()));
+        }
+        let false_positives = (0..10_000).filter(|i| filter.contains(format!("unseen_{}", i).as_bytes())).count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+        assert!(filter.estimated_fpr() < 0.02);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = scratch_dir("save_load");
+        let path = dir.join("nested").join("seen.bloom");
+        let filter = BloomFilter::load_or_new(&path, 500, 0.001).unwrap();
+        filter.insert(b"a");
+        filter.insert(b"b");
+        filter.save(&path).unwrap();
+
+        // An existing filter keeps its own sizing
+        let loaded = BloomFilter::load_or_new(&path, 1, 0.5).unwrap();
+        assert_eq!(loaded.capacity, 500);
+        assert_eq!(loaded.fpr, 0.001);
+        assert_eq!(loaded.num_bits, filter.num_bits);
+        assert_eq!(loaded.num_hashes, filter.num_hashes);
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.contains(b"a") && loaded.contains(b"b"));
+        assert!(!loaded.contains(b"c"));
+
+        fs::write(&path, b"not a bloom filter").unwrap();
+        assert!(BloomFilter::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_bad_params() {
+        assert!(BloomFilter::new(0, 0.01).is_err());
+        assert!(BloomFilter::new(10, 0.0).is_err());
+        assert!(BloomFilter::new(10, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_ids_match_between_lines_and_values() {
+        // Ids are read from raw lines before the pipeline and from parsed survivors after it
+        for line in [r#"{"id": "abc"}"#, r#"{"id": 17}"#, r#"{"meta": {"id": "x"}, "id": [1,2]}"#] {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(line_id(line, "id"), value_id(&value, "id"));
+        }
+        assert_eq!(line_id(r#"{"meta": {"id": "x"}}"#, "meta.id"), Some(String::from("x")));
+        assert_eq!(value_id(&json!({"meta": {"id": "x"}}), "meta.id"), Some(String::from("x")));
+        assert_eq!(line_id(r#"{"text": "no id"}"#, "id"), None);
+        assert_eq!(value_id(&json!({"id": null}), "id"), None);
+    }
+}
@@ -0,0 +1,45 @@
+extern crate datamap_rs;
+use datamap_rs::check_unique::check_unique;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_check_unique_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("input")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_ids_compared_by_type_and_value() {
+        let dir = scratch_dir("types");
+        let lines = [
+            r#"{"id": 1}"#,
+            r#"{"id": "1"}"#,
+            r#"{"id": true}"#,
+            r#"{"id": "true"}"#,
+            r#"{"id": "a"}"#,
+            r#"{"id": null}"#,
+            r#"{"other": 1}"#,
+        ];
+        fs::write(dir.join("input").join("0.jsonl"), lines.join("\n") + "\n").unwrap();
+        // Real duplicates, across files and of both types
+        fs::write(dir.join("input").join("1.jsonl"), "{\"id\": 1}\n{\"id\": \"a\"}\n{\"id\": \"a\"}\n").unwrap();
+
+        let output_file = dir.join("report.json");
+        check_unique(&dir.join("input"), &output_file, &String::from("id"), 100).unwrap();
+        let report: Value = serde_json::from_slice(&fs::read(&output_file).unwrap()).unwrap();
+        assert_eq!(report["total_docs"], 10);
+        assert_eq!(report["missing_ids"], 2);
+        assert_eq!(report["unique_ids"], 5);
+        assert_eq!(report["duplicate_ids"], 2);
+        assert_eq!(report["duplicate_docs"], 3);
+        assert_eq!(report["sample_duplicates"], json!(["a", 1]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,68 @@
+extern crate datamap_rs;
+use datamap_rs::reshard::coalesce;
+use datamap_rs::utils::{expand_input_dirs, read_input_lines};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_coalesce_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_shard(path: PathBuf, ids: std::ops::Range<usize>) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let contents: String = ids.map(|i| format!("{{\"id\": {:04}}}\n", i)).collect(); // 13 bytes per line
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read_shards(dir: &PathBuf) -> Vec<Vec<String>> {
+        let mut paths = expand_input_dirs(dir).unwrap();
+        paths.sort();
+        paths.iter().map(|p| read_input_lines(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_coalesce_packs_in_order() {
+        let dir = scratch_dir("pack");
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        // 5 files of 2 docs (26 bytes) each, plus one big file of 10 docs (130 bytes)
+        for i in 0..5 {
+            write_shard(input.join(format!("part_{}.jsonl", i)), (i * 2)..(i * 2 + 2));
+        }
+        write_shard(input.join("part_5.jsonl"), 10..20);
+
+        coalesce(&input, &output, 60, false, false).unwrap();
+
+        let shards = read_shards(&output);
+        let sizes: Vec<usize> = shards.iter().map(|s| s.len()).collect();
+        assert_eq!(sizes, vec![4, 4, 2, 10]);
+        // Content and order are preserved
+        let all: Vec<String> = shards.into_iter().flatten().collect();
+        let expected: Vec<String> = (0..20).map(|i| format!("{{\"id\": {:04}}}", i)).collect();
+        assert_eq!(all, expected);
+        assert!(input.join("part_0.jsonl").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_coalesce_keep_dirs() {
+        let dir = scratch_dir("dirs");
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        write_shard(input.join("a/part_0.jsonl"), 0..2);
+        write_shard(input.join("a/part_1.jsonl"), 2..4);
+        write_shard(input.join("b/part_0.jsonl"), 4..6);
+
+        coalesce(&input, &output, 1000, true, true).unwrap();
+
+        assert_eq!(read_shards(&output.join("a")), vec![vec!["{\"id\": 0000}", "{\"id\": 0001}", "{\"id\": 0002}", "{\"id\": 0003}"]]);
+        assert_eq!(read_shards(&output.join("b")).len(), 1);
+        assert!(!input.join("a/part_0.jsonl").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+extern crate datamap_rs;
+use datamap_rs::diff::diff;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_diff_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_diff_reports_added_removed_changed() {
+        let dir = scratch_dir("basic");
+        let old_dir = dir.join("old");
+        let new_dir = dir.join("new");
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+        fs::write(
+            old_dir.join("a.jsonl"),
+            concat!(
+                "{\"id\": \"kept\", \"text\": \"same\"}\n",
+                "{\"id\": \"edited\", \"text\": \"before\"}\n",
+                "{\"id\": \"dropped\", \"text\": \"gone\"}\n",
+                "{\"text\": \"no id\"}\n",
+            ),
+        )
+        .unwrap();
+        // The new side is laid out differently: only ids matter, not files or order
+        fs::write(new_dir.join("b.jsonl"), "{\"id\": \"edited\", \"text\": \"after\"}\n{\"id\": 7, \"text\": \"new\"}\n").unwrap();
+        fs::write(new_dir.join("c.jsonl"), "{\"id\": \"kept\", \"text\": \"same\", \"extra\": 1}\n").unwrap();
+
+        let report_file = dir.join("report.json");
+        diff(&old_dir, &new_dir, &report_file, &"id".to_string(), &"text".to_string(), 10).unwrap();
+        let report: Value = serde_json::from_slice(&fs::read(&report_file).unwrap()).unwrap();
+
+        assert_eq!(report["old"]["total_docs"], json!(4));
+        assert_eq!(report["old"]["missing_ids"], json!(1));
+        assert_eq!(report["new"]["total_docs"], json!(3));
+        assert_eq!(report["added"], json!(1));
+        assert_eq!(report["removed"], json!(1));
+        assert_eq!(report["changed"], json!(1));
+        assert_eq!(report["unchanged"], json!(1));
+        // Non-string ids are reported by their json representation
+        assert_eq!(report["sample_added"], json!(["7"]));
+        assert_eq!(report["sample_removed"], json!(["dropped"]));
+        assert_eq!(report["sample_changed"], json!(["edited"]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_samples_are_capped_and_sorted() {
+        let dir = scratch_dir("sample");
+        let old_dir = dir.join("old");
+        let new_dir = dir.join("new");
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+        fs::write(old_dir.join("a.jsonl"), "{\"id\": \"x\", \"text\": \"\"}\n").unwrap();
+        let added: String = ["d", "b", "a", "c"].iter().map(|id| format!("{{\"id\": \"{}\", \"text\": \"t\"}}\n", id)).collect();
+        fs::write(new_dir.join("a.jsonl"), added).unwrap();
+
+        let report_file = dir.join("report.json");
+        diff(&old_dir, &new_dir, &report_file, &"id".to_string(), &"text".to_string(), 2).unwrap();
+        let report: Value = serde_json::from_slice(&fs::read(&report_file).unwrap()).unwrap();
+        assert_eq!(report["added"], json!(4));
+        assert_eq!(report["sample_added"], json!(["a", "b"]));
+        assert_eq!(report["sample_removed"], json!(["x"]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
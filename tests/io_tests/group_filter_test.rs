@@ -0,0 +1,468 @@
+extern crate datamap_rs;
+use datamap_rs::groupfilter::{get_group_hash, get_group_hash_sonic, get_group_list_hashes, group, group_filter, group_sort, normalize_group_str, GenWriter, HashAlgo, KeyNormalization, SortKeyPart, SortKeyType};
+use datamap_rs::utils::{expand_input_dirs, read_input_lines};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_group_filter_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn run_unsorted(dir: &PathBuf, name: &str, lines: &[&str], keep_idx: i32) -> Vec<String> {
+        run_unsorted_with(dir, name, lines, keep_idx, "")
+    }
+
+    fn run_unsorted_with(dir: &PathBuf, name: &str, lines: &[&str], keep_idx: i32, extra_config: &str) -> Vec<String> {
+        let input_dir = dir.join(format!("{}_input", name));
+        let output_dir = dir.join(format!("{}_output", name));
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("chunk_00000000.00000000.group.jsonl"), lines.join("\n") + "\n").unwrap();
+        let config = dir.join(format!("{}_config.yaml", name));
+        fs::write(
+            &config,
+            format!("name: test\ngroup_keys: [group]\nsort_keys: [[date]]\nnum_buckets: 1\nkeep_idx: {}\n{}", keep_idx, extra_config),
+        )
+        .unwrap();
+        group_filter(&input_dir, &output_dir, &config, false, false).unwrap();
+        let mut kept: Vec<String> = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .flat_map(|p| read_input_lines(p).unwrap())
+            .collect();
+        kept.sort();
+        kept
+    }
+
+    #[test]
+    fn test_unsorted_ties_are_deterministic() {
+        let dir = scratch_dir("ties");
+        // Three docs in group "a" share the newest date; which one is kept must not depend on input order
+        let docs = [
+            r#"{"group":"a","date":"2024","text":"one"}"#,
+            r#"{"group":"a","date":"2024","text":"two"}"#,
+            r#"{"group":"a","date":"2023","text":"old"}"#,
+            r#"{"group":"a","date":"2024","text":"three"}"#,
+            r#"{"group":"b","date":"2020","text":"only"}"#,
+        ];
+        let mut reversed = docs;
+        reversed.reverse();
+        let rotated = [docs[3], docs[0], docs[4], docs[2], docs[1]];
+
+        for keep_idx in [0, -1] {
+            let expected = run_unsorted(&dir, &format!("fwd{}", keep_idx), &docs, keep_idx);
+            assert_eq!(expected.len(), 2);
+            assert_eq!(run_unsorted(&dir, &format!("rev{}", keep_idx), &reversed, keep_idx), expected);
+            assert_eq!(run_unsorted(&dir, &format!("rot{}", keep_idx), &rotated, keep_idx), expected);
+        }
+        // keep_idx -1 keeps the newest; among the tied newest docs the tie-break picks the same one each run
+        let newest = run_unsorted(&dir, "newest", &docs, -1);
+        let group_a: Vec<&String> = newest.iter().filter(|l| l.contains("\"group\":\"a\"")).collect();
+        assert_eq!(group_a.len(), 1);
+        assert!(group_a[0].contains("\"date\":\"2024\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn both_hashes(line: &str, keys: &[&str], normalize_keys: &HashMap<String, Vec<KeyNormalization>>, algo: HashAlgo) -> (Option<usize>, Option<usize>) {
+        let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+        let serde_val: serde_json::Value = serde_json::from_str(line).unwrap();
+        let sonic_val: sonic_rs::Value = sonic_rs::from_str(line).unwrap();
+        (
+            get_group_hash(&serde_val, &keys, normalize_keys, algo).unwrap(),
+            get_group_hash_sonic(&sonic_val, &keys, normalize_keys, algo).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_xxh3_group_hash_is_pinned_and_phase_independent() {
+        let no_norm = HashMap::new();
+        // Pinned: bucket assignments must not move between builds
+        let (filter_hash, group_hash) = both_hashes(r#"{"url":"example.com"}"#, &["url"], &no_norm, HashAlgo::Xxh3);
+        let mut expected = (11u64).to_le_bytes().to_vec();
+        expected.extend(b"example.com");
+        assert_eq!(group_hash, Some(xxhash_rust::xxh3::xxh3_64(&expected) as usize));
+        assert_eq!(filter_hash, group_hash);
+
+        // The group (sonic) and filter (serde) phases agree on every kind of key
+        let line = r#"{"s":"Hi","n":3,"f":1.5,"b":true,"z":null,"a":[1,"x"],"o":{"k":"v"}}"#;
+        for key in ["s", "n", "f", "b", "z", "a", "o"] {
+            let (filter_hash, group_hash) = both_hashes(line, &[key], &no_norm, HashAlgo::Xxh3);
+            assert!(group_hash.is_some());
+            assert_eq!(filter_hash, group_hash, "key {}", key);
+        }
+        let norm = HashMap::from([("s".to_string(), vec![KeyNormalization::Lowercase])]);
+        let (filter_hash, group_hash) = both_hashes(line, &["s", "n"], &norm, HashAlgo::Xxh3);
+        assert_eq!(filter_hash, group_hash);
+        assert_eq!(group_hash, both_hashes(r#"{"s":"hi","n":3}"#, &["s", "n"], &no_norm, HashAlgo::Xxh3).1);
+
+        // Key boundaries are part of the hash
+        let (_, split_a) = both_hashes(r#"{"x":"ab","y":"c"}"#, &["x", "y"], &no_norm, HashAlgo::Xxh3);
+        let (_, split_b) = both_hashes(r#"{"x":"a","y":"bc"}"#, &["x", "y"], &no_norm, HashAlgo::Xxh3);
+        assert_ne!(split_a, split_b);
+        assert_eq!(both_hashes(line, &["s", "missing"], &no_norm, HashAlgo::Xxh3), (None, None));
+    }
+
+    #[test]
+    fn test_xxh3_hashes_large_integers_exactly() {
+        // 2^60 and 2^60 + 1 are the same f64: they must still be different groups
+        let no_norm = HashMap::new();
+        let (filter_a, group_a) = both_hashes(r#"{"id":1152921504606846976}"#, &["id"], &no_norm, HashAlgo::Xxh3);
+        let (filter_b, group_b) = both_hashes(r#"{"id":1152921504606846977}"#, &["id"], &no_norm, HashAlgo::Xxh3);
+        assert_eq!(filter_a, group_a);
+        assert_eq!(filter_b, group_b);
+        assert_ne!(group_a, group_b);
+        let (filter_neg, group_neg) = both_hashes(r#"{"id":-1152921504606846977}"#, &["id"], &no_norm, HashAlgo::Xxh3);
+        assert_eq!(filter_neg, group_neg);
+        assert_ne!(group_neg, group_b);
+
+        // Small integers hash as before, and an integral float is the same key as the integer
+        let mut expected = (1u64).to_le_bytes().to_vec();
+        expected.extend(b"3");
+        assert_eq!(both_hashes(r#"{"n":3}"#, &["n"], &no_norm, HashAlgo::Xxh3), (Some(xxhash_rust::xxh3::xxh3_64(&expected) as usize), Some(xxhash_rust::xxh3::xxh3_64(&expected) as usize)));
+        assert_eq!(both_hashes(r#"{"n":3.0}"#, &["n"], &no_norm, HashAlgo::Xxh3).1, both_hashes(r#"{"n":3}"#, &["n"], &no_norm, HashAlgo::Xxh3).1);
+    }
+
+    #[test]
+    fn test_group_filter_keeps_large_integer_ids_apart() {
+        let dir = scratch_dir("large_ids");
+        let lines = [
+            r#"{"group":1152921504606846976,"date":"2024","text":"a"}"#,
+            r#"{"group":1152921504606846977,"date":"2024","text":"b"}"#,
+        ];
+        let out = run_unsorted_with(&dir, "large_ids", &lines, 0, "");
+        assert_eq!(out.len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_next_file_idx_sees_compressed_and_plain_chunks() {
+        // An incremental group run with a different --no-compress must not reuse a file index
+        let dir = scratch_dir("next_file_idx");
+        assert_eq!(GenWriter::next_file_idx(&dir, "group").unwrap(), 0);
+        fs::write(dir.join("chunk_00000000.00000000.group.jsonl.zst"), "").unwrap();
+        fs::write(dir.join("chunk_00000003.00000002.group.jsonl"), "").unwrap();
+        fs::write(dir.join("chunk_00000001.00000007.other.jsonl"), "").unwrap();
+        assert_eq!(GenWriter::next_file_idx(&dir, "group").unwrap(), 3);
+        assert_eq!(
+            GenWriter::get_filename(&dir, 1, 3, "group"),
+            dir.join("chunk_00000001.00000003.group.jsonl.zst")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ahash_keeps_legacy_group_hash() {
+        use std::hash::{BuildHasher, Hash, Hasher};
+        let (_, group_hash) = both_hashes(r#"{"url":"example.com"}"#, &["url"], &HashMap::new(), HashAlgo::Ahash);
+        let mut hasher = ahash::RandomState::with_seeds(1, 2, 3, 4).build_hasher();
+        "example.com".hash(&mut hasher);
+        assert_eq!(group_hash, Some(hasher.finish() as usize));
+    }
+
+    fn run_expanded(dir: &PathBuf, name: &str, lines: &[&str], num_buckets: usize) -> Vec<String> {
+        let input_dir = dir.join(format!("{}_input", name));
+        let group_dir = dir.join(format!("{}_group", name));
+        let output_dir = dir.join(format!("{}_output", name));
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("docs.jsonl"), lines.join("\n") + "\n").unwrap();
+        let config = dir.join(format!("{}_config.yaml", name));
+        fs::write(
+            &config,
+            format!("name: test\ngroup_keys: [meta.ids]\nexpand_lists: true\nsort_keys: [[date]]\nnum_buckets: {}\nkeep_idx: -1\n", num_buckets),
+        )
+        .unwrap();
+        group(&input_dir, &group_dir, &config, None, None, false).unwrap();
+        group_filter(&group_dir, &output_dir, &config, false, false).unwrap();
+        let mut kept: Vec<String> = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .flat_map(|p| read_input_lines(p).unwrap())
+            .map(|l| serde_json::from_str::<serde_json::Value>(&l).unwrap()["text"].as_str().unwrap().to_string())
+            .collect();
+        kept.sort();
+        kept
+    }
+
+    #[test]
+    fn test_expand_lists_joins_every_listed_group() {
+        let dir = scratch_dir("expand");
+        // Keep the newest doc of each group: a -> one, b -> one, c -> two, d -> four
+        let docs = [
+            r#"{"meta":{"ids":["a","b"]},"date":"2024","text":"one"}"#,
+            r#"{"meta":{"ids":["b","c","c"]},"date":"2023","text":"two"}"#,
+            r#"{"meta":{"ids":["c"]},"date":"2022","text":"three"}"#,
+            r#"{"meta":{"ids":"d"},"date":"2021","text":"four"}"#,
+            r#"{"meta":{"ids":[]},"date":"2020","text":"no groups"}"#,
+            r#"{"date":"2020","text":"no key"}"#,
+        ];
+        // With a single bucket every doc is written at most once
+        assert_eq!(run_expanded(&dir, "one_bucket", &docs, 1), vec!["four", "no groups", "no key", "one", "two"]);
+
+        // With more buckets a doc winning groups in several buckets is written once per such bucket
+        let mut many = run_expanded(&dir, "many_buckets", &docs, 16);
+        assert!(many.iter().filter(|t| *t == "one").count() <= 2);
+        many.dedup();
+        assert_eq!(many, vec!["four", "no groups", "no key", "one", "two"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_expand_lists_hashes_elements_like_scalar_keys() {
+        let no_norm = HashMap::new();
+        let keys = vec!["k".to_string()];
+        let scalar = get_group_hash(&serde_json::json!({"k": "a"}), &keys, &no_norm, HashAlgo::Xxh3).unwrap().unwrap();
+        let list = get_group_list_hashes(&serde_json::json!({"k": ["a", "b", "a"]}), "k", &no_norm, HashAlgo::Xxh3).unwrap().unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(&scalar));
+        assert_eq!(get_group_list_hashes(&serde_json::json!({"k": []}), "k", &no_norm, HashAlgo::Xxh3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_expand_lists_config_validation() {
+        let dir = scratch_dir("expand_config");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        let two_keys = dir.join("two_keys.yaml");
+        fs::write(&two_keys, "name: t\ngroup_keys: [a, b]\nexpand_lists: true\nsort_keys: []\nnum_buckets: 1\nkeep_idx: 0\n").unwrap();
+        assert!(group(&input_dir, &dir.join("g1"), &two_keys, None, None, false).is_err());
+        let ahash = dir.join("ahash.yaml");
+        fs::write(&ahash, "name: t\ngroup_keys: [a]\nexpand_lists: true\nhash_algo: ahash\nsort_keys: []\nnum_buckets: 1\nkeep_idx: 0\n").unwrap();
+        assert!(group(&input_dir, &dir.join("g2"), &ahash, None, None, false).is_err());
+        let ok = dir.join("ok.yaml");
+        fs::write(&ok, "name: t\ngroup_keys: [a]\nexpand_lists: true\nsort_keys: []\nnum_buckets: 1\nkeep_idx: 0\n").unwrap();
+        // A doc's groups aren't contiguous, so the pre-sorted filter can't be used
+        assert!(group_filter(&input_dir, &dir.join("out"), &ok, true, false).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn run_sorted(dir: &PathBuf, name: &str, lines: &[&str], assert_grouped: bool) -> anyhow::Result<Vec<String>> {
+        let input_dir = dir.join(format!("{}_input", name));
+        let output_dir = dir.join(format!("{}_output", name));
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("chunk_00000000.00000000.group.jsonl"), lines.join("\n") + "\n").unwrap();
+        let config = dir.join(format!("{}_config.yaml", name));
+        fs::write(&config, "name: test\ngroup_keys: [group]\nsort_keys: [[date]]\nnum_buckets: 1\nkeep_idx: 0\n").unwrap();
+        group_filter(&input_dir, &output_dir, &config, true, assert_grouped)?;
+        Ok(read_input_lines(&output_dir.join("chunk_00000000.00000000.group.jsonl")).unwrap())
+    }
+
+    #[test]
+    fn test_assert_grouped() {
+        let dir = scratch_dir("assert_grouped");
+        let grouped = [
+            r#"{"group":"a","date":"1"}"#,
+            r#"{"group":"a","date":"2"}"#,
+            r#"{"date":"3"}"#,
+            r#"{"group":"b","date":"4"}"#,
+        ];
+        assert_eq!(run_sorted(&dir, "ok", &grouped, true).unwrap().len(), 3);
+
+        // "a" comes back after "b": without the check each run of "a" keeps a doc
+        let split = [
+            r#"{"group":"a","date":"1"}"#,
+            r#"{"group":"b","date":"2"}"#,
+            r#"{"group":"a","date":"3"}"#,
+        ];
+        assert_eq!(run_sorted(&dir, "unchecked", &split, false).unwrap().len(), 3);
+        let err = run_sorted(&dir, "checked", &split, true).unwrap_err();
+        assert!(err.to_string().contains("isn't grouped"));
+        // A doc without a group also ends the run
+        let interrupted = [r#"{"group":"a","date":"1"}"#, r#"{"date":"2"}"#, r#"{"group":"a","date":"3"}"#];
+        assert!(run_sorted(&dir, "interrupted", &interrupted, true).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sort_key_part_coercion() {
+        let part = |v: serde_json::Value, t: SortKeyType, keep_idx: i32| SortKeyPart::from_value(&v, t, keep_idx);
+        // string: everything by its string form, as before (so 10 < 9)
+        assert_eq!(part(serde_json::json!(10), SortKeyType::String, 0), SortKeyPart::Str(String::from("10")));
+        assert!(part(serde_json::json!(10), SortKeyType::String, 0) < part(serde_json::json!(9), SortKeyType::String, 0));
+        // number: numeric strings are parsed, so 9 < " 10 " < 1e3
+        assert_eq!(part(serde_json::json!(" 10 "), SortKeyType::Number, 0), SortKeyPart::Number(10.0));
+        assert!(part(serde_json::json!(9), SortKeyType::Number, 0) < part(serde_json::json!("10"), SortKeyType::Number, 0));
+        assert!(part(serde_json::json!("10"), SortKeyType::Number, 0) < part(serde_json::json!("1e3"), SortKeyType::Number, 0));
+        // number: uncoercible values lose to every number, whichever end is kept
+        for bad in [serde_json::json!("n/a"), serde_json::json!(null), serde_json::json!(true), serde_json::json!([1]), serde_json::json!("NaN")] {
+            assert!(part(bad.clone(), SortKeyType::Number, 0) > part(serde_json::json!(1e300), SortKeyType::Number, 0));
+            assert!(part(bad, SortKeyType::Number, -1) < part(serde_json::json!(-1e300), SortKeyType::Number, -1));
+        }
+        // auto: numbers first (numerically), then strings
+        assert!(part(serde_json::json!("9"), SortKeyType::Auto, -1) < part(serde_json::json!(10), SortKeyType::Auto, -1));
+        assert!(part(serde_json::json!(1e9), SortKeyType::Auto, -1) < part(serde_json::json!("abc"), SortKeyType::Auto, -1));
+        assert!(part(serde_json::json!("abc"), SortKeyType::Auto, -1) < part(serde_json::json!("abd"), SortKeyType::Auto, -1));
+    }
+
+    #[test]
+    fn test_sort_key_type_in_group_filter() {
+        let dir = scratch_dir("sort_key_type");
+        // One group whose "date" is an inconsistently typed version number
+        let docs = [
+            r#"{"group":"a","date":9,"text":"nine"}"#,
+            r#"{"group":"a","date":"10","text":"ten"}"#,
+            r#"{"group":"a","date":"unknown","text":"dirty"}"#,
+            r#"{"group":"a","date":2,"text":"two"}"#,
+        ];
+        let kept = |name: &str, keep_idx: i32, extra: &str| {
+            let kept = run_unsorted_with(&dir, name, &docs, keep_idx, extra);
+            assert_eq!(kept.len(), 1);
+            let doc: serde_json::Value = serde_json::from_str(&kept[0]).unwrap();
+            doc["text"].as_str().unwrap().to_string()
+        };
+        // Default (string): "unknown" > "9" > "2" > "10"
+        assert_eq!(kept("str_last", -1, ""), "dirty");
+        assert_eq!(kept("str_first", 0, ""), "ten");
+        // number: the dirty doc is never kept while a numeric one exists
+        assert_eq!(kept("num_last", -1, "sort_key_type: number\n"), "ten");
+        assert_eq!(kept("num_first", 0, "sort_key_type: number\n"), "two");
+        // auto: numbers numerically, strings after them
+        assert_eq!(kept("auto_last", -1, "sort_key_type: auto\n"), "dirty");
+        assert_eq!(kept("auto_first", 0, "sort_key_type: auto\n"), "two");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_group_sort() {
+        let dir = scratch_dir("group_sort");
+        let input_dir = dir.join("input");
+        let output_dir = dir.join("output");
+        fs::create_dir_all(&input_dir).unwrap();
+        let docs = [
+            r#"{"group":"a","date":"2022","text":"a2"}"#,
+            r#"{"group":"b","date":"2021","text":"b1"}"#,
+            r#"{"group":"c","date":"2020","text":"c0"}"#,
+            r#"{"text":"no group"}"#,
+            r#"{"group":"a","date":"2020","text":"a0"}"#,
+            r#"{"group":"b","date":"2023","text":"b3"}"#,
+            r#"{"group":"a","date":"2021","text":"a1"}"#,
+        ];
+        fs::write(input_dir.join("part0.jsonl"), docs[..4].join("\n") + "\n").unwrap();
+        fs::write(input_dir.join("part1.jsonl"), docs[4..].join("\n") + "\n").unwrap();
+        let config = dir.join("config.yaml");
+        fs::write(&config, "name: test\ngroup_keys: [group]\nsort_keys: [[date]]\nnum_buckets: 2\nkeep_idx: 0\n").unwrap();
+        group_sort(&input_dir, &output_dir, &config, None).unwrap();
+
+        let mut total = 0;
+        let mut sorted_paths = expand_input_dirs(&output_dir).unwrap();
+        sorted_paths.sort();
+        for path in &sorted_paths {
+            assert!(path.to_str().unwrap().ends_with(".group.jsonl.zst"));
+            let lines: Vec<serde_json::Value> = read_input_lines(path)
+                .unwrap()
+                .iter()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect();
+            total += lines.len();
+            // Every group is one contiguous run, sorted by date; the doc without a group comes after the groups
+            let mut finished: Vec<String> = Vec::new();
+            for (i, doc) in lines.iter().enumerate() {
+                if doc.get("group").is_none() {
+                    assert_eq!(i, lines.len() - 1);
+                    continue;
+                }
+                let group = doc["group"].as_str().unwrap().to_string();
+                assert!(!finished.contains(&group), "group {} is split", group);
+                if let Some(next) = lines.get(i + 1) {
+                    if next["group"] == doc["group"] {
+                        assert!(doc["date"].as_str() < next["date"].as_str());
+                    } else {
+                        finished.push(group);
+                    }
+                }
+            }
+        }
+        assert_eq!(total, docs.len());
+
+        // A pre-sorted filter over the group-sort output keeps what the unsorted filter keeps
+        let texts = |lines: Vec<String>| -> Vec<String> {
+            let mut texts: Vec<String> = lines
+                .iter()
+                .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["text"].as_str().unwrap().to_string())
+                .collect();
+            texts.sort();
+            texts
+        };
+        let filtered = dir.join("filtered");
+        group_filter(&output_dir, &filtered, &config, true, true).unwrap();
+        let kept: Vec<String> = expand_input_dirs(&filtered)
+            .unwrap()
+            .iter()
+            .flat_map(|p| read_input_lines(p).unwrap())
+            .collect();
+        let kept = texts(kept);
+        assert_eq!(kept, vec!["a0", "b1", "c0", "no group"]);
+        assert_eq!(texts(run_unsorted(&dir, "unsorted", &docs, 0)), kept);
+
+        // expand_lists needs the two-phase commands
+        let expand_config = dir.join("expand.yaml");
+        fs::write(&expand_config, "name: test\ngroup_keys: [group]\nsort_keys: [[date]]\nnum_buckets: 2\nkeep_idx: 0\nexpand_lists: true\n").unwrap();
+        assert!(group_sort(&input_dir, &dir.join("expand_out"), &expand_config, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_incremental_group_only_adds_new_inputs() {
+        let dir = scratch_dir("incremental");
+        let input_dir = dir.join("input");
+        let group_dir = dir.join("group");
+        fs::create_dir_all(&input_dir).unwrap();
+        let config = dir.join("config.yaml");
+        fs::write(&config, "name: t\ngroup_keys: [g]\nsort_keys: [[date]]\nnum_buckets: 4\nkeep_idx: -1\n").unwrap();
+        let grouped_docs = |group_dir: &PathBuf| -> usize {
+            expand_input_dirs(group_dir).unwrap().iter().map(|p| read_input_lines(p).unwrap().len()).sum()
+        };
+
+        fs::write(input_dir.join("a.jsonl"), "{\"g\":\"x\",\"date\":\"1\"}\n{\"g\":\"y\",\"date\":\"1\"}\n").unwrap();
+        group(&input_dir, &group_dir, &config, None, None, true).unwrap();
+        assert_eq!(grouped_docs(&group_dir), 2);
+        // Nothing new: nothing is regrouped
+        group(&input_dir, &group_dir, &config, None, None, true).unwrap();
+        assert_eq!(grouped_docs(&group_dir), 2);
+
+        fs::write(input_dir.join("b.jsonl"), "{\"g\":\"x\",\"date\":\"2\"}\n").unwrap();
+        group(&input_dir, &group_dir, &config, None, None, true).unwrap();
+        assert_eq!(grouped_docs(&group_dir), 3);
+        let manifest = fs::read_to_string(group_dir.join("group_manifest.group.txt")).unwrap();
+        assert_eq!(manifest, "a.jsonl\nb.jsonl\n");
+
+        // The old and new chunk files of a bucket are filtered together
+        let output_dir = dir.join("output");
+        group_filter(&group_dir, &output_dir, &config, false, false).unwrap();
+        let mut kept: Vec<String> = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .flat_map(|p| read_input_lines(p).unwrap())
+            .collect();
+        kept.sort();
+        assert_eq!(kept, vec![r#"{"g":"x","date":"2"}"#, r#"{"g":"y","date":"1"}"#]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_group_str() {
+        use KeyNormalization::*;
+        assert_eq!(normalize_group_str(" Https://Ex.com ", &[]), " Https://Ex.com ");
+        assert_eq!(normalize_group_str(" \tEx.com\n", &[Trim]), "Ex.com");
+        assert_eq!(normalize_group_str("ÄBc", &[Lowercase]), "äbc");
+
+        assert_eq!(normalize_group_str("https://Ex.com/a://b", &[StripScheme]), "Ex.com/a://b");
+        assert_eq!(normalize_group_str("svn+ssh://host", &[StripScheme]), "host");
+        // Not a scheme: missing, empty, or with characters a scheme can't have
+        assert_eq!(normalize_group_str("mailto:a@ex.com", &[StripScheme]), "mailto:a@ex.com");
+        assert_eq!(normalize_group_str("://ex.com", &[StripScheme]), "://ex.com");
+        assert_eq!(normalize_group_str("see https://ex.com", &[StripScheme]), "see https://ex.com");
+        assert_eq!(normalize_group_str(" https://ex.com", &[StripScheme]), " https://ex.com");
+
+        // Always trim -> strip_scheme -> lowercase, whatever the config order
+        let all = [Lowercase, StripScheme, Trim];
+        assert_eq!(normalize_group_str("  HTTPS://Ex.COM/Page ", &all), "ex.com/page");
+        assert_eq!(normalize_group_str("  HTTPS://Ex.COM/Page ", &[Trim, StripScheme, Lowercase]), "ex.com/page");
+    }
+}
@@ -0,0 +1,84 @@
+extern crate datamap_rs;
+use datamap_rs::head::head;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_head_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("input")).unwrap();
+        dir
+    }
+
+    fn write_input(dir: &PathBuf) {
+        for f in 0..3 {
+            let lines: Vec<String> = (0..10).map(|i| format!("{{\"id\": {}}}", f * 10 + i)).collect();
+            fs::write(dir.join("input").join(format!("{}.jsonl", f)), lines.join("\n") + "\n").unwrap();
+        }
+    }
+
+    fn output_lines(path: &PathBuf) -> Vec<String> {
+        fs::read_to_string(path).unwrap().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_head_takes_first_lines_across_files() {
+        let dir = scratch_dir("first");
+        write_input(&dir);
+        // Written uncompressed even with a .zst extension
+        let output_file = dir.join("out.jsonl.zst");
+        head(&dir.join("input"), &output_file, 15, false, None).unwrap();
+        let expected: Vec<String> = (0..15).map(|i| format!("{{\"id\": {}}}", i)).collect();
+        assert_eq!(output_lines(&output_file), expected);
+
+        // Asking for more than there is just takes everything
+        head(&dir.join("input"), &output_file, 100, false, None).unwrap();
+        assert_eq!(output_lines(&output_file).len(), 30);
+    }
+
+    #[test]
+    fn test_head_stops_reading_after_n_lines() {
+        // Only the lines that are needed get read: the invalid UTF-8 after them (which would fail a whole-file
+        // read) and the later broken file are never reached
+        let dir = scratch_dir("stream");
+        let mut contents = b"\n{\"id\": 0}\n\n{\"id\": 1}\n{\"id\": 2}\n".to_vec();
+        contents.extend_from_slice(b"{\"id\": \xff\xfe}\n");
+        fs::write(dir.join("input").join("0.jsonl"), contents).unwrap();
+        fs::write(dir.join("input").join("1.jsonl.zst"), b"not zstd at all").unwrap();
+        let output_file = dir.join("out.jsonl");
+        head(&dir.join("input"), &output_file, 3, false, None).unwrap();
+        assert_eq!(output_lines(&output_file), vec!["{\"id\": 0}", "{\"id\": 1}", "{\"id\": 2}"]);
+
+        // Reading on into them does fail
+        assert!(head(&dir.join("input"), &output_file, 4, false, None).is_err());
+    }
+
+    #[test]
+    fn test_head_json_array_input() {
+        let dir = scratch_dir("array");
+        fs::write(dir.join("input").join("0.json"), "  [{\"id\": 0}, {\"id\": 1}, {\"id\": 2}]").unwrap();
+        let output_file = dir.join("out.jsonl");
+        head(&dir.join("input"), &output_file, 2, false, None).unwrap();
+        assert_eq!(output_lines(&output_file), vec!["{\"id\":0}", "{\"id\":1}"]);
+    }
+
+    #[test]
+    fn test_head_random_is_seeded_sample() {
+        let dir = scratch_dir("random");
+        write_input(&dir);
+        let output_file = dir.join("out.jsonl");
+        head(&dir.join("input"), &output_file, 10, true, Some(7)).unwrap();
+        let sample = output_lines(&output_file);
+        assert_eq!(sample.len(), 10);
+        assert_eq!(sample.iter().collect::<HashSet<_>>().len(), 10);
+        assert!(sample.iter().all(|l| l.starts_with("{\"id\": ")));
+
+        head(&dir.join("input"), &output_file, 10, true, Some(7)).unwrap();
+        assert_eq!(output_lines(&output_file), sample);
+    }
+}
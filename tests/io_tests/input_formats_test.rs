@@ -0,0 +1,93 @@
+extern crate datamap_rs;
+use datamap_rs::merge::{merge_jsonl, ConflictStrategy};
+use datamap_rs::utils::{expand_input_dirs, read_input_lines, read_input_lines_lossy};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_input_formats_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_expand_input_dirs_formats() {
+        let dir = scratch_dir("expand");
+        for name in ["a.jsonl", "b.ndjson", "c.json", "d.jsonl.zst", "notes.txt", "e.jsonlx"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let mut found: Vec<String> = expand_input_dirs(&dir)
+            .unwrap()
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        found.sort();
+        assert_eq!(found, vec!["a.jsonl", "b.ndjson", "c.json", "d.jsonl.zst"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_input_lines() {
+        let dir = scratch_dir("read");
+        let jsonl = dir.join("a.jsonl");
+        fs::write(&jsonl, "{\"id\": 1}\n\n{\"id\": 2}\n").unwrap();
+        assert_eq!(read_input_lines(&jsonl).unwrap(), vec!["{\"id\": 1}", "{\"id\": 2}"]);
+
+        let json_array = dir.join("b.json");
+        fs::write(&json_array, "  [{\"id\": 1},\n {\"id\": 2}]\n").unwrap();
+        let lines = read_input_lines(&json_array).unwrap();
+        assert_eq!(lines.len(), 2);
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["id"], 1);
+
+        // .json files holding one doc per line are still read as jsonl
+        let json_lines = dir.join("c.json");
+        fs::write(&json_lines, "{\"id\": 1}\n{\"id\": 2}\n").unwrap();
+        assert_eq!(read_input_lines(&json_lines).unwrap().len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_input_lines_lossy() {
+        let dir = scratch_dir("lossy");
+        let path = dir.join("bad.jsonl");
+        let mut contents = b"{\"text\": \"caf".to_vec();
+        contents.extend([0xE9, 0xFF]); // latin-1 e-acute and a stray byte, neither valid UTF-8
+        contents.extend(b"\"}\n{\"text\": \"fine\"}\n");
+        fs::write(&path, contents).unwrap();
+
+        // Strict by default
+        assert!(read_input_lines(&path).is_err());
+
+        let lines = read_input_lines_lossy(&path).unwrap();
+        assert_eq!(lines.len(), 2);
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["text"], "caf\u{FFFD}\u{FFFD}");
+        assert_eq!(lines[1], "{\"text\": \"fine\"}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_plain_jsonl_is_processed() {
+        let dir = scratch_dir("merge");
+        let (left, right, output) = (dir.join("left"), dir.join("right"), dir.join("output"));
+        fs::create_dir_all(&left).unwrap();
+        fs::create_dir_all(&right).unwrap();
+        fs::write(left.join("docs.jsonl"), "{\"id\": \"a\", \"text\": \"hello\"}\n{\"id\": \"b\", \"text\": \"world\"}\n").unwrap();
+        fs::write(right.join("scores.json"), "[{\"id\": \"a\", \"score\": 0.5}]").unwrap();
+
+        merge_jsonl(&left, &right, &output, &String::from("id"), &None, ConflictStrategy::KeepLeft, &None).unwrap();
+
+        let lines = read_input_lines(&output.join("docs.jsonl")).unwrap();
+        assert_eq!(lines.len(), 2);
+        let first: Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(first["score"], 0.5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
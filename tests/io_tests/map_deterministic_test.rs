@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_map_deterministic_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_deterministic_sorts_by_id_field() {
+        let dir = scratch_dir("id_field");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        let docs = [
+            json!({"id": "a", "meta": {"doc_id": "3"}, "text": "third"}),
+            json!({"id": "c", "meta": {"doc_id": "1"}, "text": "first"}),
+            json!({"id": "b", "meta": {"doc_id": "2"}, "text": "second"}),
+        ];
+        let input: String = docs.iter().map(|d| format!("{}\n", d)).collect();
+        fs::write(input_dir.join("shard_0.jsonl"), input).unwrap();
+
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 1}}]});
+        let output_dir = dir.join("output");
+        let output = Command::new(env!("CARGO_BIN_EXE_datamap-rs"))
+            .args(["--quiet", "map", "--config-inline", &config.to_string(), "--deterministic", "--id-field", "meta.doc_id"])
+            .arg("--input-dir").arg(&input_dir)
+            .arg("--output-dir").arg(&output_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        let written = fs::read_to_string(output_dir.join("step_final").join("shard_0.jsonl")).unwrap();
+        let texts: Vec<String> = written
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["text"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(texts, vec!["first", "second", "third"]);
+
+        // The end-of-run stats land next to the outputs
+        let stats: Value = serde_json::from_str(&fs::read_to_string(output_dir.join("map_stats.json")).unwrap()).unwrap();
+        assert_eq!(stats["docs_out"], 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
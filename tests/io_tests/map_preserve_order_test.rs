@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_map_preserve_order_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn ids(path: &Path) -> Vec<u64> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["id"].as_u64().unwrap())
+            .collect()
+    }
+
+    fn map(input_dir: &Path, output_dir: &Path, extra: &[&str]) -> std::process::Output {
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 5}}]});
+        Command::new(env!("CARGO_BIN_EXE_datamap-rs"))
+            .args(["--quiet", "map", "--config-inline", &config.to_string()])
+            .arg("--input-dir").arg(input_dir)
+            .arg("--output-dir").arg(output_dir)
+            .args(extra)
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_preserve_order_keeps_input_order() {
+        let dir = scratch_dir("order");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        // Ids are deliberately not sorted, so the order can only come from the input
+        let n = 500;
+        let docs: Vec<Value> = (0..n)
+            .map(|i| json!({"id": (i * 7919) % n, "text": "x".repeat((i % 10) as usize)}))
+            .collect();
+        let input: String = docs.iter().map(|d| format!("{}\n", d)).collect();
+        fs::write(input_dir.join("shard_0.jsonl"), input).unwrap();
+
+        let output_dir = dir.join("output");
+        let output = map(&input_dir, &output_dir, &["--preserve-order"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        // Survivors and rejects each keep their relative input order
+        let text_len = |d: &Value| d["text"].as_str().unwrap().len();
+        let kept: Vec<u64> = docs.iter().filter(|d| text_len(d) >= 5).map(|d| d["id"].as_u64().unwrap()).collect();
+        let removed: Vec<u64> = docs.iter().filter(|d| text_len(d) < 5).map(|d| d["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids(&output_dir.join("step_final").join("shard_0.jsonl")), kept);
+        assert_eq!(ids(&output_dir.join("step_00").join("shard_0.jsonl")), removed);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_preserve_order_conflicts_with_deterministic() {
+        let dir = scratch_dir("conflict");
+        let output = map(&dir.join("input"), &dir.join("output"), &["--preserve-order", "--deterministic"]);
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
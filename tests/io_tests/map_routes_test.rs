@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_map_routes_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_docs(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_routed_map_sends_invalid_json_to_err_dir() {
+        let dir = scratch_dir("invalid");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        let docs = [
+            json!({"id": 0, "source": "web", "text": "a web doc"}),
+            json!({"id": 1, "source": "books", "text": "a book"}),
+            json!({"id": 2, "source": "web", "text": "no"}),
+        ];
+        let input = format!("{}\n{}\nnot json\n{}\n", docs[0], docs[1], docs[2]);
+        fs::write(input_dir.join("shard_0.jsonl"), input).unwrap();
+
+        let config = json!({
+            "route_field": "source",
+            "routes": [
+                {"name": "web", "values": ["web"], "pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 5}}]},
+                {"name": "rest", "default": true, "pipeline": []},
+            ]
+        });
+        let output_dir = dir.join("output");
+        let err_dir = dir.join("err");
+        let output = Command::new(env!("CARGO_BIN_EXE_datamap-rs"))
+            .args(["--quiet", "map", "--config-inline", &config.to_string()])
+            .arg("--input-dir").arg(&input_dir)
+            .arg("--output-dir").arg(&output_dir)
+            .arg("--err-dir").arg(&err_dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+        assert_eq!(read_docs(&output_dir.join("web").join("step_final").join("shard_0.jsonl")), vec![docs[0].clone()]);
+        assert_eq!(read_docs(&output_dir.join("web").join("step_00").join("shard_0.jsonl")), vec![docs[2].clone()]);
+        assert_eq!(read_docs(&output_dir.join("rest").join("step_final").join("shard_0.jsonl")), vec![docs[1].clone()]);
+        // The invalid line isn't part of any route, so it's neither in the default route's outputs nor its err_dir
+        assert_eq!(fs::read_to_string(err_dir.join("shard_0.jsonl")).unwrap(), "not json\n");
+        assert!(!err_dir.join("rest").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,260 @@
+extern crate datamap_rs;
+use datamap_rs::bloom::BloomFilter;
+use datamap_rs::map_fxn::{PipelineProcessor, StepError};
+use datamap_rs::map_run::{
+    check_drop_fraction, file_report, rejected_output_dir, run_stats, sample_pipeline, stamp_provenance, SeenBloom,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_map_run_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn two_step_processor() -> PipelineProcessor {
+        PipelineProcessor::new(&json!({"pipeline": [
+            {"name": "text_len_filter", "kwargs": {"lower_bound": 3}},
+            {"name": "rename_modifier", "kwargs": {"old_field": "a", "new_field": "b"}},
+        ]}))
+        .unwrap()
+    }
+
+    fn lines() -> Vec<String> {
+        vec![
+            "not json".to_string(),
+            json!({"text": "x", "a": 1}).to_string(),      // removed by step 0
+            json!({"text": "hello", "a": 2}).to_string(),  // survives
+            json!({"text": "hello"}).to_string(),          // errors in step 1
+        ]
+    }
+
+    #[test]
+    fn test_step_errors_carry_their_step() {
+        let processor = two_step_processor();
+        let err = processor
+            .process(json!({"text": "hello"}), &mut HashMap::new(), &mut HashMap::new(), &mut HashMap::new())
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<StepError>().unwrap().step, 1);
+
+        let (_, err_lines, _, _, _, _, error_info) =
+            processor.process_lines(lines(), &PathBuf::from("in.jsonl")).unwrap();
+        assert_eq!(err_lines.len(), 2);
+        // The unparseable line never reached a step
+        assert_eq!(error_info.len(), 1);
+        assert_eq!(error_info[&1], 1);
+    }
+
+    #[test]
+    fn test_file_report_attributes_errors_per_step() {
+        let processor = two_step_processor();
+        let (_, err_lines, timing_info, filter_info, text_bytes_info, _, error_info) =
+            processor.process_lines(lines(), &PathBuf::from("in.jsonl")).unwrap();
+        let report = file_report(
+            &PathBuf::from("/data/in/sub/in.jsonl"),
+            &PathBuf::from("/data/in"),
+            &processor,
+            4,
+            0,
+            err_lines.len(),
+            &timing_info,
+            &filter_info,
+            &text_bytes_info,
+            &error_info,
+            0,
+        );
+
+        assert_eq!(report["input_file"], "sub/in.jsonl");
+        assert_eq!(report["docs_in"], 4);
+        assert_eq!(report["docs_out"], 1);
+        assert_eq!(report["errors"], 2);
+        let steps = report["steps"].as_array().unwrap();
+        // The doc that errored in step 1 still went through step 0
+        assert_eq!(steps[0]["docs_in"], 3);
+        assert_eq!(steps[0]["removed"], 1);
+        assert_eq!(steps[0]["errors"], 0);
+        assert_eq!(steps[1]["docs_in"], 2);
+        assert_eq!(steps[1]["removed"], 0);
+        assert_eq!(steps[1]["errors"], 1);
+    }
+
+    #[test]
+    fn test_file_report_skipped_seen() {
+        let processor = two_step_processor();
+        let filter_info = [(usize::MAX, 1)].into_iter().collect();
+        let report = file_report(
+            &PathBuf::from("in.jsonl"),
+            &PathBuf::from("/data/in"),
+            &processor,
+            5,
+            4,
+            0,
+            &Default::default(),
+            &filter_info,
+            &Default::default(),
+            &Default::default(),
+            0,
+        );
+        assert_eq!(report["input_file"], "in.jsonl");
+        assert_eq!(report["steps"][0]["docs_in"], 1);
+        assert_eq!(report["steps"][1]["docs_in"], 1);
+        assert_eq!(report["kept_frac"], 0.2);
+    }
+
+    #[test]
+    fn test_file_report_limit_cut() {
+        // 3 of 4 docs survived the pipeline, but --limit only let 1 be written
+        let processor = two_step_processor();
+        let filter_info = [(0, 1), (1, 0), (usize::MAX, 1)].into_iter().collect();
+        let report = file_report(
+            &PathBuf::from("in.jsonl"),
+            &PathBuf::from("/data/in"),
+            &processor,
+            4,
+            0,
+            0,
+            &Default::default(),
+            &filter_info,
+            &Default::default(),
+            &Default::default(),
+            2,
+        );
+        assert_eq!(report["docs_out"], 1);
+        assert_eq!(report["kept_frac"], 0.25);
+        assert_eq!(report["limit_cut"], 2);
+        assert_eq!(report["steps"][1]["docs_in"], 3);
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let processor = PipelineProcessor::new(&json!({
+            "stats_group_by": "src",
+            "pipeline": [
+                {"name": "text_len_filter", "kwargs": {"lower_bound": 3}},
+                {"name": "rename_modifier", "kwargs": {"old_field": "a", "new_field": "b"}},
+            ]
+        }))
+        .unwrap();
+        let lines = vec![
+            json!({"text": "x", "a": 1, "src": "web"}).to_string(),
+            json!({"text": "hello", "a": 2, "src": "web"}).to_string(),
+            json!({"text": "world", "a": 3, "src": "books"}).to_string(),
+        ];
+        let (_, _, timing_info, filter_info, text_bytes_info, grouped_filter_info, _) =
+            processor.process_lines(lines, &PathBuf::from("in.jsonl")).unwrap();
+        let step_times = timing_info.into_iter().map(|(k, v)| (k, v as usize)).collect();
+        let stats = run_stats(&processor, 7, &step_times, &filter_info, &text_bytes_info, &grouped_filter_info, 1);
+
+        assert_eq!(stats["total_time_secs"], 7);
+        // The doc cut by --limit survived the pipeline, so it counts toward docs_in but not docs_out
+        assert_eq!(stats["docs_in"], 4);
+        assert_eq!(stats["docs_out"], 2);
+        assert_eq!(stats["limit_cut"], 1);
+        assert_eq!(stats["steps"][0]["docs_in"], 4);
+        assert_eq!(stats["steps"][0]["removed"], 1);
+        assert_eq!(stats["steps"][0]["removed_frac"], 0.25);
+        assert_eq!(stats["steps"][1]["docs_in"], 3);
+        assert_eq!(stats["steps"][1]["text_bytes_in"], 10);
+
+        assert_eq!(stats["group_by"], "src");
+        let step_0 = processor.steps[0].clone();
+        assert_eq!(stats["groups"]["web"], json!({"docs_in": 2, "removed": {step_0: 1}, "docs_out": 1}));
+        assert_eq!(stats["groups"]["books"], json!({"docs_in": 1, "removed": {}, "docs_out": 1}));
+
+        // No breakdown without stats_group_by
+        let stats = run_stats(&two_step_processor(), 0, &Default::default(), &filter_info, &Default::default(), &Default::default(), 0);
+        assert!(stats.get("groups").is_none());
+        assert_eq!(stats["steps"][0]["time_frac"], 0.0);
+    }
+
+    #[test]
+    fn test_rejected_output_dir() {
+        let output_dir = PathBuf::from("/out");
+        let rejected_dir = PathBuf::from("/rejected");
+        let err_dir = PathBuf::from("/err");
+        let route = |rejected: Option<&PathBuf>, keep: bool, flat: bool, err: Option<&PathBuf>| {
+            rejected_output_dir(&output_dir, rejected, keep, flat, err)
+        };
+
+        assert_eq!(route(None, true, false, None).unwrap(), Some(output_dir.clone()));
+        assert_eq!(route(None, true, false, Some(&err_dir)).unwrap(), Some(output_dir.clone()));
+        // Flat output_dirs only hold survivors
+        assert_eq!(route(None, true, true, Some(&err_dir)).unwrap(), Some(err_dir.clone()));
+        assert_eq!(route(None, true, true, None).unwrap(), None);
+        assert_eq!(route(Some(&rejected_dir), true, false, None).unwrap(), Some(rejected_dir.clone()));
+        assert_eq!(route(Some(&rejected_dir), true, true, Some(&err_dir)).unwrap(), Some(rejected_dir.clone()));
+        assert_eq!(route(None, false, false, None).unwrap(), None);
+        assert_eq!(route(None, false, true, Some(&err_dir)).unwrap(), None);
+
+        let err = route(Some(&rejected_dir), false, false, None).unwrap_err();
+        assert!(err.to_string().contains("--keep-rejected false"));
+    }
+
+    #[test]
+    fn test_check_drop_fraction() {
+        assert!(check_drop_fraction(100, 50, 0.5, "Test").is_ok());
+        assert!(check_drop_fraction(100, 100, 0.0, "Test").is_ok());
+        let err = check_drop_fraction(100, 49, 0.5, "Test").unwrap_err();
+        assert!(err.to_string().starts_with("Test filtered out 51.00% of 100 docs"), "{}", err);
+        // Nothing processed, nothing dropped
+        assert!(check_drop_fraction(0, 0, 0.0, "Test").is_ok());
+    }
+
+    #[test]
+    fn test_sample_pipeline() {
+        let dir = scratch_dir("sample");
+        // Ids 0..5 have short texts, 5..10 long ones
+        let lines: Vec<String> = (0..10)
+            .map(|i| json!({"id": i, "text": if i < 5 { "x" } else { "hello" }, "a": 1}).to_string())
+            .collect();
+        fs::write(dir.join("0.jsonl"), lines[..6].join("\n") + "\nnot json\n").unwrap();
+        fs::write(dir.join("1.jsonl"), lines[6..].join("\n")).unwrap();
+        let files = vec![dir.join("0.jsonl"), dir.join("1.jsonl")];
+        let processor = two_step_processor();
+
+        let sample = sample_pipeline(&processor, &files, 100, false, None).unwrap();
+        assert_eq!((sample.docs, sample.errors, sample.survived(), sample.skipped_seen), (10, 1, 5, 0));
+        assert!(check_drop_fraction(sample.docs, sample.survived(), 0.4, "--sample-check").is_err());
+
+        // Stops after `sample` docs (errors included)
+        let sample = sample_pipeline(&processor, &files, 8, false, None).unwrap();
+        assert_eq!((sample.docs, sample.errors, sample.survived()), (7, 1, 2));
+
+        // Docs the seen-bloom already holds aren't sampled, just like the map skips them
+        let seen_bloom = SeenBloom {
+            filter: BloomFilter::new(1000, 1e-6).unwrap(),
+            path: dir.join("seen.bloom"),
+            id_field: String::from("id"),
+        };
+        (0..5).for_each(|i: i32| {
+            seen_bloom.filter.insert(i.to_string().as_bytes());
+        });
+        let sample = sample_pipeline(&processor, &files, 100, false, Some(&seen_bloom)).unwrap();
+        assert_eq!((sample.docs, sample.errors, sample.survived(), sample.skipped_seen), (5, 1, 5, 5));
+        assert!(check_drop_fraction(sample.docs, sample.survived(), 0.4, "--sample-check").is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stamp_provenance() {
+        let mut docs = vec![json!({"id": 0}), json!({"id": 1, "meta": {"source": "old", "lang": "en"}})];
+        let field = String::from("meta.source");
+        stamp_provenance(&mut docs, &field, &PathBuf::from("/data/in/crawl_01/shard.jsonl.zst"), &PathBuf::from("/data/in"))
+            .unwrap();
+        assert_eq!(docs[0], json!({"id": 0, "meta": {"source": "crawl_01/shard.jsonl.zst"}}));
+        assert_eq!(docs[1], json!({"id": 1, "meta": {"source": "crawl_01/shard.jsonl.zst", "lang": "en"}}));
+
+        // A file outside input_dir keeps its full path
+        let field = String::from("source");
+        stamp_provenance(&mut docs, &field, &PathBuf::from("/elsewhere/shard.jsonl"), &PathBuf::from("/data/in")).unwrap();
+        assert_eq!(docs[0]["source"], "/elsewhere/shard.jsonl");
+    }
+}
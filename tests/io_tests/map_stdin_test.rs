@@ -0,0 +1,29 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn test_map_stdin_stdout_is_only_jsonl() {
+        // Survivors (and nothing else) on stdout, stats and bad lines on stderr
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 3}}]});
+        let mut child = Command::new(env!("CARGO_BIN_EXE_datamap-rs"))
+            .args(["--quiet", "map", "--stdin", "--config-inline", &config.to_string()])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let input = "{\"id\": 0, \"text\": \"hello\"}\n{\"id\": 1, \"text\": \"x\"}\nnot json\n\n{\"id\": 2, \"text\": \"world\"}\n";
+        child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let docs: Vec<Value> = stdout.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(docs, vec![json!({"id": 0, "text": "hello"}), json!({"id": 2, "text": "world"})]);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("Processed 3 docs | 2 survived | 1 errors"), "{}", stderr);
+    }
+}
@@ -0,0 +1,94 @@
+extern crate datamap_rs;
+use datamap_rs::partition::discrete_partition;
+use datamap_rs::utils::{expand_input_dirs, read_input_lines, DocLimit, RuntimeBudget};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_max_runtime_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_inputs(input_dir: &PathBuf) {
+        for shard in 0..3 {
+            let contents: String = (0..4)
+                .map(|i| format!("{{\"id\": {}, \"lang\": \"{}\"}}\n", shard * 4 + i, if i % 2 == 0 { "en" } else { "fr" }))
+                .collect();
+            fs::write(input_dir.join(format!("shard_{}.jsonl", shard)), contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_runtime_budget() {
+        let path = PathBuf::from("a.jsonl");
+        let budget = RuntimeBudget::new(None);
+        assert!(!budget.out_of_time(&path));
+        assert!(budget.finish().is_ok());
+
+        let budget = RuntimeBudget::new(Some(3600));
+        assert!(!budget.out_of_time(&path));
+        assert!(budget.finish().is_ok());
+
+        // A zero budget is spent before the first file, and finish() reports the skipped files
+        let budget = RuntimeBudget::new(Some(0));
+        assert!(budget.out_of_time(&path));
+        assert!(budget.out_of_time(&PathBuf::from("b.jsonl")));
+        let err = budget.finish().unwrap_err().to_string();
+        assert!(err.contains("2 input files were not processed"), "{}", err);
+    }
+
+    #[test]
+    fn test_doc_limit() {
+        let doc_limit = DocLimit::new(5);
+        assert!(!doc_limit.reached());
+
+        // The first file fits entirely, the second is cut down to what's left
+        let mut survivors = vec![0, 1, 2];
+        assert!(doc_limit.truncate(&mut survivors).is_empty());
+        assert_eq!(survivors, vec![0, 1, 2]);
+        let mut survivors = vec![3, 4, 5, 6];
+        assert_eq!(doc_limit.truncate(&mut survivors), vec![5, 6]);
+        assert_eq!(survivors, vec![3, 4]);
+        assert!(doc_limit.reached());
+
+        // Files already in flight when it was reached get nothing
+        let mut survivors = vec![7];
+        assert_eq!(doc_limit.truncate(&mut survivors), vec![7]);
+        assert!(survivors.is_empty());
+        let mut survivors: Vec<i32> = vec![];
+        assert!(doc_limit.truncate(&mut survivors).is_empty());
+        doc_limit.finish();
+    }
+
+    #[test]
+    fn test_partition_stops_cleanly() {
+        let dir = scratch_dir("partition");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        write_inputs(&input_dir);
+
+        // Out of time right away: no file is started, the writers still finish and the command errors out
+        let output_dir = dir.join("stopped");
+        let key = Some(String::from("lang"));
+        assert!(discrete_partition(&input_dir, &output_dir, &None, &key, Some(0), &None).is_err());
+        assert!(!output_dir.exists() || expand_input_dirs(&output_dir).unwrap().is_empty());
+
+        // Plenty of time: same as no budget
+        let output_dir = dir.join("finished");
+        discrete_partition(&input_dir, &output_dir, &None, &key, Some(3600), &None).unwrap();
+        let total: usize = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .map(|p| read_input_lines(p).unwrap().len())
+            .sum();
+        assert_eq!(total, 12);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
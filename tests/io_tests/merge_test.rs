@@ -0,0 +1,65 @@
+extern crate datamap_rs;
+use datamap_rs::merge::{merge_jsonl, merge_records, ConflictStrategy};
+use datamap_rs::utils::read_input_lines;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_merge_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_conflict_strategies() {
+        let left = json!({"id": 1, "text": "a", "metadata": {"score": 1, "src": "x"}});
+        let right = json!({"id": 1, "text": "a", "metadata": {"score": 2, "label": "y"}});
+
+        let mut kept = left.clone();
+        merge_records(&mut kept, &right, ConflictStrategy::KeepLeft).unwrap();
+        assert_eq!(kept, json!({"id": 1, "text": "a", "metadata": {"score": 1, "src": "x", "label": "y"}}));
+
+        let mut preferred = left.clone();
+        merge_records(&mut preferred, &right, ConflictStrategy::PreferRight).unwrap();
+        assert_eq!(preferred, json!({"id": 1, "text": "a", "metadata": {"score": 2, "src": "x", "label": "y"}}));
+
+        // Equal values (id, text) are not conflicts; the differing nested score is, and is named in the error
+        let mut strict = left.clone();
+        let err = merge_records(&mut strict, &right, ConflictStrategy::ErrorOnConflict).unwrap_err();
+        assert!(err.to_string().contains("metadata.score"));
+        let mut strict = left.clone();
+        merge_records(&mut strict, &json!({"id": 1, "metadata": {"label": "y"}}), ConflictStrategy::ErrorOnConflict).unwrap();
+        assert_eq!(strict["metadata"]["label"], json!("y"));
+
+        assert!(ConflictStrategy::from_name("prefer_right").is_ok());
+        assert!(ConflictStrategy::from_name("prefer_parquet").is_err());
+    }
+
+    #[test]
+    fn test_nest_under_and_error_on_conflict() {
+        let dir = scratch_dir("nest");
+        let left = dir.join("left");
+        let right = dir.join("right");
+        fs::create_dir_all(&left).unwrap();
+        fs::create_dir_all(&right).unwrap();
+        fs::write(left.join("docs.jsonl"), "{\"id\": \"a\", \"score\": 1}\n{\"id\": \"b\"}\n").unwrap();
+        fs::write(right.join("ann.jsonl"), "{\"id\": \"a\", \"score\": 5, \"label\": \"good\"}\n").unwrap();
+
+        let output = dir.join("nested");
+        merge_jsonl(&left, &right, &output, &String::from("id"), &Some(vec![String::from("score"), String::from("label")]), ConflictStrategy::ErrorOnConflict, &Some(String::from("annotations.v2"))).unwrap();
+        let docs: Vec<Value> = read_input_lines(&output.join("docs.jsonl")).unwrap().iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        // Nested under their own key, the right-side fields can't collide with the left's top-level score
+        assert_eq!(docs[0], json!({"id": "a", "score": 1, "annotations": {"v2": {"score": 5, "label": "good"}}}));
+        assert_eq!(docs[1], json!({"id": "b"}));
+
+        let output = dir.join("flat");
+        assert!(merge_jsonl(&left, &right, &output, &String::from("id"), &None, ConflictStrategy::ErrorOnConflict, &None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
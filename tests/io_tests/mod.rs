@@ -0,0 +1,20 @@
+pub mod input_formats_test;
+pub mod percentilize_test;
+pub mod coalesce_test;
+pub mod max_runtime_test;
+pub mod shuffle_test;
+pub mod group_filter_test;
+pub mod diff_test;
+pub mod merge_test;
+pub mod partition_test;
+pub mod bloom_test;
+pub mod reservoir_sample_test;
+pub mod reshard_test;
+pub mod head_test;
+pub mod map_run_test;
+pub mod map_stdin_test;
+pub mod check_unique_test;
+pub mod map_deterministic_test;
+pub mod map_preserve_order_test;
+pub mod no_compress_test;
+pub mod map_routes_test;
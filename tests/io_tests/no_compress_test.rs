@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_no_compress_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_zst_input(input_dir: &Path) -> Vec<Value> {
+        fs::create_dir_all(input_dir.join("sub")).unwrap();
+        let docs: Vec<Value> = (0..20).map(|i| json!({"id": i, "score": i, "text": "x".repeat(i as usize)})).collect();
+        let input: String = docs.iter().map(|d| format!("{}\n", d)).collect();
+        fs::write(input_dir.join("sub").join("shard_0.jsonl.zst"), zstd::encode_all(input.as_bytes(), 3).unwrap()).unwrap();
+        docs
+    }
+
+    fn read_plain(path: &Path) -> Vec<Value> {
+        // Fails on zstd bytes, so this also checks the file really is uncompressed
+        fs::read_to_string(path).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    fn run(args: &[&str]) {
+        let output = Command::new(env!("CARGO_BIN_EXE_datamap-rs")).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn test_map_no_compress_drops_input_compression() {
+        let dir = scratch_dir("map");
+        let input_dir = dir.join("input");
+        let docs = write_zst_input(&input_dir);
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 5}}]}).to_string();
+
+        let output_dir = dir.join("output");
+        run(&[
+            "--quiet", "--no-compress", "map", "--config-inline", &config,
+            "--input-dir", input_dir.to_str().unwrap(), "--output-dir", output_dir.to_str().unwrap(),
+        ]);
+        for (step, keep) in [("step_final", true), ("step_00", false)] {
+            let step_dir = output_dir.join(step).join("sub");
+            assert!(!step_dir.join("shard_0.jsonl.zst").exists());
+            let expected: Vec<Value> =
+                docs.iter().filter(|d| (d["text"].as_str().unwrap().len() >= 5) == keep).cloned().collect();
+            assert_eq!(read_plain(&step_dir.join("shard_0.jsonl")), expected);
+        }
+
+        // Without the flag the output keeps the input's name and compression
+        let output_dir = dir.join("output_compressed");
+        run(&[
+            "--quiet", "map", "--config-inline", &config,
+            "--input-dir", input_dir.to_str().unwrap(), "--output-dir", output_dir.to_str().unwrap(),
+        ]);
+        let compressed = fs::read(output_dir.join("step_final").join("sub").join("shard_0.jsonl.zst")).unwrap();
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap().iter().filter(|&&b| b == b'\n').count(), 15);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_percentilize_no_compress_drops_input_compression() {
+        let dir = scratch_dir("percentilize");
+        let input_dir = dir.join("input");
+        write_zst_input(&input_dir);
+
+        let output_dir = dir.join("output");
+        run(&[
+            "--quiet", "--no-compress", "percentilize",
+            "--input-dir", input_dir.to_str().unwrap(), "--output-dir", output_dir.to_str().unwrap(),
+            "--value", "score", "--output-field", "pct", "--num-buckets", "4",
+        ]);
+        assert!(!output_dir.join("sub").join("shard_0.jsonl.zst").exists());
+        let docs = read_plain(&output_dir.join("sub").join("shard_0.jsonl"));
+        assert_eq!(docs.len(), 20);
+        assert!(docs.iter().all(|d| d["pct"].is_number()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
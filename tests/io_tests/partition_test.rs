@@ -0,0 +1,156 @@
+extern crate datamap_rs;
+use datamap_rs::partition::{discrete_partition, language_partition, range_partition};
+use datamap_rs::utils::{expand_input_dirs, read_input_lines};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_partition_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_max_files_per_category_nests_overflow() {
+        let dir = scratch_dir("max_files");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        // Each input file is one write per category; with a 1-byte max_file_size every write rolls to a new file
+        for i in 0..5 {
+            fs::write(input_dir.join(format!("in_{}.jsonl", i)), format!("{{\"lang\": \"en\", \"id\": {}}}\n{{\"lang\": \"fr\", \"id\": {}}}\n", i, i)).unwrap();
+        }
+        let config = dir.join("config.yaml");
+        fs::write(&config, "name: test\npartition_key: lang\nchoices: [en]\nmax_file_size: 1\nmax_files_per_category: 2\n").unwrap();
+        let output_dir = dir.join("output");
+        discrete_partition(&input_dir, &output_dir, &Some(config), &None, None, &None).unwrap();
+
+        let mut files: Vec<String> = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .filter(|p| p.starts_with(output_dir.join("en")))
+            .map(|p| p.strip_prefix(&output_dir).unwrap().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec![
+            "en/chunk_00000000.jsonl.zst",
+            "en/chunk_00000001.jsonl.zst",
+            "en/overflow_0001/chunk_00000002.jsonl.zst",
+            "en/overflow_0001/chunk_00000003.jsonl.zst",
+            "en/overflow_0002/chunk_00000004.jsonl.zst",
+        ]);
+        let total: usize = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .map(|p| read_input_lines(p).unwrap().len())
+            .sum();
+        assert_eq!(total, 10);
+
+        // 0 is rejected
+        let bad_config = dir.join("bad.yaml");
+        fs::write(&bad_config, "name: test\npartition_key: lang\nmax_files_per_category: 0\n").unwrap();
+        assert!(discrete_partition(&input_dir, &dir.join("bad"), &Some(bad_config), &None, None, &None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_discrete_partition_filename_template() {
+        let dir = scratch_dir("template");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(input_dir.join("in.jsonl"), "{\"lang\": \"en\"}\n{\"lang\": \"fr\"}\n{\"lang\": null}\n").unwrap();
+        let output_dir = dir.join("output");
+        let template = Some("{bucket}-{index}.{ext}".to_string());
+        discrete_partition(&input_dir, &output_dir, &None, &Some("lang".to_string()), None, &template).unwrap();
+
+        let mut files: Vec<String> = expand_input_dirs(&output_dir)
+            .unwrap()
+            .iter()
+            .map(|p| p.strip_prefix(&output_dir).unwrap().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        // Only the file names change, not the category dirs
+        assert_eq!(files, vec![
+            "en/en-00000000.jsonl.zst",
+            "fr/fr-00000000.jsonl.zst",
+            "no_category/no_category-00000000.jsonl.zst",
+        ]);
+
+        let bad = Some("{bucket}.{ext}".to_string());
+        assert!(discrete_partition(&input_dir, &dir.join("bad"), &None, &Some("lang".to_string()), None, &bad).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn bucket_counts(output_dir: &PathBuf) -> Vec<(String, usize)> {
+        let mut counts: std::collections::BTreeMap<String, usize> = std::collections::BTreeMap::new();
+        for path in expand_input_dirs(output_dir).unwrap() {
+            let bucket = path.parent().unwrap().file_name().unwrap().to_string_lossy().to_string();
+            *counts.entry(bucket).or_default() += read_input_lines(&path).unwrap().len();
+        }
+        counts.into_iter().collect()
+    }
+
+    #[test]
+    fn test_range_partition_missing_bucket() {
+        let dir = scratch_dir("missing_bucket");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        fs::write(
+            input_dir.join("in.jsonl"),
+            "{\"score\": 0.0}\n{\"score\": 0.7}\n{\"id\": 1}\n{\"score\": null}\n{\"score\": \"high\"}\n",
+        )
+        .unwrap();
+
+        // Opt-in: missing, null and non-numeric values get their own bucket, a genuine 0.0 stays in bucket 0
+        let config = dir.join("config.yaml");
+        fs::write(&config, "name: test\nvalue: score\nrange_groups: [0.5]\nmissing_bucket: true\n").unwrap();
+        let output_dir = dir.join("output");
+        range_partition(&input_dir, &output_dir, &Some(config), &None, &None, &None, &None, &None, &None, &None, false, None, &None).unwrap();
+        assert_eq!(bucket_counts(&output_dir), vec![
+            (String::from("bucket_0000"), 1),
+            (String::from("bucket_0001"), 1),
+            (String::from("bucket_missing"), 3),
+        ]);
+
+        // Default: missing values are coerced to default_value (0.0 here) as before
+        fs::write(input_dir.join("in.jsonl"), "{\"score\": 0.0}\n{\"score\": 0.7}\n{\"id\": 1}\n").unwrap();
+        let default_dir = dir.join("default");
+        range_partition(&input_dir, &default_dir, &None, &Some(String::from("score")), &None, &Some(vec![0.5]), &None, &None, &None, &None, false, None, &None).unwrap();
+        assert_eq!(bucket_counts(&default_dir), vec![(String::from("bucket_0000"), 2), (String::from("bucket_0001"), 1)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_language_partition() {
+        let dir = scratch_dir("language");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        let english = "The quick brown fox jumps over the lazy dog, and then it runs back into the forest to sleep.";
+        let german = "Der schnelle braune Fuchs springt über den faulen Hund und läuft dann zurück in den Wald, um zu schlafen.";
+        let lines: Vec<String> = [english, german, english]
+            .iter()
+            .enumerate()
+            .map(|(i, text)| serde_json::json!({"id": i, "body": text}).to_string())
+            .collect();
+        fs::write(input_dir.join("in.jsonl"), lines.join("\n") + "\n").unwrap();
+        let fast_text_file = PathBuf::from("ft_classifiers/lid176.bin");
+        let text_key = String::from("body");
+
+        let output_dir = dir.join("output");
+        language_partition(&input_dir, &output_dir, &fast_text_file, &text_key, 0.0, 256_000_000, None, &None).unwrap();
+        assert_eq!(bucket_counts(&output_dir), vec![(String::from("lang_de"), 1), (String::from("lang_en"), 2)]);
+        // Docs are written unchanged
+        let de_file = output_dir.join("lang_de").join("shard_00000000.jsonl.zst");
+        assert_eq!(read_input_lines(&de_file).unwrap(), vec![lines[1].clone()]);
+
+        // Nothing is that confident, so everything is unknown
+        let unknown_dir = dir.join("unknown");
+        language_partition(&input_dir, &unknown_dir, &fast_text_file, &text_key, 1.01, 256_000_000, None, &None).unwrap();
+        assert_eq!(bucket_counts(&unknown_dir), vec![(String::from("lang_unknown"), 3)]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
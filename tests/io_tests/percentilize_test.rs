@@ -0,0 +1,68 @@
+extern crate datamap_rs;
+use datamap_rs::percentilize::percentilize;
+use datamap_rs::utils::read_input_lines;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_percentilize_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_scores(dir: &PathBuf) {
+        let mut contents = String::new();
+        for i in 0..100 {
+            contents.push_str(&format!("{{\"id\": {}, \"metadata\": {{\"score\": {}}}}}\n", i, i));
+        }
+        contents.push_str("{\"id\": 100}\n");
+        fs::write(dir.join("docs.jsonl"), contents).unwrap();
+    }
+
+    #[test]
+    fn test_percentilize() {
+        let dir = scratch_dir("basic");
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        fs::create_dir_all(&input).unwrap();
+        write_scores(&input);
+
+        percentilize(&input, &output, &String::from("metadata.score"), &String::from("metadata.score_pct"), 1000, 10, None).unwrap();
+
+        let docs: Vec<Value> = read_input_lines(&output.join("docs.jsonl"))
+            .unwrap()
+            .iter()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(docs.len(), 101);
+        assert_eq!(docs[5]["metadata"]["score_pct"], 0.0);
+        assert_eq!(docs[15]["metadata"]["score_pct"], 10.0);
+        assert_eq!(docs[95]["metadata"]["score_pct"], 90.0);
+        assert_eq!(docs[95]["metadata"]["score"], 95);
+        // No value and no default: passed through unannotated
+        assert!(docs[100].get("metadata").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_percentilize_default_value() {
+        let dir = scratch_dir("default");
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        fs::create_dir_all(&input).unwrap();
+        write_scores(&input);
+
+        percentilize(&input, &output, &String::from("metadata.score"), &String::from("pct"), 1000, 10, Some(1000.0)).unwrap();
+
+        let lines = read_input_lines(&output.join("docs.jsonl")).unwrap();
+        let last: Value = serde_json::from_str(&lines[100]).unwrap();
+        assert_eq!(last["pct"], 90.0);
+
+        assert!(percentilize(&input, &output, &String::from("metadata.score"), &String::from("pct"), 1000, 1, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+extern crate datamap_rs;
+use datamap_rs::reservoir_sample::reservoir_sample;
+use datamap_rs::utils::read_input_lines;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_reservoir_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_inputs(input_dir: &PathBuf) {
+        // 3 shards x 20 docs; every 10th doc has no score
+        for shard in 0..3 {
+            let contents: String = (0..20)
+                .map(|i| {
+                    let id = shard * 20 + i;
+                    if i % 10 == 9 {
+                        format!("{{\"id\": {}, \"text\": \"no score here\"}}\n", id)
+                    } else {
+                        format!("{{\"id\": {}, \"score\": {}, \"text\": \"{}\"}}\n", id, id, "word ".repeat(i + 1))
+                    }
+                })
+                .collect();
+            fs::write(input_dir.join(format!("shard_{}.jsonl", shard)), contents).unwrap();
+        }
+    }
+
+    fn sample_docs(input: &PathBuf, output_file: &PathBuf, key: &str, reservoir_size: usize, token_weighted: bool) -> Vec<Value> {
+        let (key, text_key) = (String::from(key), String::from("text"));
+        reservoir_sample(input, output_file, &key, reservoir_size, token_weighted, &text_key, true).unwrap();
+        read_input_lines(output_file).unwrap().iter().map(|l| serde_json::from_str(l).unwrap()).collect()
+    }
+
+    fn ids(docs: &[Value]) -> Vec<u64> {
+        docs.iter().map(|d| d["id"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_sample_docs_gathers_full_documents() {
+        let dir = scratch_dir("gather");
+        let input = dir.join("input");
+        fs::create_dir_all(&input).unwrap();
+        write_inputs(&input);
+
+        // A reservoir bigger than the data keeps every doc with a score, whole, in file/line order
+        let docs = sample_docs(&input, &dir.join("all.jsonl"), "score", 1000, false);
+        let expected: Vec<u64> = (0..60).filter(|i| i % 10 != 9).collect();
+        assert_eq!(ids(&docs), expected);
+        assert_eq!(docs[1]["text"], "word word ");
+
+        // Same through a compressed output
+        let docs = sample_docs(&input, &dir.join("all.jsonl.zst"), "score", 1000, false);
+        assert_eq!(ids(&docs), expected);
+
+        // Token-weighted sampling gathers the same way (its key must be numeric on every doc)
+        let docs = sample_docs(&input, &dir.join("weighted.jsonl"), "id", 1000, true);
+        assert_eq!(ids(&docs), (0..60).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    fn test_sample_docs_subsample() {
+        let dir = scratch_dir("subsample");
+        let input = dir.join("input");
+        fs::create_dir_all(&input).unwrap();
+        write_inputs(&input);
+
+        let docs = sample_docs(&input, &dir.join("sample.jsonl"), "score", 30, false);
+        let sampled = ids(&docs);
+        assert!(!sampled.is_empty() && sampled.len() <= 30, "{:?}", sampled);
+        assert_eq!(sampled.iter().collect::<HashSet<_>>().len(), sampled.len());
+        assert!(sampled.iter().all(|id| id % 10 != 9 && *id < 60));
+    }
+}
+
+mod stratified {
+    use datamap_rs::reservoir_sample::stratified_sample;
+    use datamap_rs::utils::read_input_lines;
+    use serde_json::Value;
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_stratified_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_inputs(input_dir: &PathBuf) {
+        // 4 shards x 25 docs with score == id in [0, 100); ids 95..100 have no score
+        for shard in 0..4 {
+            let contents: String = (0..25)
+                .map(|i| {
+                    let id = shard * 25 + i;
+                    if id >= 95 {
+                        format!("{{\"id\": {}, \"text\": \"no score\"}}\n", id)
+                    } else {
+                        format!("{{\"id\": {}, \"score\": {}, \"text\": \"doc {}\"}}\n", id, id, id)
+                    }
+                })
+                .collect();
+            fs::write(input_dir.join(format!("shard_{}.jsonl", shard)), contents).unwrap();
+        }
+    }
+
+    fn read_ids(output_file: &PathBuf) -> Vec<u64> {
+        read_input_lines(output_file)
+            .unwrap()
+            .iter()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["id"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_range_groups_balanced() {
+        let dir = scratch_dir("ranges");
+        let input = dir.join("input");
+        fs::create_dir_all(&input).unwrap();
+        write_inputs(&input);
+
+        let output = dir.join("sample.jsonl");
+        let value = String::from("score");
+        stratified_sample(&input, &output, &value, &Some(vec![10.0, 50.0]), &None, None, 5, None).unwrap();
+        let ids = read_ids(&output);
+        assert_eq!(ids.len(), 15, "{:?}", ids);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 15);
+        assert_eq!(ids.iter().filter(|id| **id < 10).count(), 5);
+        assert_eq!(ids.iter().filter(|id| (10..50).contains(*id)).count(), 5);
+        assert_eq!(ids.iter().filter(|id| (50..95).contains(*id)).count(), 5);
+
+        // A bucket smaller than per_bucket is kept whole; docs without a score only count with default_value
+        let output = dir.join("small.jsonl");
+        stratified_sample(&input, &output, &value, &Some(vec![3.0]), &None, None, 10, None).unwrap();
+        let ids = read_ids(&output);
+        assert_eq!(ids.iter().filter(|id| **id < 3).count(), 3);
+        assert_eq!(ids.len(), 13);
+        assert!(ids.iter().all(|id| *id < 95));
+
+        let output = dir.join("default.jsonl");
+        stratified_sample(&input, &output, &value, &Some(vec![-1.0]), &None, None, 100, Some(-5.0)).unwrap();
+        let ids = read_ids(&output);
+        assert_eq!(ids.len(), 100);
+    }
+
+    #[test]
+    fn test_num_buckets_quantiles() {
+        let dir = scratch_dir("quantiles");
+        let input = dir.join("input");
+        fs::create_dir_all(&input).unwrap();
+        write_inputs(&input);
+
+        // Bounds from a provided reservoir: 0..95 in 5 buckets -> [19, 38, 57, 76]
+        let reservoir: Vec<f64> = (0..95).map(|i| i as f64).collect();
+        let reservoir_path = dir.join("reservoir.json");
+        fs::write(&reservoir_path, serde_json::to_vec(&reservoir).unwrap()).unwrap();
+        let output = dir.join("sample.jsonl");
+        let value = String::from("score");
+        stratified_sample(&input, &output, &value, &None, &Some(reservoir_path), Some(5), 4, None).unwrap();
+        let ids = read_ids(&output);
+        assert_eq!(ids.len(), 20);
+        for (lo, hi) in [(0, 19), (19, 38), (38, 57), (57, 76), (76, 95)] {
+            assert_eq!(ids.iter().filter(|id| (lo..hi).contains(*id)).count(), 4, "{:?}", ids);
+        }
+
+        // Without a reservoir the bounds are estimated from the input itself
+        let output = dir.join("estimated.jsonl");
+        stratified_sample(&input, &output, &value, &None, &None, Some(5), 4, None).unwrap();
+        assert_eq!(read_ids(&output).len(), 20);
+
+        // Exactly one of range_groups / num_buckets
+        assert!(stratified_sample(&input, &output, &value, &None, &None, None, 4, None).is_err());
+        assert!(stratified_sample(&input, &output, &value, &Some(vec![1.0]), &None, Some(5), 4, None).is_err());
+    }
+}
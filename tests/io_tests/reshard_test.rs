@@ -0,0 +1,87 @@
+extern crate datamap_rs;
+use datamap_rs::reshard::reshard;
+use datamap_rs::utils::{expand_input_dirs, read_input_lines};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_reshard_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_groups(path: PathBuf, groups: &[(&str, usize)]) {
+        // Each group's docs are contiguous, like after group_filter
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut contents = String::new();
+        for (group, size) in groups {
+            for i in 0..*size {
+                contents.push_str(&format!("{{\"url\": \"{}\", \"i\": {}}}\n", group, i));
+            }
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read_shards(dir: &Path) -> Vec<Vec<Value>> {
+        let mut paths = expand_input_dirs(dir).unwrap();
+        paths.sort();
+        paths
+            .iter()
+            .map(|p| read_input_lines(p).unwrap().iter().map(|l| serde_json::from_str(l).unwrap()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_respect_groups_keeps_groups_together() {
+        let dir = scratch_dir("groups");
+        let (input, output) = (dir.join("input"), dir.join("output"));
+        write_groups(input.join("a.jsonl"), &[("a1", 3), ("a2", 2), ("a3", 4), ("a4", 1)]);
+        write_groups(input.join("b.jsonl"), &[("b1", 1), ("b2", 3), ("b3", 3)]);
+
+        reshard(&input, &output, 4, 0, 0.0, false, false, Some("url")).unwrap();
+
+        let shards = read_shards(&output);
+        let mut shard_of: HashMap<String, usize> = HashMap::new();
+        let mut total = 0;
+        for (shard_num, shard) in shards.iter().enumerate() {
+            assert!(shard.len() <= 4, "{:?}", shard);
+            let mut prev: Option<&str> = None;
+            for doc in shard {
+                let url = doc["url"].as_str().unwrap();
+                // Every group lives in exactly one shard, contiguously
+                if prev != Some(url) {
+                    assert!(shard_of.insert(url.to_string(), shard_num).is_none(), "{} was split", url);
+                }
+                prev = Some(url);
+                total += 1;
+            }
+        }
+        assert_eq!(total, 17);
+        assert_eq!(shard_of.len(), 7);
+    }
+
+    #[test]
+    fn test_respect_groups_errors() {
+        let dir = scratch_dir("errors");
+        let input = dir.join("input");
+        write_groups(input.join("a.jsonl"), &[("a1", 2), ("a2", 5)]);
+        // A group bigger than a shard
+        assert!(reshard(&input, &dir.join("too_big"), 4, 0, 0.0, false, false, Some("url")).is_err());
+        // Fine without respecting groups
+        assert!(reshard(&input, &dir.join("plain"), 4, 0, 0.0, false, false, None).is_ok());
+
+        // A group that isn't contiguous
+        let scattered = dir.join("scattered");
+        write_groups(scattered.join("a.jsonl"), &[("a1", 1), ("a2", 1), ("a1", 1)]);
+        assert!(reshard(&scattered, &dir.join("scattered_out"), 4, 0, 0.0, false, false, Some("url")).is_err());
+
+        assert!(reshard(&input, &dir.join("subsampled"), 4, 0, 0.5, false, false, Some("url")).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+extern crate datamap_rs;
+use datamap_rs::groupfilter::GenWriter;
+use datamap_rs::shuffle::shuffle;
+use datamap_rs::utils::{expand_input_dirs, read_input_lines};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_shuffle_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_all_sorted(dir: &PathBuf) -> Vec<String> {
+        let mut lines: Vec<String> = expand_input_dirs(dir)
+            .unwrap()
+            .iter()
+            .flat_map(|p| read_input_lines(p).unwrap())
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    #[test]
+    fn test_shuffle_keeps_every_doc() {
+        let dir = scratch_dir("roundtrip");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        for shard in 0..4 {
+            let contents: String = (0..250).map(|i| format!("{{\"id\": {:05}}}\n", shard * 250 + i)).collect();
+            fs::write(input_dir.join(format!("shard_{}.jsonl", shard)), contents).unwrap();
+        }
+        let expected = read_all_sorted(&input_dir);
+
+        // Tiny buffers (a few lines per batch) and tiny files, so batches get flushed mid-file and files rotate
+        for (name, buffer_size) in [("tiny", 64), ("default", datamap_rs::groupfilter::DEFAULT_WRITE_BUFFER_SIZE)] {
+            let output_dir = dir.join(name);
+            shuffle(&input_dir, &output_dir, 5, 2_000, false, buffer_size, &None).unwrap();
+            assert_eq!(read_all_sorted(&output_dir), expected);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_shuffle_filename_template() {
+        let dir = scratch_dir("template");
+        let input_dir = dir.join("input");
+        fs::create_dir_all(&input_dir).unwrap();
+        let contents: String = (0..100).map(|i| format!("{{\"id\": {:05}}}\n", i)).collect();
+        fs::write(input_dir.join("shard.jsonl"), contents).unwrap();
+        let expected = read_all_sorted(&input_dir);
+
+        let output_dir = dir.join("output");
+        let template = Some("part-{bucket}-{index}.{ext}".to_string());
+        shuffle(&input_dir, &output_dir, 2, 256_000_000, false, 64, &template).unwrap();
+        let mut names: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["part-00000000-00000000.jsonl.zst", "part-00000001-00000000.jsonl.zst"]);
+        assert_eq!(read_all_sorted(&output_dir), expected);
+
+        // Several outputs in one dir need {bucket} to tell them apart
+        let no_bucket = Some("part-{index}.{ext}".to_string());
+        assert!(shuffle(&input_dir, &dir.join("bad"), 2, 256_000_000, false, 64, &no_bucket).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_buffered_writer_flush() {
+        let dir = scratch_dir("flush");
+        let writer = GenWriter::new(&dir, 2, "test", 1_000_000, 1_000_000);
+        let mut buffered = writer.buffered();
+        buffered.write_line(0, b"{\"a\": 1}").unwrap();
+        buffered.write_line(1, b"{\"a\": 2}").unwrap();
+        buffered.flush().unwrap();
+        // Flushing again (and dropping) with nothing buffered writes nothing more
+        buffered.flush().unwrap();
+        drop(buffered);
+        writer.finish().unwrap();
+        let chunk_1 = read_input_lines(&GenWriter::get_filename(&dir, 1, 0, "test")).unwrap();
+        assert_eq!(chunk_1, vec!["{\"a\": 2}"]);
+        assert_eq!(read_all_sorted(&dir).len(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_buffered_writer_flushes_on_drop() {
+        let dir = scratch_dir("drop");
+        let writer = GenWriter::new(&dir, 2, "test", 1_000_000, 1_000_000);
+        {
+            let mut buffered = writer.buffered();
+            buffered.write_line(0, b"{\"a\": 1}").unwrap();
+            buffered.write_line(1, b"{\"a\": 2}").unwrap();
+            buffered.write_line(0, b"{\"a\": 3}").unwrap();
+        }
+        writer.finish().unwrap();
+        let chunk_0 = read_input_lines(&GenWriter::get_filename(&dir, 0, 0, "test")).unwrap();
+        assert_eq!(chunk_0, vec!["{\"a\": 1}", "{\"a\": 3}"]);
+        assert_eq!(read_all_sorted(&dir).len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,3 @@
 pub mod map_fxn_tests;
+pub mod io_tests;
+pub mod utils_tests;
@@ -26,7 +26,7 @@ fn test_new_with_custom_id_key() {
 #[test]
 fn test_process_adds_id_to_empty_object() {
     // Test that process adds an id to an empty JSON object
-    let modifier = AddIdModifier { id_key: String::from("id") };
+    let modifier = AddIdModifier::new(&json!({"id_key": "id"})).unwrap();
     let data = json!({});
     
     let result = modifier.process(data).unwrap().unwrap();
@@ -42,7 +42,7 @@ fn test_process_adds_id_to_empty_object() {
 #[test]
 fn test_process_with_custom_id_key() {
     // Test that process adds an id using the custom id_key
-    let modifier = AddIdModifier { id_key: String::from("custom_id") };
+    let modifier = AddIdModifier::new(&json!({"id_key": "custom_id"})).unwrap();
     let data = json!({"name": "test"});
     
     let result = modifier.process(data).unwrap().unwrap();
@@ -59,7 +59,7 @@ fn test_process_with_custom_id_key() {
 #[test]
 fn test_process_overwrites_existing_id() {
     // Test that process overwrites an existing id field
-    let modifier = AddIdModifier { id_key: String::from("id") };
+    let modifier = AddIdModifier::new(&json!({"id_key": "id"})).unwrap();
     let data = json!({"id": "old-id", "name": "test"});
     
     let result = modifier.process(data).unwrap().unwrap();
@@ -77,7 +77,7 @@ fn test_process_overwrites_existing_id() {
 #[test]
 fn test_process_with_nested_id_field() {
     // Test that process works with a nested path for id_key
-    let modifier = AddIdModifier { id_key: String::from("metadata.id") };
+    let modifier = AddIdModifier::new(&json!({"id_key": "metadata.id"})).unwrap();
     let data = json!({"name": "test", "metadata": {}});
     
     let result = modifier.process(data).unwrap().unwrap();
@@ -95,7 +95,7 @@ fn test_process_with_nested_id_field() {
 #[test]
 fn test_process_creates_nested_path() {
     // Test that process creates the nested path if it doesn't exist
-    let modifier = AddIdModifier { id_key: String::from("metadata.nested.id") };
+    let modifier = AddIdModifier::new(&json!({"id_key": "metadata.nested.id"})).unwrap();
     let data = json!({"name": "test"});
     
     let result = modifier.process(data).unwrap().unwrap();
@@ -111,3 +111,46 @@ fn test_process_creates_nested_path() {
     assert!(Uuid::parse_str(id_str).is_ok());
 }
 
+
+#[test]
+fn test_bad_id_type() {
+    assert!(AddIdModifier::new(&json!({"id_type": "sequential"})).is_err());
+    assert!(AddIdModifier::new(&json!({"id_type": "uuid5", "namespace": "not-a-uuid"})).is_err());
+}
+
+#[test]
+fn test_content_hash_is_deterministic() {
+    let modifier = AddIdModifier::new(&json!({"id_type": "content_hash"})).unwrap();
+    assert_eq!(modifier.source_field, "text");
+
+    let first = modifier.process(json!({"text": "hello world"})).unwrap().unwrap();
+    let again = modifier.process(json!({"text": "hello world", "other": 1})).unwrap().unwrap();
+    let different = modifier.process(json!({"text": "hello there"})).unwrap().unwrap();
+    assert_eq!(first["id"], again["id"]);
+    assert_ne!(first["id"], different["id"]);
+
+    // Stable across processor instances (i.e. across runs), and the same value as hash_annotator with 128 bits
+    let rebuilt = AddIdModifier::new(&json!({"id_type": "content_hash"})).unwrap();
+    assert_eq!(rebuilt.process(json!({"text": "hello world"})).unwrap().unwrap()["id"], first["id"]);
+    assert_eq!(first["id"], xxhash_rust::xxh3::xxh3_128(b"hello world").to_string());
+
+    // The source field is required
+    assert!(modifier.process(json!({"content": "hello world"})).is_err());
+}
+
+#[test]
+fn test_uuid5() {
+    let config = json!({"id_type": "uuid5", "source_field": "url", "namespace": "url"});
+    let modifier = AddIdModifier::new(&config).unwrap();
+    let result = modifier.process(json!({"url": "https://example.com"})).unwrap().unwrap();
+    let id = result["id"].as_str().unwrap();
+    assert_eq!(id, Uuid::new_v5(&Uuid::NAMESPACE_URL, b"https://example.com").to_string());
+    assert_eq!(Uuid::parse_str(id).unwrap().get_version_num(), 5);
+
+    // Custom namespaces give different (but still stable) ids
+    let config = json!({"id_type": "uuid5", "source_field": "url", "namespace": "6ba7b810-9dad-11d1-80b4-00c04fd430c9"});
+    let modifier = AddIdModifier::new(&config).unwrap();
+    let other = modifier.process(json!({"url": "https://example.com"})).unwrap().unwrap();
+    assert_ne!(other["id"], result["id"]);
+    assert_eq!(modifier.process(json!({"url": "https://example.com"})).unwrap().unwrap()["id"], other["id"]);
+}
@@ -0,0 +1,47 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{AnyFieldPresentFilter, DataProcessor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_any_field_present_filter_new() {
+        let filter = AnyFieldPresentFilter::new(&json!({"fields": ["text", "content"]})).unwrap();
+        assert_eq!(filter.fields, vec!["text", "content"]);
+        assert_eq!(filter.mode, "any");
+        assert!(filter.nonempty);
+
+        assert!(AnyFieldPresentFilter::new(&json!({})).is_err());
+        assert!(AnyFieldPresentFilter::new(&json!({"fields": []})).is_err());
+        assert!(AnyFieldPresentFilter::new(&json!({"fields": [1]})).is_err());
+        assert!(AnyFieldPresentFilter::new(&json!({"fields": ["text"], "mode": "most"})).is_err());
+    }
+
+    #[test]
+    fn test_any_mode() {
+        let filter = AnyFieldPresentFilter::new(&json!({"fields": ["text", "content", "meta.body"]})).unwrap();
+        assert!(filter.process(json!({"text": "hi"})).unwrap().is_some());
+        assert!(filter.process(json!({"text": "", "content": "hi"})).unwrap().is_some());
+        assert!(filter.process(json!({"meta": {"body": "hi"}})).unwrap().is_some());
+        // Non-string values count as present
+        assert!(filter.process(json!({"content": ["a"]})).unwrap().is_some());
+
+        assert!(filter.process(json!({"title": "hi"})).unwrap().is_none());
+        assert!(filter.process(json!({"text": null, "content": "  \n", "meta": {}})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_all_mode_and_nonempty() {
+        let filter = AnyFieldPresentFilter::new(&json!({"fields": ["text", "url"], "mode": "all"})).unwrap();
+        assert!(filter.process(json!({"text": "hi", "url": "http://a.com"})).unwrap().is_some());
+        assert!(filter.process(json!({"text": "hi"})).unwrap().is_none());
+        assert!(filter.process(json!({"text": "hi", "url": ""})).unwrap().is_none());
+
+        let config = json!({"fields": ["text", "url"], "mode": "all", "nonempty": false});
+        let filter = AnyFieldPresentFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"text": "hi", "url": ""})).unwrap().is_some());
+        assert!(filter.process(json!({"text": "hi", "url": null})).unwrap().is_none());
+    }
+}
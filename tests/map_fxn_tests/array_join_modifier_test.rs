@@ -0,0 +1,45 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{ArrayJoinModifier, DataProcessor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_array_join_modifier_new() {
+        let modifier = ArrayJoinModifier::new(&json!({"text_field": "segments"})).unwrap();
+        assert_eq!(modifier.text_field, "segments");
+        assert_eq!(modifier.output_field, "segments");
+        assert_eq!(modifier.separator, "\n\n");
+        assert!(modifier.skip_empty);
+    }
+
+    #[test]
+    fn test_join_in_place_and_to_output_field() {
+        let modifier = ArrayJoinModifier::new(&json!({"text_field": "segments"})).unwrap();
+        let data = json!({"segments": ["para1", "", null, "para2", 3]});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["segments"], "para1\n\npara2\n\n3");
+
+        let config = json!({"text_field": "segments", "output_field": "text", "separator": " ", "skip_empty": false});
+        let modifier = ArrayJoinModifier::new(&config).unwrap();
+        let result = modifier.process(json!({"segments": ["a", "", "b"]})).unwrap().unwrap();
+        assert_eq!(result["text"], "a  b");
+        // The segments are left alone when writing elsewhere
+        assert_eq!(result["segments"], json!(["a", "", "b"]));
+    }
+
+    #[test]
+    fn test_passthrough_and_errors() {
+        let modifier = ArrayJoinModifier::new(&json!({})).unwrap();
+        let data = json!({"text": "already a string"});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+        let data = json!({"other": ["a"]});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+        assert_eq!(modifier.process(json!({"text": []})).unwrap().unwrap()["text"], "");
+
+        assert!(modifier.process(json!({"text": ["a", ["nested"]]})).is_err());
+        assert!(modifier.process(json!({"text": [{"a": 1}]})).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{madlad_split_sentences, AvgSentenceLengthFilter, DataProcessor};
+use serde_json::json;
+
+
+#[test]
+fn test_defaults() {
+    let filter = AvgSentenceLengthFilter::new(&json!({})).unwrap();
+    assert_eq!(filter.text_field, "text");
+    assert_eq!(filter.lower_bound, 0.0);
+    assert_eq!(filter.upper_bound, f64::MAX);
+    assert_eq!(filter.on_empty, "remove");
+    assert!(AvgSentenceLengthFilter::new(&json!({"lower_bound": 3.0, "upper_bound": 2.0})).is_err());
+    assert!(AvgSentenceLengthFilter::new(&json!({"on_empty": "maybe"})).is_err());
+}
+
+#[test]
+fn test_madlad_splitter() {
+    assert_eq!(
+        madlad_split_sentences("It rained. We stayed in!! Did you? Yes"),
+        vec!["It rained", "We stayed in", "Did you", "Yes"]
+    );
+    // Punctuation not followed by whitespace doesn't split
+    assert_eq!(madlad_split_sentences("Costs $3.50 now."), vec!["Costs $3.50 now."]);
+    assert!(madlad_split_sentences(" \n ").is_empty());
+}
+
+#[test]
+fn test_avg_sentence_length() {
+    // 2 + 4 + 3 words over 3 sentences
+    assert_eq!(
+        AvgSentenceLengthFilter::avg_sentence_length("It rained. We stayed in today. Then we left."),
+        Some(3.0)
+    );
+    assert_eq!(AvgSentenceLengthFilter::avg_sentence_length("no punctuation at all"), Some(4.0));
+    assert_eq!(AvgSentenceLengthFilter::avg_sentence_length(""), None);
+}
+
+#[test]
+fn test_filter_bounds_and_on_empty() {
+    let filter = AvgSentenceLengthFilter::new(&json!({"lower_bound": 3.0, "upper_bound": 10.0})).unwrap();
+    assert!(filter.process(json!({"text": "It rained. We stayed in today. Then we left."})).unwrap().is_some());
+    assert!(filter.process(json!({"text": "Home. About. Contact. Login."})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "word ".repeat(11)})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "   "})).unwrap().is_none());
+    assert!(filter.process(json!({"other": "x"})).is_err());
+
+    let keep_empty = AvgSentenceLengthFilter::new(&json!({"lower_bound": 3.0, "on_empty": "keep"})).unwrap();
+    assert!(keep_empty.process(json!({"text": ""})).unwrap().is_some());
+    assert!(keep_empty.process(json!({"text": "Home. About."})).unwrap().is_none());
+}
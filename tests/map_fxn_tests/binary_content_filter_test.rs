@@ -0,0 +1,83 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{BinaryContentFilter, DataProcessor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const PROSE: &str = "The committee met on Tuesday to discuss the budget. After a long debate, \
+        the members agreed to fund the new library wing (estimated cost: $2.4M) and to revisit \
+        the parking proposal next quarter.\n\nMinutes were recorded by the secretary.";
+
+    fn base64_blob(len: usize) -> String {
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        (0..len).map(|i| alphabet[(i * 7 + i / 3) % alphabet.len()] as char).collect()
+    }
+
+    #[test]
+    fn test_binary_content_filter_new() {
+        let filter = BinaryContentFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.max_non_printable_ratio, 0.1);
+        assert_eq!(filter.max_base64_run, 256);
+        assert_eq!(filter.min_whitespace_ratio, 0.02);
+        assert_eq!(filter.min_whitespace_chars, 1000);
+        assert!(BinaryContentFilter::new(&json!({"max_non_printable_ratio": 1.5})).is_err());
+    }
+
+    #[test]
+    fn test_prose_is_kept() {
+        let filter = BinaryContentFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.binary_reason(PROSE), None);
+        assert!(filter.process(json!({"text": PROSE})).unwrap().is_some());
+        // A long hash or url is nowhere near a blob
+        let text = format!("{} sha256 {} at https://example.com/a/b?c=d", PROSE, "ab12".repeat(16));
+        assert!(filter.process(json!({"text": text})).unwrap().is_some());
+        assert!(filter.process(json!({"text": ""})).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_base64_blob_is_dropped() {
+        let filter = BinaryContentFilter::new(&json!({})).unwrap();
+        let text = format!("{}\n<img src=\"data:image/png;base64,{}\">", PROSE, base64_blob(2000));
+        assert_eq!(filter.binary_reason(&text), Some("base64_run"));
+        assert!(filter.process(json!({"text": text})).unwrap().is_none());
+
+        // The blob also leaves the doc with little whitespace
+        let lenient = BinaryContentFilter::new(&json!({"max_base64_run": 5000})).unwrap();
+        assert_eq!(lenient.binary_reason(&text), Some("whitespace_ratio"));
+        let lenient = BinaryContentFilter::new(&json!({"max_base64_run": 5000, "min_whitespace_ratio": 0.0})).unwrap();
+        assert_eq!(lenient.binary_reason(&text), None);
+    }
+
+    #[test]
+    fn test_non_printable_and_whitespace() {
+        let filter = BinaryContentFilter::new(&json!({"text_field": "body"})).unwrap();
+        let garbage: String = "ELF\u{0}\u{1}\u{2}\u{3}\u{fffd}\u{fffd}ab\u{7f}\u{0}".repeat(20);
+        assert_eq!(filter.binary_reason(&garbage), Some("non_printable_ratio"));
+        assert!(filter.process(json!({"body": garbage})).unwrap().is_none());
+
+        // Long text with (almost) no whitespace, but short base64-ish runs
+        let dense = "a.b,c;d:".repeat(200);
+        assert_eq!(filter.binary_reason(&dense), Some("whitespace_ratio"));
+        assert_eq!(filter.binary_reason(&dense[..400]), None);
+    }
+
+    #[test]
+    fn test_unspaced_scripts_are_not_binary() {
+        // Long Chinese, Japanese and Thai prose has next to no spaces, but it's text
+        let filter = BinaryContentFilter::new(&json!({})).unwrap();
+        let chinese = "委员会于星期二开会讨论预算。经过长时间的辩论，成员们同意为新图书馆扩建项目提供资金。".repeat(40);
+        let japanese = "委員会は火曜日に予算について話し合いました。長い議論の末、図書館の新館に資金を出すことに合意しました。".repeat(30);
+        let thai = "คณะกรรมการประชุมกันในวันอังคารเพื่อหารือเรื่องงบประมาณ".repeat(40);
+        for text in [&chinese, &japanese, &thai] {
+            assert!(text.chars().count() >= 1000);
+            assert_eq!(filter.binary_reason(text), None);
+            assert!(filter.process(json!({"text": text})).unwrap().is_some());
+        }
+        // A base64 blob next to CJK text is still caught
+        let text = format!("{}{}", chinese, base64_blob(2000));
+        assert_eq!(filter.binary_reason(&text), Some("base64_run"));
+    }
+}
@@ -33,6 +33,9 @@ mod tests {
         let filter = BulletFilter::new(&config).unwrap();
         assert_eq!(filter.text_field, "text");
         assert_eq!(filter.max_bullet_ratio, f32::MAX);
+        assert_eq!(filter.min_lines, 0);
+        assert!(!filter.require_low_prose);
+        assert_eq!(filter.min_prose_words_per_line, 5.0);
         
         // Test with custom values
         let config = json!({
@@ -49,6 +52,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Text with bullet ratio below threshold (2/5 = 0.4 < 0.5)
@@ -66,6 +72,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.3,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Text with bullet ratio above threshold (2/5 = 0.4 > 0.3)
@@ -82,6 +91,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Empty text should not cause a division by zero
@@ -103,6 +115,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Text with all bullet points (ratio = 1.0 > 0.5)
@@ -119,6 +134,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Text with no bullet points (ratio = 0.0 < 0.5)
@@ -136,6 +154,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("content"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Using a custom text field
@@ -154,6 +175,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Test with different bullet symbols
@@ -171,6 +195,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Data without the specified text field
@@ -188,6 +215,9 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.5,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         let data = json!({
@@ -202,10 +232,50 @@ mod tests {
         let filter = BulletFilter {
             text_field: String::from("text"),
             max_bullet_ratio: 0.4,
+            min_lines: 0,
+            require_low_prose: false,
+            min_prose_words_per_line: 5.0,
         };
         
         // Ratio is 2/5 = 0.4 = 0.4, so should be some
         let result = filter.process(data).unwrap();
         assert!(result.is_some());
     }
-}
\ No newline at end of file
+
+    const RECIPE: &str = "Classic Pancakes\n\
+Ingredients:\n\
+- 1 cup all-purpose flour\n\
+- 2 tablespoons sugar\n\
+- 1 cup milk\n\
+- 1 large egg\n\
+- 2 tablespoons melted butter\n\
+Whisk the flour and sugar together in a large bowl until there are no lumps left.\n\
+Beat the egg into the milk, then stir in the melted butter and pour it over the dry ingredients.\n\
+Cook ladlefuls of batter on a hot greased pan until bubbles form, then flip and cook the other side.";
+
+    const LINK_FARM: &str = "- Home\n- About us\n- Cheap loans\n- Best casino\n- Contact\n\
+- Free spins\n- Login\n- Sitemap\n- Privacy\nCopyright 2024";
+
+    #[test]
+    fn test_require_low_prose_keeps_recipe_drops_link_farm() {
+        let config = json!({"max_bullet_ratio": 0.3, "require_low_prose": true});
+        let filter = BulletFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"text": RECIPE})).unwrap().is_some());
+        assert!(filter.process(json!({"text": LINK_FARM})).unwrap().is_none());
+
+        // The plain ratio check drops both
+        let filter = BulletFilter::new(&json!({"max_bullet_ratio": 0.3})).unwrap();
+        assert!(filter.process(json!({"text": RECIPE})).unwrap().is_none());
+        assert!(filter.process(json!({"text": LINK_FARM})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_min_lines_skips_short_docs() {
+        let config = json!({"max_bullet_ratio": 0.5, "min_lines": 5});
+        let filter = BulletFilter::new(&config).unwrap();
+        // 4 lines, all bullets, but too short to judge
+        let data = json!({"text": "• Bullet one\n- Bullet two\n* Bullet three\n● Bullet four"});
+        assert!(filter.process(data).unwrap().is_some());
+        assert!(filter.process(json!({"text": LINK_FARM})).unwrap().is_none());
+    }
+}
@@ -0,0 +1,64 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{code_density, CodeDensityAnnotator, CodeDensityFilter, DataProcessor};
+use serde_json::json;
+
+
+const PROSE: &str = "The committee met on Tuesday to discuss the budget.\nMost members agreed, though a few raised concerns.";
+const FENCED: &str = "Here is how:\n```rust\nfn main() {\n    let x = vec![1, 2];\n    println!(\"{:?}\", x);\n}\n```";
+const INDENTED: &str = "Example:\n    if (a == b) { return c[0]; }\n    x = y * 2;\n\tfoo(&bar);";
+
+#[test]
+fn test_prose_scores_low() {
+    let density = code_density(PROSE);
+    assert_eq!(density.code_line_frac, 0.0);
+    assert_eq!(density.symbol_frac, 0.0);
+    assert_eq!(density.score, 0.0);
+    assert_eq!(code_density("").score, 0.0);
+}
+
+#[test]
+fn test_fenced_and_indented_code() {
+    // 6 of the 7 lines are fences or inside the fence
+    let fenced = code_density(FENCED);
+    assert!((fenced.code_line_frac - 6.0 / 7.0).abs() < 1e-9, "{:?}", fenced);
+    assert!(fenced.symbol_frac > 0.1);
+    assert!(fenced.score > 0.7, "{:?}", fenced);
+
+    // Tab and 4-space indents count, the unindented intro line doesn't
+    let indented = code_density(INDENTED);
+    assert_eq!(indented.code_line_frac, 0.75);
+    assert!(indented.score > 0.7, "{:?}", indented);
+
+    // Blank lines don't count either way
+    assert_eq!(code_density("    code();\n\n\nprose here").code_line_frac, 0.5);
+}
+
+#[test]
+fn test_annotator() {
+    let annotator = CodeDensityAnnotator::new(&json!({})).unwrap();
+    let result = annotator.process(json!({"text": INDENTED})).unwrap().unwrap();
+    assert_eq!(result["metadata"]["code_density"], json!(code_density(INDENTED).score));
+    assert_eq!(result["metadata"].as_object().unwrap().len(), 1);
+
+    let annotator = CodeDensityAnnotator::new(&json!({"output_field": "cd", "components_field": "cd_parts"})).unwrap();
+    let result = annotator.process(json!({"text": PROSE})).unwrap().unwrap();
+    assert_eq!(result["cd"], json!(0.0));
+    assert_eq!(result["cd_parts"], json!({"code_line_frac": 0.0, "symbol_frac": 0.0}));
+}
+
+#[test]
+fn test_filter() {
+    let prose_only = CodeDensityFilter::new(&json!({"max_density": 0.3})).unwrap();
+    assert!(prose_only.process(json!({"text": PROSE})).unwrap().is_some());
+    assert!(prose_only.process(json!({"text": FENCED})).unwrap().is_none());
+
+    let code_only = CodeDensityFilter::new(&json!({"min_density": 0.5})).unwrap();
+    assert!(code_only.process(json!({"text": PROSE})).unwrap().is_none());
+    assert!(code_only.process(json!({"text": FENCED})).unwrap().is_some());
+
+    let default = CodeDensityFilter::new(&json!({})).unwrap();
+    assert!(default.process(json!({"text": PROSE})).unwrap().is_some());
+    assert!(default.process(json!({"text": FENCED})).unwrap().is_some());
+
+    assert!(CodeDensityFilter::new(&json!({"min_density": 0.8, "max_density": 0.2})).is_err());
+}
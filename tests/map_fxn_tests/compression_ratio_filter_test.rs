@@ -0,0 +1,66 @@
+extern crate datamap_rs;
+
+use datamap_rs::map_fxn::{CompressionRatioFilter, DataProcessor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    const PROSE: &str = "The committee met on Tuesday to discuss the proposed budget. \
+        Several members raised concerns about infrastructure spending, while others \
+        argued that education deserved a larger share of the funds this year.";
+
+    #[test]
+    fn test_new_with_defaults() {
+        let filter = CompressionRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.lower_bound, 0.0);
+        assert_eq!(filter.upper_bound, f64::MAX);
+        assert_eq!(filter.anno_field, None);
+        assert!(!filter.keep_empty);
+    }
+
+    #[test]
+    fn test_new_bad_bounds() {
+        let config = json!({"lower_bound": 0.8, "upper_bound": 0.2});
+        assert!(CompressionRatioFilter::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_repetitive_text_filtered() {
+        let config = json!({"lower_bound": 0.2, "anno_field": "metadata.compression_ratio"});
+        let filter = CompressionRatioFilter::new(&config).unwrap();
+
+        let repetitive = "buy now ".repeat(500);
+        assert!(filter.process(create_doc(&repetitive)).unwrap().is_none());
+
+        let result = filter.process(create_doc(PROSE)).unwrap().unwrap();
+        let ratio = result["metadata"]["compression_ratio"].as_f64().unwrap();
+        assert!(ratio >= 0.2 && ratio <= 1.5);
+    }
+
+    #[test]
+    fn test_upper_bound() {
+        let config = json!({"upper_bound": 0.1});
+        let filter = CompressionRatioFilter::new(&config).unwrap();
+        assert!(filter.process(create_doc(PROSE)).unwrap().is_none());
+        let repetitive = "a".repeat(10_000);
+        assert!(filter.process(create_doc(&repetitive)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_empty_text() {
+        let filter = CompressionRatioFilter::new(&json!({"anno_field": "ratio"})).unwrap();
+        assert!(filter.process(create_doc("")).unwrap().is_none());
+
+        let config = json!({"anno_field": "ratio", "keep_empty": true});
+        let filter = CompressionRatioFilter::new(&config).unwrap();
+        let result = filter.process(create_doc("")).unwrap().unwrap();
+        assert_eq!(result["ratio"], Value::Null);
+    }
+}
@@ -0,0 +1,61 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{ConcatFieldsModifier, DataProcessor};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_concat_fields_modifier_new() {
+        let modifier = ConcatFieldsModifier::new(&json!({"source_fields": ["title", "abstract"]})).unwrap();
+        assert_eq!(modifier.separator, "\n\n");
+        assert_eq!(modifier.output_field, "text");
+        assert_eq!(modifier.labels, None);
+        assert!(modifier.skip_empty);
+
+        assert!(ConcatFieldsModifier::new(&json!({})).is_err());
+        assert!(ConcatFieldsModifier::new(&json!({"source_fields": []})).is_err());
+        assert!(ConcatFieldsModifier::new(&json!({"source_fields": ["title", 3]})).is_err());
+        assert!(ConcatFieldsModifier::new(&json!({"source_fields": ["title", "body"], "labels": ["Title"]})).is_err());
+    }
+
+    #[test]
+    fn test_concat_skips_missing() {
+        let modifier = ConcatFieldsModifier::new(&json!({
+            "source_fields": ["title", "meta.abstract", "body", "footer"], "separator": " | "
+        }))
+        .unwrap();
+        let data = json!({"title": "A title", "meta": {"abstract": ""}, "body": "The body", "footer": null});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["text"], "A title | The body");
+        assert_eq!(result["title"], "A title");
+
+        let modifier = ConcatFieldsModifier::new(&json!({
+            "source_fields": ["title", "meta.abstract"], "separator": " | ", "skip_empty": false
+        }))
+        .unwrap();
+        let result = modifier.process(json!({"title": "A title", "meta": {"abstract": ""}})).unwrap().unwrap();
+        assert_eq!(result["text"], "A title | ");
+
+        // Nothing to join still writes the output field
+        let result = modifier.process(json!({"id": 1})).unwrap().unwrap();
+        assert_eq!(result["text"], "");
+    }
+
+    #[test]
+    fn test_concat_labels_and_scalars() {
+        let modifier = ConcatFieldsModifier::new(&json!({
+            "source_fields": ["title", "year", "body"],
+            "labels": ["Title", "Year", "Body"],
+            "separator": "\n",
+            "output_field": "meta.joined"
+        }))
+        .unwrap();
+        let result = modifier.process(json!({"title": "T", "year": 2024, "body": "B"})).unwrap().unwrap();
+        assert_eq!(result["meta"]["joined"], "Title: T\nYear: 2024\nBody: B");
+
+        let result = modifier.process(json!({"title": "T", "year": [2024]})).unwrap_err();
+        assert!(result.to_string().contains("year"));
+    }
+}
@@ -0,0 +1,71 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, DictionaryWordRatioFilter};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+
+fn dictionary_file(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("datamap_dictionary_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("words.txt");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn make_filter(name: &str, extra: Value) -> DictionaryWordRatioFilter {
+    let path = dictionary_file(name, "the\ncat\nSat\n\n on \nmat\n");
+    let mut config = json!({"dictionary_file": path.to_str().unwrap()});
+    for (k, v) in extra.as_object().unwrap() {
+        config[k] = v.clone();
+    }
+    DictionaryWordRatioFilter::new(&config).unwrap()
+}
+
+#[test]
+fn test_loads_dictionary() {
+    let filter = make_filter("load", json!({}));
+    assert_eq!(filter.dictionary.len(), 5);
+    assert!(filter.dictionary.contains("sat") && filter.dictionary.contains("on"));
+    assert_eq!(filter.min_ratio, 0.0);
+    assert_eq!(filter.max_ratio, 1.0);
+    assert_eq!(filter.min_words, 0);
+}
+
+#[test]
+fn test_dictionary_counts() {
+    let filter = make_filter("counts", json!({}));
+    assert_eq!(filter.dictionary_counts("The cat sat on the mat."), (6, 6));
+    assert_eq!(filter.dictionary_counts("THE CAT xqzt vvbn"), (2, 4));
+    assert_eq!(filter.dictionary_counts("   ...  "), (0, 0));
+}
+
+#[test]
+fn test_ratio_bounds() {
+    let filter = make_filter("bounds", json!({"min_ratio": 0.5}));
+    assert!(filter.process(json!({"text": "the cat sat on the mat"})).unwrap().is_some());
+    assert!(filter.process(json!({"text": "the cat qwrt zxvb"})).unwrap().is_some());
+    assert!(filter.process(json!({"text": "the qwrt zxvb lkjh"})).unwrap().is_none());
+    assert!(filter.process(json!({"text": ""})).unwrap().is_none());
+
+    let filter = make_filter("upper", json!({"max_ratio": 0.5}));
+    assert!(filter.process(json!({"text": "the cat sat on the mat"})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "the cat qwrt zxvb"})).unwrap().is_some());
+}
+
+#[test]
+fn test_min_words() {
+    let filter = make_filter("min_words", json!({"min_words": 4}));
+    assert!(filter.process(json!({"text": "the cat sat"})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "the cat sat on"})).unwrap().is_some());
+}
+
+#[test]
+fn test_invalid_configs() {
+    assert!(DictionaryWordRatioFilter::new(&json!({})).is_err());
+    assert!(DictionaryWordRatioFilter::new(&json!({"dictionary_file": "/nonexistent/words.txt"})).is_err());
+    let empty = dictionary_file("empty", "\n  \n");
+    assert!(DictionaryWordRatioFilter::new(&json!({"dictionary_file": empty.to_str().unwrap()})).is_err());
+    let path = dictionary_file("inverted", "word\n");
+    assert!(DictionaryWordRatioFilter::new(&json!({"dictionary_file": path.to_str().unwrap(), "min_ratio": 0.8, "max_ratio": 0.2})).is_err());
+}
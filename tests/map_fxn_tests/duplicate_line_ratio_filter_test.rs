@@ -0,0 +1,66 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, DuplicateLineRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    #[test]
+    fn test_duplicate_line_ratio_filter_creation() {
+        let filter = DuplicateLineRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.max_ratio, 1.0);
+        assert!(!filter.count_chars);
+
+        let config = json!({"text_field": "content", "max_ratio": 0.3, "count_chars": true});
+        let filter = DuplicateLineRatioFilter::new(&config).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.max_ratio, 0.3);
+        assert!(filter.count_chars);
+
+        assert!(DuplicateLineRatioFilter::new(&json!({"max_ratio": 1.5})).is_err());
+    }
+
+    #[test]
+    fn test_line_ratio() {
+        let filter = DuplicateLineRatioFilter::new(&json!({"max_ratio": 0.3})).unwrap();
+        // 5 lines, "a" repeats twice after its first occurrence => 2/5
+        let text = "a\nb\na\nc\na";
+        assert_eq!(filter.duplicate_line_ratio(text), 0.4);
+        assert!(filter.process(create_test_doc(text)).unwrap().is_none());
+
+        // 4 lines, one repeat => 1/4; blank lines are ignored
+        let text = "a\n\nb\n\nc\n\nb";
+        assert_eq!(filter.duplicate_line_ratio(text), 0.25);
+        assert!(filter.process(create_test_doc(text)).unwrap().is_some());
+
+        // Exact match only: trailing whitespace makes a different line
+        assert_eq!(filter.duplicate_line_ratio("a\na \nb"), 0.0);
+    }
+
+    #[test]
+    fn test_char_ratio() {
+        let filter = DuplicateLineRatioFilter::new(&json!({"max_ratio": 0.3, "count_chars": true})).unwrap();
+        // Lines of 1 + 9 + 1 chars, the 1-char line repeats => 1/11
+        let text = "a\nlong line\na";
+        assert_eq!(filter.duplicate_line_ratio(text), 1.0 / 11.0);
+        assert!(filter.process(create_test_doc(text)).unwrap().is_some());
+
+        // Same line structure in line mode => 1/3
+        let line_filter = DuplicateLineRatioFilter::new(&json!({"max_ratio": 0.3})).unwrap();
+        assert!(line_filter.process(create_test_doc(text)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_empty_and_missing() {
+        let filter = DuplicateLineRatioFilter::new(&json!({"max_ratio": 0.0})).unwrap();
+        assert!(filter.process(create_test_doc("")).unwrap().is_some());
+        assert!(filter.process(create_test_doc("\n\n\n")).unwrap().is_some());
+        assert!(filter.process(json!({"content": "a"})).is_err());
+    }
+}
@@ -112,6 +112,50 @@ mod tests {
     }
     
     
+    #[test]
+    fn test_head_text_limits() {
+        let config = json!({
+            "fast_text_file": "ft_classifiers/lid176.bin",
+            "max_words": 3,
+            "max_chars": 50
+        });
+        let annotator = FastTextAnnotator::new(&config).unwrap();
+        assert_eq!(annotator.max_words, 3);
+        assert_eq!(annotator.max_chars, 50);
+        assert_eq!(annotator.max_text_length, 0);
+
+        // Only the head is scored, but the stored text is left intact
+        let text = "This is English text. ".repeat(1000);
+        let data = json!({"text": text.clone(), "metadata": {}});
+        let processed = annotator.process(data).unwrap().unwrap();
+        assert_eq!(processed["text"], text);
+        assert!(processed.pointer("/metadata/fasttext").unwrap().is_object());
+    }
+
+    #[test]
+    fn test_on_error() {
+        // An embedded NUL byte can't be handed to fasttext, so prediction fails
+        let data = json!({"text": "Bad \u{0000} bytes", "metadata": {}});
+        let annotator_for = |on_error: &str| {
+            FastTextAnnotator::new(&json!({"fast_text_file": "ft_classifiers/lid176.bin", "on_error": on_error})).unwrap()
+        };
+
+        let default = FastTextAnnotator::new(&create_basic_config("ft_classifiers/lid176.bin")).unwrap();
+        assert_eq!(default.on_error, "drop");
+        assert_eq!(default.process(data.clone()).unwrap(), None);
+
+        assert_eq!(annotator_for("keep").process(data.clone()).unwrap(), Some(data.clone()));
+
+        let processed = annotator_for("annotate_empty").process(data.clone()).unwrap().unwrap();
+        assert_eq!(processed["metadata"]["fasttext"], json!({}));
+        assert_eq!(processed["text"], data["text"]);
+
+        assert!(annotator_for("route_err").process(data.clone()).is_err());
+
+        let config = json!({"fast_text_file": "ft_classifiers/lid176.bin", "on_error": "explode"});
+        assert!(FastTextAnnotator::new(&config).is_err());
+    }
+
     #[test]
     fn test_process_empty_text() {
         let config = create_basic_config("ft_classifiers/lid176.bin");
@@ -0,0 +1,81 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, FixEncodingModifier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn default_modifier() -> FixEncodingModifier {
+        FixEncodingModifier::new(&json!({})).unwrap()
+    }
+
+    #[test]
+    fn test_fix_encoding_modifier_creation() {
+        let modifier = default_modifier();
+        assert_eq!(modifier.text_field, "text");
+        assert!(modifier.fix_mojibake);
+        assert!(modifier.fix_c1_controls);
+        assert!(modifier.remove_bom);
+        assert!(!modifier.fix_line_breaks);
+
+        let config = json!({"text_field": "content", "fix_mojibake": false, "fix_line_breaks": true});
+        let modifier = FixEncodingModifier::new(&config).unwrap();
+        assert_eq!(modifier.text_field, "content");
+        assert!(!modifier.fix_mojibake);
+        assert!(modifier.fix_line_breaks);
+    }
+
+    #[test]
+    fn test_classic_mojibake() {
+        let modifier = default_modifier();
+        assert_eq!(modifier.fix_text("It\u{e2}\u{20ac}\u{2122}s"), "It’s"); // "Itâ€™s"
+        assert_eq!(modifier.fix_text("cafÃ©"), "café");
+        assert_eq!(modifier.fix_text("â€œquotedâ€\u{9d}"), "“quoted”");
+        assert_eq!(modifier.fix_text("naÃ¯ve rÃ©sumÃ©"), "naïve résumé");
+        assert_eq!(modifier.fix_text("ðŸ˜€"), "😀");
+        // Decoded as Latin-1 rather than Windows-1252
+        assert_eq!(modifier.fix_text("It\u{e2}\u{80}\u{99}s"), "It’s");
+        // Double encoded
+        assert_eq!(modifier.fix_text("cafÃƒÂ©"), "café");
+    }
+
+    #[test]
+    fn test_clean_text_untouched_and_idempotent() {
+        let modifier = default_modifier();
+        let clean = [
+            "Plain ASCII text.",
+            "café naïve résumé – “quotes” and ’apostrophes’ …",
+            "Ünïcödé: 日本語, Ελληνικά, 😀",
+            "Prices: 5€, £3, ©2024, ±1°",
+        ];
+        for text in clean {
+            assert_eq!(modifier.fix_text(text), text);
+        }
+
+        let once = modifier.fix_text("Itâ€™s cafÃ© time");
+        assert_eq!(modifier.fix_text(&once), once);
+    }
+
+    #[test]
+    fn test_other_fixes() {
+        let modifier = FixEncodingModifier::new(&json!({"fix_line_breaks": true})).unwrap();
+        assert_eq!(modifier.fix_text("\u{feff}Hello"), "Hello");
+        assert_eq!(modifier.fix_text("a\u{93}b\u{94}"), "a“b”");
+        assert_eq!(modifier.fix_text("a\r\nb\rc\u{2028}d"), "a\nb\nc\nd");
+
+        let modifier = FixEncodingModifier::new(&json!({"fix_mojibake": false, "fix_c1_controls": false, "remove_bom": false})).unwrap();
+        assert_eq!(modifier.fix_text("\u{feff}cafÃ©"), "\u{feff}cafÃ©");
+    }
+
+    #[test]
+    fn test_process() {
+        let modifier = default_modifier();
+        let data = json!({"text": "donâ€™t", "id": 1});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["text"], "don’t");
+        assert_eq!(result["id"], 1);
+
+        assert!(modifier.process(json!({"content": "x"})).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+extern crate datamap_rs;
+use serde_json::json;
+use datamap_rs::map_fxn::{DataProcessor, HashAnnotator};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+
+#[test]
+fn test_default_is_pinned_xxh3_128() {
+    let annotator = HashAnnotator::new(&json!({})).unwrap();
+    assert_eq!(annotator.hash_algo, "xxh3");
+    let result = annotator.process(json!({"text": "hello"})).unwrap().unwrap();
+    let expected = xxhash_rust::xxh3::xxh3_128(b"hello").to_string();
+    assert_eq!(result["metadata"]["text_hash"], json!(expected));
+}
+
+#[test]
+fn test_xxh3_64() {
+    let annotator = HashAnnotator::new(&json!({"num_bits": 64, "hash_destination": "h"})).unwrap();
+    let result = annotator.process(json!({"text": "hello"})).unwrap().unwrap();
+    assert_eq!(result["h"], json!(xxhash_rust::xxh3::xxh3_64(b"hello")));
+}
+
+#[test]
+fn test_ahash_matches_legacy_group_hash() {
+    let annotator = HashAnnotator::new(&json!({"num_bits": 64, "hash_algo": "ahash", "hash_destination": "h"})).unwrap();
+    let result = annotator.process(json!({"text": "hello"})).unwrap().unwrap();
+    let mut hasher = ahash::RandomState::with_seeds(1, 2, 3, 4).build_hasher();
+    "hello".hash(&mut hasher);
+    assert_eq!(result["h"], json!(hasher.finish()));
+}
+
+#[test]
+fn test_invalid_configs() {
+    assert!(HashAnnotator::new(&json!({"hash_algo": "md5"})).is_err());
+    // ahash is 64 bit only
+    assert!(HashAnnotator::new(&json!({"hash_algo": "ahash"})).is_err());
+}
+
+#[test]
+fn test_multi_field_hash() {
+    let annotator = HashAnnotator::new(&json!({"hash_source": ["url", "title"], "num_bits": 64, "hash_destination": "h"})).unwrap();
+    let doc = json!({"url": "https://a.com", "title": "A", "text": "body"});
+    let result = annotator.process(doc.clone()).unwrap().unwrap();
+    assert_eq!(result["h"], json!(xxhash_rust::xxh3::xxh3_64("https://a.com\u{1f}A".as_bytes())));
+    // Stable across runs and instances
+    let again = HashAnnotator::new(&json!({"hash_source": ["url", "title"], "num_bits": 64, "hash_destination": "h"})).unwrap();
+    assert_eq!(again.process(doc.clone()).unwrap().unwrap()["h"], result["h"]);
+
+    // Order sensitive
+    let reversed = HashAnnotator::new(&json!({"hash_source": ["title", "url"], "num_bits": 64, "hash_destination": "h"})).unwrap();
+    assert_ne!(reversed.process(doc.clone()).unwrap().unwrap()["h"], result["h"]);
+
+    // The separator keeps shifted boundaries apart; missing fields hash as empty
+    let annotator = HashAnnotator::new(&json!({"hash_source": ["a", "b"]})).unwrap();
+    let h1 = annotator.process(json!({"a": "xy", "b": "z"})).unwrap().unwrap();
+    let h2 = annotator.process(json!({"a": "x", "b": "yz"})).unwrap().unwrap();
+    assert_ne!(h1["metadata"]["text_hash"], h2["metadata"]["text_hash"]);
+    let missing = annotator.process(json!({"a": "x"})).unwrap().unwrap();
+    let empty = annotator.process(json!({"a": "x", "b": ""})).unwrap().unwrap();
+    assert_eq!(missing["metadata"]["text_hash"], empty["metadata"]["text_hash"]);
+}
+
+#[test]
+fn test_single_field_list_matches_string() {
+    let doc = json!({"url": "https://a.com"});
+    let as_str = HashAnnotator::new(&json!({"hash_source": "url"})).unwrap();
+    let as_list = HashAnnotator::new(&json!({"hash_source": ["url"]})).unwrap();
+    assert_eq!(as_str.process(doc.clone()).unwrap().unwrap(), as_list.process(doc).unwrap().unwrap());
+    assert!(HashAnnotator::new(&json!({"hash_source": []})).is_err());
+    assert!(HashAnnotator::new(&json!({"hash_source": ["url", 1]})).is_err());
+}
+
+#[test]
+fn test_normalize_for_hash() {
+    let doc_a = json!({"text": "Hello   world,\n\nthis is  a doc. "});
+    let doc_b = json!({"text": "hello world, this is a\tdoc."});
+
+    let plain = HashAnnotator::new(&json!({})).unwrap();
+    let a = plain.process(doc_a.clone()).unwrap().unwrap();
+    let b = plain.process(doc_b.clone()).unwrap().unwrap();
+    assert_ne!(a["metadata"]["text_hash"], b["metadata"]["text_hash"]);
+
+    let normalized = HashAnnotator::new(&json!({"normalize_for_hash": true})).unwrap();
+    let a = normalized.process(doc_a.clone()).unwrap().unwrap();
+    let b = normalized.process(doc_b.clone()).unwrap().unwrap();
+    assert_eq!(a["metadata"]["text_hash"], b["metadata"]["text_hash"]);
+    let expected = xxhash_rust::xxh3::xxh3_128(b"hello world, this is a doc.").to_string();
+    assert_eq!(a["metadata"]["text_hash"], json!(expected));
+    // Only the hash sees the normalized text
+    assert_eq!(a["text"], doc_a["text"]);
+    assert_eq!(b["text"], doc_b["text"]);
+}
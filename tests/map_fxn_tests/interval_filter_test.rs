@@ -0,0 +1,109 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, IntervalFilter};
+use serde_json::{json, Value};
+
+
+fn run(config: Value, data: Value) -> Option<Value> {
+    let mut config = config;
+    config["text_field"] = json!("text");
+    config["interval_field"] = json!("spans");
+    IntervalFilter::new(&config).unwrap().process(data).unwrap()
+}
+
+#[test]
+fn test_defaults() {
+    let filter = IntervalFilter::new(&json!({"interval_field": "spans"})).unwrap();
+    assert!(!filter.invert);
+    assert_eq!(filter.separator, "\n");
+}
+
+#[test]
+fn test_scrub_and_keep() {
+    let data = json!({"text": "0123456789", "spans": [[1, 3], [6, 8]]});
+    let scrubbed = run(json!({}), data.clone()).unwrap();
+    assert_eq!(scrubbed["text"], "034589");
+
+    let kept = run(json!({"invert": true, "separator": "|"}), data.clone()).unwrap();
+    assert_eq!(kept["text"], "12|67");
+
+    let kept = run(json!({"invert": true, "output_text_field": "spans_text"}), data).unwrap();
+    assert_eq!(kept["spans_text"], "12\n67");
+    assert_eq!(kept["text"], "0123456789");
+}
+
+#[test]
+fn test_overlapping_and_unsorted_intervals() {
+    // [6, 9] comes first, [1, 4] and [2, 5] overlap: both modes see [1, 5] and [6, 9]
+    let data = json!({"text": "0123456789", "spans": [[6, 9], [1, 4], [2, 5]]});
+    assert_eq!(run(json!({}), data.clone()).unwrap()["text"], "059");
+    assert_eq!(run(json!({"invert": true, "separator": " "}), data).unwrap()["text"], "1234 678");
+}
+
+#[test]
+fn test_fuzzy_merge_applies_to_both_modes() {
+    // The 1-char gap between the spans is sandwiched by 4-char spans, so it gets merged in
+    let data = json!({"text": "aaaa-bbbb rest", "spans": [[0, 4], [5, 9]]});
+    let config = json!({"fuzzy_merge": true, "merge_fuzziness": 0.5});
+    assert_eq!(run(config.clone(), data.clone()).unwrap()["text"], " rest");
+    let mut inverted = config;
+    inverted["invert"] = json!(true);
+    assert_eq!(run(inverted, data).unwrap()["text"], "aaaa-bbbb");
+}
+
+#[test]
+fn test_empty_output_removes_doc() {
+    assert!(run(json!({}), json!({"text": "abc", "spans": [[0, 3]]})).is_none());
+    assert!(run(json!({"invert": true}), json!({"text": "abc", "spans": []})).is_none());
+    // No intervals field: doc is left as is
+    assert_eq!(run(json!({"invert": true}), json!({"text": "abc"})).unwrap()["text"], "abc");
+}
+
+#[test]
+fn test_bad_intervals_are_clamped() {
+    // "中" spans bytes 1..4: offsets inside it widen to the whole char instead of panicking
+    let data = json!({"text": "a中bc", "spans": [[2, 3]]});
+    assert_eq!(run(json!({}), data.clone()).unwrap()["text"], "abc");
+    assert_eq!(run(json!({"invert": true}), data).unwrap()["text"], "中");
+
+    let data = json!({"text": "a中bc", "spans": [[0, 2], [5, 6]]});
+    assert_eq!(run(json!({}), data.clone()).unwrap()["text"], "b");
+    assert_eq!(run(json!({"invert": true, "separator": "|"}), data).unwrap()["text"], "a中|c");
+
+    // Past the end of the text is clamped, reversed and empty intervals are ignored
+    let data = json!({"text": "0123456789", "spans": [[8, 100], [5, 3], [4, 4], [50, 60]]});
+    assert_eq!(run(json!({}), data.clone()).unwrap()["text"], "01234567");
+    assert_eq!(run(json!({"invert": true}), data).unwrap()["text"], "89");
+}
+
+#[test]
+fn test_on_invalid_interval_policies() {
+    // [2, 3] lands mid-codepoint, [8, 100] runs past the end; [0, 1] is fine
+    let data = json!({"text": "a中bcdefg", "spans": [[0, 1], [2, 3], [8, 100]]});
+
+    let skipped = run(json!({"on_invalid_interval": "skip_interval"}), data.clone()).unwrap();
+    assert_eq!(skipped["text"], "中bcdefg");
+
+    assert!(run(json!({"on_invalid_interval": "drop_doc"}), data.clone()).is_none());
+
+    let mut config = json!({"on_invalid_interval": "error"});
+    config["text_field"] = json!("text");
+    config["interval_field"] = json!("spans");
+    let filter = IntervalFilter::new(&config).unwrap();
+    assert!(filter.process(data.clone()).is_err());
+    // Valid intervals pass through every policy untouched
+    let ok = filter.process(json!({"text": "a中bcdefg", "spans": [[0, 1], [1, 4]]})).unwrap().unwrap();
+    assert_eq!(ok["text"], "bcdefg");
+
+    // Default repair widens/clamps instead
+    assert_eq!(run(json!({}), data).unwrap()["text"], "bcde");
+
+    assert!(IntervalFilter::new(&json!({"interval_field": "spans", "on_invalid_interval": "panic"})).is_err());
+}
+
+#[test]
+fn test_malformed_intervals_do_not_panic() {
+    let data = json!({"text": "0123456789", "spans": [[1, 3], [-1, 4], ["a", 5], [7], 9]});
+    assert_eq!(run(json!({}), data.clone()).unwrap()["text"], "03456789");
+    assert_eq!(run(json!({"on_invalid_interval": "skip_interval"}), data.clone()).unwrap()["text"], "03456789");
+    assert!(run(json!({"on_invalid_interval": "drop_doc"}), data).is_none());
+}
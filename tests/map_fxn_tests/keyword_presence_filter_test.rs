@@ -0,0 +1,71 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, KeywordPresenceFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+
+    #[test]
+    fn test_keyword_presence_filter_new() {
+        let filter = KeywordPresenceFilter::new(&json!({"keywords": ["Heart", "heart", " lung ", ""]})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.min_keywords, 1);
+        assert!(!filter.case_sensitive);
+        assert!(!filter.match_substrings);
+        assert_eq!(filter.keywords, vec!["heart", "lung"]);
+
+        assert!(KeywordPresenceFilter::new(&json!({})).is_err());
+        assert!(KeywordPresenceFilter::new(&json!({"keywords": []})).is_err());
+        assert!(KeywordPresenceFilter::new(&json!({"keywords": ["heart", 1]})).is_err());
+        assert!(KeywordPresenceFilter::new(&json!({"keywords": ["heart"], "min_keywords": 2})).is_err());
+        assert!(KeywordPresenceFilter::new(&json!({"keywords": ["heart"], "min_keywords": 0})).is_err());
+    }
+
+    #[test]
+    fn test_distinct_keywords() {
+        let filter = KeywordPresenceFilter::new(&json!({
+            "keywords": ["heart", "heart attack", "lung", "kidney"], "min_keywords": 2
+        }))
+        .unwrap();
+        // Repeats of one keyword count once
+        assert_eq!(filter.count_keywords("heart heart HEART"), 1);
+        assert!(filter.process(json!({"text": "Heart, heart and more heart."})).unwrap().is_none());
+        // Overlapping keywords are both found
+        assert_eq!(filter.count_keywords("a heart attack"), 2);
+        assert!(filter.process(json!({"text": "The LUNG and the Kidney."})).unwrap().is_some());
+        // Missing text has no keywords
+        assert!(filter.process(json!({"id": 1})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_word_boundaries_and_case() {
+        let filter = KeywordPresenceFilter::new(&json!({"keywords": ["cardio"]})).unwrap();
+        assert!(filter.process(json!({"text": "cardiology dept"})).unwrap().is_none());
+        assert!(filter.process(json!({"text": "(cardio) class"})).unwrap().is_some());
+
+        let filter = KeywordPresenceFilter::new(&json!({"keywords": ["cardio"], "match_substrings": true})).unwrap();
+        assert!(filter.process(json!({"text": "cardiology dept"})).unwrap().is_some());
+
+        let filter = KeywordPresenceFilter::new(&json!({"keywords": ["DNA"], "case_sensitive": true})).unwrap();
+        assert!(filter.process(json!({"text": "dna sample"})).unwrap().is_none());
+        assert!(filter.process(json!({"text": "DNA sample"})).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_keywords_file() {
+        let dir = std::env::temp_dir().join("datamap_keyword_presence_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keywords.txt");
+        fs::write(&path, "aorta\n\nartery\n").unwrap();
+        let filter = KeywordPresenceFilter::new(&json!({
+            "keywords_file": path.to_str().unwrap(), "keywords": ["vein"], "min_keywords": 3, "text_field": "body"
+        }))
+        .unwrap();
+        assert_eq!(filter.keywords.len(), 3);
+        assert!(filter.process(json!({"body": "aorta, artery and vein"})).unwrap().is_some());
+        assert!(filter.process(json!({"body": "aorta and artery"})).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
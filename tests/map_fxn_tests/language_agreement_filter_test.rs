@@ -0,0 +1,84 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{fasttext_top1, DataProcessor, LanguageAgreementFilter};
+use serde_json::json;
+
+
+#[test]
+fn test_languages_agree() {
+    assert!(LanguageAgreementFilter::languages_agree("en", "__label__en"));
+    assert!(LanguageAgreementFilter::languages_agree(" EN ", "__label__en"));
+    assert!(LanguageAgreementFilter::languages_agree("__label__de", "__label__de"));
+    assert!(!LanguageAgreementFilter::languages_agree("en", "__label__de"));
+    assert!(!LanguageAgreementFilter::languages_agree("en", "__label__eng"));
+}
+
+#[test]
+fn test_config_errors() {
+    // Options are validated before the model is loaded, so these fail on the option itself
+    let err = LanguageAgreementFilter::new(&json!({})).unwrap_err();
+    assert!(err.to_string().contains("fast_text_file"));
+    let err = LanguageAgreementFilter::new(&json!({"fast_text_file": "missing.bin", "on_missing": "maybe"})).unwrap_err();
+    assert!(err.to_string().contains("on_missing"));
+    let err = LanguageAgreementFilter::new(&json!({"fast_text_file": "missing.bin", "threshold": 1.5})).unwrap_err();
+    assert!(err.to_string().contains("threshold"));
+    assert!(LanguageAgreementFilter::new(&json!({"fast_text_file": "missing.bin"})).is_err());
+}
+
+fn lid_filter(extra: serde_json::Value) -> LanguageAgreementFilter {
+    let mut config = json!({"fast_text_file": "ft_classifiers/lid176.bin"});
+    config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+    LanguageAgreementFilter::new(&config).unwrap()
+}
+
+const ENGLISH: &str = "The quick brown fox jumps over the lazy dog, and then it runs back into the forest to sleep.";
+const GERMAN: &str = "Der schnelle braune Fuchs springt über den faulen Hund und läuft dann zurück in den Wald, um zu schlafen.";
+
+#[test]
+fn test_process_keeps_agreeing_languages() {
+    let filter = lid_filter(json!({}));
+    let data = json!({"text": ENGLISH, "metadata": {"language": "EN"}});
+    assert_eq!(filter.process(data.clone()).unwrap(), Some(data));
+    let data = json!({"text": GERMAN, "metadata": {"language": "__label__de"}});
+    assert_eq!(filter.process(data.clone()).unwrap(), Some(data));
+}
+
+#[test]
+fn test_process_removes_confident_mismatch() {
+    let filter = lid_filter(json!({}));
+    let data = json!({"text": GERMAN, "metadata": {"language": "en"}});
+    assert_eq!(filter.process(data).unwrap(), None);
+
+    // Custom fields
+    let filter = lid_filter(json!({"text_field": "body", "declared_lang_field": "lang"}));
+    assert_eq!(filter.process(json!({"body": ENGLISH, "lang": "fr"})).unwrap(), None);
+    let data = json!({"body": ENGLISH, "lang": "en"});
+    assert_eq!(filter.process(data.clone()).unwrap(), Some(data));
+}
+
+#[test]
+fn test_process_threshold() {
+    // A short, ambiguous text: the mismatch is only acted on if the prediction is at least threshold
+    let text = "ja gut ok";
+    let filter = lid_filter(json!({}));
+    let (predicted, prob) = fasttext_top1(&filter.model, text).unwrap().unwrap();
+    assert!(prob < 0.99);
+    let other = if LanguageAgreementFilter::languages_agree("fr", &predicted) { "es" } else { "fr" };
+    let data = json!({"text": text, "metadata": {"language": other}});
+
+    let below = lid_filter(json!({"threshold": prob - 0.01}));
+    assert_eq!(below.process(data.clone()).unwrap(), None);
+    let above = lid_filter(json!({"threshold": prob + 0.01}));
+    assert_eq!(above.process(data.clone()).unwrap(), Some(data));
+}
+
+#[test]
+fn test_process_missing_declared_language() {
+    let data = json!({"text": GERMAN, "metadata": {}});
+    let blank = json!({"text": GERMAN, "metadata": {"language": "  "}});
+    let keep = lid_filter(json!({}));
+    assert_eq!(keep.process(data.clone()).unwrap(), Some(data.clone()));
+    assert_eq!(keep.process(blank.clone()).unwrap(), Some(blank.clone()));
+    let remove = lid_filter(json!({"on_missing": "remove"}));
+    assert_eq!(remove.process(data).unwrap(), None);
+    assert_eq!(remove.process(blank).unwrap(), None);
+}
@@ -0,0 +1,64 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, Madlad400RuleFilter};
+use serde_json::{json, Value};
+
+
+fn doc(num_sentences: usize, rule_1: Vec<usize>) -> Value {
+    json!({
+        "text": "...",
+        "metadata": {
+            "madlad_status": "survived",
+            "madlad": {"num_sentences": [num_sentences], "rule.1": rule_1},
+        }
+    })
+}
+
+fn kept(filter: &Madlad400RuleFilter, num_sentences: usize, num_sus: usize) -> bool {
+    filter.process(doc(num_sentences, (0..num_sus).collect())).unwrap().is_some()
+}
+
+#[test]
+fn test_proportional_threshold_by_default() {
+    let filter = Madlad400RuleFilter::new(&json!({"rules_to_remove": [[1]]})).unwrap();
+    assert_eq!(filter.max_sus_fraction, None);
+    assert_eq!(filter.min_sus_sentences, None);
+    assert!(kept(&filter, 10, 1));
+    assert!(!kept(&filter, 10, 2)); // 2 >= 0.2 * 10
+    assert!(kept(&filter, 1000, 150));
+}
+
+#[test]
+fn test_min_sus_sentences_catches_long_docs() {
+    let filter = Madlad400RuleFilter::new(&json!({"rules_to_remove": [[1]], "min_sus_sentences": 50})).unwrap();
+    // 5% of a long doc is still 50 bad sentences
+    assert!(!kept(&filter, 1000, 50));
+    assert!(kept(&filter, 1000, 49));
+    // The proportional threshold still applies too
+    assert!(!kept(&filter, 10, 2));
+}
+
+#[test]
+fn test_max_sus_fraction_on_short_docs() {
+    let filter = Madlad400RuleFilter::new(&json!({"rules_to_remove": [[1]], "max_sus_fraction": 0.5})).unwrap();
+    // Strictly above the fraction: 1 of 2 sentences is fine, where threshold: 0.5 would remove it
+    assert!(kept(&filter, 2, 1));
+    assert!(!kept(&filter, 2, 2));
+    assert!(kept(&filter, 1000, 500));
+    assert!(!kept(&filter, 1000, 501));
+
+    // Either condition removes the doc
+    let both = Madlad400RuleFilter::new(
+        &json!({"rules_to_remove": [[1]], "max_sus_fraction": 0.5, "min_sus_sentences": 3}),
+    )
+    .unwrap();
+    assert!(kept(&both, 4, 2));
+    assert!(!kept(&both, 4, 3));
+    assert!(!kept(&both, 3, 2));
+}
+
+#[test]
+fn test_invalid_configs() {
+    assert!(Madlad400RuleFilter::new(&json!({"threshold": 0.3, "max_sus_fraction": 0.5})).is_err());
+    assert!(Madlad400RuleFilter::new(&json!({"max_sus_fraction": 1.5})).is_err());
+    assert!(Madlad400RuleFilter::new(&json!({"min_sus_sentences": 0})).is_err());
+}
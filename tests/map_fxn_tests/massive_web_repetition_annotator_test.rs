@@ -0,0 +1,77 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{
+    DataProcessor, MassiveWebRepetitionAnnotator, MassiveWebRepetitionFilter, MASSIVE_WEB_REPETITION_CHECKS,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const CLEAN_TEXT: &str = "The quick brown fox jumps over the lazy dog.\n\n\
+        A second paragraph talks about something else entirely, with new words.\n\
+        And a final line wraps things up without repeating anything above.";
+
+    const REPETITIVE_TEXT: &str = "Buy now\nBuy now\nBuy now\nBest prices on shoes\nBuy now\nBest prices on shoes";
+
+    fn annotate(annotator: &MassiveWebRepetitionAnnotator, text: &str) -> Value {
+        annotator.process(json!({"text": text})).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_annotator_creation() {
+        let annotator = MassiveWebRepetitionAnnotator::new(&json!({})).unwrap();
+        assert_eq!(annotator.text_field, "text");
+        assert_eq!(annotator.annotation_key, "metadata.repetition");
+        assert!(!annotator.include_bounds);
+
+        let config = json!({"text_field": "content", "annotation_key": "stats.rep", "include_bounds": true});
+        let annotator = MassiveWebRepetitionAnnotator::new(&config).unwrap();
+        assert_eq!(annotator.text_field, "content");
+        assert_eq!(annotator.annotation_key, "stats.rep");
+        assert!(annotator.include_bounds);
+    }
+
+    #[test]
+    fn test_records_every_fraction() {
+        let annotator = MassiveWebRepetitionAnnotator::new(&json!({})).unwrap();
+        let doc = annotate(&annotator, REPETITIVE_TEXT);
+        let annotation = doc["metadata"]["repetition"].as_object().unwrap();
+        assert_eq!(annotation.len(), MASSIVE_WEB_REPETITION_CHECKS.len());
+        assert!(annotation.get("exceeded").is_none());
+
+        // The values are the filter's own fractions
+        let lines: Vec<&str> = REPETITIVE_TEXT.split('\n').collect();
+        let dup_line_frac = MassiveWebRepetitionFilter::_rep_counter_fraction(&lines, 1, false).unwrap();
+        assert_eq!(annotation["dup_line_frac"].as_f64().unwrap() as f32, dup_line_frac);
+        assert_eq!(dup_line_frac, 1.0);
+        for check in MASSIVE_WEB_REPETITION_CHECKS.iter() {
+            let frac = annotation[check.name].as_f64().unwrap();
+            assert!((0.0..=1.0).contains(&frac), "{} = {}", check.name, frac);
+        }
+        // Never filters, and leaves the rest of the doc alone
+        assert_eq!(doc["text"], REPETITIVE_TEXT);
+    }
+
+    #[test]
+    fn test_exceeded_matches_filter() {
+        let annotator = MassiveWebRepetitionAnnotator::new(&json!({"include_bounds": true})).unwrap();
+        let filter = MassiveWebRepetitionFilter::new(&json!({})).unwrap();
+
+        let doc = annotate(&annotator, CLEAN_TEXT);
+        assert_eq!(doc["metadata"]["repetition"]["exceeded"], json!([]));
+        assert_eq!(doc["metadata"]["repetition"]["dup_line_frac"], json!(0.0));
+        assert!(filter.process(json!({"text": CLEAN_TEXT})).unwrap().is_some());
+
+        let doc = annotate(&annotator, REPETITIVE_TEXT);
+        let exceeded = doc["metadata"]["repetition"]["exceeded"].as_array().unwrap();
+        assert!(exceeded.contains(&json!("dup_line_frac")), "{:?}", exceeded);
+        assert!(filter.process(json!({"text": REPETITIVE_TEXT})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_text_field() {
+        let annotator = MassiveWebRepetitionAnnotator::new(&json!({})).unwrap();
+        assert!(annotator.process(json!({"other": "x"})).is_err());
+    }
+}
@@ -0,0 +1,51 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, MinAlphaCharsFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kept(filter: &MinAlphaCharsFilter, text: &str) -> bool {
+        filter.process(json!({"text": text})).unwrap().is_some()
+    }
+
+    #[test]
+    fn test_min_alpha_chars_filter_new() {
+        let filter = MinAlphaCharsFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.min_alpha, 1);
+
+        let filter = MinAlphaCharsFilter::new(&json!({"text_field": "content", "min_alpha": 20})).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.min_alpha, 20);
+    }
+
+    #[test]
+    fn test_symbol_and_whitespace_only() {
+        let filter = MinAlphaCharsFilter::new(&json!({"min_alpha": 5})).unwrap();
+        assert!(!kept(&filter, "!!! --- *** ### 12345 %%%"));
+        assert!(!kept(&filter, "   \n\t\n   "));
+        assert!(!kept(&filter, ""));
+        // Default: a single letter is enough
+        let filter = MinAlphaCharsFilter::new(&json!({})).unwrap();
+        assert!(!kept(&filter, "$$ 42 ::"));
+        assert!(kept(&filter, "$$ 42 :: x"));
+    }
+
+    #[test]
+    fn test_normal_text() {
+        let filter = MinAlphaCharsFilter::new(&json!({"min_alpha": 5})).unwrap();
+        assert!(kept(&filter, "Hello, world!"));
+        assert!(!kept(&filter, "Hi! 1234567890"));
+        // Exactly min_alpha letters is enough, in any script
+        assert!(kept(&filter, "ab-cd-e"));
+        assert!(kept(&filter, "日本語です"));
+    }
+
+    #[test]
+    fn test_missing_text_field_is_error() {
+        let filter = MinAlphaCharsFilter::new(&json!({})).unwrap();
+        assert!(filter.process(json!({"other": "text"})).is_err());
+    }
+}
@@ -4,6 +4,7 @@ pub mod santa_code_pl_filter_test;
 pub mod url_substring_filter_test;
 pub mod newline_removal_modifier_test;
 pub mod fast_text_annotator_test;
+pub mod language_agreement_filter_test;
 pub mod float_filter_test;
 pub mod page_len_filter_test;
 pub mod word_len_filter_test;
@@ -11,6 +12,7 @@ pub mod symbol_ratio_filter_test;
 pub mod bullet_filter_test;
 pub mod ellipsis_line_ratio_filter_test;
 pub mod alphabetic_word_ratio_filter_test;
+pub mod min_alpha_chars_filter_test;
 pub mod stop_word_filter_test;
 pub mod word_count_adder_test;
 pub mod ratio_line_modifier_test;
@@ -20,3 +22,39 @@ pub mod substring_line_modifier_test;
 pub mod word_removal_ratio_filter_test;
 pub mod massive_repetition_filter_test;
 pub mod regex_text_filter_test;
+pub mod template_annotator_test;
+pub mod compression_ratio_filter_test;
+pub mod regex_filter_test;
+pub mod non_empty_line_filter_test;
+pub mod whitespace_normalize_modifier_test;
+pub mod duplicate_line_ratio_filter_test;
+pub mod fix_encoding_modifier_test;
+pub mod parse_json_field_modifier_test;
+pub mod weighted_subsample_filter_test;
+pub mod non_ascii_ratio_filter_test;
+pub mod unique_word_ratio_filter_test;
+pub mod url_parse_modifier_test;
+pub mod numeric_list_filter_test;
+pub mod pipeline_processor_test;
+pub mod split_field_modifier_test;
+pub mod any_field_present_filter_test;
+pub mod uppercase_ratio_filter_test;
+pub mod hash_annotator_test;
+pub mod rename_modifier_test;
+pub mod interval_filter_test;
+pub mod sentence_count_filter_test;
+pub mod subsample_filter_test;
+pub mod short_line_ratio_filter_test;
+pub mod numeric_line_ratio_filter_test;
+pub mod massive_web_repetition_annotator_test;
+pub mod numeric_normalize_modifier_test;
+pub mod concat_fields_modifier_test;
+pub mod array_join_modifier_test;
+pub mod keyword_presence_filter_test;
+pub mod token_len_filter_test;
+pub mod binary_content_filter_test;
+pub mod dictionary_word_ratio_filter_test;
+pub mod code_density_test;
+pub mod routed_pipeline_test;
+pub mod avg_sentence_length_filter_test;
+pub mod madlad400_rule_filter_test;
@@ -29,6 +29,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 2,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with text having excessive newlines
@@ -46,6 +48,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("content"),
             max_consecutive: 3,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with text having excessive newlines
@@ -63,6 +67,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 2,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with text having no excessive newlines
@@ -80,6 +86,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 2,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with empty text
@@ -97,6 +105,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 1,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with multiple patterns of excessive newlines
@@ -114,6 +124,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 2,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test that other fields in the JSON are preserved
@@ -142,6 +154,8 @@ mod tests {
         let modifier = NewlineRemovalModifier {
             text_field: String::from("text"),
             max_consecutive: 2,
+            trim_leading: false,
+            trim_trailing: false,
         };
 
         // Test with missing field - this would panic in a real scenario
@@ -158,4 +172,36 @@ mod tests {
         
         assert!(result.is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_trim_leading_and_trailing_blank_lines() {
+        let input = json!({"text": "\n \n\t\n\n\n  Indented\n\n\n\nBody  \n\n \n\n"});
+        let untouched = NewlineRemovalModifier::new(&json!({})).unwrap();
+        assert!(!untouched.trim_leading && !untouched.trim_trailing);
+        // Collapse only: boundaries keep their (collapsed) blank runs
+        let result = untouched.process(input.clone()).unwrap().unwrap();
+        assert_eq!(result["text"], "\n \n\t\n\n  Indented\n\nBody  \n\n \n\n");
+
+        let leading = NewlineRemovalModifier::new(&json!({"trim_leading": true})).unwrap();
+        let result = leading.process(input.clone()).unwrap().unwrap();
+        assert_eq!(result["text"], "  Indented\n\nBody  \n\n \n\n");
+
+        let trailing = NewlineRemovalModifier::new(&json!({"trim_trailing": true})).unwrap();
+        let result = trailing.process(input.clone()).unwrap().unwrap();
+        assert_eq!(result["text"], "\n \n\t\n\n  Indented\n\nBody  ");
+
+        let both = NewlineRemovalModifier::new(&json!({"trim_leading": true, "trim_trailing": true})).unwrap();
+        let result = both.process(input).unwrap().unwrap();
+        assert_eq!(result["text"], "  Indented\n\nBody  ");
+    }
+
+    #[test]
+    fn test_trim_edge_cases() {
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("\n \n\n", true, false), "");
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("\n \n\n", false, true), "");
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("  ", true, true), "");
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("One line", true, true), "One line");
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("a\n", true, true), "a");
+        assert_eq!(NewlineRemovalModifier::trim_blank_lines("\n\na\n\n", false, false), "\n\na\n\n");
+    }
+}
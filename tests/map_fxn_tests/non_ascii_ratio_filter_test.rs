@@ -0,0 +1,56 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, NonAsciiRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    #[test]
+    fn test_non_ascii_ratio_filter_creation() {
+        let filter = NonAsciiRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.min_ratio, 0.0);
+        assert_eq!(filter.max_ratio, 1.0);
+
+        let config = json!({"text_field": "content", "min_ratio": 0.5, "max_ratio": 0.9});
+        let filter = NonAsciiRatioFilter::new(&config).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.min_ratio, 0.5);
+        assert_eq!(filter.max_ratio, 0.9);
+
+        assert!(NonAsciiRatioFilter::new(&json!({"min_ratio": 0.8, "max_ratio": 0.2})).is_err());
+    }
+
+    #[test]
+    fn test_ratios() {
+        assert_eq!(NonAsciiRatioFilter::non_ascii_ratio("Hello, world!"), 0.0);
+        assert_eq!(NonAsciiRatioFilter::non_ascii_ratio("日本語です"), 1.0);
+        // 4 of 8 chars are non-ASCII (counted as chars, not bytes)
+        assert_eq!(NonAsciiRatioFilter::non_ascii_ratio("abcd日本語で"), 0.5);
+        assert_eq!(NonAsciiRatioFilter::non_ascii_ratio(""), 0.0);
+    }
+
+    #[test]
+    fn test_english_subset() {
+        let filter = NonAsciiRatioFilter::new(&json!({"max_ratio": 0.1})).unwrap();
+        assert!(filter.process(create_test_doc("A plain English sentence.")).unwrap().is_some());
+        // One accented char in a long sentence is fine
+        assert!(filter.process(create_test_doc("We went to the café after the long meeting.")).unwrap().is_some());
+        assert!(filter.process(create_test_doc("这是一个中文句子。")).unwrap().is_none());
+        assert!(filter.process(create_test_doc("Mixed: 这是一个中文句子")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cjk_subset() {
+        let filter = NonAsciiRatioFilter::new(&json!({"min_ratio": 0.5})).unwrap();
+        assert!(filter.process(create_test_doc("这是一个中文句子。")).unwrap().is_some());
+        assert!(filter.process(create_test_doc("東京 Tokyo 2024")).unwrap().is_none());
+        assert!(filter.process(create_test_doc("A plain English sentence.")).unwrap().is_none());
+        assert!(filter.process(json!({"content": "x"})).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, NonEmptyLineFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    #[test]
+    fn test_non_empty_line_filter_creation() {
+        let filter = NonEmptyLineFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.lower_bound, 1);
+        assert_eq!(filter.upper_bound, usize::MAX);
+
+        let config = json!({"text_field": "content", "lower_bound": 2, "upper_bound": 5});
+        let filter = NonEmptyLineFilter::new(&config).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.lower_bound, 2);
+        assert_eq!(filter.upper_bound, 5);
+
+        let config = json!({"lower_bound": 5, "upper_bound": 2});
+        assert!(NonEmptyLineFilter::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_blank_lines_not_counted() {
+        let filter = NonEmptyLineFilter::new(&json!({"lower_bound": 3})).unwrap();
+
+        // 10 lines, but only 2 with content
+        let mostly_blank = "first\n\n\n   \n\t\n\n\n\n\nsecond";
+        assert!(filter.process(create_test_doc(mostly_blank)).unwrap().is_none());
+
+        let enough = "first\n\nsecond\n  \nthird";
+        assert!(filter.process(create_test_doc(enough)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_upper_bound_and_empty() {
+        let filter = NonEmptyLineFilter::new(&json!({"upper_bound": 2})).unwrap();
+        assert!(filter.process(create_test_doc("a\nb\n\n\n")).unwrap().is_some());
+        assert!(filter.process(create_test_doc("a\nb\nc")).unwrap().is_none());
+        // Default lower_bound of 1 removes docs with no content at all
+        assert!(filter.process(create_test_doc("\n \n\t")).unwrap().is_none());
+        assert!(filter.process(create_test_doc("")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_field_errors() {
+        let filter = NonEmptyLineFilter::new(&json!({})).unwrap();
+        assert!(filter.process(json!({"content": "a"})).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, NumericLineRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const TABLE_DOC: &str = "Quarterly results\n\
+        Q1 | 12.5 | 3,400 | 98%\n\
+        Q2 | 13.1 | 3,650 | 97%\n\
+        Q3 | 11.9 | 3,120 | 99%\n\
+        \n\
+        Q4 | 14.2 | 4,010 | 96%\n\
+        2023 | 51.7 | 14,180 | 97%";
+
+    const PROSE_DOC: &str = "The committee met on Tuesday to discuss the 2024 budget.\n\
+        After a long debate, the 12 members agreed to postpone the vote.\n\
+        Several speakers raised concerns about the impact on local schools.\n\
+        The next meeting is scheduled for March 3.";
+
+    #[test]
+    fn test_numeric_line_ratio_filter_creation() {
+        let filter = NumericLineRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.line_numeric_threshold, 0.5);
+        assert_eq!(filter.max_ratio, 1.0);
+        assert_eq!(filter.min_lines, 0);
+
+        assert!(NumericLineRatioFilter::new(&json!({"max_ratio": 1.5})).is_err());
+        assert!(NumericLineRatioFilter::new(&json!({"line_numeric_threshold": -0.1})).is_err());
+    }
+
+    #[test]
+    fn test_count_numeric_lines() {
+        let filter = NumericLineRatioFilter::new(&json!({})).unwrap();
+        // The blank line doesn't count, the title isn't numeric
+        assert_eq!(filter.count_numeric_lines(TABLE_DOC), (6, 5));
+        assert_eq!(filter.count_numeric_lines(PROSE_DOC), (4, 0));
+        assert_eq!(filter.count_numeric_lines(""), (0, 0));
+    }
+
+    #[test]
+    fn test_drops_table_keeps_prose() {
+        let filter = NumericLineRatioFilter::new(&json!({"max_ratio": 0.5})).unwrap();
+        assert!(filter.process(json!({"text": TABLE_DOC})).unwrap().is_none());
+        let data = json!({"text": PROSE_DOC});
+        assert_eq!(filter.process(data.clone()).unwrap(), Some(data));
+        assert!(filter.process(json!({"text": ""})).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_min_lines() {
+        let filter = NumericLineRatioFilter::new(&json!({"max_ratio": 0.5, "min_lines": 10})).unwrap();
+        assert!(filter.process(json!({"text": TABLE_DOC})).unwrap().is_some());
+        let filter = NumericLineRatioFilter::new(&json!({"max_ratio": 0.5, "min_lines": 6})).unwrap();
+        assert!(filter.process(json!({"text": TABLE_DOC})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_text_field_is_error() {
+        let filter = NumericLineRatioFilter::new(&json!({})).unwrap();
+        assert!(filter.process(json!({"other": "1 2 3"})).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, NumericAllowListFilter, NumericDenyListFilter, NumericIdSet};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn write_list(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_numeric_list_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        if name.ends_with(".zst") {
+            fs::write(&path, zstd::stream::encode_all(contents.as_bytes(), 3).unwrap()).unwrap();
+        } else {
+            fs::write(&path, contents).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_numeric_id_set() {
+        let path = write_list("ids.txt", "1\n 42 \n\n-7\n18446744073709551615\n");
+        let ids = NumericIdSet::from_file(&path).unwrap();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids.contains(&json!(42)), Some(true));
+        assert_eq!(ids.contains(&json!(-7)), Some(true));
+        assert_eq!(ids.contains(&json!(u64::MAX)), Some(true));
+        assert_eq!(ids.contains(&json!(7)), Some(false));
+        assert_eq!(ids.contains(&json!(-1)), Some(false));
+        // Not integers: no answer either way
+        assert_eq!(ids.contains(&json!(42.5)), None);
+        assert_eq!(ids.contains(&json!("42")), None);
+        assert_eq!(ids.contains(&json!(null)), None);
+
+        let bad = write_list("bad.txt", "1\ntwo\n");
+        assert!(NumericIdSet::from_file(&bad).is_err());
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let path = write_list("allow.txt.zst", "1\n2\n3\n");
+        let config = json!({"field": "meta.doc_id", "list_file": path.to_str().unwrap()});
+        let filter = NumericAllowListFilter::new(&config).unwrap();
+        assert_eq!(filter.on_null, "remove");
+        assert!(filter.process(json!({"meta": {"doc_id": 2}})).unwrap().is_some());
+        assert!(filter.process(json!({"meta": {"doc_id": 4}})).unwrap().is_none());
+        assert!(filter.process(json!({"meta": {}})).unwrap().is_none());
+        assert!(filter.process(json!({"meta": {"doc_id": "2"}})).unwrap().is_none());
+
+        let config = json!({"field": "meta.doc_id", "list_file": path.to_str().unwrap(), "on_null": "keep"});
+        let filter = NumericAllowListFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"meta": {"doc_id": null}})).unwrap().is_some());
+        assert!(filter.process(json!({"meta": {"doc_id": 4}})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_deny_list() {
+        let path = write_list("deny.txt", "10\n20\n");
+        let config = json!({"field": "id", "list_file": path.to_str().unwrap()});
+        let filter = NumericDenyListFilter::new(&config).unwrap();
+        assert_eq!(filter.on_null, "keep");
+        assert!(filter.process(json!({"id": 10})).unwrap().is_none());
+        assert!(filter.process(json!({"id": 11})).unwrap().is_some());
+        assert!(filter.process(json!({"text": "no id"})).unwrap().is_some());
+
+        let config = json!({"field": "id", "list_file": path.to_str().unwrap(), "on_null": "remove"});
+        let filter = NumericDenyListFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"text": "no id"})).unwrap().is_none());
+        assert!(filter.process(json!({"id": 11})).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_bad_configs() {
+        let path = write_list("cfg.txt", "1\n");
+        assert!(NumericDenyListFilter::new(&json!({"list_file": path.to_str().unwrap()})).is_err());
+        assert!(NumericDenyListFilter::new(&json!({"field": "id"})).is_err());
+        let config = json!({"field": "id", "list_file": path.to_str().unwrap(), "on_null": "maybe"});
+        assert!(NumericAllowListFilter::new(&config).is_err());
+    }
+}
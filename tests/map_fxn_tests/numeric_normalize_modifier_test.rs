@@ -0,0 +1,79 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, NumericNormalizeModifier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_numeric_normalize_modifier_creation() {
+        let config = json!({"value_field": "metadata.score", "source_min": 0, "source_max": 5});
+        let modifier = NumericNormalizeModifier::new(&config).unwrap();
+        assert_eq!(modifier.value_field, "metadata.score");
+        assert_eq!(modifier.output_field, "metadata.score");
+        assert_eq!(modifier.source_min, Some(0.0));
+        assert_eq!(modifier.source_max, Some(5.0));
+        assert_eq!(modifier.clamp_min, None);
+        assert_eq!(modifier.clamp_max, None);
+        assert_eq!(modifier.on_error, "keep");
+
+        // Needs a value_field and something to do
+        assert!(NumericNormalizeModifier::new(&json!({"source_min": 0, "source_max": 1})).is_err());
+        assert!(NumericNormalizeModifier::new(&json!({"value_field": "score"})).is_err());
+        // Bad ranges
+        assert!(NumericNormalizeModifier::new(&json!({"value_field": "score", "source_min": 0})).is_err());
+        assert!(NumericNormalizeModifier::new(&json!({"value_field": "score", "source_min": 1, "source_max": 1})).is_err());
+        assert!(NumericNormalizeModifier::new(&json!({"value_field": "score", "clamp_min": 1, "clamp_max": 0})).is_err());
+        assert!(NumericNormalizeModifier::new(&json!({"value_field": "score", "clamp_min": 0, "on_error": "ignore"})).is_err());
+    }
+
+    #[test]
+    fn test_min_max_normalize() {
+        let config = json!({"value_field": "score", "source_min": 1, "source_max": 5, "output_field": "metadata.norm"});
+        let modifier = NumericNormalizeModifier::new(&config).unwrap();
+        let doc = modifier.process(json!({"score": 2})).unwrap().unwrap();
+        assert_eq!(doc, json!({"score": 2, "metadata": {"norm": 0.25}}));
+        assert_eq!(modifier.normalize(1.0), Some(0.0));
+        assert_eq!(modifier.normalize(5.0), Some(1.0));
+        // Out of the source range without a clamp
+        assert_eq!(modifier.normalize(6.0), None);
+    }
+
+    #[test]
+    fn test_clamp() {
+        let modifier = NumericNormalizeModifier::new(&json!({"value_field": "score", "clamp_min": -1, "clamp_max": 1})).unwrap();
+        assert_eq!(modifier.process(json!({"score": 3.5})).unwrap().unwrap(), json!({"score": 1.0}));
+        assert_eq!(modifier.process(json!({"score": -7})).unwrap().unwrap(), json!({"score": -1.0}));
+        assert_eq!(modifier.process(json!({"score": 0.5})).unwrap().unwrap(), json!({"score": 0.5}));
+
+        // One-sided clamp
+        let modifier = NumericNormalizeModifier::new(&json!({"value_field": "score", "clamp_min": 0})).unwrap();
+        assert_eq!(modifier.normalize(-3.0), Some(0.0));
+        assert_eq!(modifier.normalize(300.0), Some(300.0));
+
+        // Normalize then clamp: out-of-range source values are clamped instead of being errors
+        let config = json!({"value_field": "score", "source_min": 0, "source_max": 10, "clamp_min": 0, "clamp_max": 1});
+        let modifier = NumericNormalizeModifier::new(&config).unwrap();
+        assert_eq!(modifier.normalize(15.0), Some(1.0));
+        assert_eq!(modifier.normalize(-2.0), Some(0.0));
+        assert_eq!(modifier.normalize(5.0), Some(0.5));
+    }
+
+    #[test]
+    fn test_on_error() {
+        let config = |on_error: &str| {
+            json!({"value_field": "score", "source_min": 0, "source_max": 10, "on_error": on_error})
+        };
+        let bad_docs = [json!({"text": "no score"}), json!({"score": "high"}), json!({"score": 11})];
+
+        let keep = NumericNormalizeModifier::new(&config("keep")).unwrap();
+        let remove = NumericNormalizeModifier::new(&config("remove")).unwrap();
+        let error = NumericNormalizeModifier::new(&config("error")).unwrap();
+        for doc in bad_docs {
+            assert_eq!(keep.process(doc.clone()).unwrap(), Some(doc.clone()));
+            assert_eq!(remove.process(doc.clone()).unwrap(), None);
+            assert!(error.process(doc).is_err());
+        }
+    }
+}
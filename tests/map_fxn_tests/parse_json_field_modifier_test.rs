@@ -0,0 +1,58 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, ParseJsonFieldModifier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_json_field_modifier_new() {
+        let modifier = ParseJsonFieldModifier::new(&json!({"field": "metadata"})).unwrap();
+        assert_eq!(modifier.field, "metadata");
+        assert_eq!(modifier.output_field, None);
+        assert_eq!(modifier.on_error, "keep");
+
+        assert!(ParseJsonFieldModifier::new(&json!({})).is_err());
+        assert!(ParseJsonFieldModifier::new(&json!({"field": "a", "on_error": "explode"})).is_err());
+    }
+
+    #[test]
+    fn test_parse_in_place() {
+        let modifier = ParseJsonFieldModifier::new(&json!({"field": "metadata"})).unwrap();
+        let data = json!({"text": "hi", "metadata": "{\"lang\": \"en\", \"scores\": [1, 2]}"});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["metadata"]["lang"], "en");
+        assert_eq!(result["metadata"]["scores"], json!([1, 2]));
+        assert_eq!(result["text"], "hi");
+
+        // Already parsed (or missing): passed through untouched
+        assert_eq!(modifier.process(result.clone()).unwrap().unwrap(), result);
+        let data = json!({"text": "hi"});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+    }
+
+    #[test]
+    fn test_parse_to_output_field() {
+        let config = json!({"field": "raw.meta", "output_field": "metadata.parsed"});
+        let modifier = ParseJsonFieldModifier::new(&config).unwrap();
+        let data = json!({"raw": {"meta": "{\"lang\": \"fr\"}"}});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["metadata"]["parsed"]["lang"], "fr");
+        assert_eq!(result["raw"]["meta"], "{\"lang\": \"fr\"}");
+    }
+
+    #[test]
+    fn test_on_error() {
+        let data = json!({"metadata": "{not json"});
+
+        let modifier = ParseJsonFieldModifier::new(&json!({"field": "metadata"})).unwrap();
+        assert_eq!(modifier.process(data.clone()).unwrap(), Some(data.clone()));
+
+        let modifier = ParseJsonFieldModifier::new(&json!({"field": "metadata", "on_error": "remove"})).unwrap();
+        assert_eq!(modifier.process(data.clone()).unwrap(), None);
+
+        let modifier = ParseJsonFieldModifier::new(&json!({"field": "metadata", "on_error": "error"})).unwrap();
+        assert!(modifier.process(data).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::PipelineProcessor;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    fn ids(docs: &[Value]) -> Vec<u64> {
+        docs.iter().map(|d| d["id"].as_u64().unwrap()).collect()
+    }
+
+    fn make_lines(n: u64) -> Vec<String> {
+        // Ids are deliberately not sorted, so the order can only come from the input
+        (0..n)
+            .map(|i| json!({"id": (i * 7919) % n, "text": "x".repeat((i % 10) as usize)}).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_passthrough_preserves_order() {
+        let processor = PipelineProcessor::new(&json!({"pipeline": []})).unwrap();
+        let lines = make_lines(500);
+        let expected: Vec<u64> = lines
+            .iter()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["id"].as_u64().unwrap())
+            .collect();
+
+        let (outputs, errs, _, _, _, _, _) = processor.process_lines(lines.clone(), &PathBuf::from("in.jsonl")).unwrap();
+        assert!(errs.is_empty());
+        assert_eq!(ids(&outputs[&usize::MAX]), expected);
+    }
+
+    #[test]
+    fn test_filtered_outputs_preserve_order() {
+        // Survivors and rejects each keep their relative input order
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 5}}]});
+        let processor = PipelineProcessor::new(&config).unwrap();
+        let lines = make_lines(500);
+        let docs: Vec<Value> = lines.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let kept: Vec<u64> = ids(&docs.iter().filter(|d| d["text"].as_str().unwrap().len() >= 5).cloned().collect::<Vec<_>>());
+        let removed: Vec<u64> = ids(&docs.iter().filter(|d| d["text"].as_str().unwrap().len() < 5).cloned().collect::<Vec<_>>());
+
+        let (outputs, _, _, _, _, _, _) = processor.process_lines(lines, &PathBuf::from("in.jsonl")).unwrap();
+        assert_eq!(ids(&outputs[&usize::MAX]), kept);
+        assert_eq!(ids(&outputs[&0]), removed);
+    }
+
+    #[test]
+    fn test_key_order_is_preserved() {
+        // Input key order survives parsing, json_set (existing keys stay put, new keys go last),
+        // json_remove of a key in the middle, and serialization
+        let config = json!({"pipeline": [
+            {"name": "constant_annotator", "kwargs": {"key": "meta.added", "value": "x"}},
+            {"name": "constant_annotator", "kwargs": {"key": "meta.zeta", "value": "overwritten"}},
+            {"name": "rename_modifier", "kwargs": {"old_field": "middle", "new_field": "renamed"}},
+        ]});
+        let processor = PipelineProcessor::new(&config).unwrap();
+        let line = r#"{"zz":1,"middle":2,"meta":{"zeta":"z","alpha":"a"},"text":"hello","aa":3}"#.to_string();
+        let (outputs, errs, _, _, _, _, _) = processor.process_lines(vec![line], &PathBuf::from("in.jsonl")).unwrap();
+        assert!(errs.is_empty());
+        assert_eq!(
+            outputs[&usize::MAX][0].to_string(),
+            r#"{"zz":1,"meta":{"zeta":"overwritten","alpha":"a","added":"x"},"text":"hello","aa":3,"renamed":2}"#
+        );
+    }
+
+    #[test]
+    fn test_config_errors_name_the_step() {
+        let err = |config: Value| PipelineProcessor::new(&config).unwrap_err().to_string();
+        assert!(err(json!({})).contains("'pipeline' list"));
+        assert!(err(json!({"pipeline": [{"kwargs": {}}]})).contains("Pipeline step 0 has no 'name'"));
+
+        let e = err(json!({"pipeline": [{"name": "non_null_filter"}, {"name": "no_such_filter"}]}));
+        assert!(e.contains("Pipeline step 1: unknown processor \"no_such_filter\""), "{}", e);
+
+        // Bad settings, missing required keys and unreadable files all come back as errors for their step
+        let e = err(json!({"pipeline": [{"name": "subsample", "kwargs": {"seed_mode": "bogus"}}]}));
+        assert!(e.starts_with("Pipeline step 0 (subsample): seed_mode must be"), "{}", e);
+        let e = err(json!({"pipeline": [{"name": "non_null_filter"}, {"name": "url_substring_filter"}]}));
+        assert_eq!(e, "Pipeline step 1 (url_substring_filter): Config requires a string 'banlist_file'");
+        let e = err(json!({"pipeline": [
+            {"name": "non_null_filter"},
+            {"name": "url_substring_filter", "kwargs": {"banlist_file": "/nonexistent/banlist.txt"}}
+        ]}));
+        assert!(e.starts_with("Pipeline step 1 (url_substring_filter): "), "{}", e);
+        let e = err(json!({"pipeline": [{"name": "hash_annotator", "kwargs": {"num_bits": 32}}]}));
+        assert_eq!(e, "Pipeline step 0 (hash_annotator): num_bits must be 64 or 128, not 32");
+    }
+
+    #[test]
+    fn test_stats_group_by() {
+        let config = json!({
+            "stats_group_by": "meta.lang",
+            "pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 5}}]
+        });
+        let processor = PipelineProcessor::new(&config).unwrap();
+        let lines: Vec<String> = vec![
+            json!({"text": "long enough", "meta": {"lang": "en"}}),
+            json!({"text": "no", "meta": {"lang": "en"}}),
+            json!({"text": "nein", "meta": {"lang": "de"}}),
+            json!({"text": "short"}),
+        ]
+        .iter()
+        .map(|d| d.to_string())
+        .collect();
+        let (_, _, _, filter_info, _, grouped, _) = processor.process_lines(lines.clone(), &PathBuf::from("in.jsonl")).unwrap();
+        assert_eq!(grouped.len(), 4);
+        assert_eq!(grouped[&(usize::MAX, String::from("en"))], 1);
+        assert_eq!(grouped[&(0, String::from("en"))], 1);
+        assert_eq!(grouped[&(0, String::from("de"))], 1);
+        assert_eq!(grouped[&(usize::MAX, String::from("null"))], 1);
+        // The grouped counts add up to the ungrouped ones
+        assert_eq!(grouped.values().sum::<usize>(), filter_info.values().sum::<usize>());
+
+        // Not grouping by default
+        let processor = PipelineProcessor::new(&json!({"pipeline": []})).unwrap();
+        let (_, _, _, _, _, grouped, _) = processor.process_lines(lines, &PathBuf::from("in.jsonl")).unwrap();
+        assert!(grouped.is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, RegexFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_regex_filter_new() {
+        let config = json!({"regex_string": "foo"});
+        let filter = RegexFilter::new(&config).unwrap();
+        assert_eq!(filter.field, "text");
+        assert!(filter.keep_matches);
+        assert!(!filter.case_insensitive);
+
+        let config = json!({"text_field": "content", "regex_string": "foo"});
+        let filter = RegexFilter::new(&config).unwrap();
+        assert_eq!(filter.field, "content");
+
+        let config = json!({"text_field": "content", "field": "metadata.url", "regex_string": "foo"});
+        let filter = RegexFilter::new(&config).unwrap();
+        assert_eq!(filter.field, "metadata.url");
+
+        // Missing or invalid regexes are config errors
+        assert!(RegexFilter::new(&json!({})).is_err());
+        assert!(RegexFilter::new(&json!({"regex_string": "(unclosed"})).is_err());
+    }
+
+    #[test]
+    fn test_regex_filter_drop_matches() {
+        let config = json!({
+            "regex_string": r"\b(?:\d[ -]?){13,16}\b",
+            "keep_matches": false
+        });
+        let filter = RegexFilter::new(&config).unwrap();
+
+        let doc = json!({"text": "My card is 4111 1111 1111 1111, please charge it."});
+        assert!(filter.process(doc).unwrap().is_none());
+
+        let doc = json!({"text": "Call me at 555-1234."});
+        assert!(filter.process(doc).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_regex_filter_keep_matches_and_case() {
+        let config = json!({"field": "metadata.url", "regex_string": "wikipedia"});
+        let filter = RegexFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"metadata": {"url": "https://en.wikipedia.org/x"}})).unwrap().is_some());
+        assert!(filter.process(json!({"metadata": {"url": "https://en.WIKIPEDIA.org/x"}})).unwrap().is_none());
+        // Missing field is a non-match
+        assert!(filter.process(json!({"text": "wikipedia"})).unwrap().is_none());
+
+        let config = json!({"field": "metadata.url", "regex_string": "wikipedia", "case_insensitive": true});
+        let filter = RegexFilter::new(&config).unwrap();
+        assert!(filter.process(json!({"metadata": {"url": "https://en.WIKIPEDIA.org/x"}})).unwrap().is_some());
+    }
+}
@@ -0,0 +1,117 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, RenameModifier};
+use serde_json::{json, Value};
+
+
+fn rename(old_field: &str, new_field: &str, prune_empty: bool, data: Value) -> Value {
+    let modifier = RenameModifier::new(&json!({"old_field": old_field, "new_field": new_field, "prune_empty": prune_empty})).unwrap();
+    modifier.process(data).unwrap().unwrap()
+}
+
+#[test]
+fn test_defaults() {
+    let modifier = RenameModifier::new(&json!({"old_field": "a", "new_field": "b"})).unwrap();
+    assert!(!modifier.prune_empty);
+}
+
+#[test]
+fn test_shallow_rename() {
+    let result = rename("a", "b", false, json!({"a": 1, "c": 2}));
+    assert_eq!(result, json!({"b": 1, "c": 2}));
+}
+
+#[test]
+fn test_deep_to_deep() {
+    let data = json!({"a": {"b": {"c": 1}}, "text": "t"});
+    // Without pruning the emptied parents stay behind
+    assert_eq!(rename("a.b.c", "x.y.z", false, data.clone()), json!({"a": {"b": {}}, "text": "t", "x": {"y": {"z": 1}}}));
+    assert_eq!(rename("a.b.c", "x.y.z", true, data), json!({"text": "t", "x": {"y": {"z": 1}}}));
+}
+
+#[test]
+fn test_deep_to_shallow() {
+    let data = json!({"a": {"b": {"c": 1}, "keep": true}});
+    // Pruning stops at the first parent that still has other fields
+    assert_eq!(rename("a.b.c", "c", true, data.clone()), json!({"a": {"keep": true}, "c": 1}));
+    assert_eq!(rename("a.b.c", "c", false, data), json!({"a": {"b": {}, "keep": true}, "c": 1}));
+}
+
+#[test]
+fn test_shallow_to_deep() {
+    let data = json!({"score": 0.5, "metadata": {"source": "web"}});
+    assert_eq!(rename("score", "metadata.quality.score", true, data), json!({"metadata": {"source": "web", "quality": {"score": 0.5}}}));
+}
+
+#[test]
+fn test_move_within_own_path() {
+    // Into its own parent: the parent is replaced by the value
+    assert_eq!(rename("a.b", "a", true, json!({"a": {"b": 1}})), json!({"a": 1}));
+    // Below itself: the value is wrapped
+    assert_eq!(rename("a", "a.b", true, json!({"a": 1})), json!({"a": {"b": 1}}));
+    // Moving into the subtree that was just emptied recreates it rather than pruning it away
+    assert_eq!(rename("a.b.c", "a.b.d", true, json!({"a": {"b": {"c": 1}}})), json!({"a": {"b": {"d": 1}}}));
+    // Renaming a field onto itself leaves the doc alone
+    assert_eq!(rename("a.b", "a.b", true, json!({"a": {"b": 1}})), json!({"a": {"b": 1}}));
+}
+
+#[test]
+fn test_missing_source_is_an_error() {
+    let modifier = RenameModifier::new(&json!({"old_field": "a.b", "new_field": "c"})).unwrap();
+    assert!(modifier.process(json!({"a": {"x": 1}})).is_err());
+    assert!(modifier.process(json!({"text": "t"})).is_err());
+}
+
+fn rename_many(config: Value, data: Value) -> Value {
+    RenameModifier::new(&config).unwrap().process(data).unwrap().unwrap()
+}
+
+#[test]
+fn test_parallel_lists_and_renames_map() {
+    let data = json!({"a": 1, "b": {"c": 2}, "text": "t"});
+    let expected = json!({"text": "t", "x": 1, "y": {"z": 2}});
+    let lists = json!({"old_field": ["a", "b.c"], "new_field": ["x", "y.z"], "prune_empty": true});
+    assert_eq!(rename_many(lists, data.clone()), expected);
+    let map = json!({"renames": {"a": "x", "b.c": "y.z"}, "prune_empty": true});
+    assert_eq!(rename_many(map, data), expected);
+
+    let modifier = RenameModifier::new(&json!({"renames": {"a": "x", "b": "y"}})).unwrap();
+    assert_eq!(modifier.renames, vec![(String::from("a"), String::from("x")), (String::from("b"), String::from("y"))]);
+}
+
+#[test]
+fn test_swap_and_chain() {
+    // Every old field is taken out before any new field is written
+    let swap = json!({"old_field": ["a", "b"], "new_field": ["b", "a"]});
+    assert_eq!(rename_many(swap, json!({"a": 1, "b": 2})), json!({"b": 1, "a": 2}));
+    let chain = json!({"renames": {"a": "b", "b": "c"}});
+    assert_eq!(rename_many(chain, json!({"a": 1, "b": 2})), json!({"b": 1, "c": 2}));
+    // A nested swap across parents
+    let nested = json!({"renames": {"meta.x": "meta.y", "meta.y": "meta.x"}});
+    assert_eq!(rename_many(nested, json!({"meta": {"x": "X", "y": "Y"}})), json!({"meta": {"y": "X", "x": "Y"}}));
+}
+
+#[test]
+fn test_multi_rename_validation() {
+    let err = |config: Value| RenameModifier::new(&config).unwrap_err().to_string();
+    assert!(err(json!({"old_field": ["a", "b"], "new_field": ["x"]})).contains("old_field has 2 fields but new_field has 1"));
+    assert!(err(json!({"old_field": [], "new_field": []})).contains("at least one"));
+    assert!(err(json!({"old_field": "a"})).contains("new_field"));
+    assert!(err(json!({"renames": {"a": "b"}, "old_field": "a"})).contains("not both"));
+    assert!(err(json!({"renames": ["a", "b"]})).contains("renames must be a map"));
+    assert!(err(json!({"old_field": ["a", 1], "new_field": ["x", "y"]})).contains("must be strings"));
+    // Two values landing on the same or nested fields, or old fields nested in each other, are ambiguous
+    assert!(err(json!({"renames": {"a": "x", "b": "x"}})).contains("new fields \"x\" and \"x\" overlap"));
+    assert!(err(json!({"renames": {"a": "x", "b": "x.y"}})).contains("overlap"));
+    assert!(err(json!({"old_field": ["a", "a.b"], "new_field": ["x", "y"]})).contains("old fields \"a\" and \"a.b\" overlap"));
+    // Prefixes that aren't whole path components don't overlap
+    assert!(RenameModifier::new(&json!({"renames": {"a": "x", "ab": "xy"}})).is_ok());
+}
+
+#[test]
+fn test_multi_rename_missing_source_is_an_error() {
+    let modifier = RenameModifier::new(&json!({"renames": {"a": "x", "b": "y"}})).unwrap();
+    assert!(modifier.process(json!({"a": 1})).is_err());
+    // Identity renames are dropped, like a single rename onto itself
+    let modifier = RenameModifier::new(&json!({"renames": {"a": "a", "b": "y"}})).unwrap();
+    assert_eq!(modifier.process(json!({"b": 1})).unwrap().unwrap(), json!({"y": 1}));
+}
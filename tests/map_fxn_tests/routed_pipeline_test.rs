@@ -0,0 +1,102 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::RoutedPipeline;
+use serde_json::{json, Value};
+use std::path::Path;
+
+fn config(routes: Value) -> Value {
+    json!({"text_field": "text", "route_field": "metadata.source", "routes": routes})
+}
+
+fn doc(source: Value, text: &str) -> Value {
+    json!({"text": text, "metadata": {"source": source}})
+}
+
+#[test]
+fn test_routes_by_value_predicate_and_default() {
+    let router = RoutedPipeline::new(&config(json!([
+        {"name": "web", "values": ["cc", "web"], "pipeline": []},
+        {"name": "numbered", "values": [7], "pipeline": []},
+        {"name": "long", "when": {"name": "text_len_filter", "kwargs": {"lower_bound": 10}}, "pipeline": []},
+        {"name": "rest", "default": true, "pipeline": [{"name": "text_len_filter", "step": "len"}]},
+    ])))
+    .unwrap();
+    assert_eq!(router.routes.len(), 4);
+    assert_eq!(router.pipelines[3].steps, vec!["len"]);
+
+    let route = |doc: Value| {
+        // The doc comes back unchanged, whichever route (or 'when' step) looked at it
+        let (route, routed) = router.route(doc.clone());
+        assert_eq!(routed, doc);
+        route
+    };
+    assert_eq!(route(doc(json!("cc"), "short")), Some(0));
+    assert_eq!(route(doc(json!("web"), "a much longer text")), Some(0)); // first match wins
+    assert_eq!(route(doc(json!(7), "short")), Some(1));
+    assert_eq!(route(doc(json!("books"), "a much longer text")), Some(2));
+    assert_eq!(route(doc(json!("books"), "short")), Some(3));
+    assert_eq!(route(doc(Value::Null, "short")), Some(3));
+}
+
+#[test]
+fn test_split_lines_without_default() {
+    let router = RoutedPipeline::new(&config(json!([
+        {"name": "a", "values": ["a"], "pipeline": []},
+        {"name": "b", "values": ["b"], "pipeline": []},
+    ])))
+    .unwrap();
+    let docs = [doc(json!("b"), "1"), doc(json!("a"), "2"), doc(json!("c"), "3"), doc(json!("b"), "4")];
+    let mut lines: Vec<String> = docs.iter().map(|d| d.to_string()).collect();
+    lines.insert(2, String::from("{broken"));
+    let (routed, invalid, unrouted) = router.split_lines(lines, Path::new("in.jsonl"));
+    assert_eq!(routed, vec![vec![docs[1].clone()], vec![docs[0].clone(), docs[3].clone()]]);
+    assert_eq!(invalid, vec!["{broken"]);
+    assert_eq!(unrouted, 1);
+}
+
+#[test]
+fn test_split_lines_keeps_invalid_json_out_of_the_default_route() {
+    let router = RoutedPipeline::new(&config(json!([
+        {"name": "a", "values": ["a"], "pipeline": []},
+        {"name": "rest", "default": true, "pipeline": []},
+    ])))
+    .unwrap();
+    let lines = vec![doc(json!("x"), "1").to_string(), String::from("not json")];
+    let (routed, invalid, unrouted) = router.split_lines(lines, Path::new("in.jsonl"));
+    assert_eq!(routed, vec![vec![], vec![doc(json!("x"), "1")]]);
+    assert_eq!(invalid, vec!["not json"]);
+    assert_eq!(unrouted, 0);
+}
+
+#[test]
+fn test_route_text_field_defaults_to_top_level() {
+    let router = RoutedPipeline::new(&config(json!([
+        {"name": "a", "values": ["a"], "pipeline": []},
+        {"name": "b", "text_field": "body", "default": true, "pipeline": []},
+    ])))
+    .unwrap();
+    assert_eq!(router.pipelines[0].text_field, "text");
+    assert_eq!(router.pipelines[1].text_field, "body");
+    assert!(RoutedPipeline::is_routed(&config(json!([]))));
+    assert!(!RoutedPipeline::is_routed(&json!({"pipeline": []})));
+}
+
+#[test]
+fn test_invalid_configs() {
+    let invalid = [
+        json!([]),
+        json!([{"values": ["a"], "pipeline": []}]),
+        json!([{"name": "a/b", "default": true, "pipeline": []}]),
+        json!([{"name": "a", "pipeline": []}]),
+        json!([{"name": "a", "values": ["a"], "default": true, "pipeline": []}]),
+        json!([{"name": "a", "values": ["a"], "pipeline": []}, {"name": "a", "default": true, "pipeline": []}]),
+        json!([{"name": "a", "default": true, "pipeline": []}, {"name": "b", "values": ["b"], "pipeline": []}]),
+        json!([{"name": "a", "when": {"name": "no_such_filter"}, "pipeline": []}]),
+        json!([{"name": "a", "default": true}]),
+    ];
+    for routes in invalid {
+        assert!(RoutedPipeline::new(&config(routes.clone())).is_err(), "{}", routes);
+    }
+    // Value routes need a route_field
+    let no_field = json!({"routes": [{"name": "a", "values": ["a"], "pipeline": []}]});
+    assert!(RoutedPipeline::new(&no_field).is_err());
+}
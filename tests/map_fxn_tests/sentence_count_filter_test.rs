@@ -0,0 +1,57 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{split_sentences, DataProcessor, SentenceCountFilter};
+use serde_json::json;
+
+
+#[test]
+fn test_defaults() {
+    let filter = SentenceCountFilter::new(&json!({})).unwrap();
+    assert_eq!(filter.text_field, "text");
+    assert_eq!(filter.lower_bound, 1);
+    assert_eq!(filter.upper_bound, usize::MAX);
+    assert!(SentenceCountFilter::new(&json!({"lower_bound": 3, "upper_bound": 2})).is_err());
+}
+
+#[test]
+fn test_abbreviations_and_decimals() {
+    assert_eq!(split_sentences("Dr. Smith paid $3.50."), vec!["Dr. Smith paid $3.50."]);
+    assert_eq!(
+        split_sentences("Mr. J. R. Tolkien wrote it, e.g. in Oxford. It sold well! Did it? Yes."),
+        vec!["Mr. J. R. Tolkien wrote it, e.g. in Oxford.", "It sold well!", "Did it?", "Yes."]
+    );
+    // The crude .!? count would say 9 here
+    assert_eq!(split_sentences("Version 1.2.3 is out... finally. See Fig. 4 for details.").len(), 2);
+}
+
+#[test]
+fn test_sentence_final_words_and_pronoun_i() {
+    assert_eq!(split_sentences("The answer is no. We left."), vec!["The answer is no.", "We left."]);
+    assert_eq!(split_sentences("So did I. Then we left."), vec!["So did I.", "Then we left."]);
+    assert_eq!(
+        split_sentences("We met in Dec. The deal fell through. It was the same co. Others joined."),
+        vec!["We met in Dec.", "The deal fell through.", "It was the same co.", "Others joined."]
+    );
+    assert_eq!(split_sentences("Smith et al. Their results held.").len(), 2);
+    // "no" and "st" still abbreviate before a number or a name
+    assert_eq!(split_sentences("See No. 5 on the list. We live near St. Louis."), vec!["See No. 5 on the list.", "We live near St. Louis."]);
+    assert_eq!(split_sentences("Turn left at the first st. then right."), vec!["Turn left at the first st. then right."]);
+}
+
+#[test]
+fn test_cjk_and_whitespace() {
+    assert_eq!(split_sentences("今天天气很好。我们去公园吧！好吗？"), vec!["今天天气很好。", "我们去公园吧！", "好吗？"]);
+    assert_eq!(split_sentences("First line\n\nSecond line"), vec!["First line", "Second line"]);
+    assert!(split_sentences("   \n ").is_empty());
+    // A trailing abbreviation at the very end still closes the sentence
+    assert_eq!(split_sentences("Send it to Dr."), vec!["Send it to Dr."]);
+}
+
+#[test]
+fn test_filter_bounds() {
+    let filter = SentenceCountFilter::new(&json!({"lower_bound": 2, "upper_bound": 3})).unwrap();
+    assert!(filter.process(json!({"text": "Dr. Smith paid $3.50."})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "Dr. Smith paid $3.50. He left."})).unwrap().is_some());
+    assert!(filter.process(json!({"text": "A. B. C. D."})).unwrap().is_none());
+    assert!(filter.process(json!({"text": "One. Two. Three. Four."})).unwrap().is_none());
+    assert!(filter.process(json!({"other": "x"})).is_err());
+}
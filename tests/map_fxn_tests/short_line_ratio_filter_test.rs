@@ -0,0 +1,68 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, ShortLineRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    const MENU_DOC: &str = "Home\nAbout us\nProducts\n  Contact  \nLogin\n\nWelcome to our store, where we sell many fine products.\nPrivacy\nTerms";
+
+    const PROSE_DOC: &str = "The committee met on Tuesday to discuss the proposed changes to the budget.\n\n\
+        After a long debate, the members agreed to postpone the vote until next month.\n\
+        Several speakers raised concerns about the impact on local schools.\n\
+        Notes:\n\
+        The next meeting is scheduled for the first week of March.";
+
+    #[test]
+    fn test_short_line_ratio_filter_creation() {
+        let filter = ShortLineRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.min_line_chars, 30);
+        assert_eq!(filter.max_ratio, 1.0);
+
+        let config = json!({"text_field": "content", "min_line_chars": 10, "max_ratio": 0.5});
+        let filter = ShortLineRatioFilter::new(&config).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.min_line_chars, 10);
+        assert_eq!(filter.max_ratio, 0.5);
+
+        assert!(ShortLineRatioFilter::new(&json!({"max_ratio": 1.5})).is_err());
+    }
+
+    #[test]
+    fn test_ratio() {
+        let filter = ShortLineRatioFilter::new(&json!({"min_line_chars": 30})).unwrap();
+        // 7 of the 8 non-empty lines are short (the blank line doesn't count, "  Contact  " is trimmed)
+        assert_eq!(filter.short_line_ratio(MENU_DOC), 0.875);
+        // Only "Notes:" is short
+        assert_eq!(filter.short_line_ratio(PROSE_DOC), 0.2);
+        assert_eq!(filter.short_line_ratio(""), 0.0);
+        assert_eq!(filter.short_line_ratio("\n  \n"), 0.0);
+
+        // Lengths are in chars, not bytes
+        let filter = ShortLineRatioFilter::new(&json!({"min_line_chars": 5})).unwrap();
+        assert_eq!(filter.short_line_ratio("ééééé\nabcd"), 0.5);
+    }
+
+    #[test]
+    fn test_menu_vs_prose() {
+        let filter = ShortLineRatioFilter::new(&json!({"min_line_chars": 30, "max_ratio": 0.5})).unwrap();
+        assert!(filter.process(create_test_doc(MENU_DOC)).unwrap().is_none());
+        assert!(filter.process(create_test_doc(PROSE_DOC)).unwrap().is_some());
+
+        // The ratio has to be strictly above max_ratio to drop
+        let filter = ShortLineRatioFilter::new(&json!({"min_line_chars": 30, "max_ratio": 0.2})).unwrap();
+        assert!(filter.process(create_test_doc(PROSE_DOC)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_missing_text_field() {
+        let filter = ShortLineRatioFilter::new(&json!({})).unwrap();
+        assert!(filter.process(json!({"other": "x"})).is_err());
+    }
+}
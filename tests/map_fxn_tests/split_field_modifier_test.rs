@@ -0,0 +1,53 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, SplitFieldModifier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_split_field_modifier_new() {
+        let modifier = SplitFieldModifier::new(&json!({"delimiter": "|", "output_field": "parts"})).unwrap();
+        assert_eq!(modifier.text_field, "text");
+        assert!(modifier.trim);
+        assert!(modifier.drop_empty);
+        assert_eq!(modifier.max_splits, None);
+
+        assert!(SplitFieldModifier::new(&json!({"output_field": "parts"})).is_err());
+        assert!(SplitFieldModifier::new(&json!({"delimiter": "", "output_field": "parts"})).is_err());
+        assert!(SplitFieldModifier::new(&json!({"delimiter": "|"})).is_err());
+    }
+
+    #[test]
+    fn test_split_defaults() {
+        let modifier = SplitFieldModifier::new(&json!({"delimiter": ". ", "output_field": "sentences"})).unwrap();
+        let data = json!({"text": "First one. Second one.  .  Third. "});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(result["sentences"], json!(["First one", "Second one", "Third"]));
+        // The source field is left alone
+        assert_eq!(result["text"], "First one. Second one.  .  Third. ");
+    }
+
+    #[test]
+    fn test_split_options() {
+        let config = json!({
+            "text_field": "meta.tags", "delimiter": ",", "output_field": "meta.tag_list",
+            "trim": false, "drop_empty": false
+        });
+        let modifier = SplitFieldModifier::new(&config).unwrap();
+        let result = modifier.process(json!({"meta": {"tags": "a, b,,c"}})).unwrap().unwrap();
+        assert_eq!(result["meta"]["tag_list"], json!(["a", " b", "", "c"]));
+
+        let config = json!({"delimiter": "\n", "output_field": "lines", "max_splits": 1});
+        let modifier = SplitFieldModifier::new(&config).unwrap();
+        let result = modifier.process(json!({"text": "title\nline 1\nline 2"})).unwrap().unwrap();
+        assert_eq!(result["lines"], json!(["title", "line 1\nline 2"]));
+
+        // Empty text gives an empty list, missing/non-string fields are passed through
+        let modifier = SplitFieldModifier::new(&json!({"delimiter": "|", "output_field": "parts"})).unwrap();
+        assert_eq!(modifier.process(json!({"text": ""})).unwrap().unwrap()["parts"], json!([]));
+        let data = json!({"text": 5});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+    }
+}
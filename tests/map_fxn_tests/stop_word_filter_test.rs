@@ -159,4 +159,65 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap(), data);
     }
-}
\ No newline at end of file
+
+    fn per_language_filter(unknown_lang: &str) -> StopWordFilter {
+        let dir = std::env::temp_dir().join(format!("datamap_stop_words_{}_{}", unknown_lang, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("en.txt"), "the\nand\nof\n").unwrap();
+        std::fs::write(dir.join("es.txt"), "el\nla\n\nY\nde\n").unwrap();
+        StopWordFilter::new(&json!({
+            "stop_word_dir": dir.to_str().unwrap(),
+            "lang_field": "metadata.lang",
+            "unknown_lang": unknown_lang
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_per_language_lists() {
+        let filter = per_language_filter("keep");
+        assert_eq!(filter.lang_stop_words.len(), 2);
+        assert!(filter.lang_stop_words["es"].contains("y"));
+
+        let spanish = json!({"text": "El perro y la casa", "metadata": {"lang": "es"}});
+        assert!(filter.process(spanish).unwrap().is_some());
+        // English stop words don't count for a Spanish doc
+        let spanish = json!({"text": "the dog and the house", "metadata": {"lang": "es"}});
+        assert!(filter.process(spanish).unwrap().is_none());
+        // ... and the English list is the one from the directory, not the built-in one
+        let english = json!({"text": "the dog and the house", "metadata": {"lang": "en"}});
+        assert!(filter.process(english).unwrap().is_some());
+        let english = json!({"text": "to be with you", "metadata": {"lang": "en"}});
+        assert!(filter.process(english).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unknown_language_fallback() {
+        let german = json!({"text": "der Hund und the house", "metadata": {"lang": "de"}});
+        let no_lang = json!({"text": "der Hund und das Haus"});
+
+        let filter = per_language_filter("keep");
+        assert!(filter.process(german.clone()).unwrap().is_some());
+        assert!(filter.process(no_lang.clone()).unwrap().is_some());
+
+        let filter = per_language_filter("remove");
+        assert!(filter.process(german.clone()).unwrap().is_none());
+        assert!(filter.process(no_lang.clone()).unwrap().is_none());
+
+        // The built-in English list only finds one "the"
+        let filter = per_language_filter("default");
+        assert!(filter.process(german).unwrap().is_none());
+        let mixed = json!({"text": "der Hund and the house", "metadata": {"lang": "de"}});
+        assert!(filter.process(mixed).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_per_language_config_errors() {
+        assert!(StopWordFilter::new(&json!({"unknown_lang": "drop"})).is_err());
+        // A directory needs a language field
+        let dir = std::env::temp_dir();
+        assert!(StopWordFilter::new(&json!({"stop_word_dir": dir.to_str().unwrap()})).is_err());
+        assert!(StopWordFilter::new(&json!({"stop_word_dir": "/nonexistent/stop_words", "lang_field": "lang"})).is_err());
+    }
+}
@@ -0,0 +1,83 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, SubsampleFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kept_ids(filter: &SubsampleFilter, n: usize) -> Vec<usize> {
+        (0..n)
+            .filter(|i| filter.process(json!({"id": format!("doc-{}", i)})).unwrap().is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_subsample_filter_new() {
+        let filter = SubsampleFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.subsample_rate, 1.0);
+        assert_eq!(filter.seed, None);
+        assert_eq!(filter.seed_mode, "per_doc");
+        assert_eq!(filter.id_field, "id");
+
+        let config = json!({"subsample_rate": 0.25, "seed": 3, "seed_mode": "per_worker", "id_field": "meta.id"});
+        let filter = SubsampleFilter::new(&config).unwrap();
+        assert_eq!(filter.subsample_rate, 0.25);
+        assert_eq!(filter.seed, Some(3));
+        assert_eq!(filter.seed_mode, "per_worker");
+        assert_eq!(filter.id_field, "meta.id");
+
+        assert!(SubsampleFilter::new(&json!({"seed_mode": "per_thread"})).is_err());
+    }
+
+    #[test]
+    fn test_rates() {
+        for config in [
+            json!({"subsample_rate": 0.3}),
+            json!({"subsample_rate": 0.3, "seed": 1}),
+            json!({"subsample_rate": 0.3, "seed": 1, "seed_mode": "per_worker"}),
+        ] {
+            let filter = SubsampleFilter::new(&config).unwrap();
+            let kept = kept_ids(&filter, 10000).len();
+            assert!(kept > 2700 && kept < 3300, "{:?} kept {}", config, kept);
+        }
+        let filter = SubsampleFilter::new(&json!({"subsample_rate": 1.0, "seed": 1})).unwrap();
+        assert_eq!(kept_ids(&filter, 100).len(), 100);
+    }
+
+    #[test]
+    fn test_per_doc_seed() {
+        let filter = SubsampleFilter::new(&json!({"subsample_rate": 0.5, "seed": 1234})).unwrap();
+        let first = kept_ids(&filter, 1000);
+        // Independent of call order and of the filter instance
+        assert_eq!(first, kept_ids(&filter, 1000));
+        let other = SubsampleFilter::new(&json!({"subsample_rate": 0.5, "seed": 1234})).unwrap();
+        let mut reversed: Vec<usize> = (0..1000)
+            .rev()
+            .filter(|i| other.process(json!({"id": format!("doc-{}", i)})).unwrap().is_some())
+            .collect();
+        reversed.reverse();
+        assert_eq!(first, reversed);
+        // A different seed gives a different sample
+        let filter = SubsampleFilter::new(&json!({"subsample_rate": 0.5, "seed": 4321})).unwrap();
+        assert_ne!(first, kept_ids(&filter, 1000));
+    }
+
+    #[test]
+    fn test_per_worker_seed() {
+        let config = json!({"subsample_rate": 0.5, "seed": 99, "seed_mode": "per_worker"});
+        // Fresh instances replay the same stream on the same worker
+        let first = kept_ids(&SubsampleFilter::new(&config).unwrap(), 1000);
+        assert_eq!(first, kept_ids(&SubsampleFilter::new(&config).unwrap(), 1000));
+        // ... but a single instance keeps drawing from its stream
+        let filter = SubsampleFilter::new(&config).unwrap();
+        assert_eq!(first, kept_ids(&filter, 1000));
+        assert_ne!(first, kept_ids(&filter, 1000));
+
+        // Each rayon worker gets its own stream
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let streams: Vec<Vec<usize>> = pool.broadcast(|_| kept_ids(&SubsampleFilter::new(&config).unwrap(), 1000));
+        assert_ne!(streams[0], streams[1]);
+        assert_eq!(streams, pool.broadcast(|_| kept_ids(&SubsampleFilter::new(&config).unwrap(), 1000)));
+    }
+}
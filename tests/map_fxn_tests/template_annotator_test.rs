@@ -0,0 +1,83 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, TemplateAnnotator, TemplatePart};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_template_annotator_new() {
+        let config = json!({
+            "template": "{source}/{metadata.date}",
+            "output_field": "source_and_date"
+        });
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        assert_eq!(annotator.output_field, "source_and_date");
+        assert_eq!(annotator.on_missing, "empty");
+        assert_eq!(
+            annotator.parts,
+            vec![
+                TemplatePart::Field(String::from("source")),
+                TemplatePart::Literal(String::from("/")),
+                TemplatePart::Field(String::from("metadata.date")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_template_annotator_bad_configs() {
+        let config = json!({"template": "{source", "output_field": "out"});
+        assert!(TemplateAnnotator::new(&config).is_err());
+
+        let config = json!({"template": "{}", "output_field": "out"});
+        assert!(TemplateAnnotator::new(&config).is_err());
+
+        let config = json!({"template": "{a}", "output_field": "out", "on_missing": "explode"});
+        assert!(TemplateAnnotator::new(&config).is_err());
+    }
+
+    #[test]
+    fn test_template_annotator_basic() {
+        let config = json!({
+            "template": "{source}/{metadata.date}",
+            "output_field": "source_and_date"
+        });
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        let data = json!({"source": "cc", "metadata": {"date": "2024-01-01"}});
+        let result = annotator.process(data).unwrap().unwrap();
+        assert_eq!(result["source_and_date"], "cc/2024-01-01");
+        assert_eq!(result["source"], "cc");
+    }
+
+    #[test]
+    fn test_template_annotator_non_string_values_and_escapes() {
+        let config = json!({
+            "template": "{{count}}: {count}, flag: {flag}",
+            "output_field": "metadata.prompt"
+        });
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        let data = json!({"count": 3, "flag": true});
+        let result = annotator.process(data).unwrap().unwrap();
+        assert_eq!(result["metadata"]["prompt"], "{count}: 3, flag: true");
+    }
+
+    #[test]
+    fn test_template_annotator_on_missing() {
+        let data = json!({"source": "cc"});
+
+        let config = json!({"template": "{source}/{date}", "output_field": "out"});
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        let result = annotator.process(data.clone()).unwrap().unwrap();
+        assert_eq!(result["out"], "cc/");
+
+        let config = json!({"template": "{source}/{date}", "output_field": "out", "on_missing": "skip"});
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        let result = annotator.process(data.clone()).unwrap();
+        assert_eq!(result, Some(data.clone()));
+
+        let config = json!({"template": "{source}/{date}", "output_field": "out", "on_missing": "error"});
+        let annotator = TemplateAnnotator::new(&config).unwrap();
+        assert!(annotator.process(data).is_err());
+    }
+}
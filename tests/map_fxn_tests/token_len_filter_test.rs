@@ -0,0 +1,45 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, TokenLenFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_token_len_filter_new() {
+        let filter = TokenLenFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.tokenizer_name, "cl100k");
+        assert_eq!(filter.lower_bound, 0);
+        assert_eq!(filter.upper_bound, usize::MAX);
+
+        assert!(TokenLenFilter::new(&json!({"tokenizer_name": "o200k"})).is_ok());
+        assert!(TokenLenFilter::new(&json!({"tokenizer_name": "gpt2-ish"})).is_err());
+        assert!(TokenLenFilter::new(&json!({"lower_bound": 10, "upper_bound": 5})).is_err());
+    }
+
+    #[test]
+    fn test_token_bounds() {
+        let filter = TokenLenFilter::new(&json!({"lower_bound": 3, "upper_bound": 6})).unwrap();
+        let text = "hello world, this is";
+        let tokens = filter.token_count(text);
+        assert!((3..=6).contains(&tokens), "{} tokens", tokens);
+        assert!(filter.process(json!({"text": text})).unwrap().is_some());
+        // Too short (also short-circuited on bytes) and too long
+        assert!(filter.process(json!({"text": "hi"})).unwrap().is_none());
+        assert!(filter.process(json!({"text": "hello world, this is a somewhat longer sentence."})).unwrap().is_none());
+        // Missing text is 0 tokens
+        assert!(filter.process(json!({"id": 1})).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tokens_not_bytes() {
+        // Long words are few tokens relative to their bytes
+        let filter = TokenLenFilter::new(&json!({"text_field": "body", "upper_bound": 5})).unwrap();
+        let text = "internationalization";
+        assert!(text.len() > 5);
+        assert!(filter.token_count(text) <= 5);
+        assert!(filter.process(json!({"body": text})).unwrap().is_some());
+    }
+}
@@ -0,0 +1,55 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, UniqueWordRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    #[test]
+    fn test_unique_word_ratio_filter_creation() {
+        let filter = UniqueWordRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.min_ratio, 0.0);
+        assert_eq!(filter.min_unique_words, 0);
+
+        let config = json!({"text_field": "content", "min_ratio": 0.3, "min_unique_words": 10});
+        let filter = UniqueWordRatioFilter::new(&config).unwrap();
+        assert_eq!(filter.text_field, "content");
+        assert_eq!(filter.min_ratio, 0.3);
+        assert_eq!(filter.min_unique_words, 10);
+    }
+
+    #[test]
+    fn test_word_counts() {
+        // Lowercased, punctuation isn't a word
+        assert_eq!(UniqueWordRatioFilter::word_counts("Buy buy BUY now, now!"), (2, 5));
+        assert_eq!(UniqueWordRatioFilter::word_counts(""), (0, 0));
+    }
+
+    #[test]
+    fn test_repetitive_vs_diverse() {
+        let filter = UniqueWordRatioFilter::new(&json!({"min_ratio": 0.3})).unwrap();
+
+        let repetitive = "cheap pills cheap pills best price ".repeat(50);
+        assert!(filter.process(create_test_doc(&repetitive)).unwrap().is_none());
+
+        let diverse = "The quick brown fox jumps over the lazy dog while a curious cat watches from the windowsill.";
+        assert!(filter.process(create_test_doc(diverse)).unwrap().is_some());
+
+        // Empty docs have a ratio of 0
+        assert!(filter.process(create_test_doc("")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_min_unique_words() {
+        let filter = UniqueWordRatioFilter::new(&json!({"min_unique_words": 5})).unwrap();
+        assert!(filter.process(create_test_doc("one two three four")).unwrap().is_none());
+        assert!(filter.process(create_test_doc("one two three four five")).unwrap().is_some());
+        assert!(filter.process(json!({"content": "x"})).is_err());
+    }
+}
@@ -0,0 +1,60 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, UppercaseRatioFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn create_test_doc(text: &str) -> Value {
+        json!({ "text": text })
+    }
+
+    #[test]
+    fn test_uppercase_ratio_filter_creation() {
+        let filter = UppercaseRatioFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.text_field, "text");
+        assert_eq!(filter.max_ratio, 0.5);
+        assert_eq!(filter.min_letters, 20);
+
+        assert!(UppercaseRatioFilter::new(&json!({"max_ratio": 1.5})).is_err());
+    }
+
+    #[test]
+    fn test_letter_counts() {
+        assert_eq!(UppercaseRatioFilter::letter_counts(""), (0, 0));
+        assert_eq!(UppercaseRatioFilter::letter_counts("Hello, World! 123"), (10, 2));
+        // Caseless scripts and accents: only cased letters count
+        assert_eq!(UppercaseRatioFilter::letter_counts("ÉCOLE 学校"), (5, 5));
+    }
+
+    #[test]
+    fn test_all_caps_vs_normal() {
+        let filter = UppercaseRatioFilter::new(&json!({})).unwrap();
+        let shouting = create_test_doc("BUY NOW!!! LIMITED TIME OFFER ON ALL OUR AMAZING PRODUCTS, CLICK HERE");
+        assert!(filter.process(shouting).unwrap().is_none());
+
+        let nav_dump = create_test_doc("HOME\nABOUT US\nCONTACT\nPRODUCTS\nSERVICES\nLOGIN\nREGISTER");
+        assert!(filter.process(nav_dump).unwrap().is_none());
+
+        let normal = create_test_doc("The NASA mission launched on Tuesday. Officials in the USA said it went well.");
+        assert!(filter.process(normal).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_min_letters_floor() {
+        let filter = UppercaseRatioFilter::new(&json!({})).unwrap();
+        // Too few letters to judge
+        assert!(filter.process(create_test_doc("FAQ: HOW TO")).unwrap().is_some());
+
+        let filter = UppercaseRatioFilter::new(&json!({"min_letters": 0, "max_ratio": 0.3})).unwrap();
+        assert!(filter.process(create_test_doc("FAQ: HOW TO")).unwrap().is_none());
+        assert!(filter.process(create_test_doc("12345 !!!")).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_missing_field_errors() {
+        let filter = UppercaseRatioFilter::new(&json!({})).unwrap();
+        assert!(filter.process(json!({"content": "HELLO"})).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, UrlParseModifier};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_url_parse_modifier_new() {
+        let modifier = UrlParseModifier::new(&json!({"components_field": "url_parts"})).unwrap();
+        assert_eq!(modifier.url_key, "url");
+        assert_eq!(modifier.components_field, Some(String::from("url_parts")));
+        assert_eq!(modifier.normalized_field, None);
+        assert!(modifier.strip_fragment);
+        assert_eq!(modifier.on_error, "keep");
+
+        // Needs somewhere to write to, and a known on_error
+        assert!(UrlParseModifier::new(&json!({})).is_err());
+        assert!(UrlParseModifier::new(&json!({"normalized_field": "u", "on_error": "explode"})).is_err());
+    }
+
+    #[test]
+    fn test_components() {
+        let config = json!({"url_key": "metadata.url", "components_field": "metadata.url_parts"});
+        let modifier = UrlParseModifier::new(&config).unwrap();
+        let data = json!({"metadata": {"url": "HTTPS://Docs.Example.COM:8443/a/B?x=1&y=2#top"}});
+        let result = modifier.process(data).unwrap().unwrap();
+        assert_eq!(
+            result["metadata"]["url_parts"],
+            json!({"scheme": "https", "host": "docs.example.com", "port": 8443, "path": "/a/B", "query": "x=1&y=2"})
+        );
+        // The original url is left alone
+        assert_eq!(result["metadata"]["url"], "HTTPS://Docs.Example.COM:8443/a/B?x=1&y=2#top");
+
+        // Default port and missing query come out as null
+        let result = modifier.process(json!({"metadata": {"url": "http://example.com:80"}})).unwrap().unwrap();
+        assert_eq!(
+            result["metadata"]["url_parts"],
+            json!({"scheme": "http", "host": "example.com", "port": null, "path": "/", "query": null})
+        );
+    }
+
+    #[test]
+    fn test_normalized() {
+        let modifier = UrlParseModifier::new(&json!({"normalized_field": "canonical_url"})).unwrap();
+        let cases = [
+            ("HTTP://WWW.Example.com:80/Path?b=2&a=1#frag", "http://www.example.com/Path?a=1&b=2"),
+            ("https://example.com:443/?", "https://example.com/"),
+            ("https://example.com:8080/x?z=&a=b", "https://example.com:8080/x?a=b&z="),
+        ];
+        for (raw, expected) in cases {
+            let result = modifier.process(json!({"url": raw})).unwrap().unwrap();
+            assert_eq!(result["canonical_url"], expected, "normalizing {:?}", raw);
+        }
+
+        let config = json!({"normalized_field": "canonical_url", "strip_fragment": false});
+        let modifier = UrlParseModifier::new(&config).unwrap();
+        let result = modifier.process(json!({"url": "https://example.com/a#frag"})).unwrap().unwrap();
+        assert_eq!(result["canonical_url"], "https://example.com/a#frag");
+    }
+
+    #[test]
+    fn test_on_error() {
+        let bad = json!({"url": "not a url"});
+        let modifier = UrlParseModifier::new(&json!({"normalized_field": "u"})).unwrap();
+        assert_eq!(modifier.process(bad.clone()).unwrap().unwrap(), bad);
+
+        let modifier = UrlParseModifier::new(&json!({"normalized_field": "u", "on_error": "remove"})).unwrap();
+        assert!(modifier.process(bad.clone()).unwrap().is_none());
+
+        let modifier = UrlParseModifier::new(&json!({"normalized_field": "u", "on_error": "error"})).unwrap();
+        assert!(modifier.process(bad).is_err());
+
+        // Missing or non-string urls are never errors
+        let data = json!({"url": 5, "text": "hi"});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+        let data = json!({"text": "hi"});
+        assert_eq!(modifier.process(data.clone()).unwrap().unwrap(), data);
+    }
+}
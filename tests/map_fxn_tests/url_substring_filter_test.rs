@@ -246,4 +246,31 @@ mod tests {
         assert!(result.is_none(), "Should filter out data when primary URL is banned, ignoring good alt URL");
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_compressed_banlist_files() {
+        // Banlists go through read_pathbuf_to_mem, so .zst/.gz banlists are decompressed transparently
+        let dir = std::env::temp_dir().join(format!("datamap_banlist_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let banlist_path = dir.join("banlist.txt.zst");
+        let compressed = zstd::stream::encode_all("bad.com\nevil.org\n".as_bytes(), 3).unwrap();
+        std::fs::write(&banlist_path, compressed).unwrap();
+
+        let config = json!({
+            "url_key": "url",
+            "banlist_file": banlist_path.to_str().unwrap(),
+            "exact_domain_match": true
+        });
+        let filter = UrlSubstringFilter::new(&config).unwrap();
+        assert_eq!(filter.banlist, vec!["bad.com", "evil.org"].into_iter().map(String::from).collect::<HashSet<String>>());
+        assert!(filter.process(json!({"url": "http://bad.com/page"})).unwrap().is_none());
+        assert!(filter.process(json!({"url": "http://good.com/page"})).unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compressed_fasttext_model_rejected() {
+        // fasttext reads models from disk itself, so they have to stay uncompressed
+        let err = datamap_rs::utils::load_fasttext_model("ft_classifiers/lid176.bin.zst").unwrap_err();
+        assert!(err.to_string().contains("must be uncompressed"));
+    }
+}
@@ -0,0 +1,65 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, WeightedSubsampleFilter};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn kept_ids(filter: &WeightedSubsampleFilter, weight: f64, n: usize) -> Vec<usize> {
+        (0..n)
+            .filter(|i| filter.process(json!({"id": format!("doc-{}", i), "weight": weight})).unwrap().is_some())
+            .collect()
+    }
+
+    #[test]
+    fn test_weighted_subsample_filter_new() {
+        let filter = WeightedSubsampleFilter::new(&json!({})).unwrap();
+        assert_eq!(filter.weight_field, "weight");
+        assert_eq!(filter.scale, 1.0);
+        assert_eq!(filter.default_weight, 0.0);
+        assert_eq!(filter.seed, None);
+        assert_eq!(filter.id_field, "id");
+
+        let config = json!({"weight_field": "metadata.w", "scale": 0.5, "seed": 42});
+        let filter = WeightedSubsampleFilter::new(&config).unwrap();
+        assert_eq!(filter.weight_field, "metadata.w");
+        assert_eq!(filter.scale, 0.5);
+        assert_eq!(filter.seed, Some(42));
+
+        assert!(WeightedSubsampleFilter::new(&json!({"scale": -1.0})).is_err());
+    }
+
+    #[test]
+    fn test_extreme_weights() {
+        let filter = WeightedSubsampleFilter::new(&json!({})).unwrap();
+        // weight * scale >= 1 always keeps, 0 always drops
+        assert_eq!(kept_ids(&filter, 1.0, 100).len(), 100);
+        assert_eq!(kept_ids(&filter, 5.0, 100).len(), 100);
+        assert_eq!(kept_ids(&filter, 0.0, 100).len(), 0);
+        // Missing weight uses default_weight (0 by default)
+        assert!(filter.process(json!({"id": 1})).unwrap().is_none());
+        let filter = WeightedSubsampleFilter::new(&json!({"default_weight": 1.0})).unwrap();
+        assert!(filter.process(json!({"id": 1})).unwrap().is_some());
+        // Non-numeric weights are an error
+        assert!(filter.process(json!({"weight": "heavy"})).is_err());
+    }
+
+    #[test]
+    fn test_scale_and_rate() {
+        let filter = WeightedSubsampleFilter::new(&json!({"scale": 0.1, "seed": 7})).unwrap();
+        // weight 3 * scale 0.1 => keep ~30%
+        let kept = kept_ids(&filter, 3.0, 10000).len();
+        assert!(kept > 2700 && kept < 3300, "kept {}", kept);
+    }
+
+    #[test]
+    fn test_seed_is_deterministic() {
+        let filter = WeightedSubsampleFilter::new(&json!({"seed": 1234})).unwrap();
+        let first = kept_ids(&filter, 0.5, 1000);
+        assert_eq!(first, kept_ids(&filter, 0.5, 1000));
+
+        let other_seed = WeightedSubsampleFilter::new(&json!({"seed": 4321})).unwrap();
+        assert_ne!(first, kept_ids(&other_seed, 0.5, 1000));
+    }
+}
@@ -0,0 +1,68 @@
+extern crate datamap_rs;
+use datamap_rs::map_fxn::{DataProcessor, WhitespaceNormalizeModifier};
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn normalize(config: serde_json::Value, text: &str) -> String {
+        let modifier = WhitespaceNormalizeModifier::new(&config).unwrap();
+        let result = modifier.process(json!({"text": text})).unwrap().unwrap();
+        result["text"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_whitespace_normalize_modifier_new() {
+        let modifier = WhitespaceNormalizeModifier::new(&json!({})).unwrap();
+        assert_eq!(modifier.text_field, "text");
+        assert!(modifier.collapse_spaces);
+        assert!(modifier.trim_lines);
+        assert!(modifier.collapse_blank_lines);
+
+        let config = json!({
+            "text_field": "content",
+            "collapse_spaces": false,
+            "trim_lines": false,
+            "collapse_blank_lines": false
+        });
+        let modifier = WhitespaceNormalizeModifier::new(&config).unwrap();
+        assert_eq!(modifier.text_field, "content");
+        assert!(!modifier.collapse_spaces);
+        assert!(!modifier.trim_lines);
+        assert!(!modifier.collapse_blank_lines);
+    }
+
+    #[test]
+    fn test_tabs() {
+        let config = json!({"trim_lines": false, "collapse_blank_lines": false});
+        assert_eq!(normalize(config, "a\tb\t\tc"), "a b c");
+    }
+
+    #[test]
+    fn test_trailing_spaces() {
+        assert_eq!(normalize(json!({}), "  hello world   \nnext line \t"), "hello world\nnext line");
+
+        // Without trimming, a trailing run still collapses to a single space
+        let config = json!({"trim_lines": false});
+        assert_eq!(normalize(config, "hello   \nworld"), "hello \nworld");
+    }
+
+    #[test]
+    fn test_mixed_runs() {
+        assert_eq!(normalize(json!({}), "a \t \t b  \u{a0} c"), "a b c");
+        assert_eq!(
+            normalize(json!({}), "para one\n\n  \n\t\n\npara  two\n\n\n"),
+            "para one\n\npara two\n"
+        );
+    }
+
+    #[test]
+    fn test_collapse_blank_lines_only() {
+        let config = json!({"collapse_spaces": false, "trim_lines": false});
+        assert_eq!(normalize(config, "a  b\n\n\n\nc"), "a  b\n\nc");
+
+        // Nothing to do when everything is disabled
+        let config = json!({"collapse_spaces": false, "trim_lines": false, "collapse_blank_lines": false});
+        assert_eq!(normalize(config, " a \t\n\n\n b "), " a \t\n\n\n b ");
+    }
+}
@@ -0,0 +1,94 @@
+extern crate datamap_rs;
+use datamap_rs::utils::{tmp_path, write_mem_atomic, AtomicFile, JsonlWriter};
+use mj_io::read_pathbuf_to_mem;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("datamap_atomic_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn read_lines(path: &PathBuf) -> Vec<String> {
+    read_pathbuf_to_mem(path).unwrap().lines().map(|l| l.unwrap()).collect()
+}
+
+#[test]
+fn test_file_only_appears_on_commit() {
+    let dir = scratch_dir("commit");
+    let path = dir.join("nested").join("out.jsonl");
+    let mut file = AtomicFile::create(&path).unwrap();
+    file.write_all(b"{\"id\": 1}\n").unwrap();
+    assert!(!path.exists());
+    assert!(tmp_path(&path).exists());
+    file.commit().unwrap();
+    assert!(path.exists());
+    assert!(!tmp_path(&path).exists());
+    assert_eq!(fs::read(&path).unwrap(), b"{\"id\": 1}\n");
+
+    let discarded = dir.join("discarded.jsonl");
+    let file = AtomicFile::create(&discarded).unwrap();
+    file.discard().unwrap();
+    assert!(!discarded.exists());
+    assert!(!tmp_path(&discarded).exists());
+}
+
+#[test]
+fn test_write_mem_atomic_compresses_by_extension() {
+    let dir = scratch_dir("mem");
+    let contents = b"{\"id\": 1}\n{\"id\": 2}\n";
+    for name in ["out.jsonl", "out.jsonl.zst", "out.jsonl.gz"] {
+        let path = dir.join(name);
+        write_mem_atomic(contents, &path).unwrap();
+        assert_eq!(read_lines(&path), vec!["{\"id\": 1}", "{\"id\": 2}"], "{}", name);
+        assert!(!tmp_path(&path).exists());
+    }
+    assert_ne!(fs::read(dir.join("out.jsonl.zst")).unwrap(), contents);
+
+    // Overwrites an existing file in one go
+    write_mem_atomic(b"{\"id\": 3}\n", &dir.join("out.jsonl")).unwrap();
+    assert_eq!(read_lines(&dir.join("out.jsonl")), vec!["{\"id\": 3}"]);
+}
+
+#[test]
+fn test_jsonl_writer_matches_write_mem_atomic() {
+    let dir = scratch_dir("jsonl_writer");
+    // Enough docs to cross several chunk boundaries
+    let docs: Vec<serde_json::Value> = (0..50000)
+        .map(|i| serde_json::json!({"id": i, "text": "x".repeat(i % 97)}))
+        .collect();
+    let mut expected: Vec<u8> = Vec::new();
+    for doc in &docs {
+        expected.extend(serde_json::to_vec(doc).unwrap());
+        expected.push(b'\n');
+    }
+    for name in ["out.jsonl", "out.jsonl.zst", "out.jsonl.gz"] {
+        let mem_path = dir.join(format!("mem_{}", name));
+        write_mem_atomic(&expected, &mem_path).unwrap();
+
+        let path = dir.join(name);
+        let mut writer = JsonlWriter::create(&path).unwrap();
+        for (i, doc) in docs.iter().enumerate() {
+            if i % 2 == 0 {
+                writer.write_value(doc).unwrap();
+            } else {
+                writer.write_line(&serde_json::to_vec(doc).unwrap()).unwrap();
+            }
+        }
+        assert_eq!(writer.lines(), docs.len());
+        assert!(!path.exists());
+        writer.finish().unwrap();
+        assert_eq!(read_pathbuf_to_mem(&path).unwrap().into_inner().into_inner(), read_pathbuf_to_mem(&mem_path).unwrap().into_inner().into_inner(), "{}", name);
+        assert!(!tmp_path(&path).exists());
+    }
+
+    let discarded = dir.join("discarded.jsonl.zst");
+    let writer = JsonlWriter::create(&discarded).unwrap();
+    writer.discard().unwrap();
+    assert!(!discarded.exists());
+    assert!(!tmp_path(&discarded).exists());
+}
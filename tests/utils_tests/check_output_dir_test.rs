@@ -0,0 +1,53 @@
+extern crate datamap_rs;
+use datamap_rs::utils::check_output_dir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("datamap_check_output_dir_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_empty_or_missing_dirs_are_fine() {
+        let dir = scratch_dir("empty");
+        assert!(check_output_dir(&dir.join("missing"), false, None).is_ok());
+        assert!(check_output_dir(&dir, false, None).is_ok());
+        // Empty subdirectories (e.g. left behind by an earlier run that wrote nothing) don't count
+        fs::create_dir_all(dir.join("step_final").join("sub")).unwrap();
+        assert!(check_output_dir(&dir, false, None).is_ok());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_existing_files_need_force() {
+        let dir = scratch_dir("files");
+        let nested = dir.join("step_final").join("shard_0.jsonl.zst");
+        fs::create_dir_all(nested.parent().unwrap()).unwrap();
+        fs::write(&nested, "").unwrap();
+        let err = check_output_dir(&dir, false, None).unwrap_err().to_string();
+        assert!(err.contains("is not empty") && err.contains("shard_0.jsonl.zst") && err.contains("--force"), "{}", err);
+        assert!(check_output_dir(&dir, true, None).is_ok());
+
+        // A file where the directory should be is a conflict too
+        assert!(check_output_dir(&nested, false, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_name_filter() {
+        // Only files whose name contains the filter clash, e.g. group runs with different subexts in one dir
+        let dir = scratch_dir("name_filter");
+        fs::write(dir.join("chunk_00000000.00000000.web.jsonl.zst"), "").unwrap();
+        assert!(check_output_dir(&dir, false, Some(".code.")).is_ok());
+        assert!(check_output_dir(&dir, false, Some(".web.")).is_err());
+        assert!(check_output_dir(&dir, false, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,75 @@
+extern crate datamap_rs;
+use datamap_rs::config::ConfigSource;
+use serde_json::json;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+fn inline(body: &str) -> ConfigSource {
+    ConfigSource::new(&None, &Some(body.to_string())).unwrap()
+}
+
+#[test]
+fn test_inline_json_and_yaml() {
+    let expected = json!({"text_field": "body", "pipeline": [{"name": "non_null_filter"}]});
+    assert_eq!(inline(r#"{"text_field": "body", "pipeline": [{"name": "non_null_filter"}]}"#).parse().unwrap(), expected);
+    assert_eq!(inline("text_field: body\npipeline:\n  - name: non_null_filter\n").parse().unwrap(), expected);
+}
+
+#[test]
+fn test_unparseable_inline_reports_both_errors() {
+    let err = inline("pipeline: [unclosed").parse().unwrap_err().to_string();
+    assert!(err.contains("as json (") && err.contains(") or as yaml ("), "{}", err);
+    assert!(err.starts_with("Can't parse config --config-inline"), "{}", err);
+}
+
+#[test]
+fn test_text_key_override() {
+    let config = inline(r#"{"text_field": "body", "pipeline": []}"#);
+    assert_eq!(config.parse_with_text_key(Some("content".to_string())).unwrap()["text_field"], "content");
+    assert_eq!(config.parse_with_text_key(None).unwrap()["text_field"], "body");
+}
+
+#[test]
+fn test_config_files() {
+    let dir = std::env::temp_dir().join(format!("datamap_config_source_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("c.json"), r#"{"pipeline": []}"#).unwrap();
+    fs::write(dir.join("c.yaml"), "pipeline: []\n").unwrap();
+    fs::write(dir.join("c.txt"), "pipeline: []\n").unwrap();
+    let file = |name: &str| ConfigSource::new(&Some(dir.join(name)), &None).unwrap();
+    assert_eq!(file("c.json").parse().unwrap(), json!({"pipeline": []}));
+    assert_eq!(file("c.yaml").parse().unwrap(), json!({"pipeline": []}));
+    assert!(file("c.txt").parse().unwrap_err().to_string().contains("Weird config format"));
+    assert!(file("missing.json").parse().unwrap_err().to_string().contains("Can't open config"));
+    // A file with the wrong format for its extension isn't second-guessed
+    fs::write(dir.join("bad.json"), "pipeline: []\n").unwrap();
+    assert!(file("bad.json").parse().is_err());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(ConfigSource::new(&None, &None).is_err());
+}
+
+#[test]
+fn test_url_config() {
+    // A one-shot local http server standing in for the config host
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request).unwrap();
+        let body = "pipeline:\n  - name: non_null_filter\n";
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body).unwrap();
+    });
+    let url = format!("http://127.0.0.1:{}/configs/base.yaml?rev=2", port);
+    let config = ConfigSource::new(&Some(PathBuf::from(&url)), &None).unwrap();
+    server.join().unwrap();
+    assert_eq!(config.parse().unwrap(), json!({"pipeline": [{"name": "non_null_filter"}]}));
+    assert_eq!(config.to_string(), format!("{:?}", url));
+
+    // Nothing listening there anymore
+    let err = ConfigSource::new(&Some(PathBuf::from(&url)), &None).err().unwrap().to_string();
+    assert!(err.starts_with("Can't fetch config"), "{}", err);
+}
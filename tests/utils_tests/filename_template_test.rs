@@ -0,0 +1,24 @@
+extern crate datamap_rs;
+use datamap_rs::utils::FilenameTemplate;
+
+#[test]
+fn test_filename_template_render() {
+    let template = FilenameTemplate::new("part-{bucket}-{index}.{ext}").unwrap();
+    assert!(template.has_bucket());
+    assert_eq!(template.render("en", 3, "jsonl.zst"), "part-en-00000003.jsonl.zst");
+
+    let template = FilenameTemplate::new("{index}.jsonl").unwrap();
+    assert!(!template.has_bucket());
+    assert_eq!(template.render("en", 12, "jsonl.zst"), "00000012.jsonl");
+}
+
+#[test]
+fn test_filename_template_validation() {
+    // {index} is required so that a bucket's files don't overwrite each other
+    assert!(FilenameTemplate::new("out_{bucket}.{ext}").is_err());
+    assert!(FilenameTemplate::new("out_{idx}_{index}.{ext}").is_err());
+    assert!(FilenameTemplate::new("out_{index.{ext}").is_err());
+    assert!(FilenameTemplate::new("sub/{index}.{ext}").is_err());
+    assert_eq!(FilenameTemplate::from_option(&None).unwrap(), None);
+    assert!(FilenameTemplate::from_option(&Some("{index}".to_string())).unwrap().is_some());
+}
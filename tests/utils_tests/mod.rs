@@ -0,0 +1,7 @@
+pub mod text_helpers_test;
+pub mod output_encoder_test;
+pub mod atomic_write_test;
+pub mod filename_template_test;
+pub mod config_source_test;
+pub mod check_output_dir_test;
+pub mod progress_test;
@@ -0,0 +1,22 @@
+extern crate datamap_rs;
+use datamap_rs::utils::OutputEncoder;
+use std::io::Write;
+
+
+#[test]
+fn test_plain_is_pass_through() {
+    let mut encoder = OutputEncoder::with_compression(Vec::new(), false).unwrap();
+    encoder.write_all(b"{\"id\": 1}\n").unwrap();
+    encoder.write_all(b"{\"id\": 2}\n").unwrap();
+    let bytes = encoder.finish().unwrap();
+    assert_eq!(bytes, b"{\"id\": 1}\n{\"id\": 2}\n");
+}
+
+#[test]
+fn test_zstd_round_trip() {
+    let mut encoder = OutputEncoder::with_compression(Vec::new(), true).unwrap();
+    encoder.write_all(b"{\"id\": 1}\n").unwrap();
+    let bytes = encoder.finish().unwrap();
+    assert_ne!(bytes, b"{\"id\": 1}\n");
+    assert_eq!(zstd::decode_all(bytes.as_slice()).unwrap(), b"{\"id\": 1}\n");
+}
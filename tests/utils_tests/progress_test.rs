@@ -0,0 +1,27 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_quiet_progress_prints_the_final_line() {
+        // A run that finishes well within one poll still reports where its progress ended up
+        let dir = std::env::temp_dir().join(format!("datamap_progress_final_line_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("input")).unwrap();
+        fs::write(dir.join("input").join("shard_0.jsonl"), "{\"text\": \"hello\"}\n").unwrap();
+
+        let config = json!({"pipeline": [{"name": "text_len_filter", "kwargs": {"lower_bound": 1}}]});
+        let output = Command::new(env!("CARGO_BIN_EXE_datamap-rs"))
+            .args(["--quiet", "map", "--config-inline", &config.to_string()])
+            .arg("--input-dir").arg(dir.join("input"))
+            .arg("--output-dir").arg(dir.join("output"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.matches("Files 1/1 (100%)").count(), 1, "{}", stdout);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,37 @@
+extern crate datamap_rs;
+use datamap_rs::utils::{ceil_char_boundary, floor_char_boundary, truncate_at_byte_boundary};
+
+
+#[test]
+fn test_truncate_ascii() {
+    assert_eq!(truncate_at_byte_boundary("hello", 3), "hel");
+    assert_eq!(truncate_at_byte_boundary("hello", 5), "hello");
+    assert_eq!(truncate_at_byte_boundary("hello", 100), "hello");
+    assert_eq!(truncate_at_byte_boundary("hello", 0), "");
+    assert_eq!(truncate_at_byte_boundary("", 10), "");
+}
+
+#[test]
+fn test_truncate_multibyte() {
+    // "é" is 2 bytes, "中" is 3, "🦀" is 4
+    let text = "aé中🦀b";
+    let expected = ["", "a", "a", "aé", "aé", "aé", "aé中", "aé中", "aé中", "aé中", "aé中🦀", "aé中🦀b"];
+    for (max_bytes, expected) in expected.iter().enumerate() {
+        let truncated = truncate_at_byte_boundary(text, max_bytes);
+        assert_eq!(truncated, *expected, "max_bytes {}", max_bytes);
+        assert!(truncated.len() <= max_bytes);
+    }
+    // Only multibyte chars, cutting inside the first one
+    assert_eq!(truncate_at_byte_boundary("🦀🦀", 3), "");
+    assert_eq!(truncate_at_byte_boundary("🦀🦀", 7), "🦀");
+}
+
+#[test]
+fn test_char_boundaries() {
+    let text = "a中b";
+    assert_eq!((0..6).map(|i| floor_char_boundary(text, i)).collect::<Vec<_>>(), vec![0, 1, 1, 1, 4, 5]);
+    assert_eq!((0..6).map(|i| ceil_char_boundary(text, i)).collect::<Vec<_>>(), vec![0, 1, 4, 4, 4, 5]);
+    // Past the end clamps to the length
+    assert_eq!(floor_char_boundary(text, 99), 5);
+    assert_eq!(ceil_char_boundary(text, 99), 5);
+}